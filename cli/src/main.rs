@@ -0,0 +1,230 @@
+//! `usfm`, a command line front end to the parser.
+
+use std::{
+    env, fs,
+    io::{self, Read, Write},
+    process::ExitCode,
+};
+
+use parser::{
+    diagnostic::Severity,
+    document::Document,
+    extension::{Extensions, Version},
+    normalize::NormalizeOptions,
+    plain::PlainTextOptions,
+    reference::RefRange,
+    report,
+};
+
+const USAGE: &str = "\
+usage: usfm <command> [options] <file>...
+
+commands:
+  check [--format text|json|sarif] <file>...
+                                         validate and print diagnostics, as JSON
+                                         lines or a SARIF log if asked
+  convert --to usx|usj|html|text <file>  convert to another format
+  format [--reorder-headers] <file>      print in normalized form
+  extract <file> <reference>             print the text of a passage, such as \"MRK 1:1-5\"
+  stats <file>...                        count chapters, verses and words
+
+A file name of - reads standard input.";
+
+fn main() -> ExitCode {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    let stdout = io::stdout();
+    match run(&args, &mut stdout.lock()) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(err) => {
+            eprintln!("usfm: {err}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+fn usage() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, USAGE)
+}
+
+fn read(path: &str) -> io::Result<String> {
+    let read = if path == "-" {
+        let mut source = String::new();
+        io::stdin().read_to_string(&mut source).map(|_| source)
+    } else {
+        fs::read_to_string(path)
+    };
+    read.map_err(|err| io::Error::new(err.kind(), format!("{path}: {err}")))
+}
+
+fn parse(path: &str, source: &str) -> io::Result<Document<'static>> {
+    source
+        .parse()
+        .map_err(|err: io::Error| io::Error::new(err.kind(), format!("{path}: {err}")))
+}
+
+/// Returns whether the command succeeded; `check` fails when it finds
+/// errors.
+fn run<W: Write>(args: &[String], out: &mut W) -> io::Result<bool> {
+    let Some((command, args)) = args.split_first() else {
+        return Err(usage());
+    };
+    match (command.as_str(), args) {
+        ("check", [flag, format, files @ ..]) if flag == "--format" && !files.is_empty() => {
+            check(files, format, out)
+        }
+        ("check", files) if !files.is_empty() => check(files, "text", out),
+        ("convert", [flag, format, file]) if flag == "--to" => {
+            let source = read(file)?;
+            let doc = parse(file, &source)?;
+            match format.as_str() {
+                "usx" => doc.to_usx(&mut *out)?,
+                "usj" => writeln!(out, "{:#}", doc.to_usj())?,
+                "html" => write!(out, "{}", doc.to_html())?,
+                "text" => writeln!(out, "{}", doc.to_plain_text(PlainTextOptions::default()))?,
+                _ => return Err(usage()),
+            }
+            Ok(true)
+        }
+        ("format", [file]) | ("format", [_, file]) => {
+            let reorder_headers = match args {
+                [flag, _] if flag == "--reorder-headers" => true,
+                [_] => false,
+                _ => return Err(usage()),
+            };
+            let source = read(file)?;
+            let mut doc = parse(file, &source)?;
+            doc.normalize(NormalizeOptions { reorder_headers });
+            write!(out, "{doc}")?;
+            Ok(true)
+        }
+        ("extract", [file, reference]) => {
+            let range = reference.parse::<RefRange>()?;
+            let source = read(file)?;
+            let doc = parse(file, &source)?;
+            match doc.range_text(&range) {
+                Some(text) => writeln!(out, "{text}")?,
+                None => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!("{file}: {reference} not found"),
+                    ))
+                }
+            }
+            Ok(true)
+        }
+        ("stats", files) if !files.is_empty() => {
+            for file in files {
+                let source = read(file)?;
+                let doc = parse(file, &source)?;
+                let chapters = doc.find_all("c").count();
+                let verses = doc.iter_verses().count();
+                let words = doc
+                    .to_plain_text(PlainTextOptions::default())
+                    .split_whitespace()
+                    .count();
+                let book = doc.book().map_or("???", |book| book.code);
+                writeln!(
+                    out,
+                    "{file}: {book} {chapters} chapters, {verses} verses, {words} words"
+                )?;
+            }
+            Ok(true)
+        }
+        _ => Err(usage()),
+    }
+}
+
+fn check<W: Write>(files: &[String], format: &str, out: &mut W) -> io::Result<bool> {
+    if !matches!(format, "text" | "json" | "sarif") {
+        return Err(usage());
+    }
+    let mut ok = true;
+    let mut checked = Vec::new();
+    for file in files {
+        let source = read(file)?;
+        let (doc, mut diagnostics) = Document::from_str_lenient(&source);
+        diagnostics.extend(doc.validate(Extensions::usfm(Version::default())));
+        diagnostics.extend(doc.check_continuity(None));
+        ok &= diagnostics.iter().all(|d| d.severity != Severity::Error);
+        match format {
+            "json" => report::write_json_lines(&mut *out, file, &diagnostics)?,
+            "sarif" => checked.push((file.as_str(), diagnostics)),
+            _ => {
+                for diagnostic in &diagnostics {
+                    write!(out, "{file}: {}", diagnostic.render(&source))?;
+                }
+            }
+        }
+    }
+    if format == "sarif" {
+        let files = checked.iter().map(|(file, d)| (*file, d.as_slice()));
+        writeln!(out, "{:#}", report::sarif(files))?;
+    }
+    Ok(ok)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{env, fs, process};
+
+    use super::run;
+
+    fn usfm(args: &[&str], source: &str) -> (bool, String) {
+        let path = env::temp_dir().join(format!("usfm-cli-{}-{}.usfm", process::id(), args[0]));
+        fs::write(&path, source).expect("write");
+        let mut args = args.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+        for arg in &mut args {
+            if arg == "FILE" {
+                *arg = path.to_string_lossy().into_owned();
+            }
+        }
+        let mut out = Vec::new();
+        let ok = run(&args, &mut out).expect("run");
+        fs::remove_file(&path).expect("remove");
+        let out = String::from_utf8(out).expect("UTF-8");
+        let prefix = format!("{}: ", path.to_string_lossy());
+        (ok, out.replace(&prefix, ""))
+    }
+
+    const MARK: &str = "\\id MRK\n\\c 1\n\\p \\v 1 The beginning \\v 2 As it is written\n";
+
+    #[test]
+    fn commands() {
+        assert_eq!(
+            usfm(&["convert", "--to", "text", "FILE"], MARK),
+            (true, "The beginning As it is written\n".into())
+        );
+        assert_eq!(
+            usfm(&["extract", "FILE", "MRK 1:2"], MARK),
+            (true, "As it is written\n".into())
+        );
+        assert_eq!(
+            usfm(&["stats", "FILE"], MARK),
+            (true, "MRK 1 chapters, 2 verses, 6 words\n".into())
+        );
+        assert_eq!(
+            usfm(&["format", "FILE"], "\\id MRK\n\\p   a\n\n b\n").1,
+            "\\id MRK\n\\p a b\n"
+        );
+        assert_eq!(
+            usfm(
+                &["format", "--reorder-headers", "FILE"],
+                "\\id MRK\n\\toc1 T\n\\h H\n"
+            )
+            .1,
+            "\\id MRK\n\\h H\n\\toc1 T\n"
+        );
+        let (ok, out) = usfm(&["check", "FILE"], "\\id MRK\n\\c x\n");
+        assert!(!ok);
+        assert!(out.starts_with("error["), "{out}");
+        let (ok, out) = usfm(&["check", "--format", "json", "FILE"], "\\id MRK\n\\c x\n");
+        assert!(!ok);
+        assert!(out.contains("\"code\":\"invalid-number\""), "{out}");
+        assert_eq!(out.lines().count(), 1);
+        let (_, out) = usfm(&["check", "--format", "sarif", "FILE"], "\\id MRK\n\\c x\n");
+        assert!(out.contains("\"version\": \"2.1.0\""), "{out}");
+        assert!(run(&["check".into(), "--format".into()], &mut Vec::new()).is_err());
+        assert!(run(&["convert".into()], &mut Vec::new()).is_err());
+    }
+}
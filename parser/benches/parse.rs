@@ -0,0 +1,46 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use parser::document::{Document, ParseOptions};
+
+// A book of `chapters` chapters with poetry, footnotes and character
+// styles, close enough to real text to show where parsing spends its time.
+fn book(chapters: u32) -> String {
+    let mut usfm = String::from("\\id GEN Benchmark\n\\h Genesis\n\\mt1 Genesis\n");
+    for c in 1..=chapters {
+        usfm += &format!("\\c {c}\n\\s1 Section {c}\n\\p\n");
+        for v in 1..=30 {
+            usfm += &format!(
+                "\\v {v} In the beginning God created the heavens and the earth, \
+                 and/or \\add the earth\\add* was formless\\f + \\fr {c}:{v} \
+                 \\ft Or \\fqa empty\\fqa*\\f*.\n"
+            );
+            if v % 10 == 0 {
+                usfm += "\\q1 Let there be light,\n\\q2 and there was light.\n\\p\n";
+            }
+        }
+    }
+    usfm
+}
+
+fn parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+    for chapters in [1, 50] {
+        let usfm = book(chapters);
+        group.throughput(Throughput::Bytes(usfm.len() as u64));
+        group.bench_with_input(BenchmarkId::new("from_str", chapters), &usfm, |b, usfm| {
+            b.iter(|| usfm.parse::<Document>().expect("Document"))
+        });
+        group.bench_with_input(BenchmarkId::new("borrowed", chapters), &usfm, |b, usfm| {
+            b.iter(|| Document::from_str_with(usfm, ParseOptions::default()).expect("Document"))
+        });
+    }
+    group.finish();
+}
+
+fn write(c: &mut Criterion) {
+    let usfm = book(50);
+    let doc = Document::from_str_with(&usfm, ParseOptions::default()).expect("Document");
+    c.bench_function("write/to_string", |b| b.iter(|| doc.to_string()));
+}
+
+criterion_group!(benches, parse, write);
+criterion_main!(benches);
@@ -0,0 +1,23 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use parser::{
+    document::Document,
+    writer::{Options, Whitespace},
+};
+
+// Parsing any text must not panic. Text that parses must be written back
+// byte for byte, and a document written out must parse again to the same
+// content.
+fuzz_target!(|source: &str| {
+    if let Ok(doc) = source.parse::<Document>() {
+        let options = Options {
+            whitespace: Whitespace::Preserve,
+            ..Options::default()
+        };
+        assert_eq!(doc.to_usfm(options).to_string(), source);
+        let usfm = doc.to_string();
+        let again = usfm.parse::<Document>().expect("written document parses");
+        assert!(again.canonical_eq(&doc), "{usfm}");
+    }
+});
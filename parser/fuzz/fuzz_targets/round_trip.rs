@@ -0,0 +1,11 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use parser::document::Document;
+
+// A generated document written out and parsed again keeps its content.
+fuzz_target!(|doc: Document<'static>| {
+    let usfm = doc.to_string();
+    let parsed = usfm.parse::<Document>().expect("generated document parses");
+    assert!(parsed.canonical_eq(&doc), "{usfm}");
+});
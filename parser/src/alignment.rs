@@ -0,0 +1,223 @@
+//! Word alignments embedded as `\zaln-s ... \zaln-e` milestones around `\w`
+//! words, as produced by unfoldingWord's aligned gateway language texts.
+
+use crate::{
+    document::{Content, Document, Node},
+    reference::Reference,
+    versification::verse_range,
+};
+
+/// An original language word, from the attributes of a `\zaln-s` milestone.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SourceWord {
+    pub content: String,
+    pub strong: Option<String>,
+    pub lemma: Option<String>,
+    pub morph: Option<String>,
+    pub occurrence: u32,
+    pub occurrences: u32,
+}
+
+/// A gateway language word, from a `\w` span.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct TargetWord {
+    pub text: String,
+    pub occurrence: u32,
+    pub occurrences: u32,
+}
+
+/// The source words of one group of nested `\zaln-s` milestones with the
+/// `\w` words they enclose.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Alignment {
+    pub reference: Reference,
+    pub sources: Vec<SourceWord>,
+    pub targets: Vec<TargetWord>,
+}
+
+impl Alignment {
+    /// Every source word paired with every target word of the group.
+    pub fn pairs(&self) -> impl Iterator<Item = (&SourceWord, &TargetWord)> {
+        self.sources
+            .iter()
+            .flat_map(|source| self.targets.iter().map(move |target| (source, target)))
+    }
+}
+
+// Occurrence counts default to 1, as for a word appearing once in its verse.
+fn occurrence(node: &Node, name: &str) -> u32 {
+    node.attributes
+        .get(name)
+        .and_then(|n| n.trim().parse().ok())
+        .unwrap_or(1)
+}
+
+impl Node<'_> {
+    /// The original language word of a `\zaln-s` milestone.
+    pub fn source_word(&self) -> Option<SourceWord> {
+        if self.style != "zaln-s" {
+            return None;
+        }
+        let attribute = |name| self.attributes.get(name).map(|v| v.to_string());
+        Some(SourceWord {
+            content: attribute("x-content").unwrap_or_default(),
+            strong: attribute("x-strong"),
+            lemma: attribute("x-lemma"),
+            morph: attribute("x-morph"),
+            occurrence: occurrence(self, "x-occurrence"),
+            occurrences: occurrence(self, "x-occurrences"),
+        })
+    }
+
+    /// The gateway language word of a `\w` span.
+    pub fn target_word(&self) -> Option<TargetWord> {
+        (self.style == "w").then(|| TargetWord {
+            text: self.text(),
+            occurrence: occurrence(self, "x-occurrence"),
+            occurrences: occurrence(self, "x-occurrences"),
+        })
+    }
+}
+
+impl Document<'_> {
+    /// The alignment groups in document order. A group runs from an
+    /// outermost `\zaln-s` to its matching `\zaln-e`, so source words nested
+    /// inside it share its target words. Words in notes are not aligned.
+    pub fn alignments(&self) -> Vec<Alignment> {
+        let (Some(book), Some(root)) = (self.book(), &self.nodes) else {
+            return Vec::new();
+        };
+        let mut collector = Collector {
+            reference: Reference::new(book, 0, None),
+            depth: 0,
+            current: None,
+            alignments: Vec::new(),
+        };
+        collector.content(&root.content);
+        collector.flush();
+        collector.alignments
+    }
+}
+
+struct Collector {
+    reference: Reference,
+    // The number of open `\zaln-s` milestones.
+    depth: usize,
+    current: Option<Alignment>,
+    alignments: Vec<Alignment>,
+}
+
+impl Collector {
+    fn content(&mut self, content: &[Content]) {
+        for item in content {
+            match item {
+                Content::Chapter(node) => {
+                    self.flush();
+                    self.reference.chapter = node
+                        .attributes
+                        .get("number")
+                        .and_then(|n| n.parse().ok())
+                        .unwrap_or(self.reference.chapter);
+                    self.reference.verse = None;
+                    self.content(&node.content);
+                }
+                Content::Verse(node) => {
+                    self.flush();
+                    if let Some((first, _)) =
+                        node.attributes.get("number").and_then(|n| verse_range(n))
+                    {
+                        self.reference.verse = Some(first);
+                    }
+                }
+                Content::Milestone(node) if node.style == "zaln-e" => {
+                    self.depth = self.depth.saturating_sub(1);
+                    if self.depth == 0 {
+                        self.flush();
+                    }
+                }
+                Content::Milestone(node) => {
+                    if let Some(source) = node.source_word() {
+                        self.depth += 1;
+                        self.current
+                            .get_or_insert_with(|| Alignment {
+                                reference: self.reference,
+                                sources: Vec::new(),
+                                targets: Vec::new(),
+                            })
+                            .sources
+                            .push(source);
+                    }
+                }
+                Content::Char(node) if node.style == "w" => {
+                    if let (Some(alignment), Some(target)) = (&mut self.current, node.target_word())
+                    {
+                        alignment.targets.push(target);
+                    }
+                }
+                Content::Note(_) => {}
+                item => {
+                    if let Some(node) = item.node() {
+                        self.content(&node.content);
+                    }
+                }
+            }
+        }
+    }
+
+    // Close the current group, including one left open by a missing
+    // `\zaln-e` before a chapter or verse.
+    fn flush(&mut self) {
+        self.depth = 0;
+        self.alignments.extend(self.current.take());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::document::Document;
+
+    #[test]
+    fn alignments() {
+        let doc: Document = "\\id TIT\n\\c 1\n\\p\n\
+             \\v 1 \\zaln-s |x-strong=\"G39720\" x-lemma=\"Παῦλος\" x-occurrence=\"1\" \
+             x-occurrences=\"1\" x-content=\"Παῦλος\"\\*\\w Paul|x-occurrence=\"1\" \
+             x-occurrences=\"1\"\\w*\\zaln-e\\*, \\w unaligned\\w* \
+             \\zaln-s |x-strong=\"G14010\" x-content=\"δοῦλος\"\\*\\zaln-s |x-strong=\"G2316\" \
+             x-content=\"θεοῦ\"\\*\\w a\\w* \\w servant\\w*\\zaln-e\\*\\zaln-e\\*\n\
+             \\v 2 \\zaln-s |x-content=\"ἐπ’\"\\*\\w in\\w*\\zaln-e\\*\n"
+            .parse()
+            .expect("Document");
+        let alignments = doc.alignments();
+        let summary = alignments
+            .iter()
+            .map(|a| {
+                (
+                    a.reference.to_string(),
+                    a.sources
+                        .iter()
+                        .map(|s| s.content.as_str())
+                        .collect::<Vec<_>>(),
+                    a.targets
+                        .iter()
+                        .map(|t| t.text.as_str())
+                        .collect::<Vec<_>>(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            [
+                ("TIT 1:1".to_owned(), vec!["Παῦλος"], vec!["Paul"]),
+                (
+                    "TIT 1:1".to_owned(),
+                    vec!["δοῦλος", "θεοῦ"],
+                    vec!["a", "servant"]
+                ),
+                ("TIT 1:2".to_owned(), vec!["ἐπ’"], vec!["in"]),
+            ]
+        );
+        assert_eq!(alignments[0].sources[0].strong.as_deref(), Some("G39720"));
+        assert_eq!(alignments[0].sources[0].lemma.as_deref(), Some("Παῦλος"));
+        assert_eq!(alignments[1].pairs().count(), 4);
+    }
+}
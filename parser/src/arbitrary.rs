@@ -0,0 +1,255 @@
+//! Random documents for property tests and fuzzing.
+//!
+//! The [`Arbitrary`] implementation for [`Document`] assembles a book with
+//! [`DocumentBuilder`], taking its markers by category from the bundled
+//! USFM 3 set, so every document it makes is one the parser could have
+//! produced. Written out and parsed again, such a document should be
+//! [canonically equal](Document::canonical_eq) to the original.
+
+use std::sync::OnceLock;
+
+use arbitrary::{Arbitrary, Result, Unstructured};
+
+use crate::{
+    books::{self, Book},
+    builder::DocumentBuilder,
+    document::{Document, State},
+    extension::Category,
+};
+
+// Words to build text from, with punctuation and letters outside ASCII.
+const WORDS: &[&str] = &[
+    "In",
+    "the",
+    "beginning",
+    "God",
+    "created",
+    "heavens",
+    "and",
+    "earth.",
+    "“Light!”",
+    "said,",
+    "(see",
+    "v. 3)",
+    "Ἐν",
+    "ἀρχῇ",
+    "ἦν",
+    "λόγος,",
+    "בְּרֵאשִׁית",
+    "‘word’",
+    "1:1",
+    "–",
+    "Yahweh",
+    "niño",
+    "çà",
+    "end;",
+];
+
+// The markers of each category, leaving out the unnumbered names of
+// numbered families such as `\q`.
+struct Schema {
+    header: Vec<&'static str>,
+    title: Vec<&'static str>,
+    intro: Vec<&'static str>,
+    intro_char: Vec<&'static str>,
+    section: Vec<&'static str>,
+    para: Vec<&'static str>,
+    other: Vec<&'static str>,
+    list: Vec<&'static str>,
+    list_char: Vec<&'static str>,
+    char: Vec<&'static str>,
+    footnote: Vec<&'static str>,
+    footnote_char: Vec<&'static str>,
+    crossref: Vec<&'static str>,
+    crossref_char: Vec<&'static str>,
+}
+
+fn schema() -> &'static Schema {
+    static SCHEMA: OnceLock<Schema> = OnceLock::new();
+    SCHEMA.get_or_init(|| {
+        let markers = State::usfm_ext();
+        let names = |category| {
+            markers
+                .by_category(category)
+                .filter(|marker| marker.levels.is_none())
+                .map(|marker| marker.name.as_str())
+                .collect::<Vec<_>>()
+        };
+        Schema {
+            header: names(Category::Header),
+            title: names(Category::Title),
+            intro: names(Category::Introduction),
+            intro_char: names(Category::IntroChar),
+            section: names(Category::SectionPara),
+            para: names(Category::VersePara),
+            other: names(Category::OtherPara),
+            list: names(Category::List),
+            list_char: names(Category::ListChar),
+            char: names(Category::Char),
+            footnote: names(Category::Footnote),
+            footnote_char: names(Category::FootnoteChar),
+            crossref: names(Category::Crossreference),
+            crossref_char: names(Category::CrossreferenceChar),
+        }
+    })
+}
+
+impl<'a> Arbitrary<'a> for Document<'static> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Generator {
+            u,
+            schema: schema(),
+            builder: DocumentBuilder::new(),
+        }
+        .book()
+    }
+}
+
+struct Generator<'u, 'a> {
+    u: &'u mut Unstructured<'a>,
+    schema: &'static Schema,
+    builder: DocumentBuilder<'static>,
+}
+
+impl Generator<'_, '_> {
+    fn book(mut self) -> Result<Document<'static>> {
+        let books = books::BOOKS
+            .iter()
+            .filter(|book| !book.is_peripheral())
+            .collect::<Vec<&Book>>();
+        let book = self.u.choose(&books)?;
+        self.step(|b| b.book(book.code));
+        // Headers hold plain text.
+        for _ in 0..self.u.int_in_range(0..=3)? {
+            let style = *self.u.choose(&self.schema.header)?;
+            self.step(|b| b.para(style));
+            self.words()?;
+        }
+        for (markers, chars, max) in [
+            (&self.schema.title, None, 2),
+            (&self.schema.intro, Some(&self.schema.intro_char), 3),
+        ] {
+            for _ in 0..self.u.int_in_range(0..=max)? {
+                let style = *self.u.choose(markers)?;
+                self.step(|b| b.para(style));
+                self.inline(chars.map(Vec::as_slice), 0)?;
+            }
+        }
+        for chapter in 1..=self.u.int_in_range(1..=3)? {
+            self.step(|b| b.chapter(chapter));
+            let mut verse = 0;
+            for _ in 0..self.u.int_in_range(1..=5)? {
+                let (markers, chars, verses) = match self.u.int_in_range(0..=3)? {
+                    0 => (&self.schema.section, None, false),
+                    1 => (&self.schema.other, None, false),
+                    2 => (&self.schema.list, Some(&self.schema.list_char), true),
+                    _ => (&self.schema.para, None, true),
+                };
+                let style = *self.u.choose(markers)?;
+                self.step(|b| b.para(style));
+                if !verses {
+                    self.inline(None, 0)?;
+                    continue;
+                }
+                for _ in 0..self.u.int_in_range(0..=2)? {
+                    if self.u.arbitrary()? {
+                        verse += 1;
+                        self.step(|b| b.verse(verse));
+                    }
+                    self.inline(chars.map(Vec::as_slice), 0)?;
+                }
+            }
+        }
+        // The builder refusing a marker is a mistake in the generator.
+        Ok(self.builder.build().expect("generated document"))
+    }
+
+    fn step(&mut self, f: impl FnOnce(DocumentBuilder<'static>) -> DocumentBuilder<'static>) {
+        let builder = std::mem::take(&mut self.builder);
+        self.builder = f(builder);
+    }
+
+    fn words(&mut self) -> Result<()> {
+        let mut text = String::new();
+        for n in 0..self.u.int_in_range(1..=4)? {
+            if n > 0 {
+                text.push(' ');
+            }
+            text.push_str(self.u.choose(WORDS)?);
+        }
+        self.step(|b| b.text(&text));
+        Ok(())
+    }
+
+    // Text, character spans and notes, at most `depth` spans deep.
+    fn inline(&mut self, extra: Option<&[&'static str]>, depth: usize) -> Result<()> {
+        for n in 0..self.u.int_in_range(1..=4)? {
+            match self.u.int_in_range(0..=5)? {
+                0 if depth < 2 => {
+                    let chars = match extra {
+                        Some(extra) if self.u.arbitrary()? => extra,
+                        _ => &self.schema.char,
+                    };
+                    let style = *self.u.choose(chars)?;
+                    self.step(|b| b.start_char(style));
+                    self.inline(None, depth + 1)?;
+                    self.step(DocumentBuilder::end);
+                }
+                1 if depth == 0 => self.note()?,
+                _ => {
+                    if n > 0 {
+                        self.step(|b| b.text(" "));
+                    }
+                    self.words()?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn note(&mut self) -> Result<()> {
+        let (notes, chars) = match self.u.arbitrary()? {
+            true => (&self.schema.footnote, &self.schema.footnote_char),
+            false => (&self.schema.crossref, &self.schema.crossref_char),
+        };
+        let style = *self.u.choose(notes)?;
+        let caller = *self.u.choose(&["+", "-", "a"])?;
+        self.step(|b| b.start_note(style, caller));
+        for _ in 0..self.u.int_in_range(1..=3)? {
+            let style = *self.u.choose(chars)?;
+            self.step(|b| b.start_char(style));
+            self.inline(None, 1)?;
+            self.step(DocumentBuilder::end);
+        }
+        self.step(DocumentBuilder::end);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use arbitrary::{Arbitrary, Unstructured};
+    use proptest::prelude::*;
+
+    use crate::{
+        document::{Document, ParseOptions},
+        extension::Version,
+    };
+
+    proptest! {
+        #[test]
+        fn round_trip(bytes in prop::collection::vec(any::<u8>(), 0..2048)) {
+            let doc = Document::arbitrary(&mut Unstructured::new(&bytes)).expect("Document");
+            let usfm = doc.to_string();
+            // Generated books use USFM 3 markers without declaring them.
+            let options = ParseOptions {
+                version: Version::V3_0,
+                ..ParseOptions::default()
+            };
+            let parsed = Document::from_str_with(&usfm, options)
+                .map_err(|e| TestCaseError::fail(format!("{e}\n{usfm}")))?;
+            prop_assert!(parsed.canonical_eq(&doc), "{}", usfm);
+            prop_assert_eq!(parsed.to_string(), usfm);
+        }
+    }
+}
@@ -0,0 +1,415 @@
+//! Audio timings kept in the text as `\zaudio-s ... \zaudio-e` milestones
+//! around each verse, so an audio Bible recording can be aligned with the
+//! parse tree and the alignment survives writing the USFM back out.
+//!
+//! Timings are read from label files: Audacity label tracks as exported by
+//! HearThis, or Aeneas sync maps in its `aud` or `tsv` formats. Each line
+//! gives the start and end of a fragment in seconds and a label naming its
+//! verse, such as `3`, `v3` or `16-17`.
+
+use std::{borrow::Cow, collections::HashMap, io};
+
+use crate::{
+    document::{Content, Document, Node, State},
+    extension::Extensions,
+    reference::Reference,
+    usx::invalid,
+    verses::is_heading,
+    versification::verse_range,
+};
+
+/// The milestone starting the audio of a verse, with `begin` and `end`
+/// attributes in seconds and an optional `file`.
+pub const START: &str = "zaudio-s";
+/// The milestone ending the audio of a verse.
+pub const END: &str = "zaudio-e";
+
+/// One fragment of a timing file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Timing {
+    pub begin: f64,
+    pub end: f64,
+    pub label: String,
+}
+
+impl Timing {
+    /// The first verse named by the label, if it names one.
+    pub fn verse(&self) -> Option<u32> {
+        let label = self.label.trim();
+        let label = label.strip_prefix(['v', 'V']).unwrap_or(label);
+        verse_range(label).map(|(first, _)| first)
+    }
+}
+
+/// The audio of a verse, from its `\zaudio-s` milestone.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VerseAudio {
+    pub reference: Reference,
+    pub begin: f64,
+    pub end: f64,
+    pub file: Option<String>,
+}
+
+/// Read a label file with a tab separated start, end and label on each
+/// line. Times are in seconds, or `h:m:s` with fractional seconds.
+pub fn read_labels(text: &str) -> io::Result<Vec<Timing>> {
+    let mut timings = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        // Audacity gives the frequency range of a label on a line after it.
+        if line.trim().is_empty() || line.starts_with('\\') {
+            continue;
+        }
+        let mut fields = line.splitn(3, '\t');
+        let mut time = || {
+            let field = fields.next().unwrap_or_default().trim();
+            seconds(field).ok_or_else(|| invalid(format!("line {}: bad time {field:?}", n + 1)))
+        };
+        let (begin, end) = (time()?, time()?);
+        timings.push(Timing {
+            begin,
+            end,
+            label: fields.next().unwrap_or_default().trim().to_owned(),
+        });
+    }
+    Ok(timings)
+}
+
+fn seconds(s: &str) -> Option<f64> {
+    let mut res = 0.0;
+    for part in s.split(':') {
+        res = res * 60.0 + part.parse::<f64>().ok()?;
+    }
+    (res.is_finite() && res >= 0.0).then_some(res)
+}
+
+impl Document<'_> {
+    /// Put the timings of a chapter's verses in `\zaudio-s` and `\zaudio-e`
+    /// milestones around them, replacing any already there. The start goes
+    /// just after the `\v` marker and the end after the verse's last text,
+    /// before any heading following it. Fragments labelled with the same
+    /// verse are joined into one. Returns the timings for verses not in the
+    /// chapter and those whose labels name no verse, such as headings.
+    pub fn attach_audio(
+        &mut self,
+        chapter: u32,
+        file: Option<&str>,
+        timings: &[Timing],
+    ) -> Vec<Timing> {
+        let Some(root) = self.nodes.as_mut() else {
+            return timings.to_vec();
+        };
+        let Some(node) = root.content.iter_mut().find_map(|item| match item {
+            Content::Chapter(node) if number(node) == Some(chapter) => Some(node),
+            _ => None,
+        }) else {
+            return timings.to_vec();
+        };
+        remove_audio(node);
+
+        let mut verses = Verses {
+            markers: State::usfm_ext(),
+            path: Vec::new(),
+            open: None,
+            tail: Vec::new(),
+            verses: Vec::new(),
+        };
+        verses.block(node);
+        verses.close();
+
+        let mut times = HashMap::<u32, (f64, f64)>::new();
+        let mut unused = Vec::new();
+        for timing in timings {
+            match timing.verse() {
+                Some(verse) if verses.verses.iter().any(|(v, ..)| *v == verse) => {
+                    let (begin, end) = times.entry(verse).or_insert((timing.begin, timing.end));
+                    *begin = begin.min(timing.begin);
+                    *end = end.max(timing.end);
+                }
+                _ => unused.push(timing.clone()),
+            }
+        }
+
+        // Insert from the end backwards so the paths of those still to go
+        // stay valid, a start before an end at the same place.
+        let mut inserts = Vec::new();
+        for (verse, start, end) in verses.verses {
+            let Some((begin, finish)) = times.get(&verse) else {
+                continue;
+            };
+            let mut attributes = HashMap::from([
+                (Cow::from("begin"), Cow::from(format!("{begin:.3}"))),
+                (Cow::from("end"), Cow::from(format!("{finish:.3}"))),
+            ]);
+            if let Some(file) = file {
+                attributes.insert("file".into(), file.to_owned().into());
+            }
+            inserts.push((start, false, milestone(START, attributes)));
+            inserts.push((end, true, milestone(END, HashMap::new())));
+        }
+        inserts.sort_by(|a, b| (&a.0, a.1).cmp(&(&b.0, b.1)));
+        for (path, _, item) in inserts.into_iter().rev() {
+            insert(node, &path, item);
+        }
+        root.span = None;
+        unused
+    }
+
+    /// The audio of each verse with a `\zaudio-s` milestone, in document
+    /// order.
+    pub fn audio(&self) -> Vec<VerseAudio> {
+        let (Some(book), Some(root)) = (self.book(), &self.nodes) else {
+            return Vec::new();
+        };
+        let mut collector = Collector {
+            reference: Reference::new(book, 0, None),
+            audio: Vec::new(),
+        };
+        collector.content(&root.content);
+        collector.audio
+    }
+}
+
+fn number(node: &Node) -> Option<u32> {
+    node.attributes.get("number")?.parse().ok()
+}
+
+fn milestone<'i>(
+    style: &'static str,
+    attributes: HashMap<Cow<'i, str>, Cow<'i, str>>,
+) -> Content<'i> {
+    Content::Milestone(Node {
+        style: style.into(),
+        attributes,
+        custom: true,
+        ..Node::default()
+    })
+}
+
+// Remove the audio milestones below `node`, returning whether there were any.
+fn remove_audio(node: &mut Node) -> bool {
+    let before = node.content.len();
+    node.content.retain(
+        |item| !matches!(item, Content::Milestone(m) if matches!(m.style.as_ref(), START | END)),
+    );
+    let mut changed = node.content.len() != before;
+    for item in &mut node.content {
+        if let Some(child) = item.node_mut() {
+            changed |= remove_audio(child);
+        }
+    }
+    if changed {
+        node.span = None;
+    }
+    changed
+}
+
+// Insert `item` at a path of content indexes below `node`, dropping the
+// spans of the nodes on the way down.
+fn insert<'i>(node: &mut Node<'i>, path: &[usize], item: Content<'i>) {
+    node.span = None;
+    match path {
+        [index] => node.content.insert(*index, item),
+        [index, rest @ ..] => {
+            if let Some(child) = node.content.get_mut(*index).and_then(Content::node_mut) {
+                insert(child, rest, item);
+            }
+        }
+        [] => {}
+    }
+}
+
+// The verses of a chapter with the paths, as content indexes, of the
+// places their audio starts and ends.
+struct Verses {
+    markers: &'static Extensions,
+    path: Vec<usize>,
+    open: Option<(u32, Vec<usize>)>,
+    // Just past the last item of the open verse.
+    tail: Vec<usize>,
+    verses: Vec<(u32, Vec<usize>, Vec<usize>)>,
+}
+
+impl Verses {
+    fn after(&self) -> Vec<usize> {
+        let mut path = self.path.clone();
+        if let Some(last) = path.last_mut() {
+            *last += 1;
+        }
+        path
+    }
+
+    fn close(&mut self) {
+        if let Some((verse, start)) = self.open.take() {
+            self.verses
+                .push((verse, start, std::mem::take(&mut self.tail)));
+        }
+    }
+
+    fn block(&mut self, block: &Node) {
+        for (n, item) in block.content.iter().enumerate() {
+            self.path.push(n);
+            match item {
+                Content::Verse(node) => {
+                    self.close();
+                    if let Some((first, _)) =
+                        node.attributes.get("number").and_then(|n| verse_range(n))
+                    {
+                        self.tail = self.after();
+                        self.open = Some((first, self.after()));
+                    }
+                }
+                Content::Para(node) if is_heading(self.markers, node) => self.close(),
+                Content::Para(node)
+                | Content::List(node)
+                | Content::Stanza(node)
+                | Content::Table(node)
+                | Content::Row(node)
+                | Content::Cell(node)
+                | Content::Sidebar(node)
+                | Content::Periph(node) => self.block(node),
+                Content::Book(_) => {}
+                _ if self.open.is_some() => self.tail = self.after(),
+                _ => {}
+            }
+            self.path.pop();
+        }
+    }
+}
+
+struct Collector {
+    reference: Reference,
+    audio: Vec<VerseAudio>,
+}
+
+impl Collector {
+    fn content(&mut self, content: &[Content]) {
+        for item in content {
+            match item {
+                Content::Chapter(node) => {
+                    self.reference.chapter = number(node).unwrap_or(self.reference.chapter);
+                    self.reference.verse = None;
+                    self.content(&node.content);
+                }
+                Content::Verse(node) => {
+                    if let Some((first, _)) =
+                        node.attributes.get("number").and_then(|n| verse_range(n))
+                    {
+                        self.reference.verse = Some(first);
+                    }
+                }
+                Content::Milestone(node) if node.style == START => {
+                    let time = |name| node.attributes.get(name).and_then(|t| seconds(t));
+                    if let (Some(begin), Some(end)) = (time("begin"), time("end")) {
+                        self.audio.push(VerseAudio {
+                            reference: self.reference,
+                            begin,
+                            end,
+                            file: node.attributes.get("file").map(|f| f.to_string()),
+                        });
+                    }
+                }
+                Content::Note(_) => {}
+                item => {
+                    if let Some(node) = item.node() {
+                        self.content(&node.content);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{read_labels, Timing};
+    use crate::document::Document;
+
+    #[test]
+    fn labels() {
+        let timings = read_labels(
+            "0.000000\t2.500000\t1\n\\\t100.0\t2000.0\n2.5\t0:06.25\tv2\n6.25\t1:00:00\n",
+        )
+        .expect("labels");
+        assert_eq!(
+            timings,
+            [
+                Timing {
+                    begin: 0.0,
+                    end: 2.5,
+                    label: "1".into()
+                },
+                Timing {
+                    begin: 2.5,
+                    end: 6.25,
+                    label: "v2".into()
+                },
+                Timing {
+                    begin: 6.25,
+                    end: 3600.0,
+                    label: String::new()
+                },
+            ]
+        );
+        assert_eq!(timings[1].verse(), Some(2));
+        assert_eq!(timings[2].verse(), None);
+        let err = read_labels("1.0\t2.0\t1\nx\t3\t2\n").expect_err("bad time");
+        assert_eq!(err.to_string(), "line 2: bad time \"x\"");
+    }
+
+    #[test]
+    fn attach() {
+        let mut doc: Document =
+            "\\id MRK\n\\c 1\n\\p \\v 1 The beginning. \\v 2 As it is written,\n\
+             \\q1 “I will send\n\\s1 John\n\\p \\v 3 A voice\\f + \\ft Or\\f*\n\\v 4\n\
+             \\c 2\n\\p \\v 1 Again\n"
+                .parse()
+                .expect("Document");
+        let labels =
+            "0\t2.5\t1\n2.5\t4\t2\n4\t6\t2\n6\t7\ts1\n7\t9.25\t3\n9.25\t10\t4\n10\t11\t5\n";
+        let unused = doc.attach_audio(
+            1,
+            Some("MRK_001.mp3"),
+            &read_labels(labels).expect("labels"),
+        );
+        assert_eq!(
+            unused.iter().map(|t| t.label.as_str()).collect::<Vec<_>>(),
+            ["s1", "5"]
+        );
+        // Attaching again replaces the milestones.
+        doc.attach_audio(
+            1,
+            Some("MRK_001.mp3"),
+            &read_labels(labels).expect("labels"),
+        );
+        let usfm = doc.to_string();
+        assert_eq!(
+            usfm,
+            "\\id MRK\n\\c 1\n\\p\n\
+             \\v 1 \\zaudio-s |begin=\"0.000\" end=\"2.500\" file=\"MRK_001.mp3\"\\*The beginning. \
+             \\zaudio-e\\*\\v 2 \\zaudio-s |begin=\"2.500\" end=\"6.000\" file=\"MRK_001.mp3\"\\*\
+             As it is written,\n\\q1 “I will send\\zaudio-e\\*\n\\s1 John\n\\p\n\
+             \\v 3 \\zaudio-s |begin=\"7.000\" end=\"9.250\" file=\"MRK_001.mp3\"\\*A voice\
+             \\f + \\ft Or\\f* \\zaudio-e\\*\
+             \\v 4 \\zaudio-s |begin=\"9.250\" end=\"10.000\" file=\"MRK_001.mp3\"\\*\\zaudio-e\\*\n\
+             \\c 2\n\\p\n\\v 1 Again\n"
+        );
+
+        let doc: Document = usfm.parse().expect("round trip");
+        assert_eq!(doc.to_string(), usfm);
+        let audio = doc
+            .audio()
+            .into_iter()
+            .map(|a| (a.reference.to_string(), a.begin, a.end))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            audio,
+            [
+                ("MRK 1:1".to_owned(), 0.0, 2.5),
+                ("MRK 1:2".to_owned(), 2.5, 6.0),
+                ("MRK 1:3".to_owned(), 7.0, 9.25),
+                ("MRK 1:4".to_owned(), 9.25, 10.0),
+            ]
+        );
+        assert_eq!(doc.audio()[0].file.as_deref(), Some("MRK_001.mp3"));
+    }
+}
@@ -0,0 +1,191 @@
+//! The USFM book identifiers, in the canonical order used by Paratext.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Book {
+    /// Position in the canonical order, starting from 1 for Genesis.
+    pub number: u8,
+    pub code: &'static str,
+    pub name: &'static str,
+}
+
+impl Book {
+    /// The 39 books of the Hebrew Bible.
+    pub fn is_old_testament(&self) -> bool {
+        (1..=39).contains(&self.number)
+    }
+
+    pub fn is_new_testament(&self) -> bool {
+        (40..=66).contains(&self.number)
+    }
+
+    /// Deuterocanonical and other books accepted by some canons.
+    pub fn is_deuterocanon(&self) -> bool {
+        matches!(self.number, 67..=92 | 103..=106 | 112..=123)
+    }
+
+    /// Front and back matter, extra and other non-scripture books.
+    pub fn is_peripheral(&self) -> bool {
+        matches!(self.number, 93..=102 | 107..=111)
+    }
+}
+
+/// Look up a book by its three character identifier.
+pub fn get(code: &str) -> Option<&'static Book> {
+    BOOKS.iter().find(|book| book.code == code)
+}
+
+/// Look up a book by its position in the canonical order.
+pub fn by_number(number: u8) -> Option<&'static Book> {
+    BOOKS.get(usize::from(number).checked_sub(1)?)
+}
+
+pub static BOOKS: [Book; 123] = [
+    book(1, "GEN", "Genesis"),
+    book(2, "EXO", "Exodus"),
+    book(3, "LEV", "Leviticus"),
+    book(4, "NUM", "Numbers"),
+    book(5, "DEU", "Deuteronomy"),
+    book(6, "JOS", "Joshua"),
+    book(7, "JDG", "Judges"),
+    book(8, "RUT", "Ruth"),
+    book(9, "1SA", "1 Samuel"),
+    book(10, "2SA", "2 Samuel"),
+    book(11, "1KI", "1 Kings"),
+    book(12, "2KI", "2 Kings"),
+    book(13, "1CH", "1 Chronicles"),
+    book(14, "2CH", "2 Chronicles"),
+    book(15, "EZR", "Ezra"),
+    book(16, "NEH", "Nehemiah"),
+    book(17, "EST", "Esther (Hebrew)"),
+    book(18, "JOB", "Job"),
+    book(19, "PSA", "Psalms"),
+    book(20, "PRO", "Proverbs"),
+    book(21, "ECC", "Ecclesiastes"),
+    book(22, "SNG", "Song of Songs"),
+    book(23, "ISA", "Isaiah"),
+    book(24, "JER", "Jeremiah"),
+    book(25, "LAM", "Lamentations"),
+    book(26, "EZK", "Ezekiel"),
+    book(27, "DAN", "Daniel (Hebrew)"),
+    book(28, "HOS", "Hosea"),
+    book(29, "JOL", "Joel"),
+    book(30, "AMO", "Amos"),
+    book(31, "OBA", "Obadiah"),
+    book(32, "JON", "Jonah"),
+    book(33, "MIC", "Micah"),
+    book(34, "NAM", "Nahum"),
+    book(35, "HAB", "Habakkuk"),
+    book(36, "ZEP", "Zephaniah"),
+    book(37, "HAG", "Haggai"),
+    book(38, "ZEC", "Zechariah"),
+    book(39, "MAL", "Malachi"),
+    book(40, "MAT", "Matthew"),
+    book(41, "MRK", "Mark"),
+    book(42, "LUK", "Luke"),
+    book(43, "JHN", "John"),
+    book(44, "ACT", "Acts"),
+    book(45, "ROM", "Romans"),
+    book(46, "1CO", "1 Corinthians"),
+    book(47, "2CO", "2 Corinthians"),
+    book(48, "GAL", "Galatians"),
+    book(49, "EPH", "Ephesians"),
+    book(50, "PHP", "Philippians"),
+    book(51, "COL", "Colossians"),
+    book(52, "1TH", "1 Thessalonians"),
+    book(53, "2TH", "2 Thessalonians"),
+    book(54, "1TI", "1 Timothy"),
+    book(55, "2TI", "2 Timothy"),
+    book(56, "TIT", "Titus"),
+    book(57, "PHM", "Philemon"),
+    book(58, "HEB", "Hebrews"),
+    book(59, "JAS", "James"),
+    book(60, "1PE", "1 Peter"),
+    book(61, "2PE", "2 Peter"),
+    book(62, "1JN", "1 John"),
+    book(63, "2JN", "2 John"),
+    book(64, "3JN", "3 John"),
+    book(65, "JUD", "Jude"),
+    book(66, "REV", "Revelation"),
+    book(67, "TOB", "Tobit"),
+    book(68, "JDT", "Judith"),
+    book(69, "ESG", "Esther (Greek)"),
+    book(70, "WIS", "Wisdom of Solomon"),
+    book(71, "SIR", "Sirach"),
+    book(72, "BAR", "Baruch"),
+    book(73, "LJE", "Letter of Jeremiah"),
+    book(74, "S3Y", "Song of the 3 Young Men"),
+    book(75, "SUS", "Susanna"),
+    book(76, "BEL", "Bel and the Dragon"),
+    book(77, "1MA", "1 Maccabees"),
+    book(78, "2MA", "2 Maccabees"),
+    book(79, "3MA", "3 Maccabees"),
+    book(80, "4MA", "4 Maccabees"),
+    book(81, "1ES", "1 Esdras (Greek)"),
+    book(82, "2ES", "2 Esdras (Latin)"),
+    book(83, "MAN", "Prayer of Manasseh"),
+    book(84, "PS2", "Psalm 151"),
+    book(85, "ODA", "Odes"),
+    book(86, "PSS", "Psalms of Solomon"),
+    book(87, "JSA", "Joshua A (Septuagint)"),
+    book(88, "JDB", "Judges B (Septuagint)"),
+    book(89, "TBS", "Tobit S (Septuagint)"),
+    book(90, "SST", "Susanna Th (Septuagint)"),
+    book(91, "DNT", "Daniel Th (Septuagint)"),
+    book(92, "BLT", "Bel Th (Septuagint)"),
+    book(93, "XXA", "Extra A"),
+    book(94, "XXB", "Extra B"),
+    book(95, "XXC", "Extra C"),
+    book(96, "XXD", "Extra D"),
+    book(97, "XXE", "Extra E"),
+    book(98, "XXF", "Extra F"),
+    book(99, "XXG", "Extra G"),
+    book(100, "FRT", "Front Matter"),
+    book(101, "BAK", "Back Matter"),
+    book(102, "OTH", "Other Matter"),
+    book(103, "3ES", "3 Ezra"),
+    book(104, "EZA", "Apocalypse of Ezra"),
+    book(105, "5EZ", "5 Ezra"),
+    book(106, "6EZ", "6 Ezra"),
+    book(107, "INT", "Introduction"),
+    book(108, "CNC", "Concordance"),
+    book(109, "GLO", "Glossary"),
+    book(110, "TDX", "Topical Index"),
+    book(111, "NDX", "Names Index"),
+    book(112, "DAG", "Daniel (Greek)"),
+    book(113, "PS3", "Psalms 152-155"),
+    book(114, "2BA", "2 Baruch (Apocalypse)"),
+    book(115, "LBA", "Letter of Baruch"),
+    book(116, "JUB", "Jubilees"),
+    book(117, "ENO", "Enoch"),
+    book(118, "1MQ", "1 Meqabyan"),
+    book(119, "2MQ", "2 Meqabyan"),
+    book(120, "3MQ", "3 Meqabyan"),
+    book(121, "REP", "Reproof"),
+    book(122, "4BA", "4 Baruch"),
+    book(123, "LAO", "Letter to the Laodiceans"),
+];
+
+const fn book(number: u8, code: &'static str, name: &'static str) -> Book {
+    Book { number, code, name }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{by_number, get, BOOKS};
+
+    #[test]
+    fn registry() {
+        assert!(BOOKS.windows(2).all(|w| w[0].number + 1 == w[1].number));
+        assert_eq!(get("MRK").map(|b| (b.number, b.name)), Some((41, "Mark")));
+        assert_eq!(by_number(66).map(|b| b.code), Some("REV"));
+        assert_eq!(by_number(123).map(|b| b.code), Some("LAO"));
+        assert_eq!(by_number(0), None);
+        assert_eq!(get("mrk"), None);
+        let mrk = get("MRK").expect("MRK");
+        assert!(mrk.is_new_testament() && !mrk.is_old_testament());
+        assert!(get("TOB").expect("TOB").is_deuterocanon());
+        assert!(get("FRT").expect("FRT").is_peripheral());
+        assert!(get("XXA").expect("XXA") < get("FRT").expect("FRT"));
+    }
+}
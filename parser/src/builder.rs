@@ -0,0 +1,445 @@
+//! Building documents in code rather than parsing them.
+
+use std::io;
+
+use crate::{
+    books,
+    diagnostic::{Code, Diagnostic},
+    document::{Content, Document, Node, Span, State},
+    extension::{Category, Extensions},
+};
+
+/// Assembles a document one marker at a time, checking each against the
+/// categories in a marker set. The first mistake is kept and reported by
+/// [`DocumentBuilder::build`], so calls can be chained freely.
+///
+/// ```
+/// # use parser::builder::DocumentBuilder;
+/// let doc = DocumentBuilder::new()
+///     .book("MAT")
+///     .chapter(1)
+///     .para("p")
+///     .verse(1)
+///     .text("The book of the genealogy of ")
+///     .char("nd", "Jesus Christ")
+///     .build()
+///     .unwrap();
+/// assert_eq!(
+///     doc.to_string(),
+///     "\\id MAT\n\\c 1\n\\p\n\\v 1 The book of the genealogy of \\nd Jesus Christ\\nd*\n"
+/// );
+/// ```
+#[derive(Debug)]
+pub struct DocumentBuilder<'m> {
+    markers: &'m Extensions,
+    content: Vec<Content<'static>>,
+    chapter: Option<Node<'static>>,
+    para: Option<Node<'static>>,
+    /// Open character spans and notes, innermost last.
+    inline: Vec<Content<'static>>,
+    error: Option<io::Error>,
+}
+
+impl Default for DocumentBuilder<'static> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DocumentBuilder<'static> {
+    /// Check markers against the bundled USFM 3 marker set.
+    pub fn new() -> Self {
+        Self::with_markers(State::usfm_ext())
+    }
+}
+
+impl<'m> DocumentBuilder<'m> {
+    pub fn with_markers(markers: &'m Extensions) -> Self {
+        DocumentBuilder {
+            markers,
+            content: Vec::new(),
+            chapter: None,
+            para: None,
+            inline: Vec::new(),
+            error: None,
+        }
+    }
+
+    pub fn book(mut self, code: &str) -> Self {
+        if !self.content.is_empty() || self.chapter.is_some() || self.para.is_some() {
+            return self.fail(Code::UnexpectedMarker, "\\id must come first");
+        }
+        if books::get(code).is_none() {
+            return self.fail(Code::UnknownBook, format!("unknown book code {code}"));
+        }
+        self.content.push(Content::Book(Node {
+            style: "id".into(),
+            attributes: [("code".into(), code.to_owned().into())].into(),
+            ..Node::default()
+        }));
+        self
+    }
+
+    pub fn chapter(mut self, number: u32) -> Self {
+        if self.content.is_empty() {
+            return self.fail(Code::UnexpectedMarker, "\\c before \\id");
+        }
+        self.close_chapter();
+        self.chapter = Some(Node {
+            style: "c".into(),
+            attributes: [("number".into(), number.to_string().into())].into(),
+            ..Node::default()
+        });
+        self
+    }
+
+    /// Start a paragraph of any paragraph category, closing the current one.
+    pub fn para(mut self, style: &str) -> Self {
+        match self.category(style) {
+            Some(
+                Category::VersePara
+                | Category::OtherPara
+                | Category::SectionPara
+                | Category::Title
+                | Category::Header
+                | Category::Introduction
+                | Category::List,
+            ) => {}
+            _ => {
+                return self.fail(
+                    Code::UnexpectedMarker,
+                    format!("\\{style} is not a paragraph"),
+                )
+            }
+        }
+        if self.content.is_empty() {
+            return self.fail(Code::UnexpectedMarker, format!("\\{style} before \\id"));
+        }
+        self.close_para();
+        let mut node = Node {
+            style: style.to_owned().into(),
+            ..Node::default()
+        };
+        if let Some(level) = self.level(style) {
+            node.attributes.insert("level".into(), level.into());
+        }
+        self.para = Some(node);
+        self
+    }
+
+    pub fn verse(mut self, number: u32) -> Self {
+        if self.chapter.is_none() || self.para.is_none() || !self.inline.is_empty() {
+            return self.fail(
+                Code::UnexpectedMarker,
+                "\\v must be in a paragraph within a chapter",
+            );
+        }
+        let para = self.para.as_mut().expect("open paragraph");
+        if let Some(Content::Text(text)) = para.content.last_mut() {
+            if !text.text.ends_with(char::is_whitespace) {
+                text.text.to_mut().push(' ');
+            }
+        }
+        para.content.push(Content::Verse(Node {
+            style: "v".into(),
+            attributes: [("number".into(), number.to_string().into())].into(),
+            ..Node::default()
+        }));
+        self
+    }
+
+    pub fn text(mut self, text: &str) -> Self {
+        if text.is_empty() {
+            return self;
+        }
+        let Some(content) = self.open_content() else {
+            return self.fail(Code::UnexpectedMarker, "text outside a paragraph");
+        };
+        match content.last_mut() {
+            Some(Content::Text(last)) => last.text.to_mut().push_str(text),
+            _ => content.push(text.to_owned().into()),
+        }
+        self
+    }
+
+    /// Set an attribute on the innermost open character span, note or
+    /// paragraph.
+    pub fn attribute(mut self, name: &str, value: &str) -> Self {
+        let node = match self.inline.last_mut() {
+            Some(item) => item.node_mut(),
+            None => self.para.as_mut(),
+        };
+        match node {
+            Some(node) => {
+                node.attributes
+                    .insert(name.to_owned().into(), value.to_owned().into());
+                self
+            }
+            None => self.fail(Code::InvalidAttribute, format!("nowhere to put {name}")),
+        }
+    }
+
+    pub fn start_char(mut self, style: &str) -> Self {
+        let in_note = self
+            .inline
+            .iter()
+            .any(|item| matches!(item, Content::Note(_)));
+        let allowed = match self.category(style) {
+            Some(Category::FootnoteChar | Category::CrossreferenceChar) => in_note,
+            Some(Category::Char | Category::IntroChar | Category::ListChar) => true,
+            _ => false,
+        };
+        if !allowed || self.para.is_none() {
+            return self.fail(
+                Code::UnexpectedMarker,
+                format!("\\{style} cannot start here"),
+            );
+        }
+        let nested = matches!(self.inline.last(), Some(Content::Char(_)));
+        self.inline.push(Content::Char(Node {
+            style: style.to_owned().into(),
+            nested,
+            ..Node::default()
+        }));
+        self
+    }
+
+    pub fn start_note(mut self, style: &str, caller: &str) -> Self {
+        if !matches!(
+            self.category(style),
+            Some(Category::Footnote | Category::Crossreference)
+        ) || self.para.is_none()
+        {
+            return self.fail(Code::UnexpectedMarker, format!("\\{style} is not a note"));
+        }
+        self.inline.push(Content::Note(Node {
+            style: style.to_owned().into(),
+            attributes: [("caller".into(), caller.to_owned().into())].into(),
+            ..Node::default()
+        }));
+        self
+    }
+
+    /// Close the innermost open character span or note.
+    pub fn end(mut self) -> Self {
+        match self.inline.pop() {
+            Some(item) => {
+                self.open_content().expect("open paragraph").push(item);
+                self
+            }
+            None => self.fail(Code::UnmatchedEndmarker, "nothing to end"),
+        }
+    }
+
+    /// A whole character span holding just text.
+    pub fn char(self, style: &str, text: &str) -> Self {
+        self.start_char(style).text(text).end()
+    }
+
+    pub fn build(mut self) -> io::Result<Document<'static>> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        if !self.inline.is_empty() {
+            return Err(error(
+                Code::MissingEndmarker,
+                "unclosed character span or note",
+            ));
+        }
+        if self.content.is_empty() {
+            return Err(error(Code::Syntax, "no \\id"));
+        }
+        self.close_chapter();
+        let mut doc = Document::default();
+        doc.nodes = Some(Node {
+            style: "usfm".into(),
+            attributes: [("version".into(), "3".into())].into(),
+            content: self.content,
+            ..Node::default()
+        });
+        Ok(doc)
+    }
+
+    fn category(&self, style: &str) -> Option<Category> {
+        self.markers.category(style)
+    }
+
+    // Poetic lines and headings carry their level as the parser records it.
+    fn level(&self, style: &str) -> Option<String> {
+        let base = style.trim_end_matches(|c: char| c.is_ascii_digit());
+        let poetic = base.starts_with('q') && self.category(style) == Some(Category::VersePara);
+        if !poetic && self.category(style) != Some(Category::SectionPara) {
+            return None;
+        }
+        match &style[base.len()..] {
+            "" if self.markers.contains(&format!("{base}1")) => Some("1".into()),
+            "" if poetic => Some("1".into()),
+            "" => None,
+            level => Some(level.into()),
+        }
+    }
+
+    fn open_content(&mut self) -> Option<&mut Vec<Content<'static>>> {
+        match self.inline.last_mut() {
+            Some(item) => item.node_mut().map(|node| &mut node.content),
+            None => self.para.as_mut().map(|para| &mut para.content),
+        }
+    }
+
+    fn blocks(&mut self) -> &mut Vec<Content<'static>> {
+        match &mut self.chapter {
+            Some(chapter) => &mut chapter.content,
+            None => &mut self.content,
+        }
+    }
+
+    // Poetic lines are grouped into stanzas and list items into lists, as
+    // the parser does.
+    fn close_para(&mut self) {
+        let Some(para) = self.para.take() else {
+            return;
+        };
+        let category = self.category(&para.style);
+        let blocks = self.blocks();
+        let wrapper = match category {
+            Some(Category::List) => Some("list"),
+            Some(Category::VersePara) if para.style.starts_with('q') => Some("stanza"),
+            _ => None,
+        };
+        let Some(wrapper) = wrapper else {
+            blocks.push(Content::Para(para));
+            return;
+        };
+        match blocks.last_mut() {
+            Some(Content::List(list)) if wrapper == "list" => {
+                list.content.push(Content::Para(para))
+            }
+            Some(Content::Stanza(stanza)) if wrapper == "stanza" => {
+                stanza.content.push(Content::Para(para))
+            }
+            _ => {
+                let node = Node {
+                    style: wrapper.into(),
+                    content: vec![Content::Para(para)],
+                    ..Node::default()
+                };
+                blocks.push(match wrapper {
+                    "list" => Content::List(node),
+                    _ => Content::Stanza(node),
+                });
+            }
+        }
+    }
+
+    fn close_chapter(&mut self) {
+        self.close_para();
+        if let Some(chapter) = self.chapter.take() {
+            self.content.push(Content::Chapter(chapter));
+        }
+    }
+
+    fn fail(mut self, code: Code, message: impl Into<String>) -> Self {
+        if self.error.is_none() {
+            self.error = Some(error(code, message));
+        }
+        self
+    }
+}
+
+fn error(code: Code, message: impl Into<String>) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        Diagnostic::error(code, Span::default(), message),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::DocumentBuilder;
+    use crate::document::Document;
+
+    #[test]
+    fn build() {
+        let built = DocumentBuilder::new()
+            .book("PSA")
+            .para("mt1")
+            .text("Psalms")
+            .chapter(23)
+            .para("s1")
+            .text("The Shepherd")
+            .para("q1")
+            .verse(1)
+            .text("The ")
+            .char("nd", "Lord")
+            .text(" is my shepherd;")
+            .start_note("f", "+")
+            .char("fr", "23:1 ")
+            .char("ft", "Or ")
+            .start_char("fq")
+            .text("shepherds")
+            .end()
+            .end()
+            .para("q2")
+            .text("I shall not want.")
+            .verse(2)
+            .start_char("w")
+            .text("He")
+            .attribute("lemma", "he")
+            .end()
+            .para("b")
+            .para("li1")
+            .text("item")
+            .build()
+            .expect("Document");
+        let parsed: Document = "\\id PSA\n\
+                                \\mt1 Psalms\n\
+                                \\c 23\n\
+                                \\s1 The Shepherd\n\
+                                \\q1 \\v 1 The \\nd Lord\\nd* is my shepherd;\\f + \\fr 23:1 \\ft Or \\fq shepherds\\f*\n\
+                                \\q2 I shall not want. \\v 2 \\w He|lemma=\"he\"\\w*\n\
+                                \\b\n\
+                                \\li1 item\n"
+            .parse()
+            .expect("parsed");
+        assert_eq!(built.nodes, parsed.nodes);
+    }
+
+    #[test]
+    fn reject() {
+        let error = |builder: DocumentBuilder| builder.build().unwrap_err().to_string();
+        assert_eq!(
+            error(DocumentBuilder::new().book("XYZ")),
+            "0:0: error[unknown-book]: unknown book code XYZ"
+        );
+        assert_eq!(
+            error(DocumentBuilder::new().book("MRK").chapter(1).verse(1)),
+            "0:0: error[unexpected-marker]: \\v must be in a paragraph within a chapter"
+        );
+        assert_eq!(
+            error(DocumentBuilder::new().book("MRK").para("nd")),
+            "0:0: error[unexpected-marker]: \\nd is not a paragraph"
+        );
+        assert_eq!(
+            error(
+                DocumentBuilder::new()
+                    .book("MRK")
+                    .para("p")
+                    .start_char("ft")
+            ),
+            "0:0: error[unexpected-marker]: \\ft cannot start here"
+        );
+        assert_eq!(
+            error(
+                DocumentBuilder::new()
+                    .book("MRK")
+                    .para("p")
+                    .start_char("nd")
+            ),
+            "0:0: error[missing-endmarker]: unclosed character span or note"
+        );
+        assert_eq!(
+            error(DocumentBuilder::new().book("MRK").para("p").end()),
+            "0:0: error[unmatched-endmarker]: nothing to end"
+        );
+    }
+}
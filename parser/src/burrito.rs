@@ -0,0 +1,339 @@
+//! Scripture Burrito metadata, the `metadata.json` describing a set of
+//! USFM files: what the text is, its language and copyright, and a
+//! checksum for each file it holds.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, Read, Write},
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::{
+    books::{self, Book},
+    document::Document,
+    project::Project,
+    usx::invalid,
+};
+
+/// The parts of `metadata.json` the loader uses. Everything else is kept
+/// as read and written back unchanged.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Metadata {
+    #[serde(default)]
+    pub identification: Identification,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub languages: Vec<Language>,
+    #[serde(default)]
+    pub copyright: Copyright,
+    /// Names of the books by `book-` and their lower case code, such as
+    /// `book-mrk`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub localized_names: BTreeMap<String, LocalizedNames>,
+    /// The files of the burrito by their path relative to it.
+    #[serde(default)]
+    pub ingredients: BTreeMap<String, Ingredient>,
+    #[serde(flatten)]
+    pub other: Map<String, Value>,
+}
+
+/// Strings by BCP 47 language tag.
+pub type Localized = BTreeMap<String, String>;
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Identification {
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub name: Localized,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub abbreviation: Localized,
+    #[serde(flatten)]
+    pub other: Map<String, Value>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Language {
+    /// The BCP 47 tag, such as `en` or `tpi`.
+    pub tag: String,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub name: Localized,
+    #[serde(flatten)]
+    pub other: Map<String, Value>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Copyright {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub short_statements: Vec<Statement>,
+    #[serde(flatten)]
+    pub other: Map<String, Value>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Statement {
+    /// The statement, as HTML when `mimetype` is `text/html`.
+    pub statement: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mimetype: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lang: Option<String>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LocalizedNames {
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub short: Localized,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub abbr: Localized,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub long: Localized,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Ingredient {
+    pub checksum: Checksum,
+    pub mime_type: String,
+    pub size: u64,
+    /// The books in the file, by code, each with the chapters or verses it
+    /// holds, or none for the whole book.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<BTreeMap<String, Vec<String>>>,
+    #[serde(flatten)]
+    pub other: Map<String, Value>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checksum {
+    pub md5: String,
+}
+
+pub const USFM_MIME_TYPE: &str = "text/x-usfm";
+
+impl Metadata {
+    pub fn from_reader<R: Read>(reader: R) -> io::Result<Self> {
+        serde_json::from_reader(reader).map_err(invalid)
+    }
+
+    pub fn write_to<W: Write>(&self, mut w: W) -> io::Result<()> {
+        serde_json::to_writer_pretty(&mut w, self).map_err(invalid)?;
+        writeln!(w)
+    }
+
+    /// The USFM ingredients holding a single book, with the book, in
+    /// canonical order.
+    pub fn books(&self) -> Vec<(&'static Book, &str)> {
+        let mut res = self
+            .ingredients
+            .iter()
+            .filter(|(_, ingredient)| ingredient.mime_type == USFM_MIME_TYPE)
+            .filter_map(|(path, ingredient)| {
+                let scope = ingredient.scope.as_ref()?;
+                let [code] = scope.keys().collect::<Vec<_>>()[..] else {
+                    return None;
+                };
+                Some((books::get(code)?, path.as_str()))
+            })
+            .collect::<Vec<_>>();
+        res.sort_by_key(|(book, _)| book.number);
+        res
+    }
+
+    /// Record the new contents of an ingredient: its size and checksum.
+    /// An ingredient not listed yet is added as a USFM file of `book`.
+    pub fn update_ingredient(&mut self, path: &str, contents: &[u8], book: &Book) {
+        let ingredient = self
+            .ingredients
+            .entry(path.to_owned())
+            .or_insert_with(|| Ingredient {
+                mime_type: USFM_MIME_TYPE.into(),
+                scope: Some([(book.code.to_owned(), Vec::new())].into()),
+                ..Ingredient::default()
+            });
+        ingredient.checksum.md5 = format!("{:x}", md5::compute(contents));
+        ingredient.size = contents.len() as u64;
+    }
+
+    /// The tag of the first language.
+    pub fn language(&self) -> Option<&str> {
+        self.languages.first().map(|language| language.tag.as_str())
+    }
+
+    /// The short copyright statements, one to a line, without any HTML
+    /// markup.
+    pub fn copyright(&self) -> Option<String> {
+        let statements = self
+            .copyright
+            .short_statements
+            .iter()
+            .map(|statement| match statement.mimetype.as_deref() {
+                Some("text/html") => strip_tags(&statement.statement),
+                _ => statement.statement.trim().to_owned(),
+            })
+            .filter(|statement| !statement.is_empty())
+            .collect::<Vec<_>>();
+        (!statements.is_empty()).then(|| statements.join("\n"))
+    }
+}
+
+fn strip_tags(html: &str) -> String {
+    let mut res = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            c if !in_tag => res.push(c),
+            _ => {}
+        }
+    }
+    crate::xml::unescape(res.trim()).into_owned()
+}
+
+impl Project {
+    /// Load the USFM books listed in a burrito's `metadata.json`, adding
+    /// the names in its `localizedNames` to those understood by
+    /// [`Project::names`].
+    pub fn load_burrito(path: impl AsRef<Path>) -> io::Result<Self> {
+        let dir = path.as_ref();
+        let metadata = Metadata::from_reader(fs::File::open(dir.join("metadata.json"))?)?;
+        let files = metadata
+            .books()
+            .into_iter()
+            .map(|(_, path)| dir.join(path))
+            .collect();
+        let mut project = Self::load_default(files)?;
+        for (id, names) in &metadata.localized_names {
+            let code = id.strip_prefix("book-").unwrap_or(id).to_ascii_uppercase();
+            let Some(book) = books::get(&code) else {
+                continue;
+            };
+            for name in [&names.short, &names.abbr, &names.long]
+                .into_iter()
+                .flat_map(|forms| forms.values())
+            {
+                project.names.insert(name, book);
+            }
+        }
+        project.burrito = Some(metadata);
+        Ok(project)
+    }
+
+    /// Write the books that differ from their files in the burrito at
+    /// `path`, and its `metadata.json` with their new checksums. A book
+    /// not in the burrito yet is added as `ingredients/CODE.usfm`. Books
+    /// are written as [`Document::to_string`] does, so only changed ones
+    /// lose their original layout.
+    pub fn save_burrito(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
+        let dir = path.as_ref();
+        let mut metadata = self.burrito.take().unwrap_or_default();
+        let paths = metadata
+            .books()
+            .into_iter()
+            .map(|(book, path)| (book.code, path.to_owned()))
+            .collect::<BTreeMap<_, _>>();
+        let res = self.iter().try_for_each(|(book, doc)| {
+            let path = paths
+                .get(book.code)
+                .cloned()
+                .unwrap_or_else(|| format!("ingredients/{}.usfm", book.code));
+            let file = dir.join(&path);
+            let usfm = doc.to_string();
+            let unchanged = fs::read_to_string(&file).is_ok_and(|old| {
+                old == usfm || Document::from_str_lenient(&old).0.to_string() == usfm
+            });
+            if unchanged {
+                return Ok(());
+            }
+            if let Some(parent) = file.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&file, &usfm)?;
+            metadata.update_ingredient(&path, usfm.as_bytes(), book);
+            Ok(())
+        });
+        let res = res.and_then(|()| {
+            let mut file = io::BufWriter::new(fs::File::create(dir.join("metadata.json"))?);
+            metadata.write_to(&mut file)?;
+            file.flush()
+        });
+        self.burrito = Some(metadata);
+        res
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{env, fs};
+
+    use super::Metadata;
+    use crate::project::Project;
+
+    const METADATA: &str = r#"{
+  "format": "scripture burrito",
+  "meta": { "version": "1.0.0", "category": "source", "defaultLocale": "en" },
+  "identification": { "name": { "en": "World English Bible" }, "abbreviation": { "en": "WEB" } },
+  "languages": [{ "tag": "en", "name": { "en": "English" } }],
+  "copyright": {
+    "shortStatements": [
+      { "statement": "<p>Public domain &amp; free</p>", "mimetype": "text/html", "lang": "en" }
+    ]
+  },
+  "localizedNames": {
+    "book-mrk": { "short": { "en": "Mark" }, "abbr": { "en": "Mk" }, "long": { "en": "Gospel of Mark" } }
+  },
+  "ingredients": {
+    "ingredients/MRK.usfm": {
+      "checksum": { "md5": "0" }, "mimeType": "text/x-usfm", "size": 0, "scope": { "MRK": [] }
+    },
+    "ingredients/LUK.usfm": {
+      "checksum": { "md5": "0" }, "mimeType": "text/x-usfm", "size": 0, "scope": { "LUK": [] }
+    },
+    "ingredients/styles.sty": { "checksum": { "md5": "0" }, "mimeType": "text/x-sty", "size": 0 }
+  }
+}"#;
+
+    #[test]
+    fn burrito() {
+        let dir = env::temp_dir().join(format!("usfm-burrito-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("ingredients")).expect("temp dir");
+        fs::write(dir.join("metadata.json"), METADATA).expect("write");
+        let mrk = "\\id MRK\n\\c 1\n\\p  \\v 1 The beginning\n";
+        let luk = "\\id LUK\n\\c 1\n\\p \\v 1 Many\n";
+        fs::write(dir.join("ingredients/MRK.usfm"), mrk).expect("write");
+        fs::write(dir.join("ingredients/LUK.usfm"), luk).expect("write");
+
+        let mut project = Project::load_burrito(&dir).expect("Project");
+        assert_eq!(project.len(), 2);
+        assert_eq!(project.language(), Some("en"));
+        assert_eq!(project.copyright().as_deref(), Some("Public domain & free"));
+        assert_eq!(project.names().get("Mk").map(|book| book.code), Some("MRK"));
+
+        *project.get_mut("LUK").expect("LUK") = "\\id LUK\n\\c 1\n\\p \\v 1 Many have\n"
+            .parse()
+            .expect("Document");
+        project.save_burrito(&dir).expect("save");
+
+        let read = |path: &str| fs::read_to_string(dir.join(path)).expect("read");
+        assert_eq!(read("ingredients/MRK.usfm"), mrk);
+        let luk = read("ingredients/LUK.usfm");
+        assert_eq!(luk, "\\id LUK\n\\c 1\n\\p\n\\v 1 Many have\n");
+        let metadata = Metadata::from_reader(read("metadata.json").as_bytes()).expect("json");
+        fs::remove_dir_all(&dir).expect("cleanup");
+
+        let ingredient = |path: &str| &metadata.ingredients[path];
+        assert_eq!(ingredient("ingredients/MRK.usfm").checksum.md5, "0");
+        assert_eq!(
+            ingredient("ingredients/LUK.usfm").checksum.md5,
+            format!("{:x}", md5::compute(&luk))
+        );
+        assert_eq!(ingredient("ingredients/LUK.usfm").size, luk.len() as u64);
+        assert_eq!(metadata.other["format"], "scripture burrito");
+        assert!(metadata.ingredients.contains_key("ingredients/styles.sty"));
+    }
+}
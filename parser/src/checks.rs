@@ -0,0 +1,587 @@
+//! Checks of the text itself rather than its markup: quotation mark nesting,
+//! repeated words, unmatched brackets and capitalization.
+//!
+//! Each [`Check`] looks at the document as a series of [`Passage`]s, the
+//! text of one paragraph or note with the markers taken out, and reports
+//! what it finds as [`Diagnostic`]s. Spans point into the original source
+//! for documents parsed from USFM and are empty otherwise.
+
+use std::ops::Range;
+
+use crate::{
+    diagnostic::{Code, Diagnostic},
+    document::{Content, Document, Node, Position, Span, State},
+    extension::{Category, Extensions},
+};
+
+pub trait Check {
+    fn check(&self, passages: &[Passage], diagnostics: &mut Vec<Diagnostic>);
+}
+
+/// The checks of this module with their default settings.
+pub fn standard() -> Vec<Box<dyn Check>> {
+    vec![
+        Box::new(QuotationMarks::default()),
+        Box::new(RepeatedWords),
+        Box::new(Brackets::default()),
+        Box::new(Capitalization),
+    ]
+}
+
+/// The text of a paragraph or note. Notes are passages of their own and
+/// are left out of the paragraph they are anchored in.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Passage {
+    /// The marker of the paragraph, cell or note the text is from.
+    pub style: String,
+    pub text: String,
+    // The byte offset in `text` and source span of each character.
+    positions: Vec<(usize, Span)>,
+}
+
+impl Passage {
+    /// The source span of a byte range of the text.
+    pub fn span(&self, range: Range<usize>) -> Span {
+        Span {
+            start: self.start(range.start),
+            end: self.start(range.end),
+        }
+    }
+
+    /// The source span of the character at a byte offset of the text.
+    pub fn char_span(&self, offset: usize) -> Span {
+        match self.positions.binary_search_by_key(&offset, |(o, _)| *o) {
+            Ok(n) => self.positions[n].1,
+            Err(n) => self
+                .positions
+                .get(n)
+                .map(|(_, span)| *span)
+                .unwrap_or_default(),
+        }
+    }
+
+    // Where the character at a byte offset starts in the source, or where
+    // the last one ends for the end of the text.
+    fn start(&self, offset: usize) -> Position {
+        match self.positions.binary_search_by_key(&offset, |(o, _)| *o) {
+            Ok(n) => self.positions[n].1.start,
+            Err(n) => self.positions.get(n).map_or_else(
+                || {
+                    self.positions
+                        .last()
+                        .map(|(_, span)| span.end)
+                        .unwrap_or_default()
+                },
+                |(_, span)| span.start,
+            ),
+        }
+    }
+
+    fn push(&mut self, text: &str, start: Option<Position>, source: &str) {
+        let mut position = start.unwrap_or_default();
+        for (n, c) in text.char_indices() {
+            let begin = position;
+            if start.is_some() {
+                // The tree has `\n` for a `\r\n` in the source.
+                let crlf = source
+                    .get(position.offset..)
+                    .is_some_and(|rest| rest.starts_with("\r\n"));
+                position.offset += if c == '\n' && crlf { 2 } else { c.len_utf8() };
+                if c == '\n' {
+                    position.line += 1;
+                    position.column = 1;
+                } else {
+                    position.column += 1;
+                }
+            }
+            self.positions.push((
+                self.text.len() + n,
+                Span {
+                    start: begin,
+                    end: position,
+                },
+            ));
+        }
+        self.text.push_str(text);
+    }
+
+    /// The words of the text with their byte ranges. Apostrophes and
+    /// hyphens between letters belong to the word.
+    fn words(&self) -> Vec<(Range<usize>, &str)> {
+        let mut words = Vec::new();
+        let mut start = None;
+        let mut chars = self.text.char_indices().peekable();
+        while let Some((n, c)) = chars.next() {
+            let next = chars.peek().map(|(_, c)| *c);
+            let joiner = matches!(c, '\'' | '’' | '-' | '\u{2011}')
+                && start.is_some()
+                && next.is_some_and(char::is_alphanumeric);
+            if c.is_alphanumeric() || joiner {
+                start.get_or_insert(n);
+            } else if let Some(s) = start.take() {
+                words.push((s..n, &self.text[s..n]));
+            }
+        }
+        if let Some(s) = start {
+            words.push((s..self.text.len(), &self.text[s..]));
+        }
+        words
+    }
+}
+
+impl Document<'_> {
+    /// Run `checks` over the text, returning what they find in source order.
+    pub fn check(&self, checks: &[Box<dyn Check>]) -> Vec<Diagnostic> {
+        let passages = self.passages();
+        let mut diagnostics = Vec::new();
+        for check in checks {
+            check.check(&passages, &mut diagnostics);
+        }
+        diagnostics.sort_by_key(|d| d.span.start);
+        diagnostics
+    }
+
+    /// The text of every paragraph and note, headings included, in
+    /// document order with each note following its paragraph. Header
+    /// material such as `\toc1` and `\rem` is left out.
+    pub fn passages(&self) -> Vec<Passage> {
+        let mut collector = Collector {
+            markers: State::usfm_ext(),
+            source: self.source(),
+            passages: Vec::new(),
+        };
+        if let Some(root) = &self.nodes {
+            collector.blocks(&root.content);
+        }
+        collector.passages.retain(|p| !p.text.trim().is_empty());
+        collector.passages
+    }
+}
+
+struct Collector<'s> {
+    markers: &'static Extensions,
+    // The source the text spans point into.
+    source: &'s str,
+    passages: Vec<Passage>,
+}
+
+impl Collector<'_> {
+    fn is_header(&self, node: &Node) -> bool {
+        node.style == "rem" || self.markers.category(node.style.as_ref()) == Some(Category::Header)
+    }
+
+    fn blocks(&mut self, content: &[Content]) {
+        for item in content {
+            match item {
+                Content::Para(node) if self.is_header(node) => {}
+                Content::Para(node) | Content::Unknown(node) | Content::Cell(node) => {
+                    let mut passage = Passage {
+                        style: node.style.to_string(),
+                        ..Passage::default()
+                    };
+                    let mut notes = Vec::new();
+                    inline(&node.content, self.source, &mut passage, &mut notes);
+                    self.passages.push(passage);
+                    self.passages.extend(notes);
+                }
+                Content::Note(node) => {
+                    let mut notes = Vec::new();
+                    note(node, self.source, &mut notes);
+                    self.passages.extend(notes);
+                }
+                Content::Book(_) | Content::Figure(_) => {}
+                item => {
+                    if let Some(node) = item.node() {
+                        self.blocks(&node.content);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn note(node: &Node, source: &str, notes: &mut Vec<Passage>) {
+    let mut passage = Passage {
+        style: node.style.to_string(),
+        ..Passage::default()
+    };
+    let mut nested = Vec::new();
+    inline(&node.content, source, &mut passage, &mut nested);
+    notes.push(passage);
+    notes.extend(nested);
+}
+
+fn inline(content: &[Content], source: &str, passage: &mut Passage, notes: &mut Vec<Passage>) {
+    for item in content {
+        match item {
+            Content::Text(text) => passage.push(text.as_str(), text.span.map(|s| s.start), source),
+            Content::NoBreakSpace => passage.push("\u{a0}", None, source),
+            Content::Note(node) => note(node, source, notes),
+            Content::Figure(_) => {}
+            item => {
+                if let Some(node) = item.node() {
+                    inline(&node.content, source, passage, notes);
+                }
+            }
+        }
+    }
+}
+
+/// Checks that quotation marks open and close in turn, with each level of
+/// nesting using its own pair. A quotation running on into the next
+/// paragraph reopens there with the marks of every open level, as is usual
+/// in English. A closing mark between two letters is taken as an
+/// apostrophe.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuotationMarks {
+    /// The opening and closing marks, outermost first. Nesting deeper than
+    /// the list cycles back to the start.
+    pub levels: Vec<(char, char)>,
+}
+
+impl Default for QuotationMarks {
+    fn default() -> Self {
+        QuotationMarks::new([('“', '”'), ('‘', '’')])
+    }
+}
+
+impl QuotationMarks {
+    pub fn new(levels: impl IntoIterator<Item = (char, char)>) -> Self {
+        QuotationMarks {
+            levels: levels.into_iter().collect(),
+        }
+    }
+
+    fn level(&self, depth: usize) -> (char, char) {
+        self.levels[depth % self.levels.len()]
+    }
+
+    fn is_mark(&self, c: char) -> bool {
+        self.levels
+            .iter()
+            .any(|&(open, close)| c == open || c == close)
+    }
+}
+
+impl Check for QuotationMarks {
+    fn check(&self, passages: &[Passage], diagnostics: &mut Vec<Diagnostic>) {
+        if self.levels.is_empty() {
+            return;
+        }
+        // The open quotations as the span of their opening mark.
+        let mut open: Vec<Span> = Vec::new();
+        for passage in passages {
+            let mut chars = passage.text.char_indices().peekable();
+            // Continuation marks reopening the levels left open.
+            let mut reopened = 0;
+            while reopened < open.len() {
+                match chars.next_if(|&(_, c)| c.is_whitespace() || c == self.level(reopened).0) {
+                    Some((_, c)) if !c.is_whitespace() => reopened += 1,
+                    Some(_) => {}
+                    None => break,
+                }
+            }
+            for span in open.drain(reopened..).rev() {
+                diagnostics.push(Diagnostic::warning(
+                    Code::UnmatchedQuote,
+                    span,
+                    "quotation is not closed",
+                ));
+            }
+
+            let mut previous = None;
+            while let Some((n, c)) = chars.next() {
+                let after_letter = previous.is_some_and(char::is_alphanumeric);
+                let before_letter = chars.peek().is_some_and(|(_, c)| c.is_alphanumeric());
+                previous = Some(c);
+                if !self.is_mark(c) {
+                    continue;
+                }
+                let span = passage.span(n..n + c.len_utf8());
+                let mut closes = (0..open.len()).rev().map(|depth| self.level(depth).1);
+                let expected = closes.next();
+                if expected == Some(c) && !(after_letter && before_letter) {
+                    open.pop();
+                } else if c == self.level(open.len()).0 {
+                    open.push(span);
+                } else if self.levels.iter().any(|&(_, close)| close == c)
+                    && after_letter
+                    && (before_letter || !closes.any(|close| close == c))
+                {
+                    // An apostrophe, or a possessive such as `disciples’`.
+                } else {
+                    let message = match expected {
+                        Some(close) => format!("{c} where {close} was expected"),
+                        None => format!("{c} does not close an open quotation"),
+                    };
+                    diagnostics.push(Diagnostic::warning(Code::UnmatchedQuote, span, message));
+                }
+            }
+        }
+        for span in open {
+            diagnostics.push(Diagnostic::warning(
+                Code::UnmatchedQuote,
+                span,
+                "quotation is not closed",
+            ));
+        }
+    }
+}
+
+/// Reports a word repeated straight after itself, such as `the the`,
+/// ignoring case.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RepeatedWords;
+
+impl Check for RepeatedWords {
+    fn check(&self, passages: &[Passage], diagnostics: &mut Vec<Diagnostic>) {
+        for passage in passages {
+            let words = passage.words();
+            for pair in words.windows(2) {
+                let [(_, first), (range, second)] = pair else {
+                    continue;
+                };
+                let between = &passage.text[pair[0].0.end..range.start];
+                if between.chars().all(char::is_whitespace)
+                    && first.to_lowercase() == second.to_lowercase()
+                    && !first.chars().all(|c| c.is_numeric())
+                {
+                    diagnostics.push(Diagnostic::warning(
+                        Code::RepeatedWord,
+                        passage.span(range.clone()),
+                        format!("repeated word {second}"),
+                    ));
+                }
+            }
+        }
+    }
+}
+
+/// Checks that brackets are closed within the paragraph or note they are
+/// opened in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Brackets {
+    pub pairs: Vec<(char, char)>,
+}
+
+impl Default for Brackets {
+    fn default() -> Self {
+        Brackets {
+            pairs: vec![('(', ')'), ('[', ']'), ('{', '}')],
+        }
+    }
+}
+
+impl Check for Brackets {
+    fn check(&self, passages: &[Passage], diagnostics: &mut Vec<Diagnostic>) {
+        for passage in passages {
+            let mut open: Vec<(char, Span)> = Vec::new();
+            for (n, c) in passage.text.char_indices() {
+                let span = passage.span(n..n + c.len_utf8());
+                if let Some(&(_, close)) = self.pairs.iter().find(|(open, _)| *open == c) {
+                    open.push((close, span));
+                } else if self.pairs.iter().any(|(_, close)| *close == c) {
+                    match open.iter().rposition(|(close, _)| *close == c) {
+                        Some(n) => {
+                            for (_, span) in open.drain(n..).skip(1) {
+                                diagnostics.push(unclosed(span));
+                            }
+                        }
+                        None => diagnostics.push(Diagnostic::warning(
+                            Code::UnmatchedBracket,
+                            span,
+                            format!("{c} has no opening bracket"),
+                        )),
+                    }
+                }
+            }
+            diagnostics.extend(open.into_iter().map(|(_, span)| unclosed(span)));
+        }
+    }
+}
+
+fn unclosed(span: Span) -> Diagnostic {
+    Diagnostic::warning(Code::UnmatchedBracket, span, "bracket is not closed")
+}
+
+/// Reports a lower case letter starting a sentence, after a full stop,
+/// question or exclamation mark followed by a space. Sentence ends inside a
+/// quotation, as in `“Stop!” he cried`, are not checked.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Capitalization;
+
+impl Check for Capitalization {
+    fn check(&self, passages: &[Passage], diagnostics: &mut Vec<Diagnostic>) {
+        for passage in passages {
+            let mut end = false;
+            let mut space = false;
+            for (n, c) in passage.text.char_indices() {
+                if c.is_whitespace() {
+                    space = end;
+                    continue;
+                }
+                if space && c.is_lowercase() {
+                    diagnostics.push(Diagnostic::warning(
+                        Code::Capitalization,
+                        passage.span(n..n + c.len_utf8()),
+                        format!("sentence starts with lower case {c}"),
+                    ));
+                }
+                end = matches!(c, '.' | '!' | '?') && !space_before(&passage.text[..n]);
+                space = false;
+            }
+        }
+    }
+}
+
+// An ellipsis or a lone stop such as ` . ` is not a sentence end.
+fn space_before(text: &str) -> bool {
+    text.chars()
+        .next_back()
+        .is_none_or(|c| c.is_whitespace() || c == '.')
+}
+
+#[cfg(test)]
+mod test {
+    use super::{standard, Brackets, Capitalization, Check, QuotationMarks, RepeatedWords};
+    use crate::document::Document;
+
+    fn run(check: impl Check + 'static, text: &str) -> Vec<String> {
+        let source = format!("\\id MRK\n\\c 1\n\\p \\v 1 {text}\n");
+        let doc = &source.parse::<Document>().expect("Document");
+        doc.check(&[Box::new(check)])
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn quotation_marks() {
+        assert_eq!(
+            run(
+                QuotationMarks::default(),
+                "“Don’t go,” he said. “The disciples’ ‘boat’ is here.”"
+            ),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            run(QuotationMarks::default(), "“One ‘two” three"),
+            [
+                "3:9: warning[unmatched-quote]: quotation is not closed",
+                "3:14: warning[unmatched-quote]: quotation is not closed",
+                "3:18: warning[unmatched-quote]: ” where ’ was expected",
+            ]
+        );
+        assert_eq!(
+            run(
+                QuotationMarks::default(),
+                "He said “Go\n\\p “on.”\n\\p “stop"
+            ),
+            ["5:4: warning[unmatched-quote]: quotation is not closed"]
+        );
+        assert_eq!(
+            run(QuotationMarks::default(), "“Go\n\\p on."),
+            ["3:9: warning[unmatched-quote]: quotation is not closed"]
+        );
+        assert_eq!(
+            run(QuotationMarks::default(), "Go.” Then"),
+            ["3:12: warning[unmatched-quote]: ” does not close an open quotation"]
+        );
+        let french = QuotationMarks::new([('«', '»'), ('“', '”')]);
+        assert_eq!(
+            run(french.clone(), "« Il dit “ oui ” »"),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            run(french, "« “ »"),
+            [
+                "3:9: warning[unmatched-quote]: quotation is not closed",
+                "3:11: warning[unmatched-quote]: quotation is not closed",
+                "3:13: warning[unmatched-quote]: » where ” was expected",
+            ]
+        );
+    }
+
+    #[test]
+    fn repeated_words() {
+        assert_eq!(
+            run(
+                RepeatedWords,
+                "In the \\nd The\\nd* beginning 1 1, was, was"
+            ),
+            ["3:20: warning[repeated-word]: repeated word The"]
+        );
+    }
+
+    #[test]
+    fn brackets() {
+        assert_eq!(
+            run(
+                Brackets::default(),
+                "(one [two) three] four)\\f + \\ft (x\\f*"
+            ),
+            [
+                "3:14: warning[unmatched-bracket]: bracket is not closed",
+                "3:25: warning[unmatched-bracket]: ] has no opening bracket",
+                "3:31: warning[unmatched-bracket]: ) has no opening bracket",
+                "3:41: warning[unmatched-bracket]: bracket is not closed",
+            ]
+        );
+    }
+
+    #[test]
+    fn capitalization() {
+        assert_eq!(
+            run(
+                Capitalization,
+                "He went. then he came... and “Stop!” he cried. Why? because."
+            ),
+            [
+                "3:18: warning[capitalization]: sentence starts with lower case t",
+                "3:61: warning[capitalization]: sentence starts with lower case b",
+            ]
+        );
+    }
+
+    #[test]
+    fn crlf_spans() {
+        for line_break in ["\n", "\r\n", "\r"] {
+            let source =
+                "\\id MRK\n\\c 1\n\\p \\v 1 one\nand\nand (two\n".replace('\n', line_break);
+            let doc = source.parse::<Document>().expect("Document");
+            let diagnostics = doc.check(&standard());
+            assert_eq!(
+                diagnostics
+                    .iter()
+                    .map(|d| (d.to_string(), &source[d.span.range()]))
+                    .collect::<Vec<_>>(),
+                [
+                    (
+                        "5:1: warning[repeated-word]: repeated word and".to_owned(),
+                        "and"
+                    ),
+                    (
+                        "5:5: warning[unmatched-bracket]: bracket is not closed".to_owned(),
+                        "("
+                    ),
+                ],
+                "{line_break:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn standard_checks() {
+        let doc = "\\id MRK\n\\toc1 the the\n\\c 1\n\\s1 (Heading\n\\p \\v 1 “Fine.” \\v 2 Good.\n"
+            .parse::<Document>()
+            .expect("Document");
+        let diagnostics = doc.check(&standard());
+        assert_eq!(
+            diagnostics
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            ["4:5: warning[unmatched-bracket]: bracket is not closed"]
+        );
+    }
+}
@@ -0,0 +1,343 @@
+//! Splitting a book into standalone pieces, for translation platforms that
+//! hand out work a chunk at a time.
+
+use crate::{
+    books::Book,
+    document::{Content, Document, Node, State},
+    extension::{Category, Extensions},
+    reference::{RefRange, Reference},
+    transform::ClearSpans,
+    versification::verse_range,
+    visit::VisitMut,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkBy {
+    /// A chunk for each run of text under a section heading. The text of a
+    /// section carried over into the next chapter is a chunk of its own.
+    Section,
+    Chapter,
+    /// A chunk for every so many verses, counting a combined verse such as
+    /// `\v 16-17` as one. Headings go with the verses after them.
+    Verses(u32),
+}
+
+/// A piece of a book, written as a document of its own.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chunk {
+    /// The verses in the chunk, or its chapter if it has none. `None` for
+    /// the material before the first chapter.
+    pub range: Option<RefRange>,
+    /// The `\id` line, the `\c` of the chapter and the markers of the
+    /// paragraphs the chunk starts inside, followed by its content. A chunk
+    /// starting part way through a paragraph opens it again.
+    pub document: Document<'static>,
+}
+
+impl Document<'_> {
+    /// The chunks of the document in order. Together they hold all of its
+    /// content; only the `\id` line and the markers opening a chunk are
+    /// repeated. Chunks are written without source spans.
+    pub fn chunks(&self, by: ChunkBy) -> Vec<Chunk> {
+        let Some(root) = self.root() else {
+            return Vec::new();
+        };
+        let mut chunker = Chunker {
+            by,
+            markers: State::usfm_ext(),
+            book: self.book(),
+            id: None,
+            open: vec![(Content::Para, shell(root))],
+            chunks: Vec::new(),
+            empty: true,
+            body: false,
+            heading: false,
+            chapter: 0,
+            verses: 0,
+            first: None,
+            last: None,
+        };
+        for item in &root.content {
+            match item {
+                Content::Book(_) => {
+                    let id = item.clone().into_owned();
+                    chunker.id = Some(id.clone());
+                    chunker.open[0].1.content.push(id);
+                }
+                Content::Chapter(node) => {
+                    chunker.split();
+                    chunker.chapter = node
+                        .attributes
+                        .get("number")
+                        .and_then(|n| n.parse().ok())
+                        .unwrap_or(chunker.chapter);
+                    chunker.empty = false;
+                    chunker.body = false;
+                    chunker.block(Content::Chapter, node);
+                }
+                item => chunker.item(item),
+            }
+        }
+        chunker.split();
+        chunker.chunks
+    }
+}
+
+// A copy of a node without its content.
+fn shell(node: &Node) -> Node<'static> {
+    Node {
+        style: node.style.clone(),
+        attributes: node.attributes.clone(),
+        nested: node.nested,
+        custom: node.custom,
+        ..Node::default()
+    }
+    .into_owned()
+}
+
+// The variant a node is held in.
+type Kind = fn(Node<'static>) -> Content<'static>;
+
+struct Chunker {
+    by: ChunkBy,
+    markers: &'static Extensions,
+    book: Option<&'static Book>,
+    id: Option<Content<'static>>,
+    /// The nodes being filled, outermost first, with the variant each goes
+    /// into its parent as. The first is the root.
+    open: Vec<(Kind, Node<'static>)>,
+    chunks: Vec<Chunk>,
+    // Nothing but the `\id` line and shells in the current chunk.
+    empty: bool,
+    // Something besides shells since the current chapter started.
+    body: bool,
+    // The last block added was a section heading.
+    heading: bool,
+    chapter: u32,
+    // Verse markers in the current chunk.
+    verses: u32,
+    first: Option<(u32, u32)>,
+    last: Option<(u32, u32)>,
+}
+
+impl Chunker {
+    fn is_heading(&self, node: &Node) -> bool {
+        self.markers.category(node.style.as_ref()) == Some(Category::SectionPara)
+    }
+
+    fn push(&mut self, item: Content<'static>) {
+        self.empty = false;
+        self.body = true;
+        if let Some((_, node)) = self.open.last_mut() {
+            node.content.push(item);
+        }
+    }
+
+    fn block(&mut self, kind: Kind, node: &Node) {
+        self.open.push((kind, shell(node)));
+        for item in &node.content {
+            self.item(item);
+        }
+        if let Some((kind, node)) = self.open.pop() {
+            if let Some((_, parent)) = self.open.last_mut() {
+                parent.content.push(kind(node));
+            }
+        }
+    }
+
+    fn item(&mut self, item: &Content) {
+        match item {
+            Content::Para(node) if self.is_heading(node) => {
+                let split = match self.by {
+                    ChunkBy::Section => self.body && !self.heading,
+                    ChunkBy::Verses(n) => self.verses >= n,
+                    ChunkBy::Chapter => false,
+                };
+                if split {
+                    self.split();
+                }
+                self.push(item.clone().into_owned());
+                self.heading = true;
+                return;
+            }
+            Content::Verse(node) => {
+                if matches!(self.by, ChunkBy::Verses(n) if self.verses >= n) {
+                    self.split();
+                }
+                self.verses += 1;
+                if let Some((first, last)) =
+                    node.attributes.get("number").and_then(|n| verse_range(n))
+                {
+                    self.first.get_or_insert((self.chapter, first));
+                    self.last = Some((self.chapter, last));
+                }
+                self.push(item.clone().into_owned());
+            }
+            Content::Para(node)
+            | Content::List(node)
+            | Content::Stanza(node)
+            | Content::Table(node)
+            | Content::Row(node)
+            | Content::Cell(node)
+            | Content::Sidebar(node)
+            | Content::Periph(node) => {
+                self.empty = false;
+                self.body = true;
+                let kind = match item {
+                    Content::Para(_) => Content::Para,
+                    Content::List(_) => Content::List,
+                    Content::Stanza(_) => Content::Stanza,
+                    Content::Table(_) => Content::Table,
+                    Content::Row(_) => Content::Row,
+                    Content::Cell(_) => Content::Cell,
+                    Content::Sidebar(_) => Content::Sidebar,
+                    _ => Content::Periph,
+                };
+                self.block(kind, node);
+            }
+            item => self.push(item.clone().into_owned()),
+        }
+        if matches!(item, Content::Para(_)) {
+            self.heading = false;
+        }
+    }
+
+    // Finish the current chunk, if it has anything in it, and open the
+    // same nodes again for the next.
+    fn split(&mut self) {
+        if self.empty {
+            return;
+        }
+        let shells = self
+            .open
+            .iter()
+            .map(|(kind, node)| (*kind, shell(node)))
+            .collect::<Vec<_>>();
+        let mut open = std::mem::replace(&mut self.open, shells);
+        let mut root = None;
+        while let Some((kind, node)) = open.pop() {
+            // Nodes opened just before the split start the next chunk
+            // instead.
+            if node.content.is_empty() && !open.is_empty() {
+                continue;
+            }
+            match open.last_mut() {
+                Some((_, parent)) => parent.content.push(kind(node)),
+                None => root = Some(node),
+            }
+        }
+        let Some(mut root) = root else {
+            return;
+        };
+        let _ = ClearSpans.visit_node_mut(&mut root);
+        let mut document = Document::default();
+        document.nodes = Some(root);
+        self.chunks.push(Chunk {
+            range: self.range(),
+            document,
+        });
+        self.open[0].1.content.extend(self.id.clone());
+        self.empty = true;
+        self.verses = 0;
+        self.first = None;
+        self.last = None;
+    }
+
+    fn range(&self) -> Option<RefRange> {
+        let book = self.book?;
+        let reference = |(chapter, verse)| Reference::new(book, chapter, Some(verse));
+        match (self.first, self.last) {
+            (Some(first), Some(last)) => Some(RefRange {
+                start: reference(first),
+                end: reference(last),
+            }),
+            _ if self.chapter > 0 => Some(Reference::new(book, self.chapter, None).into()),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ChunkBy;
+    use crate::document::Document;
+
+    const USFM: &str = "\\id MRK\n\\h Mark\n\\mt1 Mark\n\\c 1\n\\s1 John\n\\r (Luke 3)\n\
+                        \\p \\v 1 One\n\\v 2 Two\n\\q1 \\v 3 Three\n\\s1 Jesus\n\
+                        \\p \\v 4 Four\n\\v 5-6 Five\n\\c 2\n\\p \\v 1 Again\n";
+
+    fn chunks(by: ChunkBy) -> Vec<(String, String)> {
+        let doc = USFM.parse::<Document>().expect("Document");
+        doc.chunks(by)
+            .into_iter()
+            .map(|chunk| {
+                let range = chunk.range.map_or_else(String::new, |r| r.to_string());
+                (range, chunk.document.to_string())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn chapters() {
+        let chunks = chunks(ChunkBy::Chapter);
+        let ranges = chunks.iter().map(|(r, _)| r.as_str()).collect::<Vec<_>>();
+        assert_eq!(ranges, ["", "MRK 1:1-6", "MRK 2:1"]);
+        assert_eq!(chunks[0].1, "\\id MRK\n\\h Mark\n\\mt1 Mark\n");
+        assert_eq!(chunks[2].1, "\\id MRK\n\\c 2\n\\p\n\\v 1 Again\n");
+    }
+
+    #[test]
+    fn sections() {
+        let chunks = chunks(ChunkBy::Section);
+        assert_eq!(
+            chunks[1..],
+            [
+                (
+                    "MRK 1:1-3".into(),
+                    "\\id MRK\n\\c 1\n\\s1 John\n\\r (Luke 3)\n\\p\n\\v 1 One\n\\v 2 Two\n\
+                     \\q1\n\\v 3 Three\n"
+                        .into()
+                ),
+                (
+                    "MRK 1:4-6".into(),
+                    "\\id MRK\n\\c 1\n\\s1 Jesus\n\\p\n\\v 4 Four\n\\v 5-6 Five\n".into()
+                ),
+                (
+                    "MRK 2:1".into(),
+                    "\\id MRK\n\\c 2\n\\p\n\\v 1 Again\n".into()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn verses() {
+        let chunks = chunks(ChunkBy::Verses(1));
+        assert_eq!(
+            chunks[1..],
+            [
+                (
+                    "MRK 1:1".into(),
+                    "\\id MRK\n\\c 1\n\\s1 John\n\\r (Luke 3)\n\\p\n\\v 1 One \n".into()
+                ),
+                ("MRK 1:2".into(), "\\id MRK\n\\c 1\n\\p\n\\v 2 Two\n".into()),
+                (
+                    "MRK 1:3".into(),
+                    "\\id MRK\n\\c 1\n\\q1\n\\v 3 Three\n".into()
+                ),
+                (
+                    "MRK 1:4".into(),
+                    "\\id MRK\n\\c 1\n\\s1 Jesus\n\\p\n\\v 4 Four \n".into()
+                ),
+                (
+                    "MRK 1:5-6".into(),
+                    "\\id MRK\n\\c 1\n\\p\n\\v 5-6 Five\n".into()
+                ),
+                (
+                    "MRK 2:1".into(),
+                    "\\id MRK\n\\c 2\n\\p\n\\v 1 Again\n".into()
+                ),
+            ]
+        );
+    }
+}
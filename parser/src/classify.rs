@@ -0,0 +1,167 @@
+//! Telling the text of scripture from the text around it: introductions,
+//! headings, notes and peripheral material, by the categories of the
+//! markers it is in.
+
+use std::slice;
+
+use crate::{
+    document::{Content, Document, State},
+    extension::{Category, Extensions},
+};
+
+/// What kind of text a node holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum TextClass {
+    /// The text of scripture itself, including the `\d` titles of psalms.
+    Canonical,
+    Introduction,
+    /// Titles and section headings added by the translators.
+    Heading,
+    /// Footnotes and cross references.
+    Note,
+    /// Header material, remarks, figures, sidebars, alternate and published
+    /// numbers, and the front and back matter books.
+    Peripheral,
+}
+
+impl TextClass {
+    /// The class a marker gives the text in it, from its category in
+    /// `markers`. `None` for markers such as character styles, chapters and
+    /// verses, whose text is of the class of whatever they are in.
+    pub fn of_marker(markers: &Extensions, style: &str) -> Option<TextClass> {
+        // Markers filed under a category that does not say what they hold.
+        match style {
+            "d" => return Some(TextClass::Canonical),
+            "ip" | "iex" => return Some(TextClass::Introduction),
+            "qa" => return Some(TextClass::Heading),
+            "rem" | "sts" | "lit" | "id" | "usfm" | "ca" | "va" | "vp" | "cp" | "cat" | "fig"
+            | "esb" | "periph" => return Some(TextClass::Peripheral),
+            _ => {}
+        }
+        match markers.category(style)? {
+            Category::VersePara | Category::List => Some(TextClass::Canonical),
+            Category::Introduction | Category::IntroChar => Some(TextClass::Introduction),
+            Category::Title | Category::SectionPara => Some(TextClass::Heading),
+            Category::Footnote
+            | Category::FootnoteChar
+            | Category::Crossreference
+            | Category::CrossreferenceChar => Some(TextClass::Note),
+            Category::Header => Some(TextClass::Peripheral),
+            _ => None,
+        }
+    }
+
+    // The class of a node with this class around it and `own` its marker's.
+    // Notes stay notes wherever they are, and nothing inside a note or
+    // peripheral text becomes scripture.
+    fn within(self, own: Option<TextClass>) -> TextClass {
+        match (self, own) {
+            (_, Some(TextClass::Note)) => TextClass::Note,
+            (TextClass::Note | TextClass::Peripheral, _) | (_, None) => self,
+            (_, Some(own)) => own,
+        }
+    }
+}
+
+/// Depth-first, pre-order traversal of a document, giving each item with
+/// the class of its text. See [`Document::classified`].
+#[derive(Debug, Clone)]
+pub struct Classified<'d> {
+    markers: &'d Extensions,
+    stack: Vec<(slice::Iter<'d, Content<'d>>, TextClass)>,
+}
+
+impl<'d> Iterator for Classified<'d> {
+    type Item = (TextClass, &'d Content<'d>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (items, class) = self.stack.last_mut()?;
+            let class = *class;
+            let Some(item) = items.next() else {
+                self.stack.pop();
+                continue;
+            };
+            let Some(node) = item.node() else {
+                return Some((class, item));
+            };
+            let class = class.within(TextClass::of_marker(self.markers, &node.style));
+            self.stack.push((node.content.iter(), class));
+            return Some((class, item));
+        }
+    }
+}
+
+impl Document<'_> {
+    /// Every item of the document in the order of [`Document::iter`], with
+    /// the class of its text by the bundled marker set. The text of the
+    /// front and back matter books is peripheral throughout, apart from
+    /// their notes.
+    pub fn classified(&self) -> Classified<'_> {
+        self.classified_with(State::usfm_ext())
+    }
+
+    /// As [`Document::classified`], with the categories of `markers`.
+    pub fn classified_with<'d>(&'d self, markers: &'d Extensions) -> Classified<'d> {
+        let class = match self.book() {
+            Some(book) if book.is_peripheral() => TextClass::Peripheral,
+            _ => TextClass::Canonical,
+        };
+        Classified {
+            markers,
+            stack: self
+                .root()
+                .map(|root| (root.content.iter(), class))
+                .into_iter()
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::TextClass;
+    use crate::document::{Content, Document};
+
+    fn texts(usfm: &str, class: TextClass) -> Vec<String> {
+        let doc: Document = usfm.parse().expect("Document");
+        doc.classified()
+            .filter(|(c, _)| *c == class)
+            .filter_map(|(_, item)| match item {
+                Content::Text(text) => Some(text.text.trim().to_owned()),
+                _ => None,
+            })
+            .filter(|text| !text.is_empty())
+            .collect()
+    }
+
+    #[test]
+    fn classified() {
+        let usfm = "\\id PSA Psalms\n\\h Psalms\n\\rem checked\n\\mt1 The \\bd Psalms\\bd*\n\
+                    \\ip The psalms are \\bk songs\\bk*.\n\\c 3\n\\s1 Trust\n\
+                    \\d A psalm of \\w David\\w*\n\\q1 \\v 1 Lord, \\nd how\\nd* many\
+                    \\f + \\fr 3:1 \\ft Or \\+w so\\+w*\\f*\n\\q2 \\v 2 are my foes\n";
+        assert_eq!(
+            texts(usfm, TextClass::Canonical),
+            ["A psalm of", "David", "Lord,", "how", "many", "are my foes"]
+        );
+        assert_eq!(
+            texts(usfm, TextClass::Introduction),
+            ["The psalms are", "songs", "."]
+        );
+        assert_eq!(texts(usfm, TextClass::Heading), ["The", "Psalms", "Trust"]);
+        assert_eq!(texts(usfm, TextClass::Note), ["3:1", "Or", "so"]);
+        assert_eq!(
+            texts(usfm, TextClass::Peripheral),
+            ["Psalms", "Psalms", "checked"]
+        );
+
+        let front = "\\id FRT\n\\periph Preface|id=\"preface\"\n\\p Read \\bd this\\bd*\
+                     \\f + \\ft first\\f*\n";
+        assert_eq!(texts(front, TextClass::Peripheral), ["Read", "this"]);
+        assert_eq!(texts(front, TextClass::Note), ["first"]);
+        assert!(texts(front, TextClass::Canonical).is_empty());
+    }
+}
@@ -0,0 +1,291 @@
+//! Running the community USFM test suite, the `tests` folder of
+//! usfm-grammar, against the parser and the USX and USJ readers.
+//!
+//! Each test is a folder holding `origin.usfm` and, where the suite has
+//! them, the same text as USX in `origin.xml` and as USJ in `origin.json`,
+//! with a `metadata.xml` whose `<validated>` says whether the USFM is
+//! meant to parse. A test passes when the USFM parses or fails to as
+//! expected and its tree is canonically equal, see
+//! [`Document::canonical_eq`], to the ones read from the USX and USJ.
+//!
+//! The suite is not bundled: check it out as a submodule or download it,
+//! and give its folder to [`run`].
+
+use std::{
+    fmt::{self, Display},
+    fs, io,
+    path::Path,
+};
+
+use crate::document::Document;
+
+/// How one check of a test went.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Pass,
+    Fail(String),
+    /// The test has nothing to check against, such as a missing USX file,
+    /// or nothing to convert because the USFM failed to parse as it should.
+    Skip,
+}
+
+impl Outcome {
+    pub fn is_fail(&self) -> bool {
+        matches!(self, Outcome::Fail(_))
+    }
+}
+
+/// The checks of one test folder.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestResult {
+    /// The folder's path below the suite's.
+    pub name: String,
+    pub parse: Outcome,
+    pub usx: Outcome,
+    pub usj: Outcome,
+}
+
+impl TestResult {
+    pub fn passed(&self) -> bool {
+        ![&self.parse, &self.usx, &self.usj]
+            .into_iter()
+            .any(Outcome::is_fail)
+    }
+}
+
+/// The results of a run, in the order of the test names. Displayed, it
+/// gives a line for each test and a summary.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Report {
+    pub results: Vec<TestResult>,
+}
+
+impl Report {
+    pub fn passed(&self) -> usize {
+        self.results.iter().filter(|r| r.passed()).count()
+    }
+
+    pub fn failed(&self) -> usize {
+        self.results.len() - self.passed()
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for result in &self.results {
+            let status = if result.passed() { "pass" } else { "FAIL" };
+            writeln!(f, "{status} {}", result.name)?;
+            for (check, outcome) in [
+                ("parse", &result.parse),
+                ("usx", &result.usx),
+                ("usj", &result.usj),
+            ] {
+                if let Outcome::Fail(reason) = outcome {
+                    writeln!(f, "  {check}: {reason}")?;
+                }
+            }
+        }
+        write!(
+            f,
+            "{} tests, {} passed, {} failed",
+            self.results.len(),
+            self.passed(),
+            self.failed()
+        )
+    }
+}
+
+/// Run every test below `dir`, that is every folder with an `origin.usfm`.
+pub fn run(dir: impl AsRef<Path>) -> io::Result<Report> {
+    let dir = dir.as_ref();
+    let mut tests = Vec::new();
+    find_tests(dir, &mut tests)?;
+    tests.sort();
+    let results = tests
+        .iter()
+        .map(|test| {
+            let mut result = run_test(test)?;
+            result.name = test
+                .strip_prefix(dir)
+                .unwrap_or(test)
+                .to_string_lossy()
+                .into_owned();
+            Ok(result)
+        })
+        .collect::<io::Result<_>>()?;
+    Ok(Report { results })
+}
+
+fn find_tests(dir: &Path, tests: &mut Vec<std::path::PathBuf>) -> io::Result<()> {
+    if dir.join("origin.usfm").is_file() {
+        tests.push(dir.to_owned());
+    }
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            find_tests(&path, tests)?;
+        }
+    }
+    Ok(())
+}
+
+/// Run the test in one folder.
+pub fn run_test(dir: impl AsRef<Path>) -> io::Result<TestResult> {
+    let dir = dir.as_ref();
+    let read = |name| match fs::read_to_string(dir.join(name)) {
+        Ok(text) => Ok(Some(text)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    };
+    let usfm = read("origin.usfm")?.unwrap_or_default();
+    // Tests are meant to parse unless their metadata says otherwise.
+    let valid = read("metadata.xml")?.is_none_or(|metadata| {
+        element_text(&metadata, "validated").is_none_or(|v| !v.eq_ignore_ascii_case("fail"))
+    });
+    let mut result = TestResult {
+        name: dir.to_string_lossy().into_owned(),
+        parse: Outcome::Pass,
+        usx: Outcome::Skip,
+        usj: Outcome::Skip,
+    };
+    let doc = match usfm.parse::<Document>() {
+        Ok(doc) if valid => doc,
+        Ok(_) => {
+            result.parse = Outcome::Fail("parsed but is marked invalid".into());
+            return Ok(result);
+        }
+        Err(_) if !valid => return Ok(result),
+        Err(e) => {
+            result.parse = Outcome::Fail(first_line(&e.to_string()));
+            return Ok(result);
+        }
+    };
+    if let Some(usx) = read("origin.xml")? {
+        result.usx = compare(&doc, Document::from_usx(usx.as_bytes()), "origin.xml");
+    }
+    if let Some(usj) = read("origin.json")? {
+        let expected = serde_json::from_str(&usj)
+            .map_err(io::Error::from)
+            .and_then(|usj| Document::from_usj(&usj));
+        result.usj = compare(&doc, expected, "origin.json");
+    }
+    Ok(result)
+}
+
+fn compare(doc: &Document, expected: io::Result<Document>, file: &str) -> Outcome {
+    let mut expected = match expected {
+        Ok(expected) => expected,
+        Err(e) => return Outcome::Fail(format!("{file}: {}", first_line(&e.to_string()))),
+    };
+    // The version on the root differs between formats and is not content.
+    if let (Some(root), Some(theirs)) = (doc.root(), expected.root_mut()) {
+        theirs.attributes.clone_from(&root.attributes);
+    }
+    if doc.canonical_eq(&expected) {
+        return Outcome::Pass;
+    }
+    let (ours, theirs) = (doc.to_string(), expected.to_string());
+    let (n, (ours, theirs)) = ours
+        .lines()
+        .chain(std::iter::repeat(""))
+        .zip(theirs.lines().chain(std::iter::repeat("")))
+        .enumerate()
+        .find(|(_, (a, b))| a != b)
+        .unwrap_or((0, ("", "")));
+    Outcome::Fail(format!(
+        "differs from {file} at line {} written as USFM: {ours:?} against {theirs:?}",
+        n + 1
+    ))
+}
+
+fn element_text<'x>(xml: &'x str, name: &str) -> Option<&'x str> {
+    let start = xml.find(&format!("<{name}>"))? + name.len() + 2;
+    let len = xml[start..].find(&format!("</{name}>"))?;
+    Some(xml[start..start + len].trim())
+}
+
+fn first_line(message: &str) -> String {
+    message.lines().next().unwrap_or_default().to_owned()
+}
+
+#[cfg(test)]
+mod test {
+    use std::{env, fs};
+
+    use super::{run, Outcome};
+
+    #[test]
+    fn suite() {
+        let dir = env::temp_dir().join(format!("usfm-conformance-{}", std::process::id()));
+        let test = |name: &str, files: &[(&str, &str)]| {
+            let path = dir.join(name);
+            fs::create_dir_all(&path).expect("test folder");
+            for (file, contents) in files {
+                fs::write(path.join(file), contents).expect("test file");
+            }
+        };
+        let usfm = "\\id MRK\n\\c 1\n\\p \\v 1 In the beginning\n";
+        test(
+            "basic/verse",
+            &[
+                ("origin.usfm", usfm),
+                (
+                    "origin.xml",
+                    "<usx version=\"3.0\"><book code=\"MRK\" style=\"id\"/>\
+                     <chapter number=\"1\" style=\"c\" sid=\"MRK 1\"/>\
+                     <para style=\"p\"><verse number=\"1\" style=\"v\" sid=\"MRK 1:1\"/>\
+                     In the beginning<verse eid=\"MRK 1:1\"/></para>\
+                     <chapter eid=\"MRK 1\"/></usx>",
+                ),
+                (
+                    "origin.json",
+                    "{\"type\": \"USJ\", \"version\": \"3.1\", \"content\": [\
+                     {\"type\": \"book\", \"marker\": \"id\", \"code\": \"MRK\"},\
+                     {\"type\": \"chapter\", \"marker\": \"c\", \"number\": \"1\"},\
+                     {\"type\": \"para\", \"marker\": \"p\", \"content\": [\
+                     {\"type\": \"verse\", \"marker\": \"v\", \"number\": \"1\"},\
+                     \"In the end\"]}]}",
+                ),
+            ],
+        );
+        test(
+            "invalid/no-id",
+            &[
+                ("origin.usfm", "\\c 1\n\\p text\n"),
+                (
+                    "metadata.xml",
+                    "<test-metadata><validated>fail</validated></test-metadata>",
+                ),
+            ],
+        );
+        test("invalid/unmarked", &[("origin.usfm", "\\c 1\n")]);
+
+        let report = run(&dir).expect("Report");
+        fs::remove_dir_all(&dir).expect("clean up");
+        let names = report
+            .results
+            .iter()
+            .map(|r| r.name.replace('\\', "/"))
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["basic/verse", "invalid/no-id", "invalid/unmarked"]);
+        let [verse, no_id, unmarked] = &report.results[..] else {
+            panic!("three results");
+        };
+        assert_eq!((&verse.parse, &verse.usx), (&Outcome::Pass, &Outcome::Pass));
+        assert_eq!(
+            verse.usj,
+            Outcome::Fail(
+                "differs from origin.json at line 4 written as USFM: \
+                 \"\\\\v 1 In the beginning\" against \"\\\\v 1 In the end\""
+                    .into()
+            )
+        );
+        assert!(no_id.passed());
+        assert_eq!(no_id.usx, Outcome::Skip);
+        assert!(unmarked.parse.is_fail());
+        assert_eq!(
+            report.to_string().lines().last(),
+            Some("3 tests, 1 passed, 2 failed")
+        );
+    }
+}
@@ -0,0 +1,265 @@
+//! Verse-aligned text from two translations, for building parallel corpora.
+
+use std::collections::HashMap;
+
+use crate::{
+    document::{Content, Document},
+    extension::Extensions,
+    reference::Reference,
+    verses::is_heading,
+    versification::{verse_numbers, verse_range, Versification},
+};
+
+/// A verse found in both documents. Its reference is in the original
+/// versification when the documents were aligned through their schemes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AlignedVerse {
+    pub reference: Reference,
+    pub source: String,
+    pub target: String,
+}
+
+impl Document<'_> {
+    /// The text of each verse in document order, without notes, figures or
+    /// headings. The text of combined verses such as `\v 16-17` belongs to
+    /// the first of them; the rest are listed with empty text.
+    pub fn verse_texts(&self) -> Vec<(Reference, String)> {
+        let (Some(book), Some(root)) = (self.book(), &self.nodes) else {
+            return Vec::new();
+        };
+        let mut collector = Collector {
+            markers: self.markers(),
+            chapter: 0,
+            verses: Vec::new(),
+            current: None,
+        };
+        collector.content(&root.content);
+        collector
+            .verses
+            .into_iter()
+            .map(|((chapter, verse), text)| {
+                let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+                (Reference::new(book, chapter, Some(verse)), text)
+            })
+            .collect()
+    }
+}
+
+/// Pair the verses of two documents numbered in the same scheme.
+pub fn align_verses(source: &Document, target: &Document) -> Vec<AlignedVerse> {
+    let same = Versification::default();
+    align_verses_with(source, &same, target, &same)
+}
+
+/// Pair the verses of two documents after mapping each into the original
+/// versification, so that, for example, an English Malachi 4:1 meets a
+/// Hebrew 3:19. Verses mapped onto the same original verse are joined.
+/// Pairs come in source order.
+pub fn align_verses_with(
+    source: &Document,
+    source_versification: &Versification,
+    target: &Document,
+    target_versification: &Versification,
+) -> Vec<AlignedVerse> {
+    let source = mapped(source, source_versification);
+    let target = mapped(target, target_versification);
+    let target = target.into_iter().collect::<HashMap<_, _>>();
+    source
+        .into_iter()
+        .filter_map(|(reference, text)| {
+            Some(AlignedVerse {
+                reference,
+                source: text,
+                target: target.get(&reference)?.clone(),
+            })
+        })
+        .collect()
+}
+
+// Verse texts keyed by original reference, merged where several verses map
+// onto one.
+fn mapped(doc: &Document, versification: &Versification) -> Vec<(Reference, String)> {
+    let mut res = Vec::<(Reference, String)>::new();
+    let mut index = HashMap::<Reference, usize>::new();
+    for (reference, text) in doc.verse_texts() {
+        let reference = versification.to_original(&reference);
+        match index.get(&reference) {
+            Some(&n) => {
+                let merged = &mut res[n].1;
+                if !text.is_empty() {
+                    if !merged.is_empty() {
+                        merged.push(' ');
+                    }
+                    merged.push_str(&text);
+                }
+            }
+            None => {
+                index.insert(reference, res.len());
+                res.push((reference, text));
+            }
+        }
+    }
+    res
+}
+
+struct Collector<'m> {
+    markers: &'m Extensions,
+    chapter: u32,
+    verses: Vec<((u32, u32), String)>,
+    // The index of the verse text being collected.
+    current: Option<usize>,
+}
+
+impl Collector<'_> {
+    fn content(&mut self, content: &[Content]) {
+        for item in content {
+            match item {
+                Content::Text(text) => {
+                    if let Some(n) = self.current {
+                        self.verses[n].1.push_str(text.as_str());
+                    }
+                }
+                Content::NoBreakSpace => {
+                    if let Some(n) = self.current {
+                        self.verses[n].1.push('\u{a0}');
+                    }
+                }
+                Content::Chapter(node) => {
+                    self.chapter = node
+                        .attributes
+                        .get("number")
+                        .and_then(|n| n.parse().ok())
+                        .unwrap_or(self.chapter);
+                    self.current = None;
+                    self.content(&node.content);
+                }
+                Content::Verse(node) => {
+                    let Some(range) = node.attributes.get("number").and_then(|n| verse_range(n))
+                    else {
+                        continue;
+                    };
+                    let verses = verse_numbers(range);
+                    if verses.is_empty() {
+                        continue;
+                    }
+                    self.current = Some(self.verses.len());
+                    for verse in verses {
+                        self.verses.push(((self.chapter, verse), String::new()));
+                    }
+                }
+                Content::Note(_) | Content::Figure(_) | Content::Book(_) => {}
+                Content::Para(node) if is_heading(self.markers, node) => {}
+                Content::Para(node) => {
+                    self.push_space();
+                    self.content(&node.content);
+                    self.push_space();
+                }
+                item => {
+                    if let Some(node) = item.node() {
+                        self.content(&node.content);
+                    }
+                }
+            }
+        }
+    }
+
+    fn push_space(&mut self) {
+        if let Some(n) = self.current {
+            self.verses[n].1.push(' ');
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{align_verses, align_verses_with};
+    use crate::{
+        books,
+        document::{Document, ParseOptions},
+        extension::{Extensions, Version},
+        reference::Reference,
+        versification::Versification,
+    };
+
+    fn mal(chapter: u32, verse: u32) -> Reference {
+        Reference::new(books::get("MAL").unwrap(), chapter, Some(verse))
+    }
+
+    #[test]
+    fn verse_texts() {
+        let doc: Document = "\\id MAL\n\\c 3\n\\s1 Heading\n\\p \\v 1 One\\f + \\ft note\\f*\n\
+                             \\q1 continued \\v 2-3 Two and three\n"
+            .parse()
+            .expect("Document");
+        assert_eq!(
+            doc.verse_texts(),
+            [
+                (mal(3, 1), "One continued".to_owned()),
+                (mal(3, 2), "Two and three".to_owned()),
+                (mal(3, 3), String::new()),
+            ]
+        );
+    }
+
+    #[test]
+    fn custom_headings() {
+        let markers = Extensions::usfm(Version::V2)
+            .clone()
+            .update_from_str("\\marker zsec1\n\\category sectionpara\n")
+            .expect("Extensions");
+        let doc = Document::from_str_with_markers(
+            "\\id MAL\n\\c 3\n\\p \\v 1 One\n\\zsec1 Heading\n\\p more\n",
+            markers,
+            ParseOptions::default(),
+        )
+        .expect("Document");
+        assert_eq!(doc.verse_texts(), [(mal(3, 1), "One more".to_owned())]);
+    }
+
+    #[test]
+    fn bad_ranges() {
+        let doc: Document = "\\id MAL\n\\c 3\n\\p \\v 1 One \\v 5-3 more \\v 2-4000000000 Two\n"
+            .parse()
+            .expect("Document");
+        let texts = doc.verse_texts();
+        assert_eq!(texts.len(), 200);
+        assert_eq!(texts[0], (mal(3, 1), "One more".to_owned()));
+        assert_eq!(texts[1], (mal(3, 2), "Two".to_owned()));
+        assert_eq!(texts[199], (mal(3, 200), String::new()));
+    }
+
+    #[test]
+    fn align() {
+        let english: Document =
+            "\\id MAL\n\\c 3\n\\p \\v 18 e18\n\\c 4\n\\p \\v 1 e4:1 \\v 2 e4:2\n"
+                .parse()
+                .expect("Document");
+        let hebrew: Document = "\\id MAL\n\\c 3\n\\p \\v 18 h18 \\v 19 h19 \\v 20 h20\n"
+            .parse()
+            .expect("Document");
+        let pairs = |aligned: Vec<super::AlignedVerse>| {
+            aligned
+                .into_iter()
+                .map(|a| (a.reference.to_string(), a.source, a.target))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            pairs(align_verses(&english, &hebrew)),
+            [("MAL 3:18".into(), "e18".into(), "h18".into())]
+        );
+        let vrs: Versification = "MAL 4:1-2 = MAL 3:19-20".parse().expect("Versification");
+        assert_eq!(
+            pairs(align_verses_with(
+                &english,
+                &vrs,
+                &hebrew,
+                &Versification::default()
+            )),
+            [
+                ("MAL 3:18".into(), "e18".into(), "h18".into()),
+                ("MAL 3:19".into(), "e4:1".into(), "h19".into()),
+                ("MAL 3:20".into(), "e4:2".into(), "h20".into()),
+            ]
+        );
+    }
+}
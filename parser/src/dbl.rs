@@ -0,0 +1,407 @@
+//! Opening Digital Bible Library text release bundles: a zip holding
+//! `metadata.xml` and the books as USX or USFM.
+
+use std::{
+    fs::File,
+    io::{self, Read, Seek},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+};
+
+use nom::{error::convert_error, Finish};
+use zip::ZipArchive;
+
+use crate::{
+    books::{self, Book},
+    diagnostic::{Code, Diagnostic},
+    document::{Document, Span},
+    extension::{Extensions, Version},
+    project::{self, Parsed, Project},
+    usx::invalid,
+    xml::{self, Element, Xml},
+};
+
+/// A DBL bundle with its books loaded into a [`Project`].
+#[derive(Debug)]
+pub struct Bundle {
+    pub metadata: Metadata,
+    /// The books, with the names from the metadata added to the English
+    /// ones understood by [`Project::names`]. Books that fail to load are
+    /// reported in its diagnostics.
+    pub project: Project,
+}
+
+impl Bundle {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::from_reader(File::open(path)?)
+    }
+
+    /// Read the bundle's `metadata.xml`, then every book it lists in its
+    /// default publication. Without a publication listing them, every
+    /// `.usx`, `.usfm` and `.sfm` file in the bundle is loaded instead.
+    pub fn from_reader<R: Read + Seek>(reader: R) -> io::Result<Self> {
+        let mut archive = ZipArchive::new(reader).map_err(invalid)?;
+        // Bundles are sometimes zipped with the folder holding them.
+        let metadata_path = archive
+            .file_names()
+            .filter(|name| *name == "metadata.xml" || name.ends_with("/metadata.xml"))
+            .min_by_key(|name| name.len())
+            .ok_or_else(|| invalid("no metadata.xml in the bundle"))?
+            .to_owned();
+        let root = &metadata_path[..metadata_path.len() - "metadata.xml".len()];
+        let metadata = read(&mut archive, &metadata_path)?.parse::<Metadata>()?;
+
+        let files = match metadata.contents.is_empty() {
+            false => metadata
+                .contents
+                .iter()
+                .map(|src| format!("{root}{src}"))
+                .collect(),
+            true => {
+                let mut files = archive
+                    .file_names()
+                    .filter(|name| name.starts_with(root) && is_book_file(name))
+                    .map(str::to_owned)
+                    .collect::<Vec<_>>();
+                files.sort();
+                files
+            }
+        };
+        let markers = Arc::clone(Extensions::usfm_shared(Version::default()));
+        let mut parsed = Vec::with_capacity(files.len());
+        for name in files {
+            let source = read(&mut archive, &name)?;
+            parsed.push((PathBuf::from(&name), parse(&name, &source, &markers)));
+        }
+        let mut project = Project::from_parsed(parsed, markers)?;
+        for name in &metadata.book_names {
+            for form in [&name.long, &name.short, &name.abbreviation]
+                .into_iter()
+                .flatten()
+            {
+                project.names.insert(form, name.book);
+            }
+        }
+        Ok(Bundle { metadata, project })
+    }
+}
+
+fn is_book_file(name: &str) -> bool {
+    let ext = name.rsplit_once('.').map_or("", |(_, ext)| ext);
+    ["usx", "usfm", "sfm"]
+        .iter()
+        .any(|known| ext.eq_ignore_ascii_case(known))
+}
+
+fn read<R: Read + Seek>(archive: &mut ZipArchive<R>, name: &str) -> io::Result<String> {
+    let file = archive
+        .by_name(name)
+        .map_err(|e| invalid(format!("{name}: {e}")))?;
+    let text = io::read_to_string(file)?;
+    Ok(match text.strip_prefix('\u{FEFF}') {
+        Some(text) => text.to_owned(),
+        None => text,
+    })
+}
+
+// A USX file that does not load is reported like a damaged USFM file
+// rather than failing the whole bundle.
+fn parse(name: &str, source: &str, markers: &Arc<Extensions>) -> Parsed {
+    if !name.to_ascii_lowercase().ends_with(".usx") {
+        return Ok(project::parse_source(source, markers));
+    }
+    Ok(match Document::from_usx(source.as_bytes()) {
+        Ok(doc) => (doc, Vec::new()),
+        Err(e) => (
+            Document::default(),
+            vec![Diagnostic::error(
+                Code::Syntax,
+                Span::default(),
+                e.to_string(),
+            )],
+        ),
+    })
+}
+
+/// What a bundle's `metadata.xml` says about the text, its rights and its
+/// book names. Both the DBL 1 and DBL 2 layouts are understood.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Metadata {
+    /// The DBL id of the entry.
+    pub id: Option<String>,
+    pub revision: Option<String>,
+    pub name: Option<String>,
+    /// The name in the language of the text.
+    pub name_local: Option<String>,
+    pub abbreviation: Option<String>,
+    pub abbreviation_local: Option<String>,
+    pub description: Option<String>,
+    /// The language's ISO 639-3 code.
+    pub language: Option<String>,
+    pub language_name: Option<String>,
+    /// Who holds the rights to the text.
+    pub rights_holder: Option<String>,
+    /// The copyright statement, as plain text.
+    pub copyright: Option<String>,
+    /// The statement to show with the text when promoting it, as plain
+    /// text.
+    pub promotion: Option<String>,
+    pub book_names: Vec<BookName>,
+    /// The book files of the default publication, relative to the bundle,
+    /// in order.
+    pub contents: Vec<String>,
+}
+
+/// The names a text gives a book.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BookName {
+    pub book: &'static Book,
+    pub long: Option<String>,
+    pub short: Option<String>,
+    pub abbreviation: Option<String>,
+}
+
+impl FromStr for Metadata {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (_, root) = xml::document(s)
+            .finish()
+            .map_err(|e| invalid(convert_error(s, e)))?;
+        if root.name != "DBLMetadata" {
+            return Err(invalid(format!(
+                "expected <DBLMetadata>, found <{}>",
+                root.name
+            )));
+        }
+        let attribute = |name| root.attribute(name).map(str::to_owned);
+        let identification = child(&root, "identification");
+        let field = |name| {
+            identification
+                .and_then(|e| child(e, name))
+                .and_then(project::text)
+        };
+        let language = child(&root, "language");
+        let rights_holder = child(&root, "agencies")
+            .and_then(|agencies| child(agencies, "rightsHolder"))
+            .or_else(|| child(&root, "rightsHolder"));
+        Ok(Metadata {
+            id: attribute("id"),
+            revision: attribute("revision"),
+            name: field("name"),
+            name_local: field("nameLocal"),
+            abbreviation: field("abbreviation"),
+            abbreviation_local: field("abbreviationLocal"),
+            description: field("description"),
+            language: language
+                .and_then(|e| child(e, "iso"))
+                .and_then(project::text),
+            language_name: language
+                .and_then(|e| child(e, "name"))
+                .and_then(project::text),
+            rights_holder: rights_holder
+                .and_then(|e| child(e, "name"))
+                .and_then(project::text),
+            copyright: child(&root, "copyright").and_then(statement),
+            promotion: child(&root, "promotion").and_then(statement),
+            book_names: book_names(&root),
+            contents: contents(&root),
+        })
+    }
+}
+
+impl Metadata {
+    #[inline]
+    pub fn from_reader<R: Read>(reader: R) -> io::Result<Self> {
+        io::read_to_string(reader)?.parse()
+    }
+}
+
+fn children<'e, 'i>(element: &'e Element<'i>) -> impl Iterator<Item = &'e Element<'i>> {
+    element.children.iter().filter_map(|child| match child {
+        Xml::Element(element) => Some(element),
+        Xml::Text(_) => None,
+    })
+}
+
+fn child<'e, 'i>(element: &'e Element<'i>, name: &str) -> Option<&'e Element<'i>> {
+    children(element).find(|child| child.name == name)
+}
+
+// The text of an XHTML statement, a paragraph to a line.
+fn statement(element: &Element) -> Option<String> {
+    fn collect(element: &Element, res: &mut String) {
+        for child in &element.children {
+            match child {
+                Xml::Text(text) => res.push_str(text),
+                Xml::Element(element) => {
+                    if element.name == "p" && !res.is_empty() {
+                        res.push('\n');
+                    }
+                    collect(element, res);
+                }
+            }
+        }
+    }
+    let mut text = String::new();
+    collect(element, &mut text);
+    let text = text
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n");
+    (!text.is_empty()).then_some(text)
+}
+
+// DBL 2 lists names as `<names><name id="book-mrk">`, DBL 1 as
+// `<bookNames><book code="MRK">`.
+fn book_names(root: &Element) -> Vec<BookName> {
+    let names = child(root, "names").or_else(|| child(root, "bookNames"));
+    names
+        .into_iter()
+        .flat_map(children)
+        .filter_map(|name| {
+            let code = match name.attribute("code") {
+                Some(code) => code.to_owned(),
+                None => name.attribute("id")?.strip_prefix("book-")?.to_uppercase(),
+            };
+            let form = |form| child(name, form).and_then(project::text);
+            Some(BookName {
+                book: books::get(&code)?,
+                long: form("long"),
+                short: form("short"),
+                abbreviation: form("abbr"),
+            })
+        })
+        .collect()
+}
+
+// The book files in the structure of the default publication, or the
+// first one.
+fn contents(root: &Element) -> Vec<String> {
+    let publications = child(root, "publications")
+        .into_iter()
+        .flat_map(children)
+        .filter(|publication| publication.name == "publication")
+        .collect::<Vec<_>>();
+    let publication = publications
+        .iter()
+        .find(|publication| publication.attribute("default") == Some("true"))
+        .or(publications.first());
+    publication
+        .and_then(|publication| child(publication, "structure"))
+        .into_iter()
+        .flat_map(|structure| structure.children.iter())
+        .filter_map(|item| match item {
+            Xml::Element(content) if content.name == "content" => content.attribute("src"),
+            _ => None,
+        })
+        .filter(|src| is_book_file(src))
+        .map(str::to_owned)
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{Cursor, Write};
+
+    use zip::{write::SimpleFileOptions, ZipWriter};
+
+    use super::Bundle;
+    use crate::diagnostic::Code;
+
+    const METADATA: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<DBLMetadata id="2880c78491b2f8ce" revision="91" type="text" version="2.2">
+  <identification>
+    <name>World English Bible</name>
+    <nameLocal>World English Bible</nameLocal>
+    <abbreviation>WEB</abbreviation>
+    <abbreviationLocal>WEB</abbreviationLocal>
+  </identification>
+  <language><iso>eng</iso><name>English</name></language>
+  <agencies><rightsHolder uid="1"><name>eBible.org</name></rightsHolder></agencies>
+  <names>
+    <name id="book-mrk"><long>The Gospel of Mark</long><short>Mark</short><abbr>Mk</abbr></name>
+  </names>
+  <publications>
+    <publication default="true" id="p1">
+      <structure>
+        <content name="book-mrk" src="release/USX_1/MRK.usx" role="MRK"/>
+        <content name="book-jhn" src="release/USX_1/JHN.usfm" role="JHN"/>
+        <content name="book-act" src="release/USX_1/ACT.usx" role="ACT"/>
+      </structure>
+    </publication>
+  </publications>
+  <copyright>
+    <fullStatement><statementContent type="xhtml">
+      <p>Public   domain.</p><p>No rights reserved.</p>
+    </statementContent></fullStatement>
+  </copyright>
+</DBLMetadata>
+"#;
+
+    #[test]
+    fn bundle() {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        let files = [
+            ("web/metadata.xml", METADATA),
+            (
+                "web/release/USX_1/MRK.usx",
+                "<usx version=\"3.0\"><book code=\"MRK\" style=\"id\"/>\
+                 <chapter number=\"1\" style=\"c\"/>\
+                 <para style=\"p\"><verse number=\"1\" style=\"v\"/>The beginning</para></usx>",
+            ),
+            (
+                "web/release/USX_1/JHN.usfm",
+                "\\id JHN\n\\c 1\n\\p \\v 1 In the beginning\n",
+            ),
+            ("web/release/USX_1/ACT.usx", "<usx><para"),
+            (
+                "web/release/USX_1/LUK.usx",
+                "<usx><book code=\"LUK\" style=\"id\"/></usx>",
+            ),
+        ];
+        for (name, text) in files {
+            zip.start_file(name, SimpleFileOptions::default())
+                .expect("start_file");
+            zip.write_all(text.as_bytes()).expect("write");
+        }
+        let bundle = Bundle::from_reader(zip.finish().expect("zip")).expect("Bundle");
+
+        let metadata = &bundle.metadata;
+        assert_eq!(metadata.id.as_deref(), Some("2880c78491b2f8ce"));
+        assert_eq!(metadata.abbreviation.as_deref(), Some("WEB"));
+        assert_eq!(metadata.language.as_deref(), Some("eng"));
+        assert_eq!(metadata.rights_holder.as_deref(), Some("eBible.org"));
+        assert_eq!(
+            metadata.copyright.as_deref(),
+            Some("Public domain.\nNo rights reserved.")
+        );
+        assert_eq!(metadata.promotion, None);
+
+        let project = &bundle.project;
+        let codes = project
+            .iter()
+            .map(|(book, _)| book.code)
+            .collect::<Vec<_>>();
+        assert_eq!(codes, ["MRK", "JHN"]);
+        assert_eq!(
+            project.get("MRK").expect("MRK").to_string(),
+            "\\id MRK\n\\c 1\n\\p\n\\v 1 The beginning\n"
+        );
+        assert_eq!(project.names().get("Mk").map(|book| book.code), Some("MRK"));
+        assert_eq!(
+            project
+                .diagnostics
+                .iter()
+                .map(|d| (d.path.to_string_lossy().into_owned(), d.diagnostic.code))
+                .collect::<Vec<_>>(),
+            [
+                ("web/release/USX_1/ACT.usx".to_owned(), Code::Syntax),
+                ("web/release/USX_1/ACT.usx".to_owned(), Code::UnknownBook),
+            ]
+        );
+    }
+}
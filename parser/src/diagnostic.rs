@@ -0,0 +1,317 @@
+use std::{
+    error::Error,
+    fmt::{self, Display},
+};
+
+use crate::document::Span;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum Severity {
+    Error,
+    Warning,
+    Info,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Info => "info",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum Code {
+    /// A marker not defined by the marker set.
+    UnknownMarker,
+    /// A known marker where it cannot be used.
+    UnexpectedMarker,
+    /// An end marker with no matching start marker.
+    UnmatchedEndmarker,
+    /// A span missing its end marker.
+    MissingEndmarker,
+    /// Milestone pairs that overlap without one nesting inside the other.
+    CrossingMilestones,
+    InvalidAttribute,
+    InvalidNumber,
+    /// An `\id` code missing from the book registry.
+    UnknownBook,
+    /// A chapter or verse past the end of its book or chapter.
+    OutOfBounds,
+    /// A verse numbered before the one preceding it.
+    OutOfOrder,
+    MissingVerse,
+    MissingChapter,
+    /// A chapter or verse number used twice.
+    DuplicateNumber,
+    /// A verse range covering a verse already given.
+    OverlappingVerses,
+    /// A verse before the first chapter.
+    VerseBeforeChapter,
+    /// A quotation mark that does not open or close at the expected level.
+    UnmatchedQuote,
+    RepeatedWord,
+    UnmatchedBracket,
+    /// A sentence starting with a lower case letter.
+    Capitalization,
+    /// An `\ide` encoding that is unknown or not the one the file is in.
+    EncodingMismatch,
+    /// A construct from before USFM 3 with a USFM 3 replacement.
+    Deprecated,
+    /// Any other malformed input.
+    Syntax,
+}
+
+impl Code {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Code::UnknownMarker => "unknown-marker",
+            Code::UnexpectedMarker => "unexpected-marker",
+            Code::UnmatchedEndmarker => "unmatched-endmarker",
+            Code::MissingEndmarker => "missing-endmarker",
+            Code::CrossingMilestones => "crossing-milestones",
+            Code::InvalidAttribute => "invalid-attribute",
+            Code::InvalidNumber => "invalid-number",
+            Code::UnknownBook => "unknown-book",
+            Code::OutOfBounds => "out-of-bounds",
+            Code::OutOfOrder => "out-of-order",
+            Code::MissingVerse => "missing-verse",
+            Code::MissingChapter => "missing-chapter",
+            Code::DuplicateNumber => "duplicate-number",
+            Code::OverlappingVerses => "overlapping-verses",
+            Code::VerseBeforeChapter => "verse-before-chapter",
+            Code::UnmatchedQuote => "unmatched-quote",
+            Code::RepeatedWord => "repeated-word",
+            Code::UnmatchedBracket => "unmatched-bracket",
+            Code::Capitalization => "capitalization",
+            Code::EncodingMismatch => "encoding-mismatch",
+            Code::Deprecated => "deprecated",
+            Code::Syntax => "syntax",
+        }
+    }
+
+    /// A sentence saying what the code is for, to describe it as a rule.
+    pub fn description(&self) -> &'static str {
+        match self {
+            Code::UnknownMarker => "A marker not defined by the marker set.",
+            Code::UnexpectedMarker => "A known marker where it cannot be used.",
+            Code::UnmatchedEndmarker => "An end marker with no matching start marker.",
+            Code::MissingEndmarker => "A span missing its end marker.",
+            Code::CrossingMilestones => {
+                "Milestone pairs that overlap without one nesting inside the other."
+            }
+            Code::InvalidAttribute => "An attribute that is malformed or not allowed.",
+            Code::InvalidNumber => "A chapter or verse number that is not a number.",
+            Code::UnknownBook => "An \\id code missing from the book registry.",
+            Code::OutOfBounds => "A chapter or verse past the end of its book or chapter.",
+            Code::OutOfOrder => "A verse numbered before the one preceding it.",
+            Code::MissingVerse => "A verse skipped in the numbering.",
+            Code::MissingChapter => "A chapter skipped in the numbering.",
+            Code::DuplicateNumber => "A chapter or verse number used twice.",
+            Code::OverlappingVerses => "A verse range covering a verse already given.",
+            Code::VerseBeforeChapter => "A verse before the first chapter.",
+            Code::UnmatchedQuote => {
+                "A quotation mark that does not open or close at the expected level."
+            }
+            Code::RepeatedWord => "A word repeated with nothing between.",
+            Code::UnmatchedBracket => "A bracket without its partner.",
+            Code::Capitalization => "A sentence starting with a lower case letter.",
+            Code::EncodingMismatch => {
+                "An \\ide encoding that is unknown or not the one the file is in."
+            }
+            Code::Deprecated => "A construct from before USFM 3 with a USFM 3 replacement.",
+            Code::Syntax => "Any other malformed input.",
+        }
+    }
+}
+
+impl Display for Code {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A change to the source repairing what a diagnostic reports: the text
+/// in `span` replaced by `replacement`, an empty span being an insertion.
+/// See [`Document::apply_fixes`](crate::document::Document::apply_fixes).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Fix {
+    pub span: Span,
+    pub replacement: String,
+}
+
+impl Display for Fix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.span.start == self.span.end {
+            true => write!(f, "insert {:?}", self.replacement),
+            false => write!(f, "replace with {:?}", self.replacement),
+        }
+    }
+}
+
+/// A problem found while parsing, located in the source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Diagnostic {
+    pub code: Code,
+    pub severity: Severity,
+    pub span: Span,
+    pub message: String,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub fix: Option<Fix>,
+}
+
+impl Diagnostic {
+    pub fn error(code: Code, span: Span, message: impl Into<String>) -> Self {
+        Diagnostic {
+            code,
+            severity: Severity::Error,
+            span,
+            message: message.into(),
+            fix: None,
+        }
+    }
+
+    /// Suggest replacing `span` of the source with `replacement`.
+    pub fn with_fix(self, span: Span, replacement: impl Into<String>) -> Self {
+        Diagnostic {
+            fix: Some(Fix {
+                span,
+                replacement: replacement.into(),
+            }),
+            ..self
+        }
+    }
+
+    pub fn warning(code: Code, span: Span, message: impl Into<String>) -> Self {
+        Diagnostic {
+            severity: Severity::Warning,
+            ..Diagnostic::error(code, span, message)
+        }
+    }
+
+    pub fn info(code: Code, span: Span, message: impl Into<String>) -> Self {
+        Diagnostic {
+            severity: Severity::Info,
+            ..Diagnostic::error(code, span, message)
+        }
+    }
+
+    /// Show the diagnostic with the offending source line and a caret
+    /// under the span.
+    pub fn render<'d>(&'d self, source: &'d str) -> Render<'d> {
+        Render {
+            diagnostic: self,
+            source,
+        }
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let start = self.span.start;
+        write!(
+            f,
+            "{}:{}: {}[{}]: {}",
+            start.line, start.column, self.severity, self.code, self.message
+        )
+    }
+}
+
+impl Error for Diagnostic {}
+
+pub struct Render<'d> {
+    diagnostic: &'d Diagnostic,
+    source: &'d str,
+}
+
+impl Display for Render<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Diagnostic {
+            code,
+            severity,
+            span,
+            message,
+            fix,
+        } = self.diagnostic;
+        let (start, end) = (span.start, span.end);
+        let text = self.source.lines().nth(start.line - 1).unwrap_or_default();
+        let text = text.trim_end_matches('\r');
+        let width = start.line.to_string().len();
+        let indent = text
+            .chars()
+            .take(start.column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect::<String>();
+        let length = if end.line == start.line {
+            end.column.saturating_sub(start.column)
+        } else {
+            text.chars().count().saturating_sub(start.column - 1)
+        };
+
+        writeln!(f, "{severity}[{code}]: {message}")?;
+        writeln!(f, "{:width$}--> {}:{}", "", start.line, start.column)?;
+        writeln!(f, "{:width$} |", "")?;
+        writeln!(f, "{} | {text}", start.line)?;
+        writeln!(f, "{:width$} | {indent}{}", "", "^".repeat(length.max(1)))?;
+        match fix {
+            Some(fix) => writeln!(f, "{:width$} = fix: {fix}", ""),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Code, Diagnostic};
+    use crate::document::{Position, Span};
+
+    #[test]
+    fn render() {
+        let source = "\\id MRK\n\\c 1\n\\p \\v 1 In\\xyz the beginning\n";
+        let span = Span {
+            start: Position {
+                offset: 23,
+                line: 3,
+                column: 11,
+            },
+            end: Position {
+                offset: 27,
+                line: 3,
+                column: 15,
+            },
+        };
+        let diagnostic = Diagnostic::error(Code::UnknownMarker, span, "unknown marker \\xyz");
+        assert_eq!(
+            diagnostic.to_string(),
+            "3:11: error[unknown-marker]: unknown marker \\xyz"
+        );
+        assert_eq!(
+            diagnostic.render(source).to_string(),
+            "error[unknown-marker]: unknown marker \\xyz\n \
+             --> 3:11\n  \
+               |\n\
+             3 | \\p \\v 1 In\\xyz the beginning\n  \
+               |           ^^^^\n"
+        );
+        let fix = Span {
+            start: span.end,
+            ..span
+        };
+        let diagnostic = diagnostic.with_fix(fix, " ");
+        assert!(diagnostic
+            .render(source)
+            .to_string()
+            .ends_with("^^^^\n  = fix: insert \" \"\n"));
+    }
+}
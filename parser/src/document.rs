@@ -1,193 +1,1934 @@
 #![allow(dead_code)]
-use std::{collections::HashMap, fs::File, io, path::Path, sync::OnceLock};
+use std::{
+    borrow::Cow,
+    cell::RefCell,
+    collections::HashMap,
+    fs::File,
+    io::{self, Read},
+    ops::Range,
+    path::Path,
+    str::FromStr,
+    sync::Arc,
+};
 
 use nom::{
     branch::alt,
-    bytes::complete::{tag, take},
-    combinator::{cut, opt, value, verify},
-    error::make_error,
-    multi::many0,
+    bytes::complete::{is_not, tag, take, take_while1},
+    character::complete::{char, digit1, none_of},
+    combinator::{cut, eof, map_opt, opt, peek, recognize, value, verify},
+    error::{context, make_error, ContextError, VerboseError, VerboseErrorKind},
+    multi::{many0, many1, separated_list1},
     number::complete::float,
     sequence::{delimited, terminated},
-    AsChar, Err, Parser,
+    Err, Parser,
 };
 
 use crate::{
-    extension::{Category, Extensions},
-    terminal::{self, line_ending1, marker},
+    books::{self, Book},
+    diagnostic::{Code, Diagnostic},
+    extension::{Category, Extensions, Version},
+    terminal::{self, attrib::Attributes, marker},
 };
 
 use super::Result;
 
-#[derive(Debug, Default)]
-struct Rope {
-    segments: String,
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct Rope<'i> {
+    segments: Cow<'i, str>,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Document<'i> {
+    source: Rope<'i>,
+    pub(crate) nodes: Option<Node<'i>>,
+    /// The encoding of the bytes the document was read from, see
+    /// [`Document::from_bytes`].
+    #[cfg(feature = "encoding_rs")]
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) encoding: Option<&'static encoding_rs::Encoding>,
+    /// Set by [`Document::parse_fragment`].
+    #[cfg_attr(feature = "serde", serde(skip))]
+    fragment: Option<FragmentContext>,
+    /// The marker set the source was parsed with, unless it was a bundled
+    /// one, and the options, so that an edited source parses the same way.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    markers: Option<Arc<Extensions>>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    options: ParseOptions,
+}
+
+/// What a fragment is assumed to be part of, see [`Document::parse_fragment`].
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct FragmentContext {
+    pub book: &'static Book,
+    /// The chapter of any text before the fragment's first `\c`.
+    pub chapter: Option<u32>,
+}
+
+impl FromStr for Document<'static> {
+    type Err = io::Error;
+
+    #[inline]
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        State::new().parse(s).map(Document::into_owned)
+    }
+}
+
+/// What the parser does with a marker missing from its [`Extensions`].
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum UnknownMarkers {
+    /// Fail to parse, the default.
+    #[default]
+    Reject,
+    /// Treat a marker as a milestone when it is named or closed like one,
+    /// otherwise as [`Category::OtherPara`] when it starts a line and as
+    /// [`Category::Char`] when it does not. This is always done for `\z` markers.
+    Heuristic,
+    /// Keep the marker as a [`Content::Unknown`] node. One starting a line
+    /// holds the rest of the paragraph, otherwise it only holds content when
+    /// it has a matching end marker.
+    Preserve,
+}
+
+/// What the parser keeps of runs of whitespace.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum WhitespaceHandling {
+    /// Whitespace with a line break between two markers becomes a single
+    /// space while text keeps its own, the default.
+    #[default]
+    UsfmDefault,
+    /// Every run of whitespace in text, line breaks included, becomes a
+    /// single space as well.
+    ReduceToSingle,
+    /// Whitespace between markers is kept as written, for poetry spaced on
+    /// purpose and for round trips.
+    Preserve,
+}
+
+/// A line break as written in a source file.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    CrLf,
+    /// A lone `\r`, as written by classic Mac OS tools.
+    Cr,
+}
+
+impl LineEnding {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+            LineEnding::Cr => "\r",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct ParseOptions {
+    pub unknown_markers: UnknownMarkers,
+    pub whitespace: WhitespaceHandling,
+    /// Only accept `\n` and `\r\n` line endings where a line must end, so
+    /// a lone `\r` is an error. Otherwise all three are accepted, and each
+    /// is read as `\n` in the tree.
+    pub strict_line_endings: bool,
+    /// The USFM release assumed when there is no `\usfm` marker: 2.x by
+    /// default, as the specification has it. Texts that use USFM 3 markers
+    /// without declaring them need 3.0 here.
+    pub version: Version,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions {
+            unknown_markers: UnknownMarkers::default(),
+            whitespace: WhitespaceHandling::default(),
+            strict_line_endings: false,
+            version: Version::V2,
+        }
+    }
 }
 
-#[derive(Debug, Default)]
-pub struct Document {
-    source: Rope,
-    nodes: Option<Node>,
+impl<'i> Document<'i> {
+    pub(crate) fn source(&self) -> &str {
+        &self.source.segments
+    }
+
+    pub(crate) fn set_source(&mut self, source: String) {
+        self.source.segments = Cow::Owned(source);
+    }
+
+    /// Copy everything borrowed from the source, so the document can
+    /// outlive it.
+    pub fn into_owned(self) -> Document<'static> {
+        Document {
+            source: Rope {
+                segments: Cow::Owned(self.source.segments.into_owned()),
+            },
+            nodes: self.nodes.map(Node::into_owned),
+            #[cfg(feature = "encoding_rs")]
+            encoding: self.encoding,
+            fragment: self.fragment,
+            markers: self.markers,
+            options: self.options,
+        }
+    }
+
+    /// A parser set up as the one that parsed the document, to parse its
+    /// source again after an edit.
+    pub(crate) fn parser<'s>(&self) -> State<'s> {
+        let state = match &self.markers {
+            Some(markers) => State::from_markers(Arc::clone(markers)),
+            None => State::new(),
+        };
+        State {
+            options: self.options,
+            ..state
+        }
+    }
+
+    /// The document's own marker set, or the bundled one.
+    pub(crate) fn markers(&self) -> &Extensions {
+        self.markers.as_deref().unwrap_or(State::usfm_ext())
+    }
+
+    /// The line ending used most in the source, which the writer uses
+    /// unless told otherwise. `\n` for a document with no source.
+    pub fn line_ending(&self) -> LineEnding {
+        let source = self.source().as_bytes();
+        let (mut lf, mut crlf, mut cr) = (0, 0, 0);
+        for (n, &b) in source.iter().enumerate() {
+            match b {
+                b'\n' if n > 0 && source[n - 1] == b'\r' => crlf += 1,
+                b'\n' => lf += 1,
+                b'\r' if source.get(n + 1) != Some(&b'\n') => cr += 1,
+                _ => {}
+            }
+        }
+        if crlf > lf && crlf >= cr {
+            LineEnding::CrLf
+        } else if cr > lf && cr > crlf {
+            LineEnding::Cr
+        } else {
+            LineEnding::Lf
+        }
+    }
+
+    pub fn book(&self) -> Option<&'static Book> {
+        self.id()?.book()
+    }
+
+    /// The first paragraph of the book with the given marker, for header
+    /// markers such as `\ide` and `\h` that appear before the first chapter.
+    pub(crate) fn header(&self, style: &str) -> Option<&Node<'i>> {
+        self.nodes
+            .as_ref()?
+            .content
+            .iter()
+            .take_while(|item| !matches!(item, Content::Chapter(_)))
+            .find_map(|item| match item {
+                Content::Para(node) if node.style == style => Some(node),
+                _ => None,
+            })
+    }
+
+    /// The trimmed text of [`Document::header`], `None` when it is empty.
+    pub(crate) fn header_text(&self, style: &str) -> Option<String> {
+        let text = self.header(style)?.text();
+        let text = text.trim();
+        (!text.is_empty()).then(|| text.to_owned())
+    }
+
+    fn id(&self) -> Option<&Node<'i>> {
+        self.nodes
+            .as_ref()?
+            .content
+            .iter()
+            .find_map(|item| match item {
+                Content::Book(node) => Some(node),
+                _ => None,
+            })
+    }
+
+    /// The book code given by `\id`, such as `MRK`.
+    pub fn book_code(&self) -> Option<&str> {
+        self.id()?.attributes.get("code").map(|code| code.as_ref())
+    }
+
+    /// The rest of the `\id` line after the book code, often the name of
+    /// the translation or a file note.
+    pub fn id_line(&self) -> Option<String> {
+        let text = self.id()?.text();
+        let text = text.trim();
+        (!text.is_empty()).then(|| text.to_owned())
+    }
+
+    /// The running header from `\h`, or `\h1` when there is no `\h`.
+    pub fn running_header(&self) -> Option<String> {
+        self.header_text("h").or_else(|| self.header_text("h1"))
+    }
+
+    /// The text of every `\rem` remark, in document order.
+    pub fn remarks(&self) -> Vec<String> {
+        self.iter_paras()
+            .filter(|node| node.style == "rem")
+            .map(|node| node.text().trim().to_owned())
+            .collect()
+    }
+
+    /// The character encoding declared by `\ide`, such as `UTF-8` or
+    /// `CP-1252`, as written.
+    pub fn ide(&self) -> Option<String> {
+        self.header_text("ide")
+    }
+
+    #[inline]
+    pub fn from_reader<R: Read>(reader: R) -> io::Result<Document<'static>> {
+        State::new()
+            .parse(&io::read_to_string(reader)?)
+            .map(Document::into_owned)
+    }
+
+    /// [`Document::from_reader`] for a reader read asynchronously, such as
+    /// the body of a response. The tree is built once the input has all
+    /// arrived; to act on it while it is still downloading use
+    /// [`AsyncEventReader`](crate::events::AsyncEventReader).
+    #[cfg(feature = "tokio")]
+    pub async fn from_async_reader<R>(mut reader: R) -> io::Result<Document<'static>>
+    where
+        R: tokio::io::AsyncRead + Unpin,
+    {
+        use tokio::io::AsyncReadExt;
+
+        let mut source = String::new();
+        reader.read_to_string(&mut source).await?;
+        State::new().parse(&source).map(Document::into_owned)
+    }
+
+    /// Parse as much of a damaged file as possible, skipping to the next
+    /// paragraph marker after each error and reporting what was skipped.
+    #[inline]
+    pub fn from_str_lenient(s: &'i str) -> (Self, Vec<Diagnostic>) {
+        Self::from_str_lenient_with(s, ParseOptions::default())
+    }
+
+    /// The tree borrows its text from `s` wherever it can, use
+    /// [`Document::into_owned`] to keep it longer.
+    #[inline]
+    pub fn from_str_with(s: &'i str, options: ParseOptions) -> io::Result<Self> {
+        State {
+            options,
+            ..State::new()
+        }
+        .parse(s)
+    }
+
+    /// Parse using a marker set other than the bundled USFM 3 one, such as a
+    /// project's stylesheet loaded by [`Extensions::from_sty_reader`].
+    /// A marker set shared behind an [`Arc`] is not copied, so many books
+    /// can be parsed with one cheaply, even on several threads.
+    pub fn from_str_with_markers(
+        s: &'i str,
+        markers: impl Into<Arc<Extensions>>,
+        options: ParseOptions,
+    ) -> io::Result<Self> {
+        State {
+            options,
+            ..State::from_markers(markers.into())
+        }
+        .parse(s)
+    }
+
+    /// Parse a piece of a book that does not start with `\id`, such as a
+    /// few verses pasted into a tool. The tree gets an `\id` line for the
+    /// context's book and, when the context has a chapter, a `\c` holding
+    /// whatever comes before the fragment's own first chapter. Neither is
+    /// written back out, and checks leave alone what only a whole book
+    /// would have. Text starting with `\id` is parsed as a book.
+    #[inline]
+    pub fn parse_fragment(s: &'i str, context: FragmentContext) -> io::Result<Self> {
+        Self::parse_fragment_with(s, context, ParseOptions::default())
+    }
+
+    pub fn parse_fragment_with(
+        s: &'i str,
+        context: FragmentContext,
+        options: ParseOptions,
+    ) -> io::Result<Self> {
+        State {
+            options,
+            ..State::new()
+        }
+        .parse_fragment(s, context)
+    }
+
+    /// The context a fragment was parsed in, with a chapter only if some
+    /// of it came before a `\c`. `None` for a whole book.
+    pub fn fragment(&self) -> Option<&FragmentContext> {
+        self.fragment.as_ref()
+    }
+
+    pub fn is_fragment(&self) -> bool {
+        self.fragment.is_some()
+    }
+
+    pub fn from_str_lenient_with(s: &'i str, options: ParseOptions) -> (Self, Vec<Diagnostic>) {
+        State {
+            options,
+            lenient: true,
+            ..State::new()
+        }
+        .parse_lenient(s)
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
-enum Content {
-    Text(String),
-    Para(Node),
-    Book(Node),
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Content<'i> {
+    Text(Text<'i>),
+    Para(Node<'i>),
+    Book(Node<'i>),
+    Note(Node<'i>),
+    Char(Node<'i>),
+    Milestone(Node<'i>),
+    Table(Node<'i>),
+    Row(Node<'i>),
+    Cell(Node<'i>),
+    List(Node<'i>),
+    Stanza(Node<'i>),
+    Figure(Node<'i>),
+    Periph(Node<'i>),
+    Sidebar(Node<'i>),
+    Chapter(Node<'i>),
+    Verse(Node<'i>),
+    /// A marker the parser did not recognise, see [`UnknownMarkers::Preserve`].
+    Unknown(Node<'i>),
     OptBreak,
+    /// `~`, a space that lines are not broken at.
+    NoBreakSpace,
 }
 
-impl<'s> Default for Content {
+impl Default for Content<'_> {
     fn default() -> Self {
         Content::Text(Default::default())
     }
 }
 
-impl<S: AsRef<str>> From<S> for Content {
-    fn from(value: S) -> Self {
-        Content::Text(value.as_ref().to_owned())
+impl<'i> From<&'i str> for Content<'i> {
+    fn from(value: &'i str) -> Self {
+        Content::Text(value.into())
     }
 }
 
-#[derive(Debug, Default, PartialEq, Eq, Clone)]
-struct Node {
-    style: String,
-    attributes: HashMap<String, String>,
-    content: Vec<Content>,
+impl From<String> for Content<'_> {
+    fn from(value: String) -> Self {
+        Content::Text(value.into())
+    }
 }
 
-struct State {
-    doc: Document,
-    markers: Extensions,
-    version: f32,
+impl<'i> From<Cow<'i, str>> for Content<'i> {
+    fn from(value: Cow<'i, str>) -> Self {
+        Content::Text(value.into())
+    }
 }
 
-impl<'i> State {
-    const USFM_SRC: &'static str = include_str!("../docs/grammar/usfm.ext");
+impl<'i> Content<'i> {
+    pub fn node(&self) -> Option<&Node<'i>> {
+        match self {
+            Content::Para(node)
+            | Content::Book(node)
+            | Content::Note(node)
+            | Content::Char(node)
+            | Content::Milestone(node)
+            | Content::Table(node)
+            | Content::Row(node)
+            | Content::Cell(node)
+            | Content::List(node)
+            | Content::Stanza(node)
+            | Content::Figure(node)
+            | Content::Periph(node)
+            | Content::Sidebar(node)
+            | Content::Chapter(node)
+            | Content::Verse(node)
+            | Content::Unknown(node) => Some(node),
+            Content::Text(_) | Content::OptBreak | Content::NoBreakSpace => None,
+        }
+    }
+
+    pub fn node_mut(&mut self) -> Option<&mut Node<'i>> {
+        match self {
+            Content::Para(node)
+            | Content::Book(node)
+            | Content::Note(node)
+            | Content::Char(node)
+            | Content::Milestone(node)
+            | Content::Table(node)
+            | Content::Row(node)
+            | Content::Cell(node)
+            | Content::List(node)
+            | Content::Stanza(node)
+            | Content::Figure(node)
+            | Content::Periph(node)
+            | Content::Sidebar(node)
+            | Content::Chapter(node)
+            | Content::Verse(node)
+            | Content::Unknown(node) => Some(node),
+            Content::Text(_) | Content::OptBreak | Content::NoBreakSpace => None,
+        }
+    }
 
-    fn usfm_ext() -> &'static Extensions {
-        static USFM_EXT: OnceLock<Extensions> = OnceLock::new();
-        USFM_EXT.get_or_init(|| {
-            let mut res: Extensions = Self::USFM_SRC.parse().expect("Parsing usfm.ext");
-            res.shrink_to_fit();
-            res
-        })
+    pub fn into_owned(self) -> Content<'static> {
+        match self {
+            Content::Text(text) => Content::Text(text.into_owned()),
+            Content::Para(node) => Content::Para(node.into_owned()),
+            Content::Book(node) => Content::Book(node.into_owned()),
+            Content::Note(node) => Content::Note(node.into_owned()),
+            Content::Char(node) => Content::Char(node.into_owned()),
+            Content::Milestone(node) => Content::Milestone(node.into_owned()),
+            Content::Table(node) => Content::Table(node.into_owned()),
+            Content::Row(node) => Content::Row(node.into_owned()),
+            Content::Cell(node) => Content::Cell(node.into_owned()),
+            Content::List(node) => Content::List(node.into_owned()),
+            Content::Stanza(node) => Content::Stanza(node.into_owned()),
+            Content::Figure(node) => Content::Figure(node.into_owned()),
+            Content::Periph(node) => Content::Periph(node.into_owned()),
+            Content::Sidebar(node) => Content::Sidebar(node.into_owned()),
+            Content::Chapter(node) => Content::Chapter(node.into_owned()),
+            Content::Verse(node) => Content::Verse(node.into_owned()),
+            Content::Unknown(node) => Content::Unknown(node.into_owned()),
+            Content::OptBreak => Content::OptBreak,
+            Content::NoBreakSpace => Content::NoBreakSpace,
+        }
+    }
+}
+
+/// Nodes compare equal when their content does, wherever in the source
+/// they were parsed from.
+#[derive(Debug, Default, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Node<'i> {
+    pub style: Cow<'i, str>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "HashMap::is_empty")
+    )]
+    pub attributes: HashMap<Cow<'i, str>, Cow<'i, str>>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub content: Vec<Content<'i>>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "std::ops::Not::not")
+    )]
+    pub nested: bool,
+    /// A user defined `\z` marker.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "std::ops::Not::not")
+    )]
+    pub custom: bool,
+    /// Location of the node in the source, recorded when parsing USFM.
+    /// Writers reproduce a node with a span verbatim, so clear it on edit.
+    /// It is left out when comparing nodes.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub span: Option<Span>,
+}
+
+impl PartialEq for Node<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.style == other.style
+            && self.attributes == other.attributes
+            && self.content == other.content
+            && self.nested == other.nested
+            && self.custom == other.custom
+    }
+}
+
+/// Like nodes, text compares equal whatever its span.
+#[derive(Debug, Default, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Text<'i> {
+    pub text: Cow<'i, str>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub span: Option<Span>,
+}
+
+impl PartialEq for Text<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.text == other.text
+    }
+}
+
+impl Text<'_> {
+    #[inline]
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    pub fn into_owned(self) -> Text<'static> {
+        Text {
+            text: Cow::Owned(self.text.into_owned()),
+            span: self.span,
+        }
+    }
+}
+
+impl AsRef<str> for Text<'_> {
+    #[inline]
+    fn as_ref(&self) -> &str {
+        &self.text
+    }
+}
+
+impl<'i> From<&'i str> for Text<'i> {
+    fn from(value: &'i str) -> Self {
+        Cow::Borrowed(value).into()
+    }
+}
+
+impl From<String> for Text<'_> {
+    fn from(value: String) -> Self {
+        Cow::<str>::Owned(value).into()
+    }
+}
+
+impl<'i> From<Cow<'i, str>> for Text<'i> {
+    fn from(text: Cow<'i, str>) -> Self {
+        Text { text, span: None }
+    }
+}
+
+/// A point in the source: byte offset plus 1-based line and column, with
+/// columns counted in characters.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Position {
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Span {
+    #[inline]
+    pub fn range(&self) -> Range<usize> {
+        self.start.offset..self.end.offset
+    }
+}
+
+impl Node<'_> {
+    pub fn into_owned(self) -> Node<'static> {
+        let owned = |s: Cow<str>| Cow::Owned(s.into_owned());
+        Node {
+            style: owned(self.style),
+            attributes: self
+                .attributes
+                .into_iter()
+                .map(|(name, value)| (owned(name), owned(value)))
+                .collect(),
+            content: self.content.into_iter().map(Content::into_owned).collect(),
+            nested: self.nested,
+            custom: self.custom,
+            span: self.span,
+        }
+    }
+
+    /// The book identified by an `id` node.
+    pub fn book(&self) -> Option<&'static Book> {
+        match self.style.as_ref() {
+            "id" => books::get(self.attributes.get("code")?),
+            _ => None,
+        }
+    }
+
+    pub fn text(&self) -> String {
+        let mut res = String::new();
+        for item in &self.content {
+            match item {
+                Content::Text(text) => res.push_str(text.as_str()),
+                Content::NoBreakSpace => res.push('\u{a0}'),
+                item => res.extend(item.node().map(Node::text)),
+            }
+        }
+        res
+    }
+
+    /// Pairs each ruby base with its gloss. Colon separated glosses are
+    /// distributed over the base characters when the counts match, otherwise
+    /// the whole gloss annotates the whole base text.
+    pub fn ruby(&self) -> Option<Vec<(String, String)>> {
+        let gloss = self
+            .attributes
+            .get("gloss")
+            .filter(|_| self.style == "rb")?;
+        let base = self.text();
+        let glosses = gloss.split(':').collect::<Vec<_>>();
+        if glosses.len() > 1 && glosses.len() == base.chars().count() {
+            Some(
+                base.chars()
+                    .zip(glosses)
+                    .map(|(b, g)| (b.to_string(), g.to_owned()))
+                    .collect(),
+            )
+        } else {
+            Some(vec![(base, glosses.concat())])
+        }
+    }
+}
+
+pub(crate) struct State<'i> {
+    source: &'i str,
+    /// Shared with every other parser using the same set until changed,
+    /// see [`State::markers_mut`].
+    pub(crate) markers: Arc<Extensions>,
+    version: f32,
+    options: ParseOptions,
+    lenient: bool,
+    diagnostics: RefCell<Vec<Diagnostic>>,
+    len: usize,
+    lines: Vec<usize>,
+    /// Whether `markers` is still a bundled set, which the `\usfm` marker
+    /// may switch for another version.
+    bundled: bool,
+}
+
+impl<'i> State<'i> {
+    #[inline]
+    pub(crate) fn usfm_ext() -> &'static Extensions {
+        Extensions::usfm(Version::default())
     }
 
     pub fn new() -> Self {
         State {
-            doc: Document::default(),
-            markers: Self::usfm_ext().clone(),
+            source: "",
+            markers: Arc::clone(Extensions::usfm_shared(Version::default())),
             version: 3.0,
+            options: ParseOptions::default(),
+            lenient: false,
+            diagnostics: RefCell::default(),
+            len: 0,
+            lines: Vec::new(),
+            bundled: true,
+        }
+    }
+
+    pub(crate) fn from_markers(markers: Arc<Extensions>) -> Self {
+        State {
+            markers,
+            bundled: false,
+            ..State::new()
+        }
+    }
+
+    /// The bundled marker set for a release, as `\usfm` would select it.
+    pub(crate) fn for_version(version: Version) -> Self {
+        State {
+            markers: Arc::clone(Extensions::usfm_shared(version)),
+            ..State::new()
         }
     }
 
     pub fn with_markers<P: AsRef<Path>>(path: P) -> io::Result<Self> {
         let mut doc = Self::new();
-        doc.markers = doc.markers.update_from_reader(File::open(path.as_ref())?)?;
+        let markers = std::mem::take(doc.markers_mut());
+        *doc.markers_mut() = markers.update_from_reader(File::open(path.as_ref())?)?;
         Ok(doc)
     }
 
-    fn text(input: &str) -> Result<Content> {
-        terminal::text
-            .map(|s| Content::Text(s.to_owned()))
+    /// The marker set to change, copied first if it is shared.
+    pub(crate) fn markers_mut(&mut self) -> &mut Extensions {
+        self.bundled = false;
+        Arc::make_mut(&mut self.markers)
+    }
+
+    // Every remaining input is a suffix of the source, so its length is
+    // enough to recover where in the source a parser started or stopped.
+    fn position(&self, rest: &str) -> Position {
+        let offset = self.len - rest.len();
+        let line = self.lines.partition_point(|&start| start <= offset);
+        let start = self.lines[line - 1];
+        let column = self.source[start..offset].chars().count() + 1;
+        Position {
+            offset,
+            line,
+            column,
+        }
+    }
+
+    pub(crate) fn position_at(&self, offset: usize) -> Position {
+        self.position(&self.source[offset..])
+    }
+
+    fn locate(&self, content: &mut Content, input: &str, rest: &str) {
+        // Parsers tried on their own have no source to be located in.
+        if self.lines.is_empty() {
+            return;
+        }
+        let span = Span {
+            start: self.position(input),
+            end: self.position(rest),
+        };
+        match content {
+            Content::Text(text) => text.span = Some(span),
+            content => {
+                if let Some(node) = content.node_mut() {
+                    node.span = Some(span);
+                }
+            }
+        }
+    }
+
+    fn located<'s, P>(
+        &'s self,
+        mut parser: P,
+    ) -> impl FnMut(&'i str) -> Result<'i, Content<'i>> + 's
+    where
+        P: Parser<&'i str, Content<'i>, VerboseError<&'i str>> + 's,
+    {
+        move |input| {
+            let (rest, mut content) = parser.parse(input)?;
+            self.locate(&mut content, input, rest);
+            Ok((rest, content))
+        }
+    }
+
+    fn text(input: &str) -> Result<'_, Content<'_>> {
+        terminal::text.map(Content::from).parse(input)
+    }
+
+    fn text1(input: &str) -> Result<'_, Content<'_>> {
+        verify(terminal::text, |s: &str| !s.is_empty())
+            .map(Content::from)
+            .parse(input)
+    }
+
+    fn attributed_text1(input: &str) -> Result<'_, Content<'_>> {
+        verify(terminal::attributed_text, |s: &str| !s.is_empty())
+            .map(Content::from)
             .parse(input)
     }
 
-    fn para_text(input: &str) -> Result<Content> {
+    fn para_text(input: &str) -> Result<'_, Content<'_>> {
         terminal::text
-            .map(|s| Content::Text(s.trim_ascii_end().to_owned()))
+            .map(|s| Content::from(s.trim_ascii_end()))
             .parse(input)
     }
 
-    fn optbreak(input: &str) -> Result<Content> {
-        value(Content::OptBreak, tag("\\")).parse(input)
+    fn optbreak(input: &str) -> Result<'_, Content<'_>> {
+        value(Content::OptBreak, tag("//")).parse(input)
     }
 
-    fn identification(&mut self, input: &'i str) -> Result<'i, Content> {
-        let code = terminated(
-            verify(take(3usize), |s: &str| {
-                let (a, b) = s.chars().fold((0u8, 0u8), |(a, b), c| {
-                    (a + c.is_ascii_uppercase() as u8, b + c.is_dec_digit() as u8)
-                });
-                a + b <= 3
-            }),
-            terminal::space1,
+    fn identification(&mut self, input: &'i str) -> Result<'i, Content<'i>> {
+        let strict = self.options.strict_line_endings;
+        let code = context(
+            "book code",
+            terminated(
+                verify(take(3usize), |s: &str| books::get(s).is_some()),
+                terminal::space1.or(peek(terminal::line_break(strict))),
+            ),
         );
 
         let (input, _) = terminal::bom(input)?;
-        let (input, (code, text)) =
-            delimited(marker::tag("id"), code.and(opt(Self::text)), line_ending1).parse(input)?;
+        let (input, (code, text)) = delimited(
+            marker::tag("id"),
+            code.and(opt(self.located(Self::text1))),
+            terminal::line_breaks(strict),
+        )
+        .parse(input)?;
 
-        let (input, version) =
-            opt(delimited(marker::tag("usfm"), cut(float), line_ending1)).parse(input)?;
+        let (input, version) = opt(delimited(
+            marker::tag("usfm"),
+            cut(float),
+            terminal::line_breaks(strict),
+        ))
+        .parse(input)?;
 
         if let Some(version) = version {
             self.version = version;
         }
+        let release = version.map_or(self.options.version, Version::from_number);
+        if self.bundled && release != Version::default() {
+            self.markers = Arc::clone(Extensions::usfm_shared(release));
+        }
 
         let content = text.as_slice().into();
         Ok((
             input,
             Content::Book(Node {
                 style: "id".into(),
-                attributes: [("code".into(), code.to_owned())].into(),
+                attributes: [("code".into(), code.into())].into(),
                 content,
+                ..Node::default()
             }),
         ))
     }
 
     fn marker(&self, cat: Category) -> impl Fn(&str) -> Result<&str> + '_ {
+        self.categorised(terminal::marker, cat)
+    }
+
+    fn nested_marker(&self, cat: Category) -> impl Fn(&str) -> Result<&str> + '_ {
+        self.categorised(terminal::nested_marker, cat)
+    }
+
+    fn categorised<F>(&self, parser: F, cat: Category) -> impl Fn(&str) -> Result<&str> + '_
+    where
+        F: Fn(&str) -> Result<&str> + 'static,
+    {
+        move |start| {
+            let (input, style) = parser(start)?;
+            match self.markers.category(style) {
+                Some(category) if category == cat => Ok((input, style)),
+                None if self.implied(start, input, style) == Some(cat) => Ok((input, style)),
+                _ => Err(Err::Error(make_error(input, nom::error::ErrorKind::Tag))),
+            }
+        }
+    }
+
+    // Cell markers spanning columns, such as tc1-2, are only listed by
+    // their first column.
+    fn is_known(&self, style: &str) -> bool {
+        self.markers.contains(style)
+            || style
+                .split_once('-')
+                .is_some_and(|(base, _)| self.markers.category(base) == Some(Category::Cell))
+    }
+
+    fn at_line_start(&self, input: &str) -> bool {
+        let offset = self.len.saturating_sub(input.len());
+        offset == 0 || self.source[..offset].ends_with(['\n', '\r'])
+    }
+
+    /// The category given to a marker missing from the marker set, which is
+    /// guessed from its form and position for `\z` markers and under
+    /// [`UnknownMarkers::Heuristic`].
+    fn implied(&self, start: &str, rest: &str, style: &str) -> Option<Category> {
+        let guess = is_custom(style) || self.options.unknown_markers == UnknownMarkers::Heuristic;
+        if !guess || self.is_known(style) {
+            None
+        } else if style.ends_with("-s") || style.ends_with("-e") || rest.starts_with("\\*") {
+            Some(Category::Milestone)
+        } else if self.at_line_start(start) {
+            Some(Category::OtherPara)
+        } else {
+            Some(Category::Char)
+        }
+    }
+
+    fn unknown_marker(&self, line_start: bool) -> impl Fn(&'i str) -> Result<'i, &'i str> + '_ {
         move |input| {
-            let (input, style) = terminal::marker(input)?;
-            match self.markers.get(style) {
-                Some(marker) if marker.category == cat => Ok((input, style)),
-                Some(_) => Err(Err::Error(make_error(input, nom::error::ErrorKind::Tag))),
-                None => Err(Err::Error(make_error(input, nom::error::ErrorKind::Tag))),
+            let (rest, style) = terminal::marker(input)?;
+            match self.options.unknown_markers == UnknownMarkers::Preserve
+                && !self.is_known(style)
+                && !is_custom(style)
+                && self.at_line_start(input) == line_start
+            {
+                true => Ok((rest, style)),
+                false => Err(Err::Error(make_error(input, nom::error::ErrorKind::Tag))),
             }
         }
     }
 
-    fn headers(&self, input: &'i str) -> Result<'i, Vec<Content>> {
-        let marker = alt((
-            self.marker(Category::Header),
-            marker::tag("rem"),
-            marker::tag("sts"),
-        ));
-        let header = terminated(marker.and(Self::para_text), line_ending1).map(|(style, text)| {
-            Content::Para(Node {
+    fn unknown(&self, input: &'i str) -> Result<'i, Content<'i>> {
+        let (input, style) = self.unknown_marker(false)(input)?;
+        let content = terminated(
+            many0(alt((
+                |i| self.char_span(i),
+                |i| self.milestone(i),
+                self.located(Self::text1),
+            ))),
+            terminal::endmarker(style),
+        );
+        let (input, content) = opt(content).parse(input)?;
+        Ok((
+            input,
+            Content::Unknown(Node {
                 style: style.into(),
-                content: vec![text],
+                content: content.unwrap_or_default(),
                 ..Node::default()
-            })
-        });
-        many0(header).parse(input)
+            }),
+        ))
     }
 
-    fn titles(&self, input: &'i str) -> Result<'i, Vec<Content>> {
-        let marker = self.marker(Category::Title).or(marker::tag("rem"));
-        let content = alt((Self::text, Self::optbreak));
-        let title = terminated(marker.and(content), line_ending1).map(|(style, rest)| {
-            Content::Para(Node {
+    fn unknown_para(&self, input: &'i str) -> Result<'i, Content<'i>> {
+        match self.para_with(self.unknown_marker(true))(input)? {
+            (input, Content::Para(node)) => Ok((input, Content::Unknown(node))),
+            res => Ok(res),
+        }
+    }
+
+    fn note(&self, input: &'i str) -> Result<'i, Content<'i>> {
+        let (input, style) = alt((
+            self.marker(Category::Footnote),
+            self.marker(Category::Crossreference),
+        ))
+        .parse(input)?;
+        let chars = match self.markers.category(style) {
+            Some(Category::Crossreference) => Category::CrossreferenceChar,
+            _ => Category::FootnoteChar,
+        };
+        let caller = terminated(recognize(none_of(" \t\r\n\\")), terminal::space1);
+
+        let (input, ((caller, category), content)) = cut(terminated(
+            caller.and(opt(Self::category)).and(many0(alt((
+                self.character(chars),
+                |i| self.char_span(i),
+                self.located(Self::text1),
+            )))),
+            terminal::endmarker(style),
+        ))
+        .parse(input)?;
+
+        Ok((
+            input,
+            Content::Note(Node {
                 style: style.into(),
-                content: vec![rest],
+                attributes: [("caller".into(), caller.into())]
+                    .into_iter()
+                    .chain(category.map(|c| ("category".into(), c.into())))
+                    .collect(),
+                content,
                 ..Node::default()
-            })
+            }),
+        ))
+    }
+
+    fn end_marker<'m>(
+        &'m self,
+        style: &'m str,
+        nested: bool,
+    ) -> impl Fn(&'i str) -> Result<'i, &'i str> + 'm {
+        move |input| match self.markers.closed_by(style) {
+            Some(closer) => terminal::marker::tag(closer)(input),
+            None if nested => terminal::nested_endmarker(style)(input),
+            None => terminal::endmarker(style)(input),
+        }
+    }
+
+    fn character(&self, cat: Category) -> impl Fn(&'i str) -> Result<'i, Content<'i>> + '_ {
+        move |input| self.character_at(cat, 0, input)
+    }
+
+    fn character_at(&self, cat: Category, depth: usize, start: &'i str) -> Result<'i, Content<'i>> {
+        let input = start;
+        let (input, (nested, style)) = if depth > 0 {
+            alt((
+                self.nested_marker(cat).map(|style| (true, style)),
+                self.marker(cat).map(|style| (false, style)),
+            ))
+            .parse(input)?
+        } else {
+            self.marker(cat).map(|style| (false, style)).parse(input)?
+        };
+        let (input, content) = many0(alt((
+            |i| self.character_at(Category::Char, depth + 1, i),
+            |i| self.unknown(i),
+            self.located(Self::attributed_text1),
+        )))
+        .parse(input)?;
+        // Text in the span stops at a `|`, so one here must start attributes.
+        let (input, attributes) = match input.starts_with('|') {
+            true => self.attributes(style)(input)
+                .map(|(input, attributes)| (input, Some(attributes)))
+                .map_err(|e| match e {
+                    Err::Error(_) => Err::Failure(VerboseError {
+                        errors: vec![(input, VerboseErrorKind::Context("attributes"))],
+                    }),
+                    e => e,
+                })?,
+            false => (input, None),
+        };
+        let (input, _) = opt(self.end_marker(style, nested)).parse(input)?;
+        let mut span = Content::Char(Node {
+            style: style.into(),
+            attributes: attributes.unwrap_or_default(),
+            nested,
+            custom: is_custom(style),
+            content,
+            span: None,
         });
-        many0(title).parse(input)
+        self.locate(&mut span, start, input);
+        Ok((input, span))
     }
 
-    // fn get_subparser<'i, O, E>(&self, style: &str) -> impl nom::Parser<&str, O, E>
-    // where
-    //     E: ParseError<&str> + ContextError<&str>,
-    // {
-    //     // match self.markers.get(style)?.category
-    //     // {
-    //         // Cell => {},
-    //         // Char => {},
-    //         // Crossreference => {},
-    //         // CrossreferenceChar => {},
+    fn attributes<'m>(
+        &'m self,
+        style: &'m str,
+    ) -> impl Fn(&'i str) -> Result<'i, HashMap<Cow<'i, str>, Cow<'i, str>>> + 'm {
+        move |input| {
+            let (rest, attributes) = terminal::attrib::attributes(input)?;
+            let attributes = match attributes {
+                Attributes::Named(attribs) => attribs
+                    .into_iter()
+                    .map(|(k, v)| (k.into(), terminal::attrib::unescape(v)))
+                    .collect(),
+                Attributes::Default(value) => {
+                    let default = self.markers.get(style).and_then(|m| m.default.as_ref());
+                    let Some(name) = default else {
+                        return Err(Err::Failure(VerboseError::add_context(
+                            input,
+                            "default attribute",
+                            make_error(input, nom::error::ErrorKind::Verify),
+                        )));
+                    };
+                    [(name.clone().into(), terminal::attrib::unescape(value))].into()
+                }
+            };
+            Ok((rest, attributes))
+        }
+    }
+
+    fn char_span(&self, input: &'i str) -> Result<'i, Content<'i>> {
+        alt((
+            self.character(Category::Char),
+            self.character(Category::ListChar),
+            self.character(Category::IntroChar),
+            |i| self.unknown(i),
+        ))
+        .parse(input)
+    }
+
+    fn milestone(&self, input: &'i str) -> Result<'i, Content<'i>> {
+        let (input, style) = self.marker(Category::Milestone)(input)?;
+        let (input, attributes) = opt(self.attributes(style)).parse(input)?;
+        let (input, _) = cut(terminal::milestone_end).parse(input)?;
+        Ok((
+            input,
+            Content::Milestone(Node {
+                style: style.into(),
+                attributes: attributes.unwrap_or_default(),
+                custom: is_custom(style),
+                ..Node::default()
+            }),
+        ))
+    }
+
+    /// Give every start/end milestone pair a shared sid/eid, pairing unidentified
+    /// milestones by nesting order.
+    fn link_milestones(&self, content: &mut [Content]) {
+        let mut open = HashMap::new();
+        let mut count = 0usize;
+        self.link_milestones_in(content, &mut open, &mut count);
+    }
+
+    fn link_milestones_in(
+        &self,
+        content: &mut [Content],
+        open: &mut HashMap<String, Vec<String>>,
+        count: &mut usize,
+    ) {
+        for node in content.iter_mut().filter_map(Content::node_mut) {
+            // Stanzas, lists, tables and sidebars are not in the marker set
+            // but may hold milestones all the same.
+            let marker = self
+                .markers
+                .get(node.style.as_ref())
+                .filter(|marker| marker.category == Category::Milestone);
+            let Some(marker) = marker else {
+                self.link_milestones_in(&mut node.content, open, count);
+                continue;
+            };
+            if marker.closedby.is_some() {
+                let sid = node
+                    .attributes
+                    .entry("sid".into())
+                    .or_insert_with(|| {
+                        *count += 1;
+                        format!("{}-{count}", node.style.trim_end_matches("-s")).into()
+                    })
+                    .to_string();
+                open.entry(node.style.to_string()).or_default().push(sid);
+            } else if let Some(start) = &marker.closes {
+                let stack = open.entry(start.clone()).or_default();
+                match node.attributes.get("eid") {
+                    Some(eid) => stack.retain(|sid| sid.as_str() != eid.as_ref()),
+                    None => {
+                        if let Some(sid) = stack.pop() {
+                            node.attributes.insert("eid".into(), sid.into());
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn inline_item(&self, input: &'i str) -> Result<'i, Content<'i>> {
+        self.located(alt((
+            |i| self.note(i),
+            |i| self.char_span(i),
+            |i| self.milestone(i),
+            |i| self.figure(i),
+            |i| self.verse(i),
+            Self::text1,
+            Self::optbreak,
+        )))
+        .parse(input)
+    }
+
+    fn inline(&self, input: &'i str) -> Result<'i, Vec<Content<'i>>> {
+        let preserve = self.options.whitespace == WhitespaceHandling::Preserve;
+        let space = terminated(
+            terminal::inline_space(preserve),
+            peek(|i| self.inline_item(i)),
+        );
+        many0(alt((
+            |i| self.inline_item(i),
+            self.located(space.map(Content::from)),
+        )))
+        .map(|content| merge_runs(self.source, content))
+        .parse(input)
+    }
+
+    fn para(&self, cat: Category) -> impl Fn(&'i str) -> Result<'i, Content<'i>> + '_ {
+        self.para_with(self.marker(cat))
+    }
+
+    fn remark(&self, input: &'i str) -> Result<'i, Content<'i>> {
+        self.para_with(marker::tag("rem"))(input)
+    }
+
+    fn para_with<'m, M>(&'m self, marker: M) -> impl Fn(&'i str) -> Result<'i, Content<'i>> + 'm
+    where
+        M: Fn(&'i str) -> Result<'i, &'i str> + 'm,
+    {
+        move |input| {
+            let (input, style) = marker(input)?;
+            let (input, mut content) = self.inline(input)?;
+            let (input, _) = terminal::multispace0(input)?;
+            trim_end(&mut content);
+            Ok((
+                input,
+                Content::Para(Node {
+                    style: style.into(),
+                    custom: is_custom(style),
+                    content,
+                    ..Node::default()
+                }),
+            ))
+        }
+    }
+
+    fn list(&self, input: &'i str) -> Result<'i, Content<'i>> {
+        many1(self.located(self.para(Category::List)))
+            .map(|content| {
+                Content::List(Node {
+                    style: "list".into(),
+                    content,
+                    ..Node::default()
+                })
+            })
+            .parse(input)
+    }
+
+    fn poetic_line(&self, input: &'i str) -> Result<'i, Content<'i>> {
+        let marker = |input| match self.marker(Category::VersePara)(input)? {
+            (rest, style) if style.starts_with('q') => Ok((rest, style)),
+            _ => Err(Err::Error(make_error(input, nom::error::ErrorKind::Verify))),
+        };
+        let (input, mut line) = self.para_with(marker)(input)?;
+        if let Some(node) = line.node_mut() {
+            let level = self.level(&node.style).unwrap_or("1").to_owned();
+            node.attributes.insert("level".into(), level.into());
+        }
+        Ok((input, line))
+    }
+
+    fn heading(&self, input: &'i str) -> Result<'i, Content<'i>> {
+        let (input, mut heading) = self.para(Category::SectionPara)(input)?;
+        if let Some(node) = heading.node_mut() {
+            if let Some(level) = self.level(&node.style) {
+                let level = level.to_owned();
+                node.attributes.insert("level".into(), level.into());
+            }
+        }
+        Ok((input, heading))
+    }
+
+    pub(crate) fn level<'s>(&self, style: &'s str) -> Option<&'s str> {
+        let base = style.trim_end_matches(|c: char| c.is_ascii_digit());
+        match &style[base.len()..] {
+            "" if self.markers.contains(&format!("{base}1")) => Some("1"),
+            "" => None,
+            level => Some(level),
+        }
+    }
+
+    fn stanza(&self, input: &'i str) -> Result<'i, Content<'i>> {
+        many1(self.located(|i| self.poetic_line(i)))
+            .map(|content| {
+                Content::Stanza(Node {
+                    style: "stanza".into(),
+                    content,
+                    ..Node::default()
+                })
+            })
+            .parse(input)
+    }
+
+    fn table(&self, input: &'i str) -> Result<'i, Content<'i>> {
+        many1(self.located(|i| self.row(i)))
+            .map(|content| {
+                Content::Table(Node {
+                    style: "table".into(),
+                    content,
+                    ..Node::default()
+                })
+            })
+            .parse(input)
+    }
+
+    fn row(&self, input: &'i str) -> Result<'i, Content<'i>> {
+        delimited(
+            marker::tag("tr"),
+            many0(self.located(|i| self.cell(i))),
+            terminal::multispace0,
+        )
+        .map(|content| {
+            Content::Row(Node {
+                style: "tr".into(),
+                content,
+                ..Node::default()
+            })
+        })
+        .parse(input)
+    }
+
+    fn cell(&self, input: &'i str) -> Result<'i, Content<'i>> {
+        let (rest, style) = terminal::marker(input)?;
+        let (base, last) = style.split_once('-').unwrap_or((style, ""));
+        let column = |s: &str| {
+            s.trim_start_matches(char::is_alphabetic)
+                .parse::<usize>()
+                .ok()
+        };
+        let first = column(base);
+        let span = match last {
+            "" => Some(1),
+            last => first
+                .zip(last.parse::<usize>().ok())
+                .and_then(|(f, l)| l.checked_sub(f))
+                .map(|span| span + 1),
+        };
+        match (self.markers.get(base), span) {
+            (Some(marker), Some(span)) if marker.category == Category::Cell => {
+                let align = match base.trim_end_matches(|c: char| c.is_ascii_digit()) {
+                    "thc" | "tcc" => "center",
+                    "thr" | "tcr" => "end",
+                    _ => "start",
+                };
+                let mut attributes = HashMap::from([("align".into(), align.into())]);
+                if span > 1 {
+                    attributes.insert("colspan".into(), span.to_string().into());
+                }
+                let (rest, content) = self.inline(rest)?;
+                Ok((
+                    rest,
+                    Content::Cell(Node {
+                        style: base.into(),
+                        attributes,
+                        content,
+                        ..Node::default()
+                    }),
+                ))
+            }
+            _ => Err(Err::Error(make_error(input, nom::error::ErrorKind::Tag))),
+        }
+    }
+
+    fn figure(&self, input: &'i str) -> Result<'i, Content<'i>> {
+        const USFM2_FIELDS: [&str; 7] = ["alt", "src", "size", "loc", "copy", "", "ref"];
+
+        let usfm3 = map_opt(
+            terminal::attributed_text.and(terminal::attrib::attributes),
+            |(caption, attributes)| match attributes {
+                Attributes::Named(attributes) => Some((caption, attributes)),
+                Attributes::Default(_) => None,
+            },
+        );
+        let field = opt(is_not("|\\")).map(Option::unwrap_or_default);
+        let usfm2 = verify(separated_list1(char('|'), field), |fields: &Vec<&str>| {
+            fields.len() == USFM2_FIELDS.len()
+        })
+        .map(|fields| {
+            let caption = fields[5];
+            let attributes = USFM2_FIELDS.into_iter().zip(fields).collect();
+            (caption, attributes)
+        });
+
+        let (input, _) = marker::tag("fig")(input)?;
+        let (input, (caption, attributes)) = context(
+            "figure",
+            cut(terminated(alt((usfm3, usfm2)), terminal::endmarker("fig"))),
+        )
+        .parse(input)?;
+
+        let caption = caption.trim();
+        Ok((
+            input,
+            Content::Figure(Node {
+                style: "fig".into(),
+                attributes: attributes
+                    .into_iter()
+                    .filter(|(k, v): &(&str, &str)| !k.is_empty() && !v.trim().is_empty())
+                    .map(|(k, v)| (k.into(), terminal::attrib::unescape(v.trim())))
+                    .collect(),
+                content: (!caption.is_empty())
+                    .then(|| caption.into())
+                    .into_iter()
+                    .collect(),
+                ..Node::default()
+            }),
+        ))
+    }
+
+    fn headers(&self, input: &'i str) -> Result<'i, Vec<Content<'i>>> {
+        let marker = alt((
+            self.marker(Category::Header),
+            marker::tag("rem"),
+            marker::tag("sts"),
+        ));
+        let line_end = terminal::line_breaks(self.options.strict_line_endings);
+        let header =
+            terminated(marker.and(self.located(Self::para_text)), line_end).map(|(style, text)| {
+                Content::Para(Node {
+                    style: style.into(),
+                    content: vec![text],
+                    ..Node::default()
+                })
+            });
+        many0(self.located(header)).parse(input)
+    }
+
+    fn titles(&self, input: &'i str) -> Result<'i, Vec<Content<'i>>> {
+        many0(self.located(alt((self.para(Category::Title), |i| self.remark(i))))).parse(input)
+    }
+
+    fn introductions(&self, input: &'i str) -> Result<'i, Vec<Content<'i>>> {
+        many0(self.located(alt((self.para(Category::Introduction), |i| self.remark(i)))))
+            .parse(input)
+    }
+
+    fn verse(&self, input: &'i str) -> Result<'i, Content<'i>> {
+        let number = take_while1(|c: char| !c.is_whitespace() && c != '\\');
+        let (input, number) =
+            delimited(marker::tag("v"), number, terminal::multispace0).parse(input)?;
+        let (input, altnumber) = opt(Self::alternate("va")).parse(input)?;
+        let (input, pubnumber) = opt(Self::alternate("vp")).parse(input)?;
+        Ok((
+            input,
+            Content::Verse(Node {
+                style: "v".into(),
+                attributes: numbering(number, altnumber, pubnumber),
+                ..Node::default()
+            }),
+        ))
+    }
+
+    fn alternate(style: &str) -> impl Fn(&'i str) -> Result<'i, &'i str> + '_ {
+        move |input| {
+            delimited(
+                marker::tag(style),
+                is_not("\\").map(str::trim),
+                terminated(terminal::endmarker(style), terminal::multispace0),
+            )
+            .parse(input)
+        }
+    }
+
+    fn sidebar(&self, input: &'i str) -> Result<'i, Content<'i>> {
+        let (input, _) = marker::tag("esb")(input)?;
+        let (input, category) = opt(Self::category).parse(input)?;
+        let (input, content) = cut(terminated(
+            |i| self.blocks_in(i, &["c", "periph", "esbe"]),
+            terminated(marker::tag("esbe"), terminal::multispace0),
+        ))
+        .parse(input)?;
+        Ok((
+            input,
+            Content::Sidebar(Node {
+                style: "esb".into(),
+                attributes: category
+                    .map(|c| ("category".into(), c.into()))
+                    .into_iter()
+                    .collect(),
+                content,
+                ..Node::default()
+            }),
+        ))
+    }
+
+    fn category(input: &'i str) -> Result<'i, &'i str> {
+        delimited(
+            marker::tag("cat"),
+            is_not("\\").map(str::trim),
+            terminated(terminal::endmarker("cat"), terminal::multispace0),
+        )
+        .parse(input)
+    }
+
+    fn block(&self, input: &'i str) -> Result<'i, Content<'i>> {
+        self.located(alt((
+            |i| self.sidebar(i),
+            |i| self.table(i),
+            |i| self.list(i),
+            |i| self.stanza(i),
+            self.para(Category::VersePara),
+            |i| self.heading(i),
+            self.para(Category::OtherPara),
+            self.para(Category::Title),
+            self.para(Category::Introduction),
+            |i| self.unknown_para(i),
+        )))
+        .parse(input)
+    }
+
+    fn chapter(&self, input: &'i str) -> Result<'i, Content<'i>> {
+        let (input, number) = delimited(
+            marker::tag("c"),
+            cut(context("chapter number", digit1)),
+            terminal::multispace0,
+        )
+        .parse(input)?;
+        let (input, altnumber) = opt(Self::alternate("ca")).parse(input)?;
+        let published = is_not("\\\r\n").map(str::trim);
+        let (input, pubnumber) = opt(delimited(
+            marker::tag("cp"),
+            published,
+            terminal::multispace0,
+        ))
+        .parse(input)?;
+        let (input, content) = self.blocks(input)?;
+        Ok((
+            input,
+            Content::Chapter(Node {
+                style: "c".into(),
+                attributes: numbering(number, altnumber, pubnumber),
+                content,
+                ..Node::default()
+            }),
+        ))
+    }
+
+    fn periph(&self, input: &'i str) -> Result<'i, Content<'i>> {
+        let (input, _) = marker::tag("periph")(input)?;
+        let (input, (title, attributes)) =
+            cut(terminal::attributed_text.and(self.attributes("periph"))).parse(input)?;
+        let (input, content) = self.blocks(input)?;
+        let title = title.trim();
+        Ok((
+            input,
+            Content::Periph(Node {
+                style: "periph".into(),
+                attributes: (!title.is_empty())
+                    .then(|| ("alt".into(), title.into()))
+                    .into_iter()
+                    .chain(attributes)
+                    .collect(),
+                content,
+                ..Node::default()
+            }),
+        ))
+    }
+
+    fn blocks(&self, input: &'i str) -> Result<'i, Vec<Content<'i>>> {
+        self.blocks_in(input, &["c", "periph"])
+    }
+
+    /// Parse blocks up to one of the closing markers. When lenient, blocks
+    /// that fail to parse are skipped up to the next paragraph marker.
+    fn blocks_in(&self, mut input: &'i str, closers: &[&str]) -> Result<'i, Vec<Content<'i>>> {
+        let mut res = Vec::new();
+        loop {
+            match self.block(input) {
+                Ok((rest, block)) if rest.len() < input.len() => {
+                    res.push(block);
+                    input = rest;
+                }
+                Ok(_) => break,
+                Err(Err::Failure(e)) if !self.lenient => return Err(Err::Failure(e)),
+                Err(e) => {
+                    let closed = input.trim_start().is_empty()
+                        || terminal::marker(input).is_ok_and(|(_, name)| closers.contains(&name));
+                    if !self.lenient || closed {
+                        break;
+                    }
+                    input = self.recover(input, e, |i| self.is_block_start(i));
+                }
+            }
+        }
+        Ok((input, res))
+    }
+
+    /// Parse repeated sections such as chapters. When lenient, a section that
+    /// fails part way is skipped up to the next chapter or peripheral.
+    fn sections<P>(&self, mut input: &'i str, mut parser: P) -> Result<'i, Vec<Content<'i>>>
+    where
+        P: Parser<&'i str, Content<'i>, VerboseError<&'i str>>,
+    {
+        let mut res = Vec::new();
+        loop {
+            match parser.parse(input) {
+                Ok((rest, section)) => {
+                    res.push(section);
+                    input = rest;
+                }
+                Err(Err::Failure(e)) if self.lenient => {
+                    let section = |i: &str| matches!(terminal::marker(i), Ok((_, "c" | "periph")));
+                    input = self.recover(input, Err::Failure(e), section);
+                }
+                Err(Err::Error(_)) => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok((input, res))
+    }
+
+    fn is_block_start(&self, input: &str) -> bool {
+        let Ok((_, name)) = terminal::marker(input) else {
+            return false;
+        };
+        let unknown = !self.is_known(name)
+            && (is_custom(name) || self.options.unknown_markers != UnknownMarkers::Reject);
+        unknown
+            || matches!(name, "c" | "periph" | "esb" | "esbe" | "tr")
+            || self.markers.is_paragraph(name)
+    }
+
+    /// Record a diagnostic for `error` and skip to the next line for which
+    /// `stop` holds, or to the end of the input.
+    fn recover<S>(&self, input: &'i str, error: Err<VerboseError<&'i str>>, stop: S) -> &'i str
+    where
+        S: Fn(&str) -> bool,
+    {
+        let mut rest = &input[input.len()..];
+        let mut line = input;
+        while let Some(n) = line.find(['\n', '\r']) {
+            line = line[n + 1..].trim_start();
+            if stop(line) {
+                rest = line;
+                break;
+            }
+        }
+        let diagnostic = self.diagnose(input, &error, rest);
+        self.diagnostics.borrow_mut().push(diagnostic);
+        rest
+    }
+
+    /// Describe why parsing failed at `input`, spanning up to `rest`.
+    fn diagnose(
+        &self,
+        input: &'i str,
+        error: &Err<VerboseError<&'i str>>,
+        rest: &'i str,
+    ) -> Diagnostic {
+        let at = match error {
+            Err::Error(e) | Err::Failure(e) => e.errors.first().map_or(input, |(at, _)| *at),
+            Err::Incomplete(_) => input,
+        };
+        let at = match at.len() > input.len() || at.len() < rest.len() {
+            true => input,
+            false => at,
+        };
+        let end_marker = input
+            .strip_prefix('\\')
+            .map(|s| s.strip_prefix('+').unwrap_or(s))
+            .and_then(|s| terminal::name(s).ok())
+            .filter(|(s, _)| s.starts_with('*'));
+        let (code, at, message) = match (end_marker, terminal::marker(input)) {
+            (Some((_, name)), _) => (
+                Code::UnmatchedEndmarker,
+                input,
+                format!("unmatched end marker \\{name}*"),
+            ),
+            (_, Ok((_, name))) if !self.is_block_start(input) => {
+                match self.markers.contains(name) {
+                    true => (
+                        Code::UnexpectedMarker,
+                        input,
+                        format!("unexpected marker \\{name}"),
+                    ),
+                    false => (
+                        Code::UnknownMarker,
+                        input,
+                        format!("unknown marker \\{name}"),
+                    ),
+                }
+            }
+            _ => {
+                let (code, message) = classify(error);
+                (code, at, message)
+            }
+        };
+        let span = Span {
+            start: self.position(at),
+            end: self.position(rest),
+        };
+        match self.repair(code, input, at, error) {
+            Some((code, message, fix, replacement)) => {
+                Diagnostic::error(code, span, message).with_fix(fix, replacement)
+            }
+            None => Diagnostic::error(code, span, message),
+        }
+    }
+
+    /// A fix for one of the common slips, with a code and message naming
+    /// it more closely than the parse error does.
+    fn repair(
+        &self,
+        code: Code,
+        input: &'i str,
+        at: &'i str,
+        error: &Err<VerboseError<&'i str>>,
+    ) -> Option<(Code, String, Span, String)> {
+        let span = |from: &'i str, to: &'i str| Span {
+            start: self.position(from),
+            end: self.position(to),
+        };
+        // `\v1` for `\v 1`.
+        if let (Code::UnknownMarker, Ok((_, name))) = (code, terminal::marker(input)) {
+            let number = name.strip_prefix('v').unwrap_or_default();
+            if number.starts_with(|c: char| c.is_ascii_digit()) {
+                let after = &input[2..];
+                let message = format!("\\{name} is missing the space before its number");
+                return Some((Code::UnknownMarker, message, span(after, after), " ".into()));
+            }
+        }
+        // An attribute value running on into the end marker.
+        if let Some(end) = at.find('\\').filter(|_| at.starts_with('|')) {
+            let attributes = &at[..end];
+            if !attributes.contains('\n') && attributes.matches('"').count() % 2 == 1 {
+                let message = "attribute value is missing its closing quote".into();
+                let end = &at[end..];
+                return Some((Code::InvalidAttribute, message, span(end, end), "\"".into()));
+            }
+        }
+        let figure = match error {
+            Err::Error(e) | Err::Failure(e) => e
+                .errors
+                .iter()
+                .any(|(_, kind)| matches!(kind, VerboseErrorKind::Context("figure"))),
+            Err::Incomplete(_) => false,
+        };
+        if figure {
+            let end = at.find("\\fig*")?;
+            let (count, fields) = figure_fields(&at[..end])?;
+            let message = format!("\\fig has {count} fields rather than 7");
+            return Some((Code::Syntax, message, span(at, &at[end..]), fields));
+        }
+        None
+    }
+
+    fn book(&mut self, start: &'i str) -> Result<'i, Vec<Content<'i>>> {
+        let (input, id) = match self.identification(start) {
+            Ok((input, mut id)) => {
+                self.locate(&mut id, start, input);
+                (input, Some(id))
+            }
+            Err(e) if self.lenient => (self.recover(start, e, |i| self.is_block_start(i)), None),
+            Err(e) => return Err(e),
+        };
+        let (input, headers) = self.headers(input)?;
+        let (input, titles) = self.titles(input)?;
+        let (input, introductions) = self.introductions(input)?;
+        let (input, blocks) = self.blocks(input)?;
+        let (input, periphs) = self.sections(input, self.located(|i| self.periph(i)))?;
+        let (input, chapters) = self.sections(input, self.located(|i| self.chapter(i)))?;
+        let input = match terminated(terminal::multispace0, eof).parse(input) {
+            Ok((input, _)) => input,
+            Err(e) if self.lenient => self.recover(input, e, |_| false),
+            Err(e) => return Err(e),
+        };
+
+        let mut content = id
+            .into_iter()
+            .chain(headers)
+            .chain(titles)
+            .chain(introductions)
+            .chain(blocks)
+            .chain(periphs)
+            .chain(chapters)
+            .collect::<Vec<_>>();
+        self.tidy(&mut content);
+        Ok((input, content))
+    }
+
+    /// Parse text and blocks before any chapters, putting them in a chapter
+    /// of their own when the context gives one. The context's chapter is
+    /// cleared when there are none.
+    fn fragment(
+        &mut self,
+        start: &'i str,
+        context: &mut FragmentContext,
+    ) -> Result<'i, Vec<Content<'i>>> {
+        let (input, mut leading) = self.inline(start)?;
+        let (input, _) = terminal::multispace0(input)?;
+        let (input, blocks) = self.blocks(input)?;
+        let (input, chapters) = self.sections(input, self.located(|i| self.chapter(i)))?;
+        let (input, _) = terminated(terminal::multispace0, eof).parse(input)?;
+
+        trim_end(&mut leading);
+        leading.extend(blocks);
+        let id = Content::Book(Node {
+            style: "id".into(),
+            attributes: [("code".into(), context.book.code.into())].into(),
+            ..Node::default()
+        });
+        let mut content = vec![id];
+        match context.chapter {
+            Some(number) if !leading.is_empty() => content.push(Content::Chapter(Node {
+                style: "c".into(),
+                attributes: [("number".into(), number.to_string().into())].into(),
+                content: leading,
+                ..Node::default()
+            })),
+            _ => {
+                context.chapter = None;
+                content.extend(leading);
+            }
+        }
+        content.extend(chapters);
+        self.tidy(&mut content);
+        Ok((input, content))
+    }
+
+    fn tidy(&self, content: &mut Vec<Content<'i>>) {
+        self.link_milestones(content);
+        unescape_text(content);
+        if self.options.whitespace == WhitespaceHandling::ReduceToSingle {
+            reduce_whitespace(content);
+        }
+    }
+
+    fn prepare(&mut self, input: &'i str) {
+        self.len = input.len();
+        self.source = input;
+        self.lines = line_starts(input);
+    }
+
+    /// Parse the blocks in `range` of an edited source, as they would be
+    /// parsed inside a chapter of the book. `None` unless they parse and
+    /// use up the whole range.
+    pub(crate) fn reparse_blocks(
+        &mut self,
+        source: &'i str,
+        range: Range<usize>,
+    ) -> Option<Vec<Content<'i>>> {
+        self.prepare(source);
+        // Pick the marker set from the `\usfm` line as a full parse does.
+        self.identification(source).ok()?;
+        self.len = range.end;
+        let res = self.blocks(&source[range]);
+        self.len = source.len();
+        match res {
+            Ok(("", mut blocks)) => {
+                unescape_text(&mut blocks);
+                Some(blocks)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn parse(mut self, input: &'i str) -> io::Result<Document<'i>> {
+        self.prepare(input);
+        let res = self.book(input);
+        self.result(input, res)
+    }
+
+    pub fn parse_fragment(
+        mut self,
+        input: &'i str,
+        mut context: FragmentContext,
+    ) -> io::Result<Document<'i>> {
+        if marker::tag("id")(input.trim_start()).is_ok() {
+            return self.parse(input);
+        }
+        self.prepare(input);
+        let res = self.fragment(input, &mut context);
+        let mut doc = self.result(input, res)?;
+        doc.fragment = Some(context);
+        Ok(doc)
+    }
+
+    fn result(self, input: &'i str, res: Result<'i, Vec<Content<'i>>>) -> io::Result<Document<'i>> {
+        match res {
+            Ok((_, content)) => Ok(self.finish(input, content)),
+            Err(error) => {
+                let at = match &error {
+                    Err::Error(e) | Err::Failure(e) => e.errors.first().map_or(input, |e| e.0),
+                    Err::Incomplete(_) => input,
+                };
+                let rest = &at[at.find(['\r', '\n']).unwrap_or(at.len())..];
+                let diagnostic = self.diagnose(at, &error, rest);
+                Err(io::Error::new(io::ErrorKind::InvalidData, diagnostic))
+            }
+        }
+    }
+
+    pub fn parse_lenient(mut self, input: &'i str) -> (Document<'i>, Vec<Diagnostic>) {
+        self.lenient = true;
+        self.prepare(input);
+        let content = match self.book(input) {
+            Ok((_, content)) => content,
+            Err(e) => {
+                self.recover(input, e, |_| false);
+                Vec::new()
+            }
+        };
+        let diagnostics = self.diagnostics.take();
+        (self.finish(input, content), diagnostics)
+    }
+
+    fn finish(self, input: &'i str, content: Vec<Content<'i>>) -> Document<'i> {
+        let root = Node {
+            style: "usfm".into(),
+            attributes: [("version".into(), self.version.to_string().into())].into(),
+            content,
+            span: Some(Span {
+                start: self.position(input),
+                end: self.position(""),
+            }),
+            ..Node::default()
+        };
+        Document {
+            source: Rope {
+                segments: Cow::Borrowed(input),
+            },
+            nodes: Some(root),
+            #[cfg(feature = "encoding_rs")]
+            encoding: None,
+            fragment: None,
+            markers: (!self.bundled).then_some(self.markers),
+            options: self.options,
+        }
+    }
+
+    // fn get_subparser<'i, O, E>(&self, style: &str) -> impl nom::Parser<&str, O, E>
+    // where
+    //     E: ParseError<&str> + ContextError<&str>,
+    // {
+    //     // match self.markers.get(style)?.category
+    //     // {
+    //         // Cell => {},
+    //         // Char => {},
+    //         // Crossreference => {},
+    //         // CrossreferenceChar => {},
     //         // Footnote => {},
     //         // FootnoteChar => {},
     //         // Header => {},
@@ -203,92 +1944,1997 @@ impl<'i> State {
     //         // VersePara => {},
     //         // _ => {},
 
-    //     // }
-    //     unimplemented!()
-    // }
-}
+    //     // }
+    //     unimplemented!()
+    // }
+}
+
+fn numbering<'i>(
+    number: &'i str,
+    altnumber: Option<&'i str>,
+    pubnumber: Option<&'i str>,
+) -> HashMap<Cow<'i, str>, Cow<'i, str>> {
+    [
+        ("number", Some(number)),
+        ("altnumber", altnumber),
+        ("pubnumber", pubnumber),
+    ]
+    .into_iter()
+    .filter_map(|(k, v)| Some((k.into(), v?.into())))
+    .collect()
+}
+
+/// USFM reserves markers starting with `z` for users.
+pub(crate) fn is_custom(style: &str) -> bool {
+    style.starts_with('z')
+}
+
+/// The fields of a USFM 2 figure, `DESC|FILE|SIZE|LOC|COPY|CAP|REF`,
+/// brought to the right number, and the number there were. Missing fields
+/// are taken to be empty ones before the reference, and surplus ones are
+/// dropped if they are empty fields before the caption.
+fn figure_fields(text: &str) -> Option<(usize, String)> {
+    let mut fields = text.split('|').collect::<Vec<_>>();
+    let count = fields.len();
+    if count < 2 || count == 7 || text.contains(['=', '\\', '\n']) {
+        return None;
+    }
+    if count < 7 {
+        let reference = fields.pop()?;
+        fields.resize(6, "");
+        fields.push(reference);
+    }
+    while fields.len() > 7 {
+        let n = fields[1..fields.len() - 2]
+            .iter()
+            .rposition(|f| f.trim().is_empty())?;
+        fields.remove(n + 1);
+    }
+    Some((count, fields.join("|")))
+}
+
+fn classify(error: &Err<VerboseError<&str>>) -> (Code, String) {
+    let (Err::Error(e) | Err::Failure(e)) = error else {
+        return (Code::Syntax, "incomplete input".into());
+    };
+    let context = e.errors.iter().find_map(|(_, kind)| match kind {
+        VerboseErrorKind::Context(context) => Some(*context),
+        _ => None,
+    });
+    let code = match context {
+        Some("end tag" | "nested end tag" | "milestone end") => Code::MissingEndmarker,
+        Some("attributes" | "default attribute") => Code::InvalidAttribute,
+        Some("chapter number") => Code::InvalidNumber,
+        Some("book code") => Code::UnknownBook,
+        _ => Code::Syntax,
+    };
+    let message = match (context, e.errors.first()) {
+        (Some("default attribute"), _) => "default attribute given but none is defined".into(),
+        (Some("book code"), _) => "unknown book code".into(),
+        (Some(context), _) => format!("expected {context}"),
+        (None, Some((_, VerboseErrorKind::Char(c)))) => format!("expected '{c}'"),
+        _ => "unrecognised content".into(),
+    };
+    (code, message)
+}
+
+pub(crate) fn merge_text(content: Vec<Content>) -> Vec<Content> {
+    merge_runs("", content)
+}
+
+/// Merge adjacent text into one, borrowing from `source` rather than
+/// copying where the pieces follow each other in it.
+fn merge_runs<'i>(source: &'i str, content: Vec<Content<'i>>) -> Vec<Content<'i>> {
+    let mut res = Vec::with_capacity(content.len());
+    for item in content {
+        match (res.last_mut(), item) {
+            (Some(Content::Text(prev)), Content::Text(next)) => {
+                match (&prev.text, &next.text) {
+                    (Cow::Borrowed(a), Cow::Borrowed(b)) => {
+                        prev.text = match adjacent(source, a, b) {
+                            Some(run) => Cow::Borrowed(run),
+                            None => Cow::Owned([*a, *b].concat()),
+                        }
+                    }
+                    _ => prev.text.to_mut().push_str(&next.text),
+                }
+                prev.span = prev.span.take().zip(next.span).map(|(prev, next)| Span {
+                    start: prev.start,
+                    end: next.end,
+                });
+            }
+            (_, item) => res.push(item),
+        }
+    }
+    res
+}
+
+// `a` and `b` as one slice of `source`, if `b` starts where `a` ends.
+fn adjacent<'i>(source: &'i str, a: &str, b: &str) -> Option<&'i str> {
+    let start = (a.as_ptr() as usize).checked_sub(source.as_ptr() as usize)?;
+    let end = start + a.len();
+    let follows = b.as_ptr() as usize == source.as_ptr() as usize + end;
+    source.get(start..end + b.len()).filter(|_| follows)
+}
+
+/// Split `~` out of text as [`Content::NoBreakSpace`], replace escape
+/// sequences such as `\\` with the characters they stand for and line
+/// endings with `\n`.
+fn unescape_text(content: &mut Vec<Content>) {
+    let mut res = Vec::with_capacity(content.len());
+    for mut item in content.drain(..) {
+        match item {
+            Content::Text(text) if text.text.contains(['~', '\\', '\r']) => {
+                split_text(text, &mut res)
+            }
+            _ => {
+                if let Some(node) = item.node_mut() {
+                    unescape_text(&mut node.content);
+                }
+                res.push(item);
+            }
+        }
+    }
+    *content = res;
+}
+
+fn split_text<'i>(text: Text<'i>, res: &mut Vec<Content<'i>>) {
+    fn unescape(text: &str) -> Cow<'_, str> {
+        match terminal::attrib::unescape(text) {
+            Cow::Borrowed(text) => terminal::normalize_line_endings(text),
+            Cow::Owned(text) => Cow::Owned(terminal::normalize_line_endings(&text).into_owned()),
+        }
+    }
+    let piece = |range: Range<usize>| match &text.text {
+        Cow::Borrowed(s) => {
+            let s: &'i str = s;
+            unescape(&s[range])
+        }
+        Cow::Owned(s) => Cow::Owned(unescape(&s[range]).into_owned()),
+    };
+    let push = |range: Range<usize>, res: &mut Vec<Content<'i>>| {
+        if range.is_empty() {
+            return;
+        }
+        let span = text.span.map(|span| Span {
+            start: advance(span.start, &text.text[..range.start]),
+            end: advance(span.start, &text.text[..range.end]),
+        });
+        res.push(Content::Text(Text {
+            text: piece(range),
+            span,
+        }));
+    };
+    let mut start = 0;
+    let mut chars = text.text.char_indices();
+    while let Some((n, c)) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '~' => {
+                push(start..n, res);
+                res.push(Content::NoBreakSpace);
+                start = n + 1;
+            }
+            _ => {}
+        }
+    }
+    push(start..text.text.len(), res);
+}
+
+// The byte offset each line of `source` starts at, with lines ended by
+// `\n`, `\r\n` or `\r`.
+pub(crate) fn line_starts(source: &str) -> Vec<usize> {
+    let breaks = source
+        .match_indices(['\n', '\r'])
+        .filter(|&(n, s)| s == "\n" || source.as_bytes().get(n + 1) != Some(&b'\n'))
+        .map(|(n, _)| n + 1);
+    [0].into_iter().chain(breaks).collect()
+}
+
+fn advance(mut position: Position, text: &str) -> Position {
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        position.offset += c.len_utf8();
+        if c == '\n' || c == '\r' && chars.peek() != Some(&'\n') {
+            position.line += 1;
+            position.column = 1;
+        } else {
+            position.column += 1;
+        }
+    }
+    position
+}
+
+fn reduce_whitespace(content: &mut [Content]) {
+    for item in content {
+        match item {
+            Content::Text(Text { text, .. }) => {
+                if let Cow::Owned(reduced) = terminal::reduce_text(text) {
+                    *text = Cow::Owned(reduced);
+                }
+            }
+            item => {
+                if let Some(node) = item.node_mut() {
+                    reduce_whitespace(&mut node.content);
+                }
+            }
+        }
+    }
+}
+
+fn trim_end(content: &mut Vec<Content>) {
+    if let Some(Content::Text(Text { text, .. })) = content.last_mut() {
+        match text {
+            Cow::Borrowed(s) => *s = s.trim_end(),
+            Cow::Owned(s) => s.truncate(s.trim_end().len()),
+        }
+        if text.is_empty() {
+            content.pop();
+        }
+    }
+}
+
+// impl<'i, 'a, E> nom::Parser<&str, Document, E> for State
+// where
+//     E: ParseError<&str> + ContextError<&str>,
+// {
+//     fn parse(&mut self, input: &str) -> Result<Document> {
+//         let (input, _) = bom
+//     }
+// }
+
+#[cfg(test)]
+mod test {
+    use super::{
+        Content, Document, Extensions, FragmentContext, LineEnding, Node, ParseOptions, Position,
+        State, UnknownMarkers, Version, WhitespaceHandling,
+    };
+    use crate::{
+        books,
+        reference::Reference,
+        writer::{Options, Whitespace},
+    };
+    use nom::{multi::many0, Parser};
+    use std::{borrow::Cow, sync::Arc};
+
+    #[test]
+    fn book_identification() {
+        let mut parser = State::new();
+
+        let parse =
+            parser.identification("\\id MAT 41MATGNT92.SFM, Good News Translation, June 2003\n");
+        assert_eq!(
+            parse,
+            Ok((
+                "",
+                Content::Book(Node {
+                    style: "id".into(),
+                    attributes: [("code".into(), "MAT".into())].into(),
+                    content: vec!["41MATGNT92.SFM, Good News Translation, June 2003".into()],
+                    ..Default::default()
+                })
+            ))
+        );
+        assert_eq!(parser.version, 3.0);
+
+        let parse = parser.identification(
+            "\\id MAT 41MATGNT92.SFM, Good News Translation, June 2003\n\
+                    \\usfm 3.1\n",
+        );
+        assert_eq!(
+            parse,
+            Ok((
+                "",
+                Content::Book(Node {
+                    style: "id".into(),
+                    attributes: [("code".into(), "MAT".into())].into(),
+                    content: vec!["41MATGNT92.SFM, Good News Translation, June 2003".into()],
+                    ..Default::default()
+                })
+            ))
+        );
+        assert_eq!(parser.version, 3.1);
+    }
+
+    #[test]
+    fn book_headers() {
+        let parser = State::new();
+
+        let parse = parser.headers(
+            "\\ide some blurb\n\
+                    \\h1 Heading 1\n\
+                    \\rem A remarkable remark\n",
+        );
+        assert_eq!(
+            parse,
+            Ok((
+                "",
+                vec![
+                    Content::Para(Node {
+                        style: "ide".into(),
+                        attributes: Default::default(),
+                        content: vec!["some blurb".into()],
+                        ..Default::default()
+                    }),
+                    Content::Para(Node {
+                        style: "h1".into(),
+                        attributes: Default::default(),
+                        content: vec!["Heading 1".into()],
+                        ..Default::default()
+                    }),
+                    Content::Para(Node {
+                        style: "rem".into(),
+                        attributes: Default::default(),
+                        content: vec!["A remarkable remark".into()],
+                        ..Default::default()
+                    }),
+                ]
+            ))
+        );
+    }
+
+    #[test]
+    fn crossreference() {
+        let parser = State::new();
+
+        let parse = parser.note("\\x - \\xo 1.1: \\xt Mc 1.2; Lc 3.4\\x* rest");
+        assert_eq!(
+            parse,
+            Ok((
+                " rest",
+                Content::Note(Node {
+                    style: "x".into(),
+                    attributes: [("caller".into(), "-".into())].into(),
+                    content: vec![
+                        Content::Char(Node {
+                            style: "xo".into(),
+                            attributes: Default::default(),
+                            content: vec!["1.1: ".into()],
+                            ..Default::default()
+                        }),
+                        Content::Char(Node {
+                            style: "xt".into(),
+                            attributes: Default::default(),
+                            content: vec!["Mc 1.2; Lc 3.4".into()],
+                            ..Default::default()
+                        }),
+                    ],
+                    ..Default::default()
+                })
+            ))
+        );
+
+        let parse = parser.note("\\x + \\xo 2.7 \\xo*\\xt Gen 1.1\\xt*\\x*");
+        assert_eq!(
+            parse,
+            Ok((
+                "",
+                Content::Note(Node {
+                    style: "x".into(),
+                    attributes: [("caller".into(), "+".into())].into(),
+                    content: vec![
+                        Content::Char(Node {
+                            style: "xo".into(),
+                            attributes: Default::default(),
+                            content: vec!["2.7 ".into()],
+                            ..Default::default()
+                        }),
+                        Content::Char(Node {
+                            style: "xt".into(),
+                            attributes: Default::default(),
+                            content: vec!["Gen 1.1".into()],
+                            ..Default::default()
+                        }),
+                    ],
+                    ..Default::default()
+                })
+            ))
+        );
+
+        assert_eq!(
+            parser.note("\\f + \\fr 1.2 \\ft The \\nd Lord\\nd* said\\f*"),
+            Ok((
+                "",
+                Content::Note(Node {
+                    style: "f".into(),
+                    attributes: [("caller".into(), "+".into())].into(),
+                    content: vec![
+                        Content::Char(Node {
+                            style: "fr".into(),
+                            attributes: Default::default(),
+                            content: vec!["1.2 ".into()],
+                            ..Default::default()
+                        }),
+                        Content::Char(Node {
+                            style: "ft".into(),
+                            attributes: Default::default(),
+                            content: vec![
+                                "The ".into(),
+                                Content::Char(Node {
+                                    style: "nd".into(),
+                                    attributes: Default::default(),
+                                    content: vec!["Lord".into()],
+                                    ..Default::default()
+                                }),
+                                " said".into()
+                            ],
+                            ..Default::default()
+                        }),
+                    ],
+                    ..Default::default()
+                })
+            ))
+        );
+        assert!(parser.note("\\x - \\xo 1.1 \\ft text\\x*").is_err());
+        assert!(parser.note("\\p - text").is_err());
+    }
+
+    #[test]
+    fn character_spans() {
+        let parser = State::new();
+
+        assert_eq!(
+            parser.char_span("\\nd Lord\\nd* rest"),
+            Ok((
+                " rest",
+                Content::Char(Node {
+                    style: "nd".into(),
+                    attributes: Default::default(),
+                    content: vec!["Lord".into()],
+                    ..Default::default()
+                })
+            ))
+        );
+        assert_eq!(
+            parser.char_span("\\wj Go, \\add and\\add* sin no more\\wj*"),
+            Ok((
+                "",
+                Content::Char(Node {
+                    style: "wj".into(),
+                    attributes: Default::default(),
+                    content: vec![
+                        "Go, ".into(),
+                        Content::Char(Node {
+                            style: "add".into(),
+                            attributes: Default::default(),
+                            content: vec!["and".into()],
+                            ..Default::default()
+                        }),
+                        " sin no more".into()
+                    ],
+                    ..Default::default()
+                })
+            ))
+        );
+        assert_eq!(
+            parser.char_span("\\bd unterminated\n\\p"),
+            Ok((
+                "\n\\p",
+                Content::Char(Node {
+                    style: "bd".into(),
+                    attributes: Default::default(),
+                    content: vec!["unterminated".into()],
+                    ..Default::default()
+                })
+            ))
+        );
+        assert!(parser.char_span("\\p text").is_err());
+        assert!(parser.char_span("\\xt Gen 1.1\\xt*").is_err());
+    }
+
+    #[test]
+    fn nested_character_spans() {
+        let parser = State::new();
+
+        assert_eq!(
+            parser.char_span("\\add the \\+nd Lord\\+nd* God\\add*"),
+            Ok((
+                "",
+                Content::Char(Node {
+                    style: "add".into(),
+                    content: vec![
+                        "the ".into(),
+                        Content::Char(Node {
+                            style: "nd".into(),
+                            content: vec!["Lord".into()],
+                            nested: true,
+                            ..Default::default()
+                        }),
+                        " God".into()
+                    ],
+                    ..Default::default()
+                })
+            ))
+        );
+        assert_eq!(
+            parser.char_span("\\wj a \\+add b \\+nd c\\+nd*\\+add*\\wj*"),
+            Ok((
+                "",
+                Content::Char(Node {
+                    style: "wj".into(),
+                    content: vec![
+                        "a ".into(),
+                        Content::Char(Node {
+                            style: "add".into(),
+                            content: vec![
+                                "b ".into(),
+                                Content::Char(Node {
+                                    style: "nd".into(),
+                                    content: vec!["c".into()],
+                                    nested: true,
+                                    ..Default::default()
+                                }),
+                            ],
+                            nested: true,
+                            ..Default::default()
+                        }),
+                    ],
+                    ..Default::default()
+                })
+            ))
+        );
+        assert!(parser.char_span("\\+nd Lord\\+nd*").is_err());
+    }
+
+    #[test]
+    fn bar_in_text() {
+        let verse = |doc: &Document| {
+            doc.verse_text(&Reference::new(books::get("MRK").unwrap(), 1, Some(1)))
+        };
+        let doc: Document = "\\id MRK\n\\c 1\n\\p \\v 1 a | b\n"
+            .parse()
+            .expect("Document");
+        assert_eq!(verse(&doc).as_deref(), Some("a | b"));
+        let written = doc.to_string().parse::<Document>().expect("written");
+        assert_eq!(verse(&written).as_deref(), Some("a | b"));
+    }
+
+    #[test]
+    fn character_attributes() {
+        let parser = State::new();
+
+        assert_eq!(
+            parser.char_span("\\w gracious|lemma=\"grace\" strong=\"H1234\"\\w*"),
+            Ok((
+                "",
+                Content::Char(Node {
+                    style: "w".into(),
+                    attributes: [
+                        ("lemma".into(), "grace".into()),
+                        ("strong".into(), "H1234".into())
+                    ]
+                    .into(),
+                    content: vec!["gracious".into()],
+                    ..Default::default()
+                })
+            ))
+        );
+        assert_eq!(
+            parser.char_span("\\w gracious|grace\\w*"),
+            Ok((
+                "",
+                Content::Char(Node {
+                    style: "w".into(),
+                    attributes: [("lemma".into(), "grace".into())].into(),
+                    content: vec!["gracious".into()],
+                    ..Default::default()
+                })
+            ))
+        );
+        assert_eq!(
+            parser.char_span("\\jmp link|href=\"https://example.org\"\\jmp*"),
+            Ok((
+                "",
+                Content::Char(Node {
+                    style: "jmp".into(),
+                    attributes: [("href".into(), "https://example.org".into())].into(),
+                    content: vec!["link".into()],
+                    ..Default::default()
+                })
+            ))
+        );
+        assert!(matches!(
+            parser.char_span("\\nd Lord|value\\nd*"),
+            Err(nom::Err::Failure(_))
+        ));
+    }
+
+    #[test]
+    fn milestones() {
+        let parser = State::new();
+
+        assert_eq!(
+            parser.milestone("\\qt-s |who=\"Pilate\"\\*\"Are you"),
+            Ok((
+                "\"Are you",
+                Content::Milestone(Node {
+                    style: "qt-s".into(),
+                    attributes: [("who".into(), "Pilate".into())].into(),
+                    ..Default::default()
+                })
+            ))
+        );
+        assert_eq!(
+            parser.milestone("\\qt-e\\* text"),
+            Ok((
+                " text",
+                Content::Milestone(Node {
+                    style: "qt-e".into(),
+                    ..Default::default()
+                })
+            ))
+        );
+        assert_eq!(
+            parser.milestone("\\ts\\*"),
+            Ok((
+                "",
+                Content::Milestone(Node {
+                    style: "ts".into(),
+                    ..Default::default()
+                })
+            ))
+        );
+        assert_eq!(
+            parser.milestone("\\ts-s |ts1\\*"),
+            Ok((
+                "",
+                Content::Milestone(Node {
+                    style: "ts-s".into(),
+                    attributes: [("sid".into(), "ts1".into())].into(),
+                    ..Default::default()
+                })
+            ))
+        );
+        assert!(parser.milestone("\\qt-s |who=\"Pilate\" text").is_err());
+        assert!(parser.milestone("\\nd Lord\\nd*").is_err());
+    }
+
+    #[test]
+    fn milestone_linking() {
+        let parser = State::new();
+        let milestone = |style: &'static str, attributes: &[(&'static str, &'static str)]| {
+            Content::Milestone(Node {
+                style: style.into(),
+                attributes: attributes
+                    .iter()
+                    .map(|&(k, v)| (k.into(), v.into()))
+                    .collect(),
+                ..Default::default()
+            })
+        };
+
+        let mut content = vec![
+            milestone("qt-s", &[("who", "Pilate")]),
+            milestone("qt-s", &[]),
+            milestone("qt-e", &[]),
+            milestone("ts-s", &[("sid", "a")]),
+            milestone("qt-e", &[]),
+            milestone("ts-e", &[("eid", "a")]),
+        ];
+        parser.link_milestones(&mut content);
+        assert_eq!(
+            content,
+            vec![
+                milestone("qt-s", &[("who", "Pilate"), ("sid", "qt-1")]),
+                milestone("qt-s", &[("sid", "qt-2")]),
+                milestone("qt-e", &[("eid", "qt-2")]),
+                milestone("ts-s", &[("sid", "a")]),
+                milestone("qt-e", &[("eid", "qt-1")]),
+                milestone("ts-e", &[("eid", "a")]),
+            ]
+        );
+    }
+
+    #[test]
+    fn milestones_in_containers() {
+        fn linked(content: &[Content], res: &mut Vec<(String, String)>) {
+            for item in content {
+                match item {
+                    Content::Milestone(node) => {
+                        let id = node.attributes.get("sid").or(node.attributes.get("eid"));
+                        res.push((node.style.to_string(), id.map_or("", |id| id).to_owned()));
+                    }
+                    item => linked(item.node().map_or(&[], |node| &node.content), res),
+                }
+            }
+        }
+        let doc: Document =
+            "\\id MRK\n\\usfm 3.0\n\\c 1\n\\q1 \\v 1 \\qt-s |who=\"A\"\\*said\n\\q2 more\\qt-e\\*\n\
+                             \\li \\v 2 \\qt-s\\*one\n\\li two\\qt-e\\*\n"
+                .parse()
+                .expect("Document");
+        let mut res = Vec::new();
+        linked(&doc.nodes.expect("root").content, &mut res);
+        assert_eq!(
+            res,
+            [
+                ("qt-s".to_owned(), "qt-1".to_owned()),
+                ("qt-e".to_owned(), "qt-1".to_owned()),
+                ("qt-s".to_owned(), "qt-2".to_owned()),
+                ("qt-e".to_owned(), "qt-2".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn tables() {
+        let parser = State::new();
+        let cell = |style: &'static str,
+                    align: &'static str,
+                    span: Option<&'static str>,
+                    text: &'static str| {
+            Content::Cell(Node {
+                style: style.into(),
+                attributes: [("align".into(), align.into())]
+                    .into_iter()
+                    .chain(span.map(|s| ("colspan".into(), s.into())))
+                    .collect(),
+                content: vec![text.into()],
+                ..Default::default()
+            })
+        };
+        let row = |content| {
+            Content::Row(Node {
+                style: "tr".into(),
+                content,
+                ..Default::default()
+            })
+        };
+
+        assert_eq!(
+            parser.table(
+                "\\tr \\th1 Tribe \\th2 Leader \\thr3 Number\n\
+                 \\tr \\tc1 Reuben \\tc2 Elizur \\tcr3 46,500\n\
+                 \\tr \\tc1-2 Total \\tcr3 603,550\n\
+                 \\p"
+            ),
+            Ok((
+                "\\p",
+                Content::Table(Node {
+                    style: "table".into(),
+                    content: vec![
+                        row(vec![
+                            cell("th1", "start", None, "Tribe "),
+                            cell("th2", "start", None, "Leader "),
+                            cell("thr3", "end", None, "Number"),
+                        ]),
+                        row(vec![
+                            cell("tc1", "start", None, "Reuben "),
+                            cell("tc2", "start", None, "Elizur "),
+                            cell("tcr3", "end", None, "46,500"),
+                        ]),
+                        row(vec![
+                            cell("tc1", "start", Some("2"), "Total "),
+                            cell("tcr3", "end", None, "603,550"),
+                        ]),
+                    ],
+                    ..Default::default()
+                })
+            ))
+        );
+        assert_eq!(
+            parser.cell("\\tcc2 \\nd Lord\\nd*"),
+            Ok((
+                "",
+                Content::Cell(Node {
+                    style: "tcc2".into(),
+                    attributes: [("align".into(), "center".into())].into(),
+                    content: vec![Content::Char(Node {
+                        style: "nd".into(),
+                        content: vec!["Lord".into()],
+                        ..Default::default()
+                    })],
+                    ..Default::default()
+                })
+            ))
+        );
+        assert!(parser.cell("\\tc3-1 text").is_err());
+        assert!(parser.table("\\p text").is_err());
+    }
+
+    #[test]
+    fn lists() {
+        let parser = State::new();
+        let span = |style: &'static str, text: &'static str| {
+            Content::Char(Node {
+                style: style.into(),
+                content: vec![text.into()],
+                ..Default::default()
+            })
+        };
+        let para = |style: &'static str, content| {
+            Content::Para(Node {
+                style: style.into(),
+                content,
+                ..Default::default()
+            })
+        };
+
+        assert_eq!(
+            parser.list(
+                "\\lh Here are the tribes:\n\
+                 \\li1 \\lik Reuben\\lik* \\liv 46,500\\liv*\n\
+                 \\li2 of whom\n\
+                 \\lik Hanoch\\lik*\n\
+                 \\lf Total: \\litl 46,500\\litl*\n\
+                 \\p"
+            ),
+            Ok((
+                "\\p",
+                Content::List(Node {
+                    style: "list".into(),
+                    content: vec![
+                        para("lh", vec!["Here are the tribes:".into()]),
+                        para(
+                            "li1",
+                            vec![span("lik", "Reuben"), " ".into(), span("liv", "46,500")]
+                        ),
+                        para("li2", vec!["of whom ".into(), span("lik", "Hanoch")]),
+                        para("lf", vec!["Total: ".into(), span("litl", "46,500")]),
+                    ],
+                    ..Default::default()
+                })
+            ))
+        );
+        assert!(parser.list("\\p text").is_err());
+    }
+
+    #[test]
+    fn introductions() {
+        let parser = State::new();
+        assert_eq!(
+            parser.markers.category("ip"),
+            Some(crate::extension::Category::Introduction)
+        );
+        let para = |style: &'static str, content| {
+            Content::Para(Node {
+                style: style.into(),
+                content,
+                ..Default::default()
+            })
+        };
+
+        assert_eq!(
+            parser.introductions(
+                "\\imt1 Introduction\n\
+                 \\is Background\n\
+                 \\ip The book of \\bk Mark\\bk* tells\n\
+                 the good news.\n\
+                 \\iot Outline\n\
+                 \\io1 Preparation \\ior 1.1-13\\ior*\n\
+                 \\ie\n\
+                 \\c 1"
+            ),
+            Ok((
+                "\\c 1",
+                vec![
+                    para("imt1", vec!["Introduction".into()]),
+                    para("is", vec!["Background".into()]),
+                    para(
+                        "ip",
+                        vec![
+                            "The book of ".into(),
+                            Content::Char(Node {
+                                style: "bk".into(),
+                                content: vec!["Mark".into()],
+                                ..Default::default()
+                            }),
+                            " tells\nthe good news.".into()
+                        ]
+                    ),
+                    para("iot", vec!["Outline".into()]),
+                    para(
+                        "io1",
+                        vec![
+                            "Preparation ".into(),
+                            Content::Char(Node {
+                                style: "ior".into(),
+                                content: vec!["1.1-13".into()],
+                                ..Default::default()
+                            })
+                        ]
+                    ),
+                    para("ie", vec![]),
+                ]
+            ))
+        );
+    }
+
+    #[test]
+    fn book_pipeline() {
+        let mut parser = State::new();
+        let para = |style: &'static str, content| {
+            Content::Para(Node {
+                style: style.into(),
+                content,
+                ..Default::default()
+            })
+        };
+        let verse = |number: &'static str| {
+            Content::Verse(Node {
+                style: "v".into(),
+                attributes: [("number".into(), number.into())].into(),
+                ..Default::default()
+            })
+        };
+
+        let parse = parser.book(
+            "\\id MRK\n\
+             \\h Mark\n\
+             \\mt1 Mark\n\
+             \\ip An introduction.\n\
+             \\c 1\n\
+             \\p\n\
+             \\v 1 The beginning\n\
+             \\v 2 As it is written\n\
+             \\c 2\n\
+             \\p \\v 1 Again\n",
+        );
+        assert_eq!(
+            parse,
+            Ok((
+                "",
+                vec![
+                    Content::Book(Node {
+                        style: "id".into(),
+                        attributes: [("code".into(), "MRK".into())].into(),
+                        ..Default::default()
+                    }),
+                    para("h", vec!["Mark".into()]),
+                    para("mt1", vec!["Mark".into()]),
+                    para("ip", vec!["An introduction.".into()]),
+                    Content::Chapter(Node {
+                        style: "c".into(),
+                        attributes: [("number".into(), "1".into())].into(),
+                        content: vec![para(
+                            "p",
+                            vec![
+                                verse("1"),
+                                "The beginning ".into(),
+                                verse("2"),
+                                "As it is written".into()
+                            ]
+                        )],
+                        ..Default::default()
+                    }),
+                    Content::Chapter(Node {
+                        style: "c".into(),
+                        attributes: [("number".into(), "2".into())].into(),
+                        content: vec![para("p", vec![verse("1"), "Again".into()])],
+                        ..Default::default()
+                    }),
+                ]
+            ))
+        );
+        assert!(State::new()
+            .book("\\id MRK\n\\c 1\n\\p text \\bogus\n")
+            .is_err());
+    }
 
-// impl<'i, 'a, E> nom::Parser<&str, Document, E> for State
-// where
-//     E: ParseError<&str> + ContextError<&str>,
-// {
-//     fn parse(&mut self, input: &str) -> Result<Document> {
-//         let (input, _) = bom
-//     }
-// }
+    #[test]
+    fn poetry() {
+        let parser = State::new();
+        let line = |style: &'static str, level: &'static str, content| {
+            Content::Para(Node {
+                style: style.into(),
+                attributes: [("level".into(), level.into())].into(),
+                content,
+                ..Default::default()
+            })
+        };
+        let stanza = |content| {
+            Content::Stanza(Node {
+                style: "stanza".into(),
+                content,
+                ..Default::default()
+            })
+        };
+        let para = |style: &'static str, content| {
+            Content::Para(Node {
+                style: style.into(),
+                content,
+                ..Default::default()
+            })
+        };
 
-#[cfg(test)]
-mod test {
-    use super::{Content, Node, State};
+        let (rest, blocks) = many0(|i| parser.block(i))
+            .parse(
+                "\\qa Aleph\n\
+                 \\q1 \\v 1 Happy are those\n\
+                 \\q2 who reject the advice of evil people,\n\
+                 \\q \\qac B\\qac*ut delight\n\
+                 \\b\n\
+                 \\qr Praise the \\nd Lord\\nd*!\n\
+                 \\qc \\qs Selah\\qs*\n\
+                 \\p",
+            )
+            .expect("poetry");
+        assert_eq!(rest, "");
+        assert_eq!(
+            blocks,
+            vec![
+                para("qa", vec!["Aleph".into()]),
+                stanza(vec![
+                    line(
+                        "q1",
+                        "1",
+                        vec![
+                            Content::Verse(Node {
+                                style: "v".into(),
+                                attributes: [("number".into(), "1".into())].into(),
+                                ..Default::default()
+                            }),
+                            "Happy are those".into()
+                        ]
+                    ),
+                    line(
+                        "q2",
+                        "2",
+                        vec!["who reject the advice of evil people,".into()]
+                    ),
+                    line(
+                        "q",
+                        "1",
+                        vec![
+                            Content::Char(Node {
+                                style: "qac".into(),
+                                content: vec!["B".into()],
+                                ..Default::default()
+                            }),
+                            "ut delight".into()
+                        ]
+                    ),
+                ]),
+                para("b", vec![]),
+                stanza(vec![
+                    line(
+                        "qr",
+                        "1",
+                        vec![
+                            "Praise the ".into(),
+                            Content::Char(Node {
+                                style: "nd".into(),
+                                content: vec!["Lord".into()],
+                                ..Default::default()
+                            }),
+                            "!".into()
+                        ]
+                    ),
+                    line(
+                        "qc",
+                        "1",
+                        vec![Content::Char(Node {
+                            style: "qs".into(),
+                            content: vec!["Selah".into()],
+                            ..Default::default()
+                        })]
+                    ),
+                ]),
+                para("p", vec![]),
+            ]
+        );
+    }
 
     #[test]
-    fn book_identification() {
+    fn section_headings() {
         let mut parser = State::new();
+        let para = |style: &'static str, attributes: &[(&'static str, &'static str)], content| {
+            Content::Para(Node {
+                style: style.into(),
+                attributes: attributes
+                    .iter()
+                    .map(|&(k, v)| (k.into(), v.into()))
+                    .collect(),
+                content,
+                ..Default::default()
+            })
+        };
+        let verse = |number: &'static str| {
+            Content::Verse(Node {
+                style: "v".into(),
+                attributes: [("number".into(), number.into())].into(),
+                ..Default::default()
+            })
+        };
 
-        let parse =
-            parser.identification("\\id MAT 41MATGNT92.SFM, Good News Translation, June 2003\n");
+        let parse = parser.book(
+            "\\id SNG\n\
+             \\usfm 3.0\n\
+             \\ms The First Song\n\
+             \\mr (1.1-2.7)\n\
+             \\c 1\n\
+             \\s1 The Woman\n\
+             \\r (Psalm 45)\n\
+             \\sp The Woman\n\
+             \\p \\v 1 Your lips cover me\n\
+             \\s2 with kisses\n\
+             \\sd2\n\
+             \\p \\v 2 your love\n",
+        );
         assert_eq!(
             parse,
             Ok((
                 "",
-                Content::Book(Node {
-                    style: "id".into(),
-                    attributes: [("code".into(), "MAT".into())].into(),
-                    content: vec!["41MATGNT92.SFM, Good News Translation, June 2003".into()]
-                })
+                vec![
+                    Content::Book(Node {
+                        style: "id".into(),
+                        attributes: [("code".into(), "SNG".into())].into(),
+                        ..Default::default()
+                    }),
+                    para("ms", &[("level", "1")], vec!["The First Song".into()]),
+                    para("mr", &[], vec!["(1.1-2.7)".into()]),
+                    Content::Chapter(Node {
+                        style: "c".into(),
+                        attributes: [("number".into(), "1".into())].into(),
+                        content: vec![
+                            para("s1", &[("level", "1")], vec!["The Woman".into()]),
+                            para("r", &[], vec!["(Psalm 45)".into()]),
+                            para("sp", &[], vec!["The Woman".into()]),
+                            para("p", &[], vec![verse("1"), "Your lips cover me".into()]),
+                            para("s2", &[("level", "2")], vec!["with kisses".into()]),
+                            para("sd2", &[("level", "2")], vec![]),
+                            para("p", &[], vec![verse("2"), "your love".into()]),
+                        ],
+                        ..Default::default()
+                    }),
+                ]
             ))
         );
-        assert_eq!(parser.version, 3.0);
+    }
 
-        let parse = parser.identification(
-            "\\id MAT 41MATGNT92.SFM, Good News Translation, June 2003\n\
-                    \\usfm 3.1\n",
+    #[test]
+    fn figures() {
+        let parser = State::new();
+        let figure = Content::Figure(Node {
+            style: "fig".into(),
+            attributes: [
+                ("alt".into(), "Map of Israel".into()),
+                ("src".into(), "israel.jpg".into()),
+                ("size".into(), "span".into()),
+                ("ref".into(), "1.5".into()),
+            ]
+            .into(),
+            content: vec!["The land of Israel".into()],
+            ..Default::default()
+        });
+
+        assert_eq!(
+            parser.figure(
+                "\\fig The land of Israel|alt=\"Map of Israel\" src=\"israel.jpg\" \
+                 size=\"span\" ref=\"1.5\"\\fig* text"
+            ),
+            Ok((" text", figure.clone()))
         );
         assert_eq!(
-            parse,
+            parser.figure("\\fig Map of Israel|israel.jpg|span||| The land of Israel|1.5\\fig*"),
+            Ok(("", figure))
+        );
+        assert!(matches!(
+            parser.figure("\\fig Map|israel.jpg|span|1.5\\fig*"),
+            Err(nom::Err::Failure(_))
+        ));
+    }
+
+    #[test]
+    fn alternate_numbering() {
+        let parser = State::new();
+        let verse = |attributes: &[(&'static str, &'static str)]| {
+            Content::Verse(Node {
+                style: "v".into(),
+                attributes: attributes
+                    .iter()
+                    .map(|&(k, v)| (k.into(), v.into()))
+                    .collect(),
+                ..Default::default()
+            })
+        };
+
+        assert_eq!(
+            parser.verse("\\v 1 \\va 2\\va* \\vp 1b\\vp* text"),
+            Ok((
+                "text",
+                verse(&[("number", "1"), ("altnumber", "2"), ("pubnumber", "1b")])
+            ))
+        );
+        assert_eq!(
+            parser.verse("\\v 3 \\vp C\\vp*text"),
+            Ok(("text", verse(&[("number", "3"), ("pubnumber", "C")])))
+        );
+        assert_eq!(
+            parser.chapter("\\c 1 \\ca 2\\ca*\n\\cp A\n\\p \\v 1 \\va 3\\va*text\n"),
             Ok((
                 "",
-                Content::Book(Node {
-                    style: "id".into(),
-                    attributes: [("code".into(), "MAT".into())].into(),
-                    content: vec!["41MATGNT92.SFM, Good News Translation, June 2003".into()]
+                Content::Chapter(Node {
+                    style: "c".into(),
+                    attributes: [
+                        ("number".into(), "1".into()),
+                        ("altnumber".into(), "2".into()),
+                        ("pubnumber".into(), "A".into()),
+                    ]
+                    .into(),
+                    content: vec![Content::Para(Node {
+                        style: "p".into(),
+                        content: vec![verse(&[("number", "1"), ("altnumber", "3")]), "text".into()],
+                        ..Default::default()
+                    })],
+                    ..Default::default()
                 })
             ))
         );
-        assert_eq!(parser.version, 3.1);
     }
 
     #[test]
-    fn book_headers() {
-        let parser = State::new();
+    fn peripherals() {
+        let mut parser = State::new();
+        let para = |style: &'static str, text: &'static str| {
+            Content::Para(Node {
+                style: style.into(),
+                content: vec![text.into()],
+                ..Default::default()
+            })
+        };
+        let periph = |alt: &'static str, id: &'static str, content| {
+            Content::Periph(Node {
+                style: "periph".into(),
+                attributes: [("alt".into(), alt.into()), ("id".into(), id.into())].into(),
+                content,
+                ..Default::default()
+            })
+        };
 
-        let parse = parser.headers(
-            "\\ide some blurb\n\
-                    \\h1 Heading 1\n\
-                    \\rem A remarkable remark\n",
+        let parse = parser.book(
+            "\\id FRT Front matter\n\
+             \\periph Title Page|id=\"title\"\n\
+             \\mt1 Holy Bible\n\
+             \\periph Foreword|foreword\n\
+             \\is Foreword\n\
+             \\ip This translation was made for everyone.\n",
         );
         assert_eq!(
             parse,
             Ok((
                 "",
                 vec![
-                    Content::Para(Node {
-                        style: "ide".into(),
-                        attributes: Default::default(),
-                        content: vec!["some blurb".into()]
-                    }),
-                    Content::Para(Node {
-                        style: "h1".into(),
-                        attributes: Default::default(),
-                        content: vec!["Heading 1".into()]
-                    }),
-                    Content::Para(Node {
-                        style: "rem".into(),
-                        attributes: Default::default(),
-                        content: vec!["A remarkable remark".into()]
+                    Content::Book(Node {
+                        style: "id".into(),
+                        attributes: [("code".into(), "FRT".into())].into(),
+                        content: vec!["Front matter".into()],
+                        ..Default::default()
                     }),
+                    periph("Title Page", "title", vec![para("mt1", "Holy Bible")]),
+                    periph(
+                        "Foreword",
+                        "foreword",
+                        vec![
+                            para("is", "Foreword"),
+                            para("ip", "This translation was made for everyone.")
+                        ]
+                    ),
                 ]
             ))
         );
     }
+
+    #[test]
+    fn sidebars() {
+        let parser = State::new();
+
+        assert_eq!(
+            parser.sidebar(
+                "\\esb \\cat People\\cat*\n\
+                 \\ms Fishermen\n\
+                 \\p Fishing was a major industry\\ef - \\fr 1.16 \\ft Nets\\ef*.\n\
+                 \\esbe\n\
+                 \\p"
+            ),
+            Ok((
+                "\\p",
+                Content::Sidebar(Node {
+                    style: "esb".into(),
+                    attributes: [("category".into(), "People".into())].into(),
+                    content: vec![
+                        Content::Para(Node {
+                            style: "ms".into(),
+                            attributes: [("level".into(), "1".into())].into(),
+                            content: vec!["Fishermen".into()],
+                            ..Default::default()
+                        }),
+                        Content::Para(Node {
+                            style: "p".into(),
+                            content: vec![
+                                "Fishing was a major industry".into(),
+                                Content::Note(Node {
+                                    style: "ef".into(),
+                                    attributes: [("caller".into(), "-".into())].into(),
+                                    content: vec![
+                                        Content::Char(Node {
+                                            style: "fr".into(),
+                                            content: vec!["1.16 ".into()],
+                                            ..Default::default()
+                                        }),
+                                        Content::Char(Node {
+                                            style: "ft".into(),
+                                            content: vec!["Nets".into()],
+                                            ..Default::default()
+                                        }),
+                                    ],
+                                    ..Default::default()
+                                }),
+                                ".".into()
+                            ],
+                            ..Default::default()
+                        }),
+                    ],
+                    ..Default::default()
+                })
+            ))
+        );
+        assert!(matches!(
+            parser.sidebar("\\esb\n\\p Unterminated sidebar\n"),
+            Err(nom::Err::Failure(_))
+        ));
+    }
+
+    #[test]
+    fn ruby_glosses() {
+        let parser = State::new();
+
+        let (_, ruby) = parser
+            .char_span("\\rb 天地|gloss=\"てん:ち\"\\rb*")
+            .expect("ruby");
+        assert_eq!(
+            ruby,
+            Content::Char(Node {
+                style: "rb".into(),
+                attributes: [("gloss".into(), "てん:ち".into())].into(),
+                content: vec!["天地".into()],
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            ruby.node().and_then(Node::ruby),
+            Some(vec![
+                ("天".into(), "てん".into()),
+                ("地".into(), "ち".into())
+            ])
+        );
+
+        let (_, ruby) = parser.char_span("\\rb 天地|てんち\\rb*").expect("ruby");
+        assert_eq!(
+            ruby.node().and_then(Node::ruby),
+            Some(vec![("天地".into(), "てんち".into())])
+        );
+        let (_, ruby) = parser.char_span("\\rb 天地人|てん:ち\\rb*").expect("ruby");
+        assert_eq!(
+            ruby.node().and_then(Node::ruby),
+            Some(vec![("天地人".into(), "てんち".into())])
+        );
+        let (_, other) = parser.char_span("\\nd Lord\\nd*").expect("char");
+        assert_eq!(other.node().and_then(Node::ruby), None);
+    }
+
+    #[test]
+    fn node_spans() {
+        let source = "\\id MRK\n\\c 1\n\\p \\v 1 The \\nd Lord\\nd* said\n";
+        let doc = source.parse::<super::Document>().expect("Document");
+        let root = doc.nodes.expect("root");
+        assert_eq!(root.span.map(|s| s.range()), Some(0..source.len()));
+        let Content::Chapter(chapter) = &root.content[1] else {
+            panic!("expected chapter");
+        };
+        assert_eq!(
+            chapter.span.map(|s| (s.start, s.end.line)),
+            Some((
+                Position {
+                    offset: 8,
+                    line: 2,
+                    column: 1
+                },
+                4
+            ))
+        );
+        let Content::Para(para) = &chapter.content[0] else {
+            panic!("expected paragraph");
+        };
+        let spans = para
+            .content
+            .iter()
+            .map(|c| match c {
+                Content::Text(text) => text.span,
+                c => c.node().and_then(|node| node.span),
+            })
+            .map(|span| span.map(|s| (&source[s.range()], s.start.column)))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            spans,
+            [
+                Some(("\\v 1 ", 4)),
+                Some(("The ", 9)),
+                Some(("\\nd Lord\\nd*", 13)),
+                Some((" said", 25))
+            ]
+        );
+    }
+
+    #[test]
+    fn source_positions() {
+        let source = "\\id MRK\n\\c 1\n\\s1 Ἐν ἀρχῇ\n\\p \\v 1 Ἦν ὁ \\nd λόγος\\nd*,\n\\q1 καὶ\n";
+        let doc: Document = source.parse().expect("Document");
+        let located = doc
+            .iter()
+            .filter_map(|item| match item {
+                Content::Text(text) => Some((text.as_str(), text.span?)),
+                item => item
+                    .node()
+                    .and_then(|node| Some((node.style.as_ref(), node.span?))),
+            })
+            .map(|(name, span)| {
+                let (start, end) = (span.start, span.end);
+                (name, (start.line, start.column), (end.line, end.column))
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            located,
+            [
+                ("id", (1, 1), (2, 1)),
+                ("c", (2, 1), (6, 1)),
+                ("s1", (3, 1), (4, 1)),
+                ("Ἐν ἀρχῇ", (3, 5), (3, 12)),
+                ("p", (4, 1), (5, 1)),
+                ("v", (4, 4), (4, 9)),
+                ("Ἦν ὁ ", (4, 9), (4, 14)),
+                ("nd", (4, 14), (4, 27)),
+                ("λόγος", (4, 18), (4, 23)),
+                (",", (4, 27), (4, 28)),
+                ("stanza", (5, 1), (6, 1)),
+                ("q1", (5, 1), (6, 1)),
+                ("καὶ", (5, 5), (5, 8)),
+            ]
+        );
+        // Offsets count bytes while columns count characters.
+        let text = doc.find_all("nd").next().expect("nd");
+        let span = text.span.expect("span");
+        assert_eq!(&source[span.range()], "\\nd λόγος\\nd*");
+        assert_eq!(span.end.offset - span.start.offset, 18);
+        assert_eq!(span.end.column - span.start.column, 13);
+
+        // Spans are recorded however the text is parsed.
+        let (lenient, _) = Document::from_str_lenient(source);
+        assert_eq!(
+            lenient.root().and_then(|root| root.span),
+            doc.root().and_then(|root| root.span)
+        );
+    }
+
+    #[test]
+    fn lenient_recovery() {
+        let source = "\\id MRK\n\
+                      \\c 1\n\
+                      \\p \\v 1 Good\n\
+                      \\xyz unknown paragraph\n\
+                      more of it\n\
+                      \\p \\v 2 Broken\\f + \\ft note\n\
+                      \\p \\v 3 Fine\n\
+                      \\c x\n\
+                      \\p lost\n\
+                      \\c 2\n\
+                      \\p \\v 1 Recovered\n";
+        let (doc, diagnostics) = super::Document::from_str_lenient(source);
+        let messages = diagnostics
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            [
+                "4:1: error[unknown-marker]: unknown marker \\xyz",
+                "6:28: error[missing-endmarker]: expected end tag",
+                "8:4: error[invalid-number]: expected chapter number",
+            ]
+        );
+        let root = doc.nodes.expect("root");
+        let chapters = root
+            .content
+            .iter()
+            .filter_map(|c| match c {
+                Content::Chapter(node) => {
+                    Some(node.content.iter().map(|p| p.node().map(Node::text)))
+                }
+                _ => None,
+            })
+            .map(|paras| paras.flatten().collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        assert_eq!(chapters, [vec!["Good", "Fine"], vec!["Recovered"]]);
+
+        // Lines ended by a lone `\r` are skipped alike.
+        let cr = source.replace('\n', "\r");
+        let (doc, diagnostics) = super::Document::from_str_lenient(&cr);
+        let cr_messages = diagnostics
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        assert_eq!(cr_messages, messages);
+        assert_eq!(doc.nodes, Some(root));
+
+        let strict = "\\id MRK\n\\c 1\n\\p \\v 1 Good\n";
+        let (doc, diagnostics) = super::Document::from_str_lenient(strict);
+        assert_eq!(diagnostics, []);
+        assert_eq!(doc, strict.parse().expect("Document"));
+    }
+
+    #[test]
+    fn whitespace_handling() {
+        let source = "\\id MRK\n\\c 1\n\\q1 \\v 1 Lift   up \\nd Lord\\nd*\n  \\nd God\\nd*\n\\q2 on\n   high\n";
+        let with = |whitespace| {
+            let options = ParseOptions {
+                whitespace,
+                ..ParseOptions::default()
+            };
+            let doc = Document::from_str_with(source, options).expect("Document");
+            let root = doc.nodes.expect("root");
+            root.iter()
+                .filter_map(|item| match item {
+                    Content::Text(text) => Some(text.as_str().to_owned()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            with(WhitespaceHandling::UsfmDefault),
+            ["Lift   up ", "Lord", " ", "God", "on\n   high"]
+        );
+        assert_eq!(
+            with(WhitespaceHandling::ReduceToSingle),
+            ["Lift up ", "Lord", " ", "God", "on high"]
+        );
+        assert_eq!(
+            with(WhitespaceHandling::Preserve),
+            ["Lift   up ", "Lord", "\n  ", "God", "on\n   high"]
+        );
+    }
+
+    #[test]
+    fn no_break_space() {
+        let source = "\\id MRK\n\\c 1\n\\p \\v 1 a~b \\~ c\\\\d a\\/\\/b\n";
+        let doc: Document = source.parse().expect("Document");
+        let root = doc.root().expect("root");
+        let inline = root
+            .iter()
+            .filter_map(|item| match item {
+                Content::Text(text) => Some(text.as_str().to_owned()),
+                Content::NoBreakSpace => Some("~".to_owned()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(inline, ["a", "~", "b ~ c\\d a//b"]);
+        let written = doc.to_string();
+        assert_eq!(
+            written,
+            "\\id MRK\n\\c 1\n\\p\n\\v 1 a~b \\~ c\\\\d a\\//b\n"
+        );
+        assert_eq!(
+            written.parse::<Document>().expect("Document").root(),
+            doc.root()
+        );
+
+        let doc = source.parse::<Document>().expect("Document");
+        let spans = doc
+            .root()
+            .expect("root")
+            .iter()
+            .filter_map(|item| match item {
+                Content::Text(text) => text.span.map(|span| &source[span.range()]),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(spans, ["a", "b \\~ c\\\\d a\\/\\/b"]);
+    }
+
+    #[test]
+    fn line_endings() {
+        let source = "\\id MRK\r\\c 1\r\\p\r\\v 1 Text\rover lines\r\n\\v 2 More\r";
+        let doc = source.parse::<Document>().expect("Document");
+        assert_eq!(doc.line_ending(), LineEnding::Cr);
+        let texts = doc
+            .root()
+            .expect("root")
+            .iter()
+            .filter_map(|item| match item {
+                Content::Text(text) => Some((text.as_str().to_owned(), text.span.expect("span"))),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(texts[0].0, "Text\nover lines ");
+        assert_eq!(texts[1].0, "More");
+        assert_eq!((texts[1].1.start.line, texts[1].1.start.column), (6, 6));
+        assert_eq!(
+            doc.to_string(),
+            "\\id MRK\r\\c 1\r\\p\r\\v 1 Text over lines\r\\v 2 More\r"
+        );
+
+        let strict = ParseOptions {
+            strict_line_endings: true,
+            ..ParseOptions::default()
+        };
+        assert!(Document::from_str_with(source, strict).is_err());
+        assert!(Document::from_str_with("\\id MRK\r\n\\c 1\r\n", strict).is_ok());
+        // A `\z` marker after a lone `\r` starts a paragraph.
+        let custom = "\\id MRK\n\\c 1\n\\p \\v 1 a\n\\zq b\n";
+        assert_eq!(
+            custom
+                .replace('\n', "\r")
+                .parse::<Document>()
+                .expect("Document")
+                .nodes,
+            custom.parse::<Document>().expect("Document").nodes
+        );
+        assert_eq!(
+            "\\id MRK\r\n\\c 1\n\\p\r\n"
+                .parse::<Document>()
+                .expect("Document")
+                .line_ending(),
+            LineEnding::CrLf
+        );
+    }
+
+    #[test]
+    fn header_metadata() {
+        let doc: Document = "\\id MRK 41MRKENG  Free Bible\n\\rem First draft\n\\h Mark\n\
+                             \\mt1 Mark\n\\c 1\n\\rem Check verse 2\n\\p \\v 1 Text\n"
+            .parse()
+            .expect("Document");
+        assert_eq!(doc.book_code(), Some("MRK"));
+        assert_eq!(doc.id_line().as_deref(), Some("41MRKENG  Free Bible"));
+        assert_eq!(doc.running_header().as_deref(), Some("Mark"));
+        assert_eq!(doc.remarks(), ["First draft", "Check verse 2"]);
+
+        let doc: Document = "\\id MRK\n\\h1 Mark\n".parse().expect("Document");
+        assert_eq!(doc.id_line(), None);
+        assert_eq!(doc.running_header().as_deref(), Some("Mark"));
+        assert!(doc.remarks().is_empty());
+    }
+
+    #[test]
+    fn unknown_markers() {
+        let source =
+            "\\id MRK\n\\c 1\n\\p \\v 1 In \\xyz the\\xyz* \\abc start\n\\yy1 odd \\ww line\n";
+        let with = |unknown_markers| {
+            let options = ParseOptions {
+                unknown_markers,
+                ..ParseOptions::default()
+            };
+            let doc = Document::from_str_with(source, options).expect("Document");
+            let Some(Content::Chapter(chapter)) = doc.nodes.expect("root").content.pop() else {
+                panic!("expected a chapter");
+            };
+            chapter.content
+        };
+
+        assert!(source.parse::<Document>().is_err());
+        assert_eq!(
+            with(UnknownMarkers::Heuristic),
+            [
+                Content::Para(Node {
+                    style: "p".into(),
+                    content: vec![
+                        Content::Verse(Node {
+                            style: "v".into(),
+                            attributes: [("number".into(), "1".into())].into(),
+                            ..Node::default()
+                        }),
+                        "In ".into(),
+                        Content::Char(Node {
+                            style: "xyz".into(),
+                            content: vec!["the".into()],
+                            ..Node::default()
+                        }),
+                        " ".into(),
+                        Content::Char(Node {
+                            style: "abc".into(),
+                            content: vec!["start".into()],
+                            ..Node::default()
+                        }),
+                    ],
+                    ..Node::default()
+                }),
+                Content::Para(Node {
+                    style: "yy1".into(),
+                    content: vec![
+                        "odd ".into(),
+                        Content::Char(Node {
+                            style: "ww".into(),
+                            content: vec!["line".into()],
+                            ..Node::default()
+                        }),
+                    ],
+                    ..Node::default()
+                }),
+            ]
+        );
+        assert_eq!(
+            with(UnknownMarkers::Preserve),
+            [
+                Content::Para(Node {
+                    style: "p".into(),
+                    content: vec![
+                        Content::Verse(Node {
+                            style: "v".into(),
+                            attributes: [("number".into(), "1".into())].into(),
+                            ..Node::default()
+                        }),
+                        "In ".into(),
+                        Content::Unknown(Node {
+                            style: "xyz".into(),
+                            content: vec!["the".into()],
+                            ..Node::default()
+                        }),
+                        " ".into(),
+                        Content::Unknown(Node {
+                            style: "abc".into(),
+                            ..Node::default()
+                        }),
+                        "start".into(),
+                    ],
+                    ..Node::default()
+                }),
+                Content::Unknown(Node {
+                    style: "yy1".into(),
+                    content: vec![
+                        "odd ".into(),
+                        Content::Unknown(Node {
+                            style: "ww".into(),
+                            ..Node::default()
+                        }),
+                        "line".into(),
+                    ],
+                    ..Node::default()
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn custom_markers() {
+        let mut state = State::new();
+        assert!(Arc::ptr_eq(&state.markers, &State::new().markers));
+        let markers = std::mem::take(state.markers_mut());
+        *state.markers_mut() = markers
+            .update_from_str("\\marker zsec1\n\\category sectionpara\n")
+            .expect("Extensions");
+        assert!(!State::usfm_ext().contains("zsec1"));
+        let doc = state
+            .parse(
+                "\\id MRK\n\\c 1\n\\zsec1 Heading\n\\zpara \\v 1 In \\zw the\\zw* \\zq-s |who=\"x\"\\*word\\zq-e\\*\n",
+            )
+            .expect("Document");
+        let Some(Content::Chapter(chapter)) = doc.nodes.expect("root").content.pop() else {
+            panic!("expected a chapter");
+        };
+        let custom = |style: &'static str, content: Vec<Content<'static>>| Node {
+            style: style.into(),
+            custom: true,
+            content,
+            ..Node::default()
+        };
+        assert_eq!(
+            chapter.content,
+            [
+                Content::Para(Node {
+                    attributes: [("level".into(), "1".into())].into(),
+                    ..custom("zsec1", vec!["Heading".into()])
+                }),
+                Content::Para(custom(
+                    "zpara",
+                    vec![
+                        Content::Verse(Node {
+                            style: "v".into(),
+                            attributes: [("number".into(), "1".into())].into(),
+                            ..Node::default()
+                        }),
+                        "In ".into(),
+                        Content::Char(custom("zw", vec!["the".into()])),
+                        " ".into(),
+                        Content::Milestone(Node {
+                            attributes: [("who".into(), "x".into())].into(),
+                            ..custom("zq-s", vec![])
+                        }),
+                        "word".into(),
+                        Content::Milestone(custom("zq-e", vec![])),
+                    ]
+                )),
+            ]
+        );
+    }
+
+    #[test]
+    fn shared_markers() {
+        let bundled = Extensions::usfm_shared(Version::default());
+        assert!(Arc::ptr_eq(&State::new().markers, bundled));
+        assert!(Arc::ptr_eq(
+            &State::for_version(Version::V3_1).markers,
+            Extensions::usfm_shared(Version::V3_1)
+        ));
+
+        let markers = Arc::new(
+            bundled
+                .as_ref()
+                .clone()
+                .update_from_str("\\marker pp\n\\category versepara\n")
+                .expect("Extensions"),
+        );
+        let parse = |source| {
+            Document::from_str_with_markers(source, Arc::clone(&markers), ParseOptions::default())
+                .expect("Document")
+        };
+        let mark = parse("\\id MRK\n\\c 1\n\\pp \\v 1 In\n");
+        let luke = parse("\\id LUK\n\\c 1\n\\pp \\v 1 Since\n");
+        assert!(Arc::ptr_eq(
+            mark.markers.as_ref().expect("markers"),
+            &markers
+        ));
+        assert!(Arc::ptr_eq(
+            luke.markers.as_ref().expect("markers"),
+            &markers
+        ));
+
+        // Adding a marker copies the bundled set and leaves it as it was.
+        let len = bundled.len();
+        let mut state = State::new();
+        let copy = std::mem::take(state.markers_mut());
+        *state.markers_mut() = copy
+            .update_from_str("\\marker zsec1\n\\category sectionpara\n")
+            .expect("Extensions");
+        assert!(!Arc::ptr_eq(&state.markers, bundled));
+        assert!(state.markers.contains("zsec1"));
+        assert!(!bundled.contains("zsec1"));
+        assert_eq!(bundled.len(), len);
+        assert!(Arc::ptr_eq(&State::new().markers, bundled));
+    }
+
+    #[test]
+    fn stylesheet_markers() {
+        let sty = "\\Marker pp\n\\OccursUnder c\n\\TextType VerseText\n\\StyleType Paragraph\n\n\
+                   \\Marker qq\n\\Endmarker qq*\n\\OccursUnder pp\n\\StyleType Character\n";
+        let markers = Extensions::from_sty_str(sty).expect("Extensions");
+        let source = "\\id MRK\n\\c 1\n\\pp \\v 1 \\qq word\\qq*\n";
+        assert!(source.parse::<Document>().is_err());
+        let doc = Document::from_str_with_markers(source, markers, ParseOptions::default())
+            .expect("Document");
+        assert_eq!(doc.to_string(), source.replace("\\pp \\v", "\\pp\n\\v"));
+    }
+
+    #[test]
+    fn versioned_markers() {
+        // Without `\usfm` the source is 2.x, which has no milestones.
+        let milestone = "\\id MRK\n\\c 1\n\\p \\v 1 \\qt-s\\*Said\\qt-e\\*\n";
+        assert!(milestone.parse::<Document>().is_err());
+        let usfm3 = milestone.replace("\\c 1", "\\usfm 3.0\n\\c 1");
+        assert!(usfm3.parse::<Document>().is_ok());
+        let usfm2 = milestone.replace("\\c 1", "\\usfm 2.4\n\\c 1");
+        assert!(usfm2.parse::<Document>().is_err());
+        let options = ParseOptions {
+            version: Version::V3_0,
+            ..ParseOptions::default()
+        };
+        assert!(Document::from_str_with(milestone, options).is_ok());
+
+        let reference =
+            "\\id MRK\n\\usfm 3.1\n\\c 1\n\\p \\v 1 See \\ref Mark 1|loc=\"MRK 1:1\"\\ref*\n";
+        let doc: Document = reference.parse().expect("Document");
+        let root = doc.nodes.expect("root");
+        assert_eq!(root.attributes["version"], "3.1");
+        assert!(reference.replace("3.1", "3.0").parse::<Document>().is_err());
+
+        assert!(Extensions::usfm(Version::V3_0).contains_key("jmp"));
+        assert!(!Extensions::usfm(Version::V2).contains_key("jmp"));
+        assert!(Extensions::usfm(Version::V2).contains_key("esb"));
+        assert!(Extensions::usfm(Version::V3_1).contains_key("ref"));
+        assert_eq!(Version::from_number(2.4), Version::V2);
+        assert_eq!(Version::from_number(3.0), Version::V3_0);
+        assert_eq!(Version::from_number(3.1), Version::V3_1);
+    }
+
+    #[test]
+    fn book_registry() {
+        let doc: Document = "\\id MRK Good News\n\\c 1\n\\p \\v 1 In\n"
+            .parse()
+            .expect("Document");
+        let book = doc.book().expect("book");
+        assert_eq!((book.code, book.name, book.number), ("MRK", "Mark", 41));
+
+        let error = "\\id MRX\n\\c 1\n\\p \\v 1 In\n"
+            .parse::<Document>()
+            .expect_err("unknown book");
+        assert_eq!(
+            error.to_string(),
+            "1:5: error[unknown-book]: unknown book code"
+        );
+    }
+
+    #[test]
+    fn borrowed() {
+        let source =
+            String::from("\\id MRK\n\\c 1\n\\p \\v 1 In the \\w beginning|lemma=\"arche\"\\w*\n");
+        let doc = Document::from_str_with(&source, ParseOptions::default()).expect("Document");
+        let text = doc
+            .iter()
+            .find_map(|item| match item {
+                Content::Text(text) => Some(text),
+                _ => None,
+            })
+            .expect("text");
+        assert!(matches!(text.text, Cow::Borrowed("In the ")));
+        let word = doc.find_all("w").next().expect("w");
+        assert!(matches!(word.style, Cow::Borrowed("w")));
+        assert!(matches!(word.attributes["lemma"], Cow::Borrowed("arche")));
+
+        // Text ending a line before the next verse stays one borrowed run.
+        let preserve = ParseOptions {
+            whitespace: WhitespaceHandling::Preserve,
+            ..ParseOptions::default()
+        };
+        let lines = "\\id MRK\n\\c 1\n\\p \\v 1 In the beginning\n\\v 2 was\n";
+        let preserved = Document::from_str_with(lines, preserve).expect("Document");
+        assert!(preserved.iter().any(|item| matches!(
+            item,
+            Content::Text(super::Text {
+                text: Cow::Borrowed("In the beginning\n"),
+                ..
+            })
+        )));
+
+        let owned = doc.into_owned();
+        drop(source);
+        assert_eq!(
+            owned.to_string(),
+            "\\id MRK\n\\c 1\n\\p\n\\v 1 In the \\w beginning|lemma=\"arche\"\\w*\n"
+        );
+    }
+
+    #[test]
+    fn fragments() {
+        let mrk = books::get("MRK").unwrap();
+        let context = FragmentContext {
+            book: mrk,
+            chapter: Some(3),
+        };
+        let source = "\\v 4 In \\nd God\\nd* \\v 5 and\n\\q1 \\v 6 then\n\\c 4\n\\p \\v 2 Later\n";
+        let doc = Document::parse_fragment(source, context).expect("fragment");
+        assert_eq!(doc.fragment(), Some(&context));
+        assert_eq!(doc.book(), Some(mrk));
+        assert_eq!(
+            doc.verse_text(&Reference::new(mrk, 3, Some(6))).as_deref(),
+            Some("then")
+        );
+        assert_eq!(
+            doc.to_string(),
+            "\\v 4 In \\nd God\\nd*\n\\v 5 and\n\\q1\n\\v 6 then\n\\c 4\n\\p\n\\v 2 Later\n"
+        );
+        let preserve = Options {
+            whitespace: Whitespace::Preserve,
+            ..Options::default()
+        };
+        assert_eq!(doc.to_usfm(preserve).to_string(), source);
+
+        // Chapters need not start at verse 1.
+        assert!(doc.validate(State::usfm_ext()).is_empty());
+        assert!(doc.check_continuity(None).is_empty());
+
+        // Without a chapter, the verses come before any.
+        let context = FragmentContext {
+            chapter: None,
+            ..context
+        };
+        let doc = Document::parse_fragment("\\p \\v 7 More\n", context).expect("fragment");
+        assert_eq!(doc.fragment().unwrap().chapter, None);
+        assert!(doc.check_continuity(None).is_empty());
+        assert_eq!(doc.to_string(), "\\p\n\\v 7 More\n");
+
+        // A whole book is not a fragment.
+        let doc =
+            Document::parse_fragment("\\id MRK\n\\c 1\n\\p \\v 1 In\n", context).expect("book");
+        assert!(!doc.is_fragment());
+    }
 }
@@ -0,0 +1,555 @@
+//! Changing a parsed document.
+//!
+//! Each edit drops the source span of the nodes it touches, so the writer
+//! regenerates them rather than copying the original text.
+
+use std::{borrow::Cow, io, ops::ControlFlow, ops::Range};
+
+use crate::{
+    diagnostic::Diagnostic,
+    document::{Content, Document, Node, Position, Span, State, Text},
+    reference::Reference,
+    versification::verse_range,
+    visit::{walk_node_mut, VisitMut},
+};
+
+impl<'i> Node<'i> {
+    pub fn insert_child(&mut self, index: usize, item: impl Into<Content<'i>>) {
+        self.span = None;
+        self.content.insert(index, item.into());
+    }
+
+    pub fn push_child(&mut self, item: impl Into<Content<'i>>) {
+        self.span = None;
+        self.content.push(item.into());
+    }
+
+    /// Panics if `index` is out of bounds.
+    pub fn remove_child(&mut self, index: usize) -> Content<'i> {
+        self.span = None;
+        self.content.remove(index)
+    }
+
+    /// Panics if `index` is out of bounds.
+    pub fn replace_child(&mut self, index: usize, item: impl Into<Content<'i>>) -> Content<'i> {
+        self.span = None;
+        std::mem::replace(&mut self.content[index], item.into())
+    }
+
+    pub fn set_attribute(
+        &mut self,
+        name: impl Into<Cow<'i, str>>,
+        value: impl Into<Cow<'i, str>>,
+    ) -> Option<Cow<'i, str>> {
+        self.span = None;
+        self.attributes.insert(name.into(), value.into())
+    }
+
+    pub fn remove_attribute(&mut self, name: &str) -> Option<Cow<'i, str>> {
+        let res = self.attributes.remove(name);
+        if res.is_some() {
+            self.span = None;
+        }
+        res
+    }
+}
+
+impl<'i> Document<'i> {
+    pub fn root_mut(&mut self) -> Option<&mut Node<'i>> {
+        self.nodes.as_mut()
+    }
+
+    /// Replace the content of a verse, from its `\v` marker up to the next
+    /// verse or the end of its paragraph, returning what was there. Text of
+    /// the verse continuing into later paragraphs is left alone.
+    pub fn replace_verse(
+        &mut self,
+        reference: &Reference,
+        mut content: Vec<Content<'i>>,
+    ) -> Option<Vec<Content<'i>>> {
+        let verse = reference.verse?;
+        if self.book()? != reference.book {
+            return None;
+        }
+        let root = self.nodes.as_mut()?;
+        let chapter = root.content.iter_mut().find_map(|item| match item {
+            Content::Chapter(node)
+                if node.attributes.get("number").and_then(|n| n.parse().ok())
+                    == Some(reference.chapter) =>
+            {
+                Some(node)
+            }
+            _ => None,
+        })?;
+        let res = replace_verse(chapter, verse, &mut content)?;
+        root.span = None;
+        Some(res)
+    }
+}
+
+impl<'i> Document<'i> {
+    /// Replace `range` of the source text with `replacement` and update the
+    /// tree to match. Where the edit falls within the paragraphs of a
+    /// chapter only those around it are reparsed and spliced in, otherwise
+    /// the whole book is. The document must have been parsed from USFM, so
+    /// that it has source spans, and is left as it was if the edited text
+    /// does not parse.
+    pub fn apply_edit(&mut self, range: Range<usize>, replacement: &str) -> io::Result<()> {
+        let source = self.source();
+        if range.start > range.end
+            || !source.is_char_boundary(range.start)
+            || !source.is_char_boundary(range.end)
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("edit range {range:?} is outside the source"),
+            ));
+        }
+        if self.nodes.as_ref().is_none_or(|root| root.span.is_none()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "document has no source spans",
+            ));
+        }
+        let text = [&source[..range.start], replacement, &source[range.end..]].concat();
+        let delta = replacement.len() as isize - range.len() as isize;
+        if !self.reparse(&text, range, delta) {
+            let parser = self.parser();
+            let doc = match self.fragment() {
+                Some(&context) => parser.parse_fragment(&text, context),
+                None => parser.parse(&text),
+            };
+            *self = doc?.into_owned();
+            return Ok(());
+        }
+        self.set_source(text);
+        Ok(())
+    }
+
+    /// Apply the fixes suggested by `diagnostics` to the source and parse
+    /// the result leniently with the document's own marker set and options,
+    /// returning the problems still found by the parser. A fix repeated by several
+    /// diagnostics is applied once, and one overlapping a fix before it is
+    /// left out.
+    pub fn apply_fixes(&mut self, diagnostics: &[Diagnostic]) -> Vec<Diagnostic> {
+        let mut fixes = diagnostics
+            .iter()
+            .filter_map(|d| d.fix.as_ref())
+            .collect::<Vec<_>>();
+        fixes.sort_by_key(|fix| (fix.span.start.offset, fix.span.end.offset));
+        fixes.dedup();
+        let source = self.source();
+        let mut text = String::with_capacity(source.len());
+        let mut end = 0;
+        for fix in fixes {
+            let range = fix.span.range();
+            if range.start < end
+                || range.end > source.len()
+                || !source.is_char_boundary(range.start)
+                || !source.is_char_boundary(range.end)
+            {
+                continue;
+            }
+            text.push_str(&source[end..range.start]);
+            text.push_str(&fix.replacement);
+            end = range.end;
+        }
+        text.push_str(&source[end..]);
+        let (doc, diagnostics) = self.parser().parse_lenient(&text);
+        *self = doc.into_owned();
+        diagnostics
+    }
+
+    // Reparse the blocks of the chapter touched by an edit, together with
+    // one block either side so that merged or split paragraphs and regrouped
+    // poetry and lists come out as a full parse would have them. Milestones
+    // are linked across the whole book, so they always need a full parse.
+    fn reparse(&mut self, text: &str, range: Range<usize>, delta: isize) -> bool {
+        let mut state = self.parser();
+        let Some(root) = self.nodes.as_mut() else {
+            return false;
+        };
+        let Some((index, chapter)) = root.content.iter_mut().enumerate().find_map(|(n, item)| {
+            match item {
+                Content::Chapter(node) => Some((n, node)),
+                _ => None,
+            }
+            .filter(|(_, node)| {
+                node.span
+                    .is_some_and(|s| s.start.offset < range.start && range.end <= s.end.offset)
+            })
+        }) else {
+            return false;
+        };
+        let spans = chapter
+            .content
+            .iter()
+            .map(|item| item.node().and_then(|node| node.span))
+            .collect::<Option<Vec<_>>>();
+        let Some(spans) = spans else {
+            return false;
+        };
+        let touched = |s: &Span| s.start.offset <= range.end && range.start <= s.end.offset;
+        let (Some(first), Some(last)) = (
+            spans.iter().position(touched),
+            spans.iter().rposition(touched),
+        ) else {
+            return false;
+        };
+        if range.start < spans[first].start.offset {
+            return false;
+        }
+        let (first, last) = (first.saturating_sub(1), (last + 1).min(spans.len() - 1));
+        let old = spans[first].start.offset..spans[last].end.offset;
+        let new = old.start..old.end.saturating_add_signed(delta);
+
+        let Some(blocks) = state.reparse_blocks(text, new) else {
+            return false;
+        };
+        let has_milestone = |content: &[Content]| {
+            content.iter().any(|item| {
+                item.node().is_some_and(|node| {
+                    node.iter()
+                        .any(|item| matches!(item, Content::Milestone(_)))
+                })
+            })
+        };
+        if has_milestone(&blocks) || has_milestone(&chapter.content[first..=last]) {
+            return false;
+        }
+        let blocks: Vec<Content<'static>> = blocks.into_iter().map(Content::into_owned).collect();
+
+        let mut shift = Shift {
+            from: old.end,
+            delta,
+            state: &state,
+        };
+        for item in &mut chapter.content[last + 1..] {
+            let _ = shift.visit_content_mut(item);
+        }
+        chapter.span = chapter.span.map(|s| shift.span(s));
+        chapter.content.splice(first..=last, blocks);
+        for item in &mut root.content[index + 1..] {
+            let _ = shift.visit_content_mut(item);
+        }
+        root.span = root.span.map(|s| shift.span(s));
+        true
+    }
+}
+
+// Moves the spans at or after the end of a reparsed region to where they
+// are in the edited source.
+struct Shift<'s, 'i> {
+    from: usize,
+    delta: isize,
+    state: &'s State<'i>,
+}
+
+impl Shift<'_, '_> {
+    fn position(&self, position: Position) -> Position {
+        if position.offset < self.from {
+            return position;
+        }
+        self.state
+            .position_at(position.offset.saturating_add_signed(self.delta))
+    }
+
+    fn span(&self, span: Span) -> Span {
+        Span {
+            start: self.position(span.start),
+            end: self.position(span.end),
+        }
+    }
+}
+
+impl VisitMut for Shift<'_, '_> {
+    fn visit_node_mut(&mut self, node: &mut Node) -> ControlFlow<()> {
+        node.span = node.span.map(|s| self.span(s));
+        walk_node_mut(self, node)
+    }
+
+    fn visit_text_mut(&mut self, text: &mut Text) -> ControlFlow<()> {
+        text.span = text.span.map(|s| self.span(s));
+        ControlFlow::Continue(())
+    }
+}
+
+fn replace_verse<'i>(
+    node: &mut Node<'i>,
+    verse: u32,
+    content: &mut Vec<Content<'i>>,
+) -> Option<Vec<Content<'i>>> {
+    let is_verse = |item: &Content| matches!(item, Content::Verse(_));
+    let found = node.content.iter().position(|item| match item {
+        Content::Verse(v) => v
+            .attributes
+            .get("number")
+            .and_then(|n| verse_range(n))
+            .is_some_and(|(first, last)| (first..=last).contains(&verse)),
+        _ => false,
+    });
+    let res = match found {
+        Some(start) => {
+            let start = start + 1;
+            let end = node.content[start..]
+                .iter()
+                .position(is_verse)
+                .map_or(node.content.len(), |n| start + n);
+            if end < node.content.len() {
+                if let Some(Content::Text(text)) = content.last_mut() {
+                    if !text.text.ends_with(char::is_whitespace) {
+                        text.text.to_mut().push(' ');
+                    }
+                }
+            }
+            node.content.splice(start..end, content.drain(..)).collect()
+        }
+        None => node
+            .content
+            .iter_mut()
+            .filter_map(Content::node_mut)
+            .find_map(|child| replace_verse(child, verse, content))?,
+    };
+    node.span = None;
+    Some(res)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{borrow::Cow, sync::Arc};
+
+    use crate::{
+        document::{Content, Document, Node, ParseOptions, Span, UnknownMarkers},
+        extension::{Extensions, Version},
+        reference::Reference,
+        validate::balance,
+        writer::{Options, Whitespace},
+    };
+
+    const PRESERVE: Options = Options {
+        whitespace: Whitespace::Preserve,
+        line_ending: None,
+    };
+
+    #[test]
+    fn edit_nodes() {
+        let source = "\\id MRK\n\\c 1\n\\p\n\\v 1  In the  \\nd Lord\\nd*\n\n\\p  \\v 2 said\n";
+        let mut doc = source.parse::<Document>().expect("Document");
+        let Some(Content::Chapter(chapter)) = doc.root_mut().unwrap().content.last_mut() else {
+            panic!("expected chapter");
+        };
+        let Some(Content::Para(para)) = chapter.content.first_mut() else {
+            panic!("expected paragraph");
+        };
+        let Some(Content::Char(nd)) = para.content.last_mut() else {
+            panic!("expected char");
+        };
+        assert_eq!(nd.set_attribute("x-note", "edited"), None);
+        para.push_child(Content::Char(Node {
+            style: "w".into(),
+            content: vec!["word".into()],
+            ..Node::default()
+        }));
+        let Content::Text(text) = para.remove_child(1) else {
+            panic!("expected text");
+        };
+        assert_eq!(text.as_str(), "In the  ");
+        assert_eq!(
+            doc.to_usfm(PRESERVE).to_string(),
+            "\\id MRK\n\\c 1\n\\p\n\\v 1  \\nd Lord|x-note=\"edited\"\\nd*\\w word\\w*\n\\p  \\v 2 said\n"
+        );
+    }
+
+    #[test]
+    fn replace_verse() {
+        let source =
+            "\\id MRK\n\\c 1\n\\p \\v 1 old one \\v 2-3 old \\nd two\\nd*\n\\q1 \\v 4 four\n";
+        let mut doc = source.parse::<Document>().expect("Document");
+        let reference = |s: &str| s.parse::<Reference>().unwrap();
+        let old = doc
+            .replace_verse(&reference("MRK 1:1"), vec!["new one".into()])
+            .expect("verse 1");
+        assert!(matches!(&old[..], [Content::Text(text)] if text.as_str() == "old one "));
+        let old = doc
+            .replace_verse(&reference("MRK 1:3"), vec!["new three".into()])
+            .expect("verse 3");
+        assert_eq!(old.len(), 2);
+        assert_eq!(
+            doc.replace_verse(&reference("MRK 1:5"), vec!["five".into()]),
+            None
+        );
+        assert_eq!(
+            doc.replace_verse(&reference("MRK 2:1"), vec!["one".into()]),
+            None
+        );
+        assert_eq!(
+            doc.to_usfm(PRESERVE).to_string(),
+            "\\id MRK\n\\c 1\n\\p\n\\v 1 new one \\v 2-3 new three\n\\q1 \\v 4 four\n"
+        );
+    }
+    // Every span in the tree, as equality leaves them out.
+    fn spans(doc: &Document) -> Vec<Option<Span>> {
+        fn walk(content: &[Content], res: &mut Vec<Option<Span>>) {
+            for item in content {
+                match item {
+                    Content::Text(text) => res.push(text.span),
+                    item => {
+                        if let Some(node) = item.node() {
+                            res.push(node.span);
+                            walk(&node.content, res);
+                        }
+                    }
+                }
+            }
+        }
+        let mut res = Vec::new();
+        if let Some(root) = &doc.nodes {
+            res.push(root.span);
+            walk(&root.content, &mut res);
+        }
+        res
+    }
+
+    #[test]
+    fn apply_edit() {
+        let source = "\\id MRK\n\\c 1\n\\s1 Heading\n\\p \\v 1 In the beginning\n\\q1 \\v 2 a voice\n\\q2 crying\n\\p \\v 3 end\n\\c 2\n\\p \\v 1 more \\nd Lord\\nd*\n";
+        let edits: &[(&str, &str)] = &[
+            ("beginning", "start"),
+            ("a voice", "a voice\n\\q1 calling"),
+            ("\n\\q2 crying", " crying"),
+            ("end\n", "end\n\\p \\v 4 after\n"),
+            ("\\v 1 more", "\\v 1 much more"),
+            ("\\c 2", "\\c 3"),
+            ("Heading\n\\p", "Heading\n\\m"),
+            ("\\id MRK", "\\id LUK"),
+        ];
+        for (old, new) in edits {
+            let mut doc = source.parse::<Document>().expect("Document");
+            let start = source.find(old).expect("edit");
+            doc.apply_edit(start..start + old.len(), new)
+                .expect("apply_edit");
+            let edited = source.replacen(old, new, 1);
+            let reparsed = &edited.parse::<Document>().expect("reparsed");
+            assert_eq!(doc.nodes, reparsed.nodes, "replacing {old:?} with {new:?}");
+            assert_eq!(
+                spans(&doc),
+                spans(reparsed),
+                "replacing {old:?} with {new:?}"
+            );
+            assert_eq!(doc.to_usfm(PRESERVE).to_string(), edited);
+        }
+    }
+
+    #[test]
+    fn apply_edit_incrementally() {
+        let source = "\\id MRK\n\\c 1\n\\p \\v 1 one\n\\c 2\n\\p \\v 1 two\n\\p \\v 2 three\n";
+        let mut doc = Document::from_str_with(source, ParseOptions::default()).expect("Document");
+        let at = source.find("three").unwrap();
+        doc.apply_edit(at..at, "and ").expect("apply_edit");
+        fn text<'d>(doc: &'d Document, chapter: usize) -> &'d Cow<'d, str> {
+            let chapter = doc.find_all("c").nth(chapter).unwrap();
+            let para = chapter.content.last().unwrap().node().unwrap();
+            match para.content.last() {
+                Some(Content::Text(text)) => &text.text,
+                _ => panic!("expected text"),
+            }
+        }
+        assert!(matches!(text(&doc, 0), Cow::Borrowed("one")));
+        assert!(matches!(text(&doc, 1), Cow::Owned(_)));
+        assert_eq!(text(&doc, 1).as_ref(), "and three");
+
+        assert!(doc.apply_edit(at..at, "\\c x ").is_err());
+        assert_eq!(
+            doc.to_usfm(PRESERVE).to_string(),
+            source.replace("three", "and three")
+        );
+        assert!(doc.apply_edit(at..source.len() + 10, "").is_err());
+        let mut plain: Document = source.parse().unwrap();
+        plain.clear_spans();
+        assert!(plain.apply_edit(at..at, "and ").is_err());
+    }
+
+    #[test]
+    fn apply_edit_with_markers() {
+        let markers = Extensions::usfm(Version::default())
+            .clone()
+            .update_from_str("\\marker pp\n\\category versepara\n")
+            .expect("Extensions");
+        let markers = Arc::new(markers);
+        let options = ParseOptions {
+            unknown_markers: UnknownMarkers::Heuristic,
+            ..ParseOptions::default()
+        };
+        let source = "\\id MRK\n\\c 1\n\\pp \\v 1 one \\xyz odd\\xyz*\n\\c 2\n\\pp \\v 1 two\n";
+        let parse = |source: &str| {
+            Document::from_str_with_markers(source, Arc::clone(&markers), options)
+                .map(Document::into_owned)
+        };
+        assert!(source.parse::<Document>().is_err());
+        // Within a chapter, then across one.
+        for (old, new) in [("one", "first"), ("\\c 2", "\\c 3")] {
+            let mut doc = parse(source).expect("Document");
+            let start = source.find(old).expect("edit");
+            doc.apply_edit(start..start + old.len(), new)
+                .expect("apply_edit");
+            let edited = source.replacen(old, new, 1);
+            let reparsed = parse(&edited).expect("reparsed");
+            assert_eq!(doc.nodes, reparsed.nodes, "replacing {old:?} with {new:?}");
+            assert_eq!(
+                spans(&doc),
+                spans(&reparsed),
+                "replacing {old:?} with {new:?}"
+            );
+            assert_eq!(doc.to_usfm(PRESERVE).to_string(), edited);
+        }
+    }
+
+    #[test]
+    fn apply_fixes() {
+        let source = "\\id MRK\n\\c 1\n\\p \\v1 In the \\nd Lord \\+w God\\nd* said\n\
+                      \\p \\v 2 and \\fig Map|map.png|col|1:2\\fig*\n\\p \\v 3 the \\f + \\ft note\n\
+                      \\p \\v 4 \\w grace|lemma=\"grace\\w* end\n";
+        let markers = Extensions::usfm(Version::default());
+        let (mut doc, mut diagnostics) = Document::from_str_lenient(source);
+        diagnostics.extend(balance(source, markers));
+        let fixes = diagnostics.iter().filter_map(|d| d.fix.as_ref());
+        assert_eq!(
+            fixes
+                .map(|fix| fix.replacement.as_str())
+                .collect::<Vec<_>>(),
+            [" ", "Map|map.png|col||||1:2", "\"", "\\+w*", "\\f*"]
+        );
+        assert_eq!(doc.apply_fixes(&diagnostics), []);
+        let fixed = doc.to_usfm(PRESERVE).to_string();
+        assert_eq!(
+            fixed,
+            "\\id MRK\n\\c 1\n\\p \\v 1 In the \\nd Lord \\+w God\\+w*\\nd* said\n\
+             \\p \\v 2 and \\fig Map|map.png|col||||1:2\\fig*\n\\p \\v 3 the \\f + \\ft note\\f*\n\
+             \\p \\v 4 \\w grace|lemma=\"grace\"\\w* end\n"
+        );
+        assert_eq!(balance(&fixed, markers), []);
+    }
+
+    #[test]
+    fn apply_fixes_with_markers() {
+        let markers = Extensions::usfm(Version::default())
+            .clone()
+            .update_from_str("\\marker pp\n\\category versepara\n")
+            .expect("Extensions");
+        let markers = Arc::new(markers);
+        let options = ParseOptions {
+            unknown_markers: UnknownMarkers::Heuristic,
+            ..ParseOptions::default()
+        };
+        let source = "\\id MRK\n\\c 1\n\\pp \\v 1 one \\xyz odd\\xyz* \\nd Lord\n";
+        let mut doc = Document::from_str_with_markers(source, Arc::clone(&markers), options)
+            .expect("Document");
+        let diagnostics = balance(source, &markers);
+        assert!(diagnostics.iter().any(|d| d.fix.is_some()));
+        assert_eq!(doc.apply_fixes(&diagnostics), []);
+        let fixed = "\\id MRK\n\\c 1\n\\pp \\v 1 one \\xyz odd\\xyz* \\nd Lord\\nd*\n";
+        assert_eq!(doc.to_usfm(PRESERVE).to_string(), fixed);
+        let reparsed = Document::from_str_with_markers(fixed, markers, options).expect("Document");
+        assert_eq!(doc.nodes, reparsed.nodes);
+    }
+}
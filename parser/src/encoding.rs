@@ -0,0 +1,223 @@
+//! Reading legacy SFM files that are not UTF-8, such as Windows-1252 text
+//! from older Paratext projects, and writing them back the same way.
+
+use std::io;
+
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252};
+
+use crate::{
+    diagnostic::{Code, Diagnostic},
+    document::{Document, State},
+    writer::Options,
+};
+
+impl Document<'_> {
+    /// Parse a file in any encoding. It is taken from a UTF-16 byte order
+    /// mark if there is one, then UTF-8 if the bytes are valid UTF-8 beyond
+    /// ASCII, then from an `\ide` declaration, and Windows-1252 failing all
+    /// of those. [`Document::check_encoding`] reports a declaration that
+    /// does not match.
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Document<'static>> {
+        let (text, encoding) = decode(bytes);
+        let mut doc = State::new().parse(&text)?.into_owned();
+        doc.encoding = Some(encoding);
+        Ok(doc)
+    }
+
+    /// The encoding the document was read from by [`Document::from_bytes`].
+    pub fn encoding(&self) -> Option<&'static Encoding> {
+        self.encoding
+    }
+
+    /// The encoding named by [`Document::ide`], `None` if there is no
+    /// `\ide` or it names no known encoding.
+    pub fn declared_encoding(&self) -> Option<&'static Encoding> {
+        for_declaration(&self.ide()?)
+    }
+
+    /// A warning when `\ide` names an unknown encoding, or one other than
+    /// the encoding the document was read from.
+    pub fn check_encoding(&self) -> Vec<Diagnostic> {
+        let (Some(ide), Some(node)) = (self.ide(), self.header("ide")) else {
+            return Vec::new();
+        };
+        let span = node.span.unwrap_or_default();
+        let message = match (for_declaration(&ide), self.encoding) {
+            (None, _) => format!("\\ide names an unknown encoding {ide}"),
+            (Some(declared), Some(actual)) if declared != actual => format!(
+                "\\ide declares {} but the file is {}",
+                declared.name(),
+                actual.name()
+            ),
+            _ => return Vec::new(),
+        };
+        vec![Diagnostic::warning(Code::EncodingMismatch, span, message)]
+    }
+
+    /// Write the document in the encoding it was read from, UTF-8 when it
+    /// was not read from bytes. Fails for text the encoding cannot hold.
+    pub fn to_bytes(&self, options: Options) -> io::Result<Vec<u8>> {
+        let text = self.to_usfm(options).to_string();
+        let encoding = self.encoding.unwrap_or(UTF_8);
+        // encoding_rs only decodes UTF-16, so it is written here.
+        let utf16 = |unit: fn(u16) -> [u8; 2]| {
+            text.trim_start_matches('\u{FEFF}')
+                .encode_utf16()
+                .flat_map(unit)
+                .collect::<Vec<_>>()
+        };
+        if encoding == UTF_16LE {
+            return Ok([&[0xFF, 0xFE], &utf16(u16::to_le_bytes)[..]].concat());
+        }
+        if encoding == UTF_16BE {
+            return Ok([&[0xFE, 0xFF], &utf16(u16::to_be_bytes)[..]].concat());
+        }
+        match encoding.encode(&text) {
+            (_, _, true) => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("text cannot be written in {}", encoding.name()),
+            )),
+            (bytes, _, false) => Ok(bytes.into_owned()),
+        }
+    }
+}
+
+/// The text of `bytes` and the encoding it was decoded from, see
+/// [`Document::from_bytes`].
+pub fn decode(bytes: &[u8]) -> (String, &'static Encoding) {
+    if let Some((encoding, _)) = Encoding::for_bom(bytes).filter(|(e, _)| *e != UTF_8) {
+        let (text, _) = encoding.decode_with_bom_removal(bytes);
+        return (text.into_owned(), encoding);
+    }
+    // A UTF-8 byte order mark stays in the text, as the parser expects it.
+    // Text in any other encoding is very unlikely to be valid UTF-8, so that
+    // wins over a declaration, while plain ASCII keeps the declared one to
+    // be written back in.
+    let declared = declaration(bytes).filter(|&e| e != UTF_16LE && e != UTF_16BE);
+    let utf8 = std::str::from_utf8(bytes).ok();
+    let candidates = match utf8 {
+        Some(_) if !bytes.is_ascii() || declared.is_none() => [Some(UTF_8), None],
+        _ => [declared, Some(UTF_8)],
+    };
+    for encoding in candidates.into_iter().flatten() {
+        if let Some(text) = encoding.decode_without_bom_handling_and_without_replacement(bytes) {
+            return (text.into_owned(), encoding);
+        }
+    }
+    let (text, _) = WINDOWS_1252.decode_without_bom_handling(bytes);
+    (text.into_owned(), WINDOWS_1252)
+}
+
+// The encoding named by an `\ide` marker, either as a label such as
+// `UTF-8` or `latin1`, or as a Windows code page such as `CP-1252` or
+// `65001`.
+fn declaration(bytes: &[u8]) -> Option<&'static Encoding> {
+    let start = bytes.windows(5).position(|w| w == b"\\ide ")? + 5;
+    let line = bytes[start..].split(|&b| b == b'\n' || b == b'\r').next()?;
+    for_declaration(std::str::from_utf8(line).ok()?)
+}
+
+fn for_declaration(ide: &str) -> Option<&'static Encoding> {
+    let label = ide.split_whitespace().next()?;
+    let lower = label.to_ascii_lowercase();
+    let number = lower
+        .strip_prefix("cp")
+        .map(|n| n.trim_start_matches('-'))
+        .unwrap_or(&lower);
+    match number.parse::<u32>() {
+        Ok(code_page) => code_page_encoding(code_page),
+        Err(_) => Encoding::for_label(label.as_bytes()),
+    }
+}
+
+fn code_page_encoding(code_page: u32) -> Option<&'static Encoding> {
+    let label = match code_page {
+        65001 => "utf-8".to_owned(),
+        1200 => "utf-16le".to_owned(),
+        1201 => "utf-16be".to_owned(),
+        874 | 1250..=1258 => format!("windows-{code_page}"),
+        28591..=28606 => format!("iso-8859-{}", code_page - 28590),
+        932 => "shift_jis".to_owned(),
+        936 => "gbk".to_owned(),
+        949 => "euc-kr".to_owned(),
+        950 => "big5".to_owned(),
+        866 => "ibm866".to_owned(),
+        20866 => "koi8-r".to_owned(),
+        10000 => "macintosh".to_owned(),
+        _ => return None,
+    };
+    Encoding::for_label(label.as_bytes())
+}
+
+#[cfg(test)]
+mod test {
+    use super::decode;
+    use crate::{
+        document::{Content, Document},
+        writer::Options,
+    };
+    use encoding_rs::{UTF_16LE, UTF_8, WINDOWS_1250, WINDOWS_1252};
+
+    #[test]
+    fn detect() {
+        assert_eq!(decode(b"\\id MRK caf\xc3\xa9\n").1, UTF_8);
+        assert_eq!(
+            decode(b"\\id MRK caf\xe9\n"),
+            ("\\id MRK café\n".into(), WINDOWS_1252)
+        );
+        assert_eq!(decode(b"\\id MRK\n\\ide CP-1250\n").1, WINDOWS_1250);
+        assert_eq!(decode(b"\\id MRK\n\\ide 65001\n").1, UTF_8);
+        assert_eq!(decode(b"\\id MRK\n\\ide latin1\n").1, WINDOWS_1252);
+        assert_eq!(decode(b"\\id MRK\n\\ide CP-1252\n\xc3\xa9").1, UTF_8);
+        assert_eq!(
+            decode(b"\xff\xfe\\\0i\0d\0 \0M\0R\0K\0"),
+            ("\\id MRK".into(), UTF_16LE)
+        );
+        assert_eq!(
+            decode(b"\xef\xbb\xbf\\id MRK\n"),
+            ("\u{FEFF}\\id MRK\n".into(), UTF_8)
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let source = b"\\id MRK\n\\ide CP-1252\n\\c 1\n\\p\n\\v 1 Caf\xe9\n";
+        let mut doc = Document::from_bytes(source).expect("Document");
+        assert_eq!(doc.encoding(), Some(WINDOWS_1252));
+        assert_eq!(doc.to_bytes(Options::default()).expect("bytes"), source);
+
+        let root = doc.nodes.as_mut().expect("root");
+        root.content.push(Content::Text("\u{05D0}".into()));
+        assert!(doc.to_bytes(Options::default()).is_err());
+
+        let source = "\u{FEFF}\\id MRK\n"
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect::<Vec<_>>();
+        let doc = Document::from_bytes(&source).expect("Document");
+        assert_eq!(doc.to_bytes(Options::default()).expect("bytes"), source);
+    }
+
+    #[test]
+    fn check_encoding() {
+        let messages = |bytes: &[u8]| {
+            let doc = Document::from_bytes(bytes).expect("Document");
+            doc.check_encoding()
+                .into_iter()
+                .map(|d| d.message)
+                .collect::<Vec<_>>()
+        };
+        assert!(messages(b"\\id MRK\n\\ide CP-1252\n\\c 1\n\\p \\v 1 Caf\xe9\n").is_empty());
+        assert_eq!(
+            messages(b"\\id MRK\n\\ide UTF-8\n\\c 1\n\\p \\v 1 Caf\xe9\n"),
+            ["\\ide declares UTF-8 but the file is windows-1252"]
+        );
+        assert_eq!(
+            messages(b"\\id MRK\n\\ide Klingon\n"),
+            ["\\ide names an unknown encoding Klingon"]
+        );
+        let doc: Document = "\\id MRK\n\\ide  65001 \n".parse().expect("Document");
+        assert_eq!(doc.ide().as_deref(), Some("65001"));
+        assert_eq!(doc.declared_encoding(), Some(UTF_8));
+    }
+}
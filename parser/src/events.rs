@@ -0,0 +1,792 @@
+//! A pull parser reporting markers and text as they are read, without
+//! building a tree, for files too large to hold comfortably in memory.
+//!
+//! Structure is only followed as far as the marker categories allow: an
+//! [`Event::EndPara`] comes before the next paragraph or chapter, note
+//! character spans close at the next one, and the attributes of a
+//! character span arrive with its [`Event::EndChar`].
+
+use std::{
+    collections::{HashMap, VecDeque},
+    io::{self, BufRead},
+};
+
+use nom::{
+    bytes::complete::{is_not, take},
+    character::complete::{char, digit1, none_of},
+    combinator::{opt, recognize},
+    sequence::{delimited, pair, terminated},
+    Parser,
+};
+
+use super::Result;
+use crate::{
+    diagnostic::{Code, Diagnostic},
+    document::{Position, Span, State},
+    extension::{Category, Extensions},
+    terminal::{self, attrib},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// `\id`, followed by the text of the line.
+    Book {
+        code: String,
+    },
+    Chapter {
+        number: String,
+    },
+    Verse {
+        number: String,
+    },
+    StartPara {
+        style: String,
+    },
+    EndPara {
+        style: String,
+    },
+    StartChar {
+        style: String,
+        nested: bool,
+    },
+    EndChar {
+        style: String,
+        attributes: HashMap<String, String>,
+    },
+    StartNote {
+        style: String,
+        caller: String,
+    },
+    EndNote {
+        style: String,
+    },
+    Milestone {
+        style: String,
+        attributes: HashMap<String, String>,
+    },
+    Text(String),
+    OptBreak,
+}
+
+#[derive(Debug)]
+enum Inline {
+    Char {
+        style: String,
+        attributes: HashMap<String, String>,
+    },
+    Note {
+        style: String,
+    },
+}
+
+// Internal markers that take end markers like character spans do.
+const INTERNAL_CHARS: [&str; 5] = ["ca", "cat", "fig", "va", "vp"];
+
+/// The parser behind [`EventReader`], fed input as it arrives rather than
+/// pulling it from a reader, so that events can be taken while a file is
+/// still downloading.
+///
+/// ```
+/// # use parser::events::{Event, EventFeed};
+/// let mut feed = EventFeed::new();
+/// feed.push(b"\\id MRK\n\\c 1\n\\p \\v 1 In the beg").unwrap();
+/// assert_eq!(feed.events().count(), 2);
+/// feed.push(b"inning\n").unwrap();
+/// feed.finish().unwrap();
+/// assert_eq!(feed.events().nth(2), Some(Event::Text("In the beginning".into())));
+/// ```
+#[derive(Debug)]
+pub struct EventFeed<'m> {
+    markers: &'m Extensions,
+    /// Input after the last line break, waiting for the rest of its line.
+    pending: Vec<u8>,
+    line_number: usize,
+    queue: VecDeque<Event>,
+    text: String,
+    para: Option<String>,
+    inline: Vec<Inline>,
+    milestone: Option<(String, HashMap<String, String>)>,
+}
+
+impl Default for EventFeed<'static> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventFeed<'static> {
+    pub fn new() -> Self {
+        Self::with_markers(State::usfm_ext())
+    }
+}
+
+impl<'m> EventFeed<'m> {
+    pub fn with_markers(markers: &'m Extensions) -> Self {
+        EventFeed {
+            markers,
+            pending: Vec::new(),
+            line_number: 0,
+            queue: VecDeque::new(),
+            text: String::new(),
+            para: None,
+            inline: Vec::new(),
+            milestone: None,
+        }
+    }
+
+    /// Parse the lines completed by `chunk`. The rest of it waits for the
+    /// next chunk, so chunks may end anywhere, even inside a character.
+    pub fn push(&mut self, chunk: &[u8]) -> io::Result<()> {
+        // A `\r` left at the end of the last chunk may start a `\r\n`.
+        let start = self.pending.len().saturating_sub(1);
+        self.pending.extend_from_slice(chunk);
+        let ends = (start..self.pending.len())
+            .filter(|&n| line_end(&self.pending, n))
+            .collect::<Vec<_>>();
+        let Some(&end) = ends.last() else {
+            return Ok(());
+        };
+        let rest = self.pending.split_off(end + 1);
+        let lines = std::mem::replace(&mut self.pending, rest);
+        let mut from = 0;
+        for end in ends {
+            let line = std::str::from_utf8(&lines[from..=end])
+                .map_err(|error| self.invalid_utf8(error))?;
+            self.line(line)?;
+            from = end + 1;
+        }
+        Ok(())
+    }
+
+    /// Parse what is left after the last line break and close any open
+    /// paragraph, at the end of the input.
+    pub fn finish(&mut self) -> io::Result<()> {
+        let rest = std::mem::take(&mut self.pending);
+        if !rest.is_empty() {
+            let line =
+                String::from_utf8(rest).map_err(|error| self.invalid_utf8(error.utf8_error()))?;
+            self.line(&line)?;
+        }
+        self.close_para();
+        Ok(())
+    }
+
+    /// Take the events parsed so far.
+    pub fn events(&mut self) -> impl Iterator<Item = Event> + '_ {
+        self.queue.drain(..)
+    }
+
+    fn line(&mut self, line: &str) -> io::Result<()> {
+        self.line_number += 1;
+        match self.line_number {
+            1 => self.process(line.trim_start_matches('\u{FEFF}')),
+            _ => self.process(line),
+        }
+    }
+
+    fn invalid_utf8(&self, error: std::str::Utf8Error) -> io::Error {
+        let line = self.line_number + 1;
+        io::Error::new(io::ErrorKind::InvalidData, format!("line {line}: {error}"))
+    }
+
+    fn error(&self, code: Code, column: usize, message: String) -> io::Error {
+        let position = Position {
+            offset: 0,
+            line: self.line_number,
+            column: column + 1,
+        };
+        let span = Span {
+            start: position,
+            end: position,
+        };
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            Diagnostic::error(code, span, message),
+        )
+    }
+
+    fn emit(&mut self, event: Event) {
+        self.flush_milestone();
+        self.flush_text();
+        self.queue.push_back(event);
+    }
+
+    fn flush_text(&mut self) {
+        if !self.text.is_empty() {
+            let text = std::mem::take(&mut self.text);
+            self.queue.push_back(Event::Text(text));
+        }
+    }
+
+    fn flush_milestone(&mut self) {
+        if let Some((style, attributes)) = self.milestone.take() {
+            self.emit(Event::Milestone { style, attributes });
+        }
+    }
+
+    fn close_inline(&mut self) {
+        match self.inline.pop() {
+            Some(Inline::Char {
+                style, attributes, ..
+            }) => self.emit(Event::EndChar { style, attributes }),
+            Some(Inline::Note { style }) => self.emit(Event::EndNote { style }),
+            None => {}
+        }
+    }
+
+    fn close_para(&mut self) {
+        self.flush_milestone();
+        while !self.inline.is_empty() {
+            self.close_inline();
+        }
+        self.text.truncate(self.text.trim_end().len());
+        self.flush_text();
+        if let Some(style) = self.para.take() {
+            self.emit(Event::EndPara { style });
+        }
+    }
+
+    fn category(&self, style: &str) -> Option<Category> {
+        self.markers.category(style)
+    }
+
+    fn process(&mut self, line: &str) -> io::Result<()> {
+        let mut rest = line;
+        while !rest.is_empty() {
+            let column = line[..line.len() - rest.len()].chars().count();
+            if let Ok((tail, text)) = terminal::attributed_text(rest) {
+                if !text.is_empty() {
+                    self.flush_milestone();
+                    self.text.push_str(text);
+                    rest = tail;
+                    continue;
+                }
+            }
+            if let Some(tail) = rest.strip_prefix("//") {
+                self.emit(Event::OptBreak);
+                rest = tail;
+            } else if rest.starts_with(['\r', '\n']) {
+                if !self.text.ends_with(char::is_whitespace) && self.para.is_some() {
+                    self.text.push(' ');
+                }
+                break;
+            } else if rest.starts_with('|') {
+                rest = self.attributes(rest);
+            } else if let Some(tail) = rest.strip_prefix("\\*") {
+                match self.milestone.take() {
+                    Some((style, attributes)) => self.emit(Event::Milestone { style, attributes }),
+                    None => {
+                        let message = "milestone end without a milestone".into();
+                        return Err(self.error(Code::UnmatchedEndmarker, column, message));
+                    }
+                }
+                rest = tail;
+            } else if let Ok((tail, (nested, style))) = endmarker(rest) {
+                self.end(style, nested)
+                    .map_err(|message| self.error(Code::UnmatchedEndmarker, column, message))?;
+                rest = tail;
+            } else if let Ok((tail, (nested, style))) = start_marker(rest) {
+                rest = self.marker(tail, style, nested, column)?;
+            } else {
+                // A lone `/` or `|` that is not special here.
+                let c = rest.chars().next().expect("non-empty");
+                self.text.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+        Ok(())
+    }
+
+    fn attributes<'l>(&mut self, rest: &'l str) -> &'l str {
+        let target = match (&mut self.milestone, self.inline.last_mut()) {
+            (Some((style, attributes)), _) => Some((style.as_str(), attributes)),
+            (
+                None,
+                Some(Inline::Char {
+                    style, attributes, ..
+                }),
+            ) => Some((style.as_str(), attributes)),
+            _ => None,
+        };
+        let parsed = target.and_then(|target| Some((target, attrib::attributes(rest).ok()?)));
+        let Some(((style, attributes), (tail, parsed))) = parsed else {
+            self.text.push('|');
+            return &rest[1..];
+        };
+        match parsed {
+            attrib::Attributes::Default(value) => {
+                let name = self
+                    .markers
+                    .get(style)
+                    .and_then(|marker| marker.default.clone())
+                    .unwrap_or_else(|| "default".into());
+                attributes.insert(name, attrib::unescape(value).into_owned());
+            }
+            attrib::Attributes::Named(values) => attributes.extend(
+                values
+                    .into_iter()
+                    .map(|(name, value)| (name.to_owned(), attrib::unescape(value).into_owned())),
+            ),
+        }
+        tail
+    }
+
+    fn end(&mut self, style: &str, nested: bool) -> std::result::Result<(), String> {
+        self.flush_milestone();
+        let open = self.inline.iter().rposition(|inline| match inline {
+            Inline::Char { style: s, .. } | Inline::Note { style: s } => s == style,
+        });
+        let Some(open) = open else {
+            let plus = if nested { "+" } else { "" };
+            return Err(format!("\\{plus}{style}* has no start marker"));
+        };
+        while self.inline.len() > open {
+            self.close_inline();
+        }
+        Ok(())
+    }
+
+    fn marker<'l>(
+        &mut self,
+        rest: &'l str,
+        style: &str,
+        nested: bool,
+        column: usize,
+    ) -> io::Result<&'l str> {
+        let syntax = |this: &Self, what: &str| {
+            this.error(
+                Code::Syntax,
+                column,
+                format!("expected {what} after \\{style}"),
+            )
+        };
+        match style {
+            "id" => {
+                let Ok((rest, code)) = terminated(take(3usize), terminal::multispace0).parse(rest)
+                else {
+                    return Err(syntax(self, "a book code"));
+                };
+                self.close_para();
+                self.emit(Event::Book { code: code.into() });
+                self.para = None;
+                return Ok(rest);
+            }
+            "c" => {
+                let Ok((rest, number)) = terminated(digit1, terminal::multispace0).parse(rest)
+                else {
+                    return Err(syntax(self, "a chapter number"));
+                };
+                self.close_para();
+                self.emit(Event::Chapter {
+                    number: number.into(),
+                });
+                return Ok(rest);
+            }
+            "v" => {
+                let Ok((rest, number)) =
+                    terminated(is_not(" \t\r\n\\"), terminal::multispace0).parse(rest)
+                else {
+                    return Err(syntax(self, "a verse number"));
+                };
+                while !self.inline.is_empty() {
+                    self.close_inline();
+                }
+                self.emit(Event::Verse {
+                    number: number.into(),
+                });
+                return Ok(rest);
+            }
+            _ => {}
+        }
+        let Some(category) = self.category(style) else {
+            let message = format!("unknown marker \\{style}");
+            return Err(self.error(Code::UnknownMarker, column, message));
+        };
+        match category {
+            Category::Footnote | Category::Crossreference => {
+                let mut caller = terminated(recognize(none_of(" \t\r\n\\")), terminal::space1);
+                let Ok((rest, caller)) = caller.parse(rest) else {
+                    return Err(syntax(self, "a caller"));
+                };
+                self.emit(Event::StartNote {
+                    style: style.into(),
+                    caller: caller.into(),
+                });
+                self.inline.push(Inline::Note {
+                    style: style.into(),
+                });
+                Ok(rest)
+            }
+            Category::Milestone => {
+                self.flush_milestone();
+                self.milestone = Some((style.into(), HashMap::new()));
+                Ok(rest)
+            }
+            Category::FootnoteChar | Category::CrossreferenceChar | Category::Cell if !nested => {
+                // These run on until the next of their kind.
+                if let Some(Inline::Char { style: open, .. }) = self.inline.last() {
+                    if self.category(open) == Some(category) {
+                        self.close_inline();
+                    }
+                }
+                self.start_char(style, nested);
+                Ok(rest)
+            }
+            Category::Char
+            | Category::FootnoteChar
+            | Category::CrossreferenceChar
+            | Category::IntroChar
+            | Category::ListChar
+            | Category::Cell => {
+                self.start_char(style, nested);
+                Ok(rest)
+            }
+            Category::Internal if INTERNAL_CHARS.contains(&style) => {
+                self.start_char(style, nested);
+                Ok(rest)
+            }
+            _ => {
+                self.close_para();
+                self.emit(Event::StartPara {
+                    style: style.into(),
+                });
+                self.para = Some(style.into());
+                Ok(rest)
+            }
+        }
+    }
+
+    fn start_char(&mut self, style: &str, nested: bool) {
+        self.emit(Event::StartChar {
+            style: style.into(),
+            nested,
+        });
+        self.inline.push(Inline::Char {
+            style: style.into(),
+            attributes: HashMap::new(),
+        });
+    }
+}
+
+// Whether a line ends at byte `n`: at a `\n`, or at a `\r` known not to
+// start a `\r\n`.
+fn line_end(bytes: &[u8], n: usize) -> bool {
+    match bytes[n] {
+        b'\n' => true,
+        b'\r' => bytes.get(n + 1).is_some_and(|&b| b != b'\n'),
+        _ => false,
+    }
+}
+
+fn start_marker(input: &str) -> Result<'_, (bool, &str)> {
+    terminal::nested_marker
+        .map(|style| (true, style))
+        .or(terminal::marker.map(|style| (false, style)))
+        .parse(input)
+}
+
+fn endmarker(input: &str) -> Result<'_, (bool, &str)> {
+    delimited(
+        char('\\'),
+        pair(opt(char('+')).map(|plus| plus.is_some()), terminal::name),
+        char('*'),
+    )
+    .parse(input)
+}
+
+/// Reports the markers and text of a file as they are read, a line at a
+/// time.
+pub struct EventReader<'m, R> {
+    reader: R,
+    feed: EventFeed<'m>,
+    done: bool,
+}
+
+impl<R: BufRead> EventReader<'static, R> {
+    pub fn new(reader: R) -> Self {
+        Self::with_markers(reader, State::usfm_ext())
+    }
+}
+
+impl<'m, R: BufRead> EventReader<'m, R> {
+    pub fn with_markers(reader: R, markers: &'m Extensions) -> Self {
+        EventReader {
+            reader,
+            feed: EventFeed::with_markers(markers),
+            done: false,
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for EventReader<'_, R> {
+    type Item = io::Result<Event>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(event) = self.feed.queue.pop_front() {
+                return Some(Ok(event));
+            }
+            if self.done {
+                return None;
+            }
+            // Fed through `push`, which splits lines on `\r` as well.
+            let res = match self.reader.fill_buf() {
+                Ok([]) => {
+                    self.done = true;
+                    self.feed.finish()
+                }
+                Ok(buffer) => {
+                    let len = buffer.len();
+                    let res = self.feed.push(buffer);
+                    self.reader.consume(len);
+                    res
+                }
+                Err(error) => Err(error),
+            };
+            if let Err(error) = res {
+                self.done = true;
+                return Some(Err(error));
+            }
+        }
+    }
+}
+
+/// [`EventReader`] for a reader read asynchronously, such as the body of a
+/// response still being downloaded. Events are reported as soon as the
+/// lines holding them arrive.
+#[cfg(feature = "tokio")]
+pub struct AsyncEventReader<'m, R> {
+    reader: R,
+    feed: EventFeed<'m>,
+    buffer: Box<[u8]>,
+    done: bool,
+}
+
+#[cfg(feature = "tokio")]
+impl<R: tokio::io::AsyncRead + Unpin> AsyncEventReader<'static, R> {
+    pub fn new(reader: R) -> Self {
+        Self::with_markers(reader, State::usfm_ext())
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<'m, R: tokio::io::AsyncRead + Unpin> AsyncEventReader<'m, R> {
+    pub fn with_markers(reader: R, markers: &'m Extensions) -> Self {
+        AsyncEventReader {
+            reader,
+            feed: EventFeed::with_markers(markers),
+            buffer: vec![0; 8 * 1024].into_boxed_slice(),
+            done: false,
+        }
+    }
+
+    /// The next event, reading more of the input when none is ready.
+    pub async fn next(&mut self) -> Option<io::Result<Event>> {
+        use tokio::io::AsyncReadExt;
+
+        loop {
+            if let Some(event) = self.feed.queue.pop_front() {
+                return Some(Ok(event));
+            }
+            if self.done {
+                return None;
+            }
+            let res = match self.reader.read(&mut self.buffer).await {
+                Ok(0) => {
+                    self.done = true;
+                    self.feed.finish()
+                }
+                Ok(n) => self.feed.push(&self.buffer[..n]),
+                Err(error) => Err(error),
+            };
+            if let Err(error) = res {
+                self.done = true;
+                return Some(Err(error));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Event, EventFeed, EventReader};
+
+    const USFM: &str =
+        "\u{FEFF}\\id MRK Marc\n\\c 1\n\\p \\v 1 Au commencement, \\nd Dieu\\nd*\n\\v 2 créa";
+
+    fn events(source: &str) -> Vec<String> {
+        EventReader::new(source.as_bytes())
+            .map(|event| match event.expect("event") {
+                Event::Book { code } => format!("id {code}"),
+                Event::Chapter { number } => format!("c {number}"),
+                Event::Verse { number } => format!("v {number}"),
+                Event::StartPara { style } => format!("<{style}>"),
+                Event::EndPara { style } => format!("</{style}>"),
+                Event::StartChar { style, nested } => {
+                    format!("<{}{style}>", if nested { "+" } else { "" })
+                }
+                Event::EndChar { style, attributes } => {
+                    let mut attributes = attributes
+                        .iter()
+                        .map(|(k, v)| format!(" {k}={v}"))
+                        .collect::<Vec<_>>();
+                    attributes.sort();
+                    format!("</{style}{}>", attributes.concat())
+                }
+                Event::StartNote { style, caller } => format!("<{style} {caller}>"),
+                Event::EndNote { style } => format!("</{style}>"),
+                Event::Milestone { style, attributes } => {
+                    format!("<{style}/{}>", attributes.len())
+                }
+                Event::Text(text) => format!("{text:?}"),
+                Event::OptBreak => "//".into(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn pull() {
+        assert_eq!(
+            events(
+                "\\id MRK Mark\n\
+                 \\c 1\n\
+                 \\s1 Heading\n\
+                 \\p \\v 1 In the \\nd \\+w Lord|lemma\\+w*\\nd*\\f + \\fr 1:1 \\ft note\\f*\n\
+                 continued // \\qt-s |who=\"Mark\"\\*here\\qt-e\\*\n\
+                 \\q1 \\v 2 line\n"
+            ),
+            [
+                "id MRK",
+                "\"Mark\"",
+                "c 1",
+                "<s1>",
+                "\"Heading\"",
+                "</s1>",
+                "<p>",
+                "v 1",
+                "\"In the \"",
+                "<nd>",
+                "<+w>",
+                "\"Lord\"",
+                "</w lemma=lemma>",
+                "</nd>",
+                "<f +>",
+                "<fr>",
+                "\"1:1 \"",
+                "</fr>",
+                "<ft>",
+                "\"note\"",
+                "</ft>",
+                "</f>",
+                "\" continued \"",
+                "//",
+                "\" \"",
+                "<qt-s/1>",
+                "\"here\"",
+                "<qt-e/0>",
+                "</p>",
+                "<q1>",
+                "v 2",
+                "\"line\"",
+                "</q1>",
+            ]
+        );
+    }
+
+    #[test]
+    fn line_endings() {
+        let source =
+            "\\id MRK Mark\n\\c 1\n\\p \\v 1 In the \\nd Lord\\nd*\ncontinued\n\\q1 \\v 2 line\n";
+        let expected = events(source);
+        assert_eq!(events(&source.replace('\n', "\r")), expected);
+        assert_eq!(events(&source.replace('\n', "\r\n")), expected);
+        let error = EventReader::new("\\id MRK\r\\p text\r\\p \\xyz\r".as_bytes())
+            .find_map(Result::err)
+            .expect("error");
+        assert_eq!(
+            error.to_string(),
+            "3:4: error[unknown-marker]: unknown marker \\xyz"
+        );
+    }
+
+    #[test]
+    fn pull_errors() {
+        let error = EventReader::new("\\id MRK\n\\p text \\xyz more\n".as_bytes())
+            .find_map(Result::err)
+            .expect("error");
+        assert_eq!(
+            error.to_string(),
+            "2:9: error[unknown-marker]: unknown marker \\xyz"
+        );
+        let error = EventReader::new("\\id MRK\n\\p text\\nd*\n".as_bytes())
+            .find_map(Result::err)
+            .expect("error");
+        assert_eq!(
+            error.to_string(),
+            "2:8: error[unmatched-endmarker]: \\nd* has no start marker"
+        );
+    }
+
+    #[test]
+    fn feed() {
+        let expected = EventReader::new(USFM.as_bytes())
+            .collect::<Result<Vec<_>, _>>()
+            .expect("events");
+        // Chunks splitting lines, markers and the `é` of `créa` alike.
+        for size in [1, 2, 5, 64] {
+            let mut feed = EventFeed::new();
+            let mut events = Vec::new();
+            for chunk in USFM.as_bytes().chunks(size) {
+                feed.push(chunk).expect("chunk");
+                events.extend(feed.events());
+            }
+            feed.finish().expect("finish");
+            events.extend(feed.events());
+            assert_eq!(events, expected);
+        }
+        // A chunk ending in `\r` waits to see whether a `\n` follows.
+        for line_break in ["\r", "\r\n"] {
+            let source = USFM.replace('\n', line_break);
+            for size in [1, 2, 5] {
+                let mut feed = EventFeed::new();
+                let mut events = Vec::new();
+                for chunk in source.as_bytes().chunks(size) {
+                    feed.push(chunk).expect("chunk");
+                    events.extend(feed.events());
+                }
+                feed.finish().expect("finish");
+                events.extend(feed.events());
+                assert_eq!(events, expected, "{line_break:?} in chunks of {size}");
+            }
+        }
+
+        let mut feed = EventFeed::new();
+        assert_eq!(
+            feed.push(b"\\id MRK\n\\p caf\xe9\n")
+                .map_err(|error| error.to_string()),
+            Err("line 2: invalid utf-8 sequence of 1 bytes from index 6".into())
+        );
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn async_read() {
+        use super::AsyncEventReader;
+        use crate::document::Document;
+
+        let mut reader = AsyncEventReader::new(USFM.as_bytes());
+        let mut events = Vec::new();
+        while let Some(event) = reader.next().await {
+            events.push(event.expect("event"));
+        }
+        let expected = EventReader::new(USFM.as_bytes())
+            .collect::<Result<Vec<_>, _>>()
+            .expect("events");
+        assert_eq!(events, expected);
+
+        let doc = Document::from_async_reader(USFM.as_bytes())
+            .await
+            .expect("Document");
+        assert_eq!(doc, USFM.parse().expect("Document"));
+    }
+}
@@ -1,50 +1,187 @@
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, BTreeSet},
     fmt::Display,
-    io::{self, Read},
-    ops::Deref,
+    io::{self, Read, Write},
+    ops::{Deref, RangeInclusive},
     str::FromStr,
+    sync::{Arc, OnceLock},
 };
 
 use nom::{
     branch::{alt, permutation},
-    bytes::complete::tag_no_case,
-    character::complete::{char, not_line_ending},
-    combinator::{cut, eof, iterator, opt, success, value},
+    bytes::complete::{tag, tag_no_case, take_while1},
+    character::complete::{char, digit1, not_line_ending, u32 as nom_u32},
+    combinator::{cut, eof, iterator, map_res, opt, recognize, success, value},
     error::{context, convert_error, make_error, VerboseError},
     multi::{many0, separated_list1},
-    sequence::{delimited, terminated},
+    sequence::{delimited, preceded, separated_pair, terminated, tuple},
     Finish, Parser,
 };
 
 use super::Result;
-use crate::terminal;
+use crate::{reference::BookNames, terminal};
 
+/// A marker set keyed by marker name. It iterates, serializes and writes in
+/// name order, so output built from it is the same from run to run.
 #[derive(Debug, Default, Clone, PartialEq, Eq)]
-pub struct Extensions(HashMap<String, Marker>);
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Extensions(BTreeMap<String, Marker>);
+
+/// USFM releases with a bundled marker set.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Version {
+    /// USFM 2.x, up to 2.4.
+    V2,
+    #[default]
+    V3_0,
+    V3_1,
+}
+
+impl Version {
+    /// The release a `\usfm` version number belongs to.
+    pub fn from_number(number: f32) -> Self {
+        if number < 3.0 {
+            Version::V2
+        } else if number < 3.1 {
+            Version::V3_0
+        } else {
+            Version::V3_1
+        }
+    }
+}
 
 impl Deref for Extensions {
-    type Target = HashMap<String, Marker>;
+    type Target = BTreeMap<String, Marker>;
 
     fn deref(&self) -> &Self::Target {
         &self.0
     }
 }
 
-type Attributes = HashMap<String, bool>;
+impl<'a> IntoIterator for &'a Extensions {
+    type Item = (&'a String, &'a Marker);
+    type IntoIter = std::collections::btree_map::Iter<'a, String, Marker>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+type Attributes = BTreeMap<String, bool>;
+
+/// The values an attribute takes, declared after its name in `\attributes`
+/// as in `size?=(col|span|page)`, `loc=reference` or `level=1-4`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum AttributeSpec {
+    /// Any text, as for an attribute declared without a type.
+    #[default]
+    Text,
+    /// One of a list of words.
+    Choice(Vec<String>),
+    /// Scripture references, such as `MAT 3:1-4; 5:2`.
+    Reference,
+    /// A whole number in a range, any for `number`.
+    Number(RangeInclusive<u32>),
+}
+
+impl AttributeSpec {
+    const ANY_NUMBER: RangeInclusive<u32> = 0..=u32::MAX;
+
+    /// Check `value` against the spec, describing what was expected if it
+    /// does not match.
+    pub fn check(&self, value: &str) -> std::result::Result<(), String> {
+        let value = value.trim();
+        match self {
+            AttributeSpec::Text => Ok(()),
+            AttributeSpec::Choice(choices) if choices.iter().any(|c| c == value) => Ok(()),
+            AttributeSpec::Choice(choices) => Err(match choices.split_last() {
+                Some((last, [])) => last.clone(),
+                Some((last, rest)) => format!("{} or {last}", rest.join(", ")),
+                None => String::new(),
+            }),
+            AttributeSpec::Reference if BookNames::english().ranges(value).is_ok() => Ok(()),
+            AttributeSpec::Reference => Err("a scripture reference".into()),
+            AttributeSpec::Number(range) if value.parse().is_ok_and(|n| range.contains(&n)) => {
+                Ok(())
+            }
+            AttributeSpec::Number(range) if *range == Self::ANY_NUMBER => Err("a number".into()),
+            AttributeSpec::Number(range) => Err(format!(
+                "a number from {} to {}",
+                range.start(),
+                range.end()
+            )),
+        }
+    }
+}
+
+impl Display for AttributeSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AttributeSpec::Text => f.write_str("text"),
+            AttributeSpec::Choice(choices) => write!(f, "({})", choices.join("|")),
+            AttributeSpec::Reference => f.write_str("reference"),
+            AttributeSpec::Number(range) if *range == Self::ANY_NUMBER => f.write_str("number"),
+            AttributeSpec::Number(range) => write!(f, "{}-{}", range.start(), range.end()),
+        }
+    }
+}
 
 #[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Marker {
     pub name: String,
+    /// Attribute names, mapped to whether they are optional. A name ending
+    /// in `*`, such as `x-*`, stands for any attribute with that prefix.
     pub attributes: Attributes,
+    /// The types of attributes declared with one; the rest take any text.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "BTreeMap::is_empty")
+    )]
+    pub attribute_types: BTreeMap<String, AttributeSpec>,
     pub category: Category,
     pub closes: Option<String>,
     pub closedby: Option<String>,
     pub default: Option<String>,
     pub description: Option<String>,
+    /// Markers this one may appear under; empty when unrestricted.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "BTreeSet::is_empty")
+    )]
+    pub occurs_under: BTreeSet<String>,
+    /// The levels of a numbered family such as `\q1` to `\q4`, declared
+    /// once on the unnumbered marker with `\levels 1-4`.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub levels: Option<RangeInclusive<u8>>,
 }
 
 impl Marker {
+    /// The declared attribute `name` is matched by, itself or a wildcard.
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        if let Some((declared, _)) = self.attributes.get_key_value(name) {
+            return Some(declared);
+        }
+        self.attributes
+            .keys()
+            .filter_map(|declared| Some((declared, declared.strip_suffix('*')?)))
+            .find(|(_, prefix)| name.starts_with(prefix))
+            .map(|(declared, _)| declared.as_str())
+    }
+
+    /// The type of attribute `name`, [`AttributeSpec::Text`] if it has none.
+    pub fn attribute_type(&self, name: &str) -> &AttributeSpec {
+        const TEXT: &AttributeSpec = &AttributeSpec::Text;
+        self.attribute(name)
+            .and_then(|declared| self.attribute_types.get(declared))
+            .unwrap_or(TEXT)
+    }
+
     fn update_from(&mut self, overrides: Marker) {
         assert_eq!(self.name, overrides.name);
 
@@ -60,37 +197,69 @@ impl Marker {
         if overrides.description.is_some() {
             self.description = overrides.description
         }
-        self.attributes.extend(overrides.attributes.into_iter());
+        if !overrides.occurs_under.is_empty() {
+            self.occurs_under = overrides.occurs_under
+        }
+        if overrides.levels.is_some() {
+            self.levels = overrides.levels
+        }
+        self.attributes.extend(overrides.attributes);
+        self.attribute_types.extend(overrides.attribute_types);
     }
 }
 
 impl Display for Marker {
+    /// Writes the marker as a `.ext` record, one field per line, in the
+    /// order the record parser expects.
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_str("\\marker ")
-            .and(f.write_str(self.name.as_str()))?;
-        if self.category != Category::Unknown {
-            f.write_fmt(format_args!("\\category {}", self.category))?;
-        }
+        writeln!(f, "\\marker {}", self.name)?;
         if !self.attributes.is_empty() {
-            f.write_fmt(format_args!("\\category {}", self.category))?;
+            let attributes = self
+                .attributes
+                .iter()
+                .map(|(name, optional)| {
+                    let optional = if *optional { "?" } else { "" };
+                    match self.attribute_types.get(name) {
+                        Some(spec) => format!("{name}{optional}={spec}"),
+                        None => format!("{name}{optional}"),
+                    }
+                })
+                .collect::<Vec<_>>();
+            writeln!(f, "\\attributes {}", attributes.join(" "))?;
+        }
+        if self.category != Category::Unknown {
+            writeln!(f, "\\category {}", self.category)?;
         }
         if let Some(ref close) = self.closes {
-            f.write_fmt(format_args!("\\closes {close}"))?;
+            writeln!(f, "\\closes {close}")?;
         }
         if let Some(ref closedby) = self.closedby {
-            f.write_fmt(format_args!("\\closedby {closedby}"))?;
+            writeln!(f, "\\closedby {closedby}")?;
         }
         if let Some(ref defattrib) = self.default {
-            f.write_fmt(format_args!("\\defattrib {defattrib}"))?;
+            writeln!(f, "\\defattrib {defattrib}")?;
         }
         if let Some(ref description) = self.description {
-            f.write_fmt(format_args!("\\description {description}"))?;
+            writeln!(f, "\\description {description}")?;
+        }
+        if !self.occurs_under.is_empty() {
+            let parents = self
+                .occurs_under
+                .iter()
+                .map(String::as_str)
+                .collect::<Vec<_>>();
+            writeln!(f, "\\occursunder {}", parents.join(" "))?;
+        }
+        if let Some(ref levels) = self.levels {
+            writeln!(f, "\\levels {}-{}", levels.start(), levels.end())?;
         }
         Ok(())
     }
 }
 
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
 pub enum Category {
     Cell,
     Char,
@@ -113,13 +282,131 @@ pub enum Category {
     Unknown,
 }
 
+impl Category {
+    /// Whether markers of the category start a paragraph, closed by the
+    /// next one rather than by an end marker.
+    pub fn is_paragraph(self) -> bool {
+        matches!(
+            self,
+            Category::Header
+                | Category::Title
+                | Category::Introduction
+                | Category::SectionPara
+                | Category::VersePara
+                | Category::OtherPara
+                | Category::List
+        )
+    }
+
+    /// Whether markers of the category span text up to a matching `*` end
+    /// marker, as character styles and notes do.
+    pub fn is_spanning(self) -> bool {
+        matches!(
+            self,
+            Category::Char
+                | Category::CrossreferenceChar
+                | Category::FootnoteChar
+                | Category::IntroChar
+                | Category::ListChar
+                | Category::Footnote
+                | Category::Crossreference
+        )
+    }
+}
+
+// Where in a text a marker is to go, see [`Extensions::valid_next_markers`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Place {
+    Start,
+    /// Straight after the `\id` line.
+    Book,
+    /// Between paragraphs.
+    Block,
+    /// Straight after a chapter number.
+    Chapter,
+    /// Straight after a verse number, in a paragraph or cell.
+    Verse(Category),
+    /// Straight after a table row marker.
+    Row,
+    /// In a paragraph or table cell.
+    Text(Category),
+    /// In a character span.
+    Span(Category),
+    /// In a footnote or cross reference.
+    Note(Category),
+}
+
+impl Place {
+    fn allows(self, marker: &Marker, context: &[&str]) -> bool {
+        let has = |style| context.contains(&style);
+        let name = marker.name.as_str();
+        let block = match marker.category {
+            Category::Header | Category::Title | Category::Introduction => !has("c"),
+            Category::SectionPara | Category::VersePara | Category::OtherPara | Category::List => {
+                true
+            }
+            Category::Internal => match name {
+                "c" | "periph" | "tr" => true,
+                "esb" => !has("esb"),
+                "esbe" | "cat" => has("esb"),
+                _ => false,
+            },
+            _ => false,
+        };
+        let inline = |paragraph: Category| match marker.category {
+            Category::Char
+            | Category::Footnote
+            | Category::Crossreference
+            | Category::Milestone => paragraph != Category::Header,
+            Category::IntroChar => paragraph == Category::Introduction,
+            Category::ListChar => paragraph == Category::List,
+            Category::Cell => paragraph == Category::Cell,
+            Category::Internal => match name {
+                "fig" => true,
+                "v" => {
+                    has("c")
+                        && matches!(
+                            paragraph,
+                            Category::VersePara | Category::List | Category::Cell
+                        )
+                }
+                _ => false,
+            },
+            _ => false,
+        };
+        match self {
+            Place::Start => name == "id",
+            Place::Book => block || name == "usfm",
+            Place::Block => block,
+            Place::Chapter => block || matches!(name, "ca" | "cp"),
+            Place::Verse(paragraph) => inline(paragraph) || matches!(name, "va" | "vp"),
+            Place::Row => marker.category == Category::Cell,
+            Place::Text(paragraph) => block || inline(paragraph),
+            Place::Span(span) => match marker.category {
+                Category::Char
+                | Category::Footnote
+                | Category::Crossreference
+                | Category::Milestone => true,
+                Category::IntroChar | Category::ListChar => marker.category == span,
+                _ => false,
+            },
+            Place::Note(note) => match marker.category {
+                Category::FootnoteChar => note == Category::Footnote,
+                Category::CrossreferenceChar => note == Category::Crossreference,
+                Category::Char | Category::Milestone => true,
+                _ => name == "cat",
+            },
+        }
+    }
+}
+
 impl Display for Category {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_str(format!("{:?}", self).to_lowercase().as_str())
     }
 }
 
-fn category(input: &str) -> Result<Category> {
+fn category(input: &str) -> Result<'_, Category> {
     let parser = alt((
         value(Category::Cell, tag_no_case("cell")),
         value(Category::VersePara, tag_no_case("versepara")),
@@ -145,7 +432,7 @@ fn category(input: &str) -> Result<Category> {
     context("Category", parser).parse(input)
 }
 
-fn field<'a, 'i: 'a, O, F>(id: &'a str, mut value: F) -> impl FnMut(&'i str) -> Result<O> + '_
+fn field<'a, 'i: 'a, O, F>(id: &'a str, mut value: F) -> impl FnMut(&'i str) -> Result<O> + 'a
 where
     F: Parser<&'i str, O, VerboseError<&'i str>> + 'i,
 {
@@ -159,7 +446,7 @@ where
     }
 }
 
-fn record(input: &str) -> Result<Marker> {
+fn record(input: &str) -> Result<'_, Marker> {
     if input.trim_ascii_start().is_empty() {
         return Err(nom::Err::Error(make_error(
             input,
@@ -167,9 +454,12 @@ fn record(input: &str) -> Result<Marker> {
         )));
     }
     let attribute = |input| {
-        terminal::name
-            .and(opt(char('?')).map(|o| o.is_some()))
-            .parse(input)
+        tuple((
+            recognize(terminal::name.and(opt(char('*')))),
+            opt(char('?')).map(|o| o.is_some()),
+            opt(preceded(char('='), attribute_spec)),
+        ))
+        .parse(input)
     };
     let attributes = separated_list1(terminal::space1, attribute);
     cut(terminated(
@@ -181,6 +471,11 @@ fn record(input: &str) -> Result<Marker> {
             opt(field("defattrib", terminal::name)),
             opt(field("description", not_line_ending)),
             many0(field("attribute", attribute)),
+            opt(field(
+                "occursunder",
+                separated_list1(terminal::space1, terminal::name),
+            )),
+            opt(field("levels", levels)),
         ))),
         terminal::line_ending1.or(eof),
     ))
@@ -189,20 +484,182 @@ fn record(input: &str) -> Result<Marker> {
         attributes: Attributes::from_iter(
             field
                 .0
-                .unwrap_or_default()
-                .into_iter()
-                .chain(field.6.into_iter())
-                .map(|(k, v)| (k.to_owned(), v)),
+                .iter()
+                .flatten()
+                .chain(&field.6)
+                .map(|(k, v, _)| (k.to_string(), *v)),
         ),
+        attribute_types: field
+            .0
+            .into_iter()
+            .flatten()
+            .chain(field.6)
+            .filter_map(|(k, _, spec)| Some((k.to_owned(), spec?)))
+            .collect(),
         category: field.1,
         closes: field.2.map(str::to_owned),
         closedby: field.3.map(str::to_owned),
         default: field.4.map(str::to_owned),
         description: field.5.map(str::to_owned),
+        occurs_under: field
+            .7
+            .unwrap_or_default()
+            .into_iter()
+            .map(str::to_owned)
+            .collect(),
+        levels: field.8,
     })
     .parse(input)
 }
 
+fn attribute_spec(input: &str) -> Result<'_, AttributeSpec> {
+    let word = take_while1(|c: char| c != '|' && c != ')' && !c.is_whitespace());
+    let choice = delimited(char('('), separated_list1(char('|'), word), char(')')).map(
+        |choices: Vec<&str>| {
+            AttributeSpec::Choice(choices.into_iter().map(str::to_owned).collect())
+        },
+    );
+    let range = separated_pair(nom_u32, char('-'), nom_u32)
+        .map(|(first, last)| AttributeSpec::Number(first..=last));
+    context(
+        "attribute type",
+        alt((
+            choice,
+            range,
+            value(AttributeSpec::Reference, tag("reference")),
+            value(
+                AttributeSpec::Number(AttributeSpec::ANY_NUMBER),
+                tag("number"),
+            ),
+            value(AttributeSpec::Text, tag("text")),
+        )),
+    )
+    .parse(input)
+}
+
+fn levels(input: &str) -> Result<'_, RangeInclusive<u8>> {
+    let level = || map_res(digit1, str::parse::<u8>);
+    context(
+        "level range",
+        level()
+            .and(opt(preceded(char('-'), level())))
+            .map(|(first, last)| first..=last.unwrap_or(first)),
+    )
+    .parse(input)
+}
+
+fn sty_field(input: &str) -> Result<'_, (&str, &str)> {
+    let value = not_line_ending.map(str::trim);
+    context(
+        "stylesheet field",
+        preceded(char('\\'), terminal::name.and(value)),
+    )
+    .parse(input)
+}
+
+#[derive(Default)]
+struct StyRecord<'i> {
+    name: &'i str,
+    fields: Vec<(String, &'i str)>,
+}
+
+impl StyRecord<'_> {
+    // Markers the document parser handles structurally rather than by category.
+    const INTERNAL: [&'static str; 14] = [
+        "c", "ca", "cp", "v", "va", "vp", "id", "usfm", "periph", "esb", "esbe", "cat", "fig", "tr",
+    ];
+
+    fn get(&self, key: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find_map(|(k, v)| (k == key).then_some(*v))
+    }
+
+    fn has(&self, key: &str, value: &str) -> bool {
+        self.get(key).is_some_and(|v| v.eq_ignore_ascii_case(value))
+    }
+
+    fn marker(&self) -> Marker {
+        let mut attributes = self
+            .get("attributes")
+            .unwrap_or_default()
+            .split_whitespace()
+            .map(|a| match a.strip_prefix('?') {
+                Some(name) => (name.to_owned(), true),
+                None => (a.to_owned(), false),
+            })
+            .peekable();
+        let default = attributes
+            .peek()
+            .filter(|(_, optional)| !optional)
+            .map(|(name, _)| name.clone());
+        let milestone = self.has("styletype", "milestone");
+        Marker {
+            name: self.name.to_owned(),
+            attributes: attributes.collect(),
+            attribute_types: BTreeMap::new(),
+            category: self.category(),
+            closes: None,
+            closedby: self
+                .get("endmarker")
+                .filter(|_| milestone)
+                .map(str::to_owned),
+            default,
+            description: self.get("description").map(str::to_owned),
+            occurs_under: self
+                .get("occursunder")
+                .unwrap_or_default()
+                .split_whitespace()
+                .map(str::to_owned)
+                .collect(),
+            levels: None,
+        }
+    }
+
+    fn category(&self) -> Category {
+        let name = self.name;
+        let parents = self
+            .get("occursunder")
+            .unwrap_or_default()
+            .split_whitespace()
+            .collect::<Vec<_>>();
+        let under = |test: fn(&str) -> bool| !parents.is_empty() && parents.iter().all(|p| test(p));
+        let text_type = |value| self.has("texttype", value);
+        match self
+            .get("styletype")
+            .map(str::to_ascii_lowercase)
+            .as_deref()
+        {
+            _ if Self::INTERNAL.contains(&name) => Category::Internal,
+            _ if text_type("chapternumber") || text_type("versenumber") => Category::Internal,
+            Some("note") if name.starts_with('x') || name.starts_with("ex") => {
+                Category::Crossreference
+            }
+            Some("note") => Category::Footnote,
+            Some("milestone" | "milestoneend") => Category::Milestone,
+            Some("character") if under(|p| matches!(p, "x" | "ex")) => Category::CrossreferenceChar,
+            Some("character") if under(|p| matches!(p, "f" | "fe" | "ef")) => {
+                Category::FootnoteChar
+            }
+            Some("character") if text_type("notetext") => Category::FootnoteChar,
+            Some("character") if under(|p| p == "tr") => Category::Cell,
+            Some("character") if under(|p| p.starts_with("li")) => Category::ListChar,
+            Some("character") if under(|p| p.starts_with('i')) => Category::IntroChar,
+            Some("character") => Category::Char,
+            Some("paragraph") if text_type("title") => Category::Title,
+            Some("paragraph") if name.starts_with('i') && parents.contains(&"id") => {
+                Category::Introduction
+            }
+            Some("paragraph") if text_type("section") => Category::SectionPara,
+            Some("paragraph") if parents == ["id"] => Category::Header,
+            Some("paragraph") if text_type("versetext") && name.starts_with('l') => Category::List,
+            Some("paragraph") if text_type("versetext") => Category::VersePara,
+            Some("paragraph") => Category::OtherPara,
+            _ => Category::Unknown,
+        }
+    }
+}
+
 impl FromStr for Extensions {
     type Err = io::Error;
 
@@ -213,6 +670,47 @@ impl FromStr for Extensions {
 }
 
 impl Extensions {
+    const USFM_SRC: &'static str = include_str!("../docs/grammar/usfm.ext");
+    const USFM_3_1_SRC: &'static str = include_str!("../docs/grammar/usfm-3.1.ext");
+
+    // Markers introduced by USFM 3.0, besides the milestones.
+    const USFM_3_ONLY: [&'static str; 29] = [
+        "efm", "fw", "jmp", "lf", "lh", "lik", "lim", "lim1", "lim2", "lim3", "lim4", "litl",
+        "liv", "liv1", "liv2", "liv3", "qd", "rb", "sd", "sd1", "sd2", "sd3", "sd4", "toca1",
+        "toca2", "toca3", "usfm", "wa", "xop",
+    ];
+
+    /// The bundled marker set for a USFM release.
+    #[inline]
+    pub fn usfm(version: Version) -> &'static Extensions {
+        Self::usfm_shared(version)
+    }
+
+    /// The bundled marker set for a USFM release, to share between parsers
+    /// rather than copy. A parser adding markers of its own copies the set
+    /// first, leaving the bundled one as it was.
+    pub fn usfm_shared(version: Version) -> &'static Arc<Extensions> {
+        static SETS: [OnceLock<Arc<Extensions>>; 3] = [const { OnceLock::new() }; 3];
+        SETS[version as usize].get_or_init(|| {
+            let res = match version {
+                Version::V3_0 => Self::USFM_SRC.parse().expect("Parsing usfm.ext"),
+                Version::V3_1 => Self::usfm(Version::V3_0)
+                    .clone()
+                    .update_from_str(Self::USFM_3_1_SRC)
+                    .expect("Parsing usfm-3.1.ext"),
+                Version::V2 => {
+                    let mut res = Self::usfm(Version::V3_0).clone();
+                    res.0.retain(|name, marker| {
+                        marker.category != Category::Milestone
+                            && !Self::USFM_3_ONLY.contains(&name.as_str())
+                    });
+                    res
+                }
+            };
+            Arc::new(res)
+        })
+    }
+
     #[inline]
     pub fn from_reader<R: Read>(reader: R) -> io::Result<Self> {
         Extensions::default().update_from_reader(reader)
@@ -242,9 +740,266 @@ impl Extensions {
         self.update_from_str(io::read_to_string(reader)?)
     }
 
+    /// Merge another set into this one, such as a Paratext `custom.sty`
+    /// read by [`Extensions::from_sty_str`]. Fields of a marker already
+    /// present are overridden one by one, new markers are added whole.
+    pub fn update_from(mut self, other: Extensions) -> Self {
+        for (name, m) in other.0 {
+            match self.0.get_mut(&name) {
+                Some(e) => e.update_from(m),
+                None => {
+                    self.0.insert(name, m);
+                }
+            }
+        }
+        self
+    }
+
+    /// Read a Paratext stylesheet such as `usfm.sty` or `custom.sty`,
+    /// inferring each marker's category from its style and text types and
+    /// where it may occur.
+    pub fn from_sty_str(input: impl AsRef<str>) -> io::Result<Self> {
+        let mut records = Vec::<StyRecord>::new();
+        for (n, line) in input.as_ref().lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (_, (key, value)) = sty_field(line).finish().map_err(|e| {
+                let message = format!("line {}: {}", n + 1, convert_error(line, e));
+                io::Error::new(io::ErrorKind::InvalidData, message)
+            })?;
+            match records.last_mut() {
+                _ if key.eq_ignore_ascii_case("marker") => records.push(StyRecord {
+                    name: value,
+                    ..StyRecord::default()
+                }),
+                Some(record) => record.fields.push((key.to_ascii_lowercase(), value)),
+                None => (),
+            }
+        }
+        let mut res = Extensions(
+            records
+                .iter()
+                .map(|r| (r.name.to_owned(), r.marker()))
+                .collect(),
+        );
+        let ends = res
+            .values()
+            .filter_map(|m| Some((m.closedby.clone()?, m.name.clone())))
+            .collect::<Vec<_>>();
+        for (end, start) in ends {
+            if let Some(marker) = res.0.get_mut(&end) {
+                marker.closes = Some(start);
+            }
+        }
+        Ok(res)
+    }
+
     #[inline]
-    pub fn shrink_to_fit(&mut self) {
-        self.0.shrink_to_fit();
+    pub fn from_sty_reader<R: Read>(reader: R) -> io::Result<Self> {
+        Self::from_sty_str(io::read_to_string(reader)?)
+    }
+
+    /// Write the set as a `.ext` file, with records sorted by marker name
+    /// and separated by blank lines.
+    pub fn write_to<W: Write>(&self, mut w: W) -> io::Result<()> {
+        for (n, marker) in self.values().enumerate() {
+            if n > 0 {
+                writeln!(w)?;
+            }
+            write!(w, "{marker}")?;
+        }
+        Ok(())
+    }
+
+    /// The marker called `name`. A numbered marker without a record of its
+    /// own, such as `q3` or a nonstandard `q5`, falls back to the record of
+    /// its family when that declares `\levels`.
+    pub fn get(&self, name: &str) -> Option<&Marker> {
+        self.0.get(name).or_else(|| self.family(name))
+    }
+
+    /// Whether `name` is in the set, directly or as a level of a family.
+    #[inline]
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    fn family(&self, name: &str) -> Option<&Marker> {
+        let base = name.trim_end_matches(|c: char| c.is_ascii_digit());
+        if base.is_empty() || base.len() == name.len() {
+            return None;
+        }
+        self.0.get(base).filter(|m| m.levels.is_some())
+    }
+
+    /// The markers of a family as named in text, `q1` to `q4` for a `q`
+    /// declared with `\levels 1-4`, or just `name` for other markers.
+    pub fn expand(&self, name: &str) -> Vec<String> {
+        match self.0.get(name).and_then(|m| m.levels.clone()) {
+            Some(levels) => levels.map(|level| format!("{name}{level}")).collect(),
+            None => vec![name.to_owned()],
+        }
+    }
+
+    /// The markers of a category in name order.
+    pub fn by_category(&self, category: Category) -> impl Iterator<Item = &Marker> + '_ {
+        self.values().filter(move |m| m.category == category)
+    }
+
+    #[inline]
+    pub fn category(&self, name: &str) -> Option<Category> {
+        self.get(name).map(|m| m.category)
+    }
+
+    #[inline]
+    pub fn is_paragraph(&self, name: &str) -> bool {
+        self.category(name).is_some_and(Category::is_paragraph)
+    }
+
+    /// The marker a milestone is closed by, such as `qt-e` for `qt-s`.
+    #[inline]
+    pub fn closed_by(&self, name: &str) -> Option<&str> {
+        self.get(name)?.closedby.as_deref()
+    }
+
+    /// The name of the marker ending `name`: its `\closedby` marker if it
+    /// has one, otherwise `name*` for character styles and notes. Markers
+    /// that need no end marker, and unknown ones, have none.
+    pub fn end_marker_for(&self, name: &str) -> Option<String> {
+        let marker = self.get(name)?;
+        match &marker.closedby {
+            Some(closer) => Some(closer.clone()),
+            None if marker.category.is_spanning() => Some(format!("{name}*")),
+            None => None,
+        }
+    }
+
+    /// The markers that may come next at a point in a text, in name order,
+    /// for completion lists and the like. `context` names the markers open
+    /// at the point, outermost first, as in `["c", "p", "f"]`, with `v` or
+    /// `c` last straight after a verse or chapter number; an empty context
+    /// is the start of a book. A marker is offered when its category fits
+    /// the innermost paragraph, span or note, and, if it has an
+    /// `\occursunder` list, one of the markers in `context` or `id` is on
+    /// it.
+    pub fn valid_next_markers(&self, context: &[&str]) -> Vec<&Marker> {
+        let place = self.place(context);
+        self.values()
+            .filter(|marker| place.allows(marker, context))
+            .filter(|marker| {
+                marker.occurs_under.is_empty()
+                    || context
+                        .iter()
+                        .chain(&["id"])
+                        .any(|style| marker.occurs_under.contains(*style))
+            })
+            .collect()
+    }
+
+    // Where the innermost of `context` that the set knows leaves the point.
+    fn place(&self, context: &[&str]) -> Place {
+        let in_note = context
+            .iter()
+            .rev()
+            .find_map(|style| match self.category(style) {
+                Some(category @ (Category::Footnote | Category::Crossreference)) => Some(category),
+                _ => None,
+            });
+        if let Some(note) = in_note {
+            return Place::Note(note);
+        }
+        let Some((style, category)) = context
+            .iter()
+            .rev()
+            .find_map(|style| Some((*style, self.category(style)?)))
+        else {
+            return match context.is_empty() {
+                true => Place::Start,
+                false => Place::Block,
+            };
+        };
+        // The paragraph a verse number or cell is in.
+        let paragraph = || {
+            context
+                .iter()
+                .rev()
+                .filter_map(|style| self.category(style))
+                .find(|category| category.is_paragraph() || *category == Category::Cell)
+                .unwrap_or(Category::VersePara)
+        };
+        match (style, category) {
+            ("c", _) => Place::Chapter,
+            ("v", _) => Place::Verse(paragraph()),
+            ("id", _) => Place::Book,
+            ("tr", _) => Place::Row,
+            (_, Category::Header) => Place::Block,
+            (_, category) if category.is_paragraph() || category == Category::Cell => {
+                Place::Text(category)
+            }
+            (_, category) if category.is_spanning() => Place::Span(category),
+            _ => Place::Block,
+        }
+    }
+
+    /// Check that the set hangs together: every `\closedby` and `\closes`
+    /// names a marker in the set, and following `\closedby` or `\closes`
+    /// from a marker never comes back to it. Each problem is described in
+    /// a message; an empty list means the set is consistent.
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        for marker in self.values() {
+            let name = &marker.name;
+            if let Some(closer) = marker
+                .closedby
+                .as_deref()
+                .filter(|m| !self.contains_key(*m))
+            {
+                problems.push(format!("\\{name} is closed by unknown marker \\{closer}"));
+            }
+            if let Some(opener) = marker.closes.as_deref().filter(|m| !self.contains_key(*m)) {
+                problems.push(format!("\\{name} closes unknown marker \\{opener}"));
+            }
+        }
+        for (field, link) in [
+            (
+                "closedby",
+                (|m: &Marker| m.closedby.as_deref()) as fn(&Marker) -> Option<&str>,
+            ),
+            ("closes", |m: &Marker| m.closes.as_deref()),
+        ] {
+            for name in self.keys() {
+                if let Some(cycle) = self.cycle(name, link) {
+                    problems.push(format!("\\{field} cycle: {}", cycle.join(" -> ")));
+                }
+            }
+        }
+        problems
+    }
+
+    // The chain of markers `link` leads through from `start` back to it,
+    // reported only from the first name in the cycle so each is found once.
+    fn cycle<'s>(
+        &'s self,
+        start: &'s str,
+        link: fn(&Marker) -> Option<&str>,
+    ) -> Option<Vec<&'s str>> {
+        let mut chain = vec![start];
+        let mut current = start;
+        while let Some(next) = self.get(current).and_then(link) {
+            let next = self.get_key_value(next)?.0.as_str();
+            if next == start {
+                chain.push(next);
+                return chain.iter().all(|name| start <= *name).then_some(chain);
+            }
+            if chain.contains(&next) {
+                return None;
+            }
+            chain.push(next);
+            current = next;
+        }
+        None
     }
 }
 
@@ -259,7 +1014,9 @@ mod tests {
         Finish, IResult,
     };
 
-    use super::{field, record, Category, Extensions, Marker};
+    use std::collections::BTreeSet;
+
+    use super::{field, record, AttributeSpec, Category, Extensions, Marker, Version};
 
     type Result<'i, O = &'i str> = IResult<&'i str, O, VerboseError<&'i str>>;
 
@@ -322,11 +1079,14 @@ mod tests {
                 Marker {
                     name: "test".into(),
                     attributes: Default::default(),
+                    attribute_types: Default::default(),
                     category: Category::Internal,
                     closes: None,
                     closedby: None,
                     default: None,
-                    description: None
+                    description: None,
+                    occurs_under: Default::default(),
+                    levels: None
                 }
             ))
         );
@@ -341,11 +1101,14 @@ mod tests {
                 Marker {
                     name: "test".into(),
                     attributes: Default::default(),
+                    attribute_types: Default::default(),
                     category: Category::Internal,
                     closes: None,
                     closedby: None,
                     default: None,
-                    description: Some("A testing marker".into())
+                    description: Some("A testing marker".into()),
+                    occurs_under: Default::default(),
+                    levels: None
                 }
             ))
         );
@@ -361,11 +1124,14 @@ mod tests {
                 Marker {
                     name: "test".into(),
                     attributes: Default::default(),
+                    attribute_types: Default::default(),
                     category: Category::ListChar,
                     closes: None,
                     closedby: None,
                     default: Some("gloss".into()),
-                    description: Some("A testing marker".into())
+                    description: Some("A testing marker".into()),
+                    occurs_under: Default::default(),
+                    levels: None
                 }
             ))
         );
@@ -387,16 +1153,29 @@ mod tests {
                         ("matte".into(), true)
                     ]
                     .into(),
+                    attribute_types: Default::default(),
                     category: Category::Internal,
                     closes: None,
                     closedby: None,
                     default: Some("gloss".into()),
-                    description: Some("A testing marker".into())
+                    description: Some("A testing marker".into()),
+                    occurs_under: Default::default(),
+                    levels: None
                 }
             ))
         );
     }
 
+    #[test]
+    fn parse_record_occurs_under() {
+        let (_, marker) = record(
+            "\\marker ft\n\\category footnotechar\n\\description Footnote text\n\\occursunder f  fe",
+        )
+        .expect("Marker");
+        assert_eq!(marker.occurs_under, ["f".into(), "fe".into()].into());
+        assert_eq!(marker.category, Category::FootnoteChar);
+    }
+
     #[test]
     fn parse_records() {
         let test = r#"
@@ -429,11 +1208,14 @@ mod tests {
                         Marker {
                             name: "it".into(),
                             attributes: [].into(),
+                            attribute_types: Default::default(),
                             category: Category::Char,
                             closes: None,
                             closedby: None,
                             default: None,
-                            description: Some("A character style, use italic text".into())
+                            description: Some("A character style, use italic text".into()),
+                            occurs_under: Default::default(),
+                            levels: None
                         }
                     ),
                     (
@@ -441,13 +1223,16 @@ mod tests {
                         Marker {
                             name: "jmp".into(),
                             attributes: [("href".into(), true), ("link-href".into(), true)].into(),
+                            attribute_types: Default::default(),
                             category: Category::Char,
                             closes: None,
                             closedby: None,
                             default: Some("href".into()),
                             description: Some(
                                 "For associating linking attributes to a span of text".into()
-                            )
+                            ),
+                            occurs_under: Default::default(),
+                            levels: None
                         }
                     ),
                     (
@@ -455,11 +1240,14 @@ mod tests {
                         Marker {
                             name: "k".into(),
                             attributes: [].into(),
+                            attribute_types: Default::default(),
                             category: Category::Char,
                             closes: None,
                             closedby: None,
                             default: Some("key".into()),
-                            description: Some("For a keyword".into())
+                            description: Some("For a keyword".into()),
+                            occurs_under: Default::default(),
+                            levels: None
                         }
                     ),
                     (
@@ -467,13 +1255,16 @@ mod tests {
                         Marker {
                             name: "k1".into(),
                             attributes: [].into(),
+                            attribute_types: Default::default(),
                             category: Category::OtherPara,
                             closes: None,
                             closedby: None,
                             default: None,
                             description: Some(
                                 "Concordance main entry text or keyword, level 1".into()
-                            )
+                            ),
+                            occurs_under: Default::default(),
+                            levels: None
                         }
                     )
                 ]
@@ -481,4 +1272,311 @@ mod tests {
             )
         )
     }
+
+    #[test]
+    fn parse_sty() {
+        let sty = r#"
+# Paratext stylesheet excerpt
+\Marker id
+\Name id - File - Identification
+\TextType Other
+\StyleType Paragraph
+
+\Marker h
+\OccursUnder id
+\TextType Other
+\StyleType Paragraph
+
+\Marker mt1
+\OccursUnder id
+\TextType Title
+\StyleType Paragraph
+
+\Marker ip
+\OccursUnder id
+\TextType Other
+\StyleType Paragraph
+
+\Marker c
+\OccursUnder id
+\TextType ChapterNumber
+\StyleType Paragraph
+
+\Marker s1
+\OccursUnder c
+\TextType Section
+\StyleType Paragraph
+
+\Marker p
+\Description Paragraph text, with first line indent
+\OccursUnder c
+\TextType VerseText
+\StyleType Paragraph
+
+\Marker li1
+\OccursUnder c
+\TextType VerseText
+\StyleType Paragraph
+
+\Marker v
+\OccursUnder li1 p
+\TextType VerseText
+\TextProperties verse
+\StyleType Character
+
+\Marker f
+\Endmarker f*
+\OccursUnder p li1
+\TextType NoteText
+\StyleType Note
+
+\Marker ft
+\Endmarker ft*
+\OccursUnder f
+\StyleType Character
+
+\Marker xo
+\OccursUnder x
+\StyleType Character
+
+\Marker w
+\Endmarker w*
+\OccursUnder p li1
+\StyleType Character
+\Attributes lemma ?strong ?srcloc
+
+\Marker tc1
+\OccursUnder tr
+\StyleType Character
+
+\Marker qt-s
+\Endmarker qt-e
+\StyleType Milestone
+\Attributes ?who ?sid
+
+\Marker qt-e
+\StyleType MilestoneEnd
+\Attributes ?eid
+"#;
+        let markers = Extensions::from_sty_str(sty).expect("Extensions");
+        let category = |name: &str| markers[name].category;
+        assert_eq!(markers.len(), 16);
+        assert_eq!(category("id"), Category::Internal);
+        assert_eq!(category("h"), Category::Header);
+        assert_eq!(category("mt1"), Category::Title);
+        assert_eq!(category("ip"), Category::Introduction);
+        assert_eq!(category("c"), Category::Internal);
+        assert_eq!(category("s1"), Category::SectionPara);
+        assert_eq!(category("p"), Category::VersePara);
+        assert_eq!(category("li1"), Category::List);
+        assert_eq!(category("v"), Category::Internal);
+        assert_eq!(category("f"), Category::Footnote);
+        assert_eq!(category("ft"), Category::FootnoteChar);
+        assert_eq!(category("xo"), Category::CrossreferenceChar);
+        assert_eq!(category("tc1"), Category::Cell);
+        assert_eq!(
+            markers["w"],
+            Marker {
+                name: "w".into(),
+                attributes: [
+                    ("lemma".into(), false),
+                    ("strong".into(), true),
+                    ("srcloc".into(), true)
+                ]
+                .into(),
+                category: Category::Char,
+                default: Some("lemma".into()),
+                occurs_under: ["p".into(), "li1".into()].into(),
+                ..Marker::default()
+            }
+        );
+        assert_eq!(markers["qt-s"].category, Category::Milestone);
+        assert_eq!(markers["qt-s"].closedby.as_deref(), Some("qt-e"));
+        assert_eq!(markers["qt-e"].closes.as_deref(), Some("qt-s"));
+        assert_eq!(
+            markers["p"].description.as_deref(),
+            Some("Paragraph text, with first line indent")
+        );
+        assert!(Extensions::from_sty_str("\\Marker p\nnot a field\n").is_err());
+    }
+
+    #[test]
+    fn display_record() {
+        let marker = Marker {
+            name: "w".into(),
+            attributes: [("lemma".into(), false), ("strong".into(), true)].into(),
+            attribute_types: Default::default(),
+            category: Category::Char,
+            default: Some("lemma".into()),
+            description: Some("A wordlist entry".into()),
+            occurs_under: ["q1".into(), "p".into()].into(),
+            ..Marker::default()
+        };
+        let text = marker.to_string();
+        assert_eq!(
+            text,
+            "\\marker w\n\
+             \\attributes lemma strong?\n\
+             \\category char\n\
+             \\defattrib lemma\n\
+             \\description A wordlist entry\n\
+             \\occursunder p q1\n"
+        );
+        assert_eq!(record(&text).map(|(_, m)| m), Ok(marker));
+    }
+
+    #[test]
+    fn ordered() {
+        let markers: Extensions =
+            "\\marker zz\n\\category char\n\n\\marker aa\n\\category char\n\n\
+                                   \\marker mm\n\\category char\n"
+                .parse()
+                .expect("Extensions");
+        let names = (&markers).into_iter().map(|(name, _)| name.as_str());
+        assert_eq!(names.collect::<Vec<_>>(), ["aa", "mm", "zz"]);
+        let mut written = Vec::new();
+        markers.write_to(&mut written).expect("write");
+        let written = String::from_utf8(written).expect("UTF-8");
+        assert!(written.find("aa") < written.find("mm"));
+        assert_eq!(
+            Extensions::usfm(Version::V3_0)
+                .keys()
+                .next()
+                .map(String::as_str),
+            Some("add")
+        );
+    }
+
+    #[test]
+    fn queries() {
+        let usfm = Extensions::usfm(Version::V3_0);
+        assert!(usfm.is_paragraph("p"));
+        assert!(usfm.is_paragraph("s1"));
+        assert!(!usfm.is_paragraph("nd"));
+        assert_eq!(usfm.end_marker_for("nd").as_deref(), Some("nd*"));
+        assert_eq!(usfm.end_marker_for("f").as_deref(), Some("f*"));
+        assert_eq!(usfm.end_marker_for("qt-s").as_deref(), Some("qt-e"));
+        assert_eq!(usfm.end_marker_for("p"), None);
+        assert!(usfm.by_category(Category::Footnote).any(|m| m.name == "fe"));
+        for version in [Version::V2, Version::V3_0, Version::V3_1] {
+            assert_eq!(Extensions::usfm(version).validate(), Vec::<String>::new());
+        }
+
+        let broken: Extensions = "\\marker a\n\\closedby b\n\n\\marker b\n\\closedby a\n\n\
+                                  \\marker c\n\\closedby d\n"
+            .parse()
+            .expect("Extensions");
+        assert_eq!(
+            broken.validate(),
+            [
+                "\\c is closed by unknown marker \\d",
+                "\\closedby cycle: a -> b -> a"
+            ]
+        );
+    }
+
+    #[test]
+    fn families() {
+        let markers: Extensions = "\\marker q\n\\category versepara\n\\levels 1-4\n\n\
+                                   \\marker q1\n\\category versepara\n\\description First\n"
+            .parse()
+            .expect("Extensions");
+        assert_eq!(markers["q"].levels, Some(1..=4));
+        assert_eq!(markers.expand("q"), ["q1", "q2", "q3", "q4"]);
+        assert_eq!(markers.expand("q1"), ["q1"]);
+        assert_eq!(markers.get("q1").map(|m| m.name.as_str()), Some("q1"));
+        assert_eq!(markers.get("q3").map(|m| m.name.as_str()), Some("q"));
+        assert_eq!(markers.category("q5"), Some(Category::VersePara));
+        assert!(!markers.contains("q1x"));
+        assert!(markers["q"].to_string().ends_with("\\levels 1-4\n"));
+
+        let usfm = Extensions::usfm(Version::V3_0);
+        assert!(!usfm.contains_key("s5"));
+        assert_eq!(usfm.category("s5"), Some(Category::SectionPara));
+        assert!(!usfm.contains("toc4"));
+    }
+
+    #[test]
+    fn attribute_types() {
+        let markers: Extensions = "\\marker z\n\
+                                   \\attributes size?=(col|span) loc?=reference level?=1-4 \
+                                   count?=number x-*? note\n\
+                                   \\category char\n"
+            .parse()
+            .expect("Extensions");
+        let z = &markers["z"];
+        assert_eq!(
+            z.attribute_type("size"),
+            &AttributeSpec::Choice(vec!["col".into(), "span".into()])
+        );
+        assert_eq!(z.attribute_type("note"), &AttributeSpec::Text);
+        assert_eq!(z.attribute("x-colour"), Some("x-*"));
+        assert_eq!(z.attribute("colour"), None);
+        assert!(z.to_string().contains(
+            "\\attributes count?=number level?=1-4 loc?=reference note size?=(col|span) x-*?\n"
+        ));
+
+        let check = |name, value| z.attribute_type(name).check(value);
+        assert_eq!(check("size", "span"), Ok(()));
+        assert_eq!(check("size", "huge"), Err("col or span".into()));
+        assert_eq!(check("loc", "MAT 3:1-4"), Ok(()));
+        assert!(check("loc", "here").is_err());
+        assert_eq!(check("level", "4"), Ok(()));
+        assert_eq!(check("level", "5"), Err("a number from 1 to 4".into()));
+        assert_eq!(check("count", "x"), Err("a number".into()));
+        assert_eq!(check("note", "anything"), Ok(()));
+    }
+
+    #[test]
+    fn valid_next_markers() {
+        let markers = Extensions::usfm(Version::default());
+        let next = |context: &[&str]| {
+            markers
+                .valid_next_markers(context)
+                .into_iter()
+                .map(|marker| marker.name.as_str())
+                .collect::<BTreeSet<_>>()
+        };
+        assert_eq!(next(&[]), BTreeSet::from(["id"]));
+        let book = next(&["id"]);
+        assert!(["usfm", "h", "mt1", "ip", "c", "s1", "p"]
+            .iter()
+            .all(|name| book.contains(name)));
+        assert!(!book.contains("v") && !book.contains("nd"));
+
+        let para = next(&["id", "c", "p"]);
+        assert!(["v", "nd", "f", "x", "fig", "qt-s", "q1", "s1", "c"]
+            .iter()
+            .all(|name| para.contains(name)));
+        assert!(["ft", "ior", "mt1", "imt1", "va", "tc1"]
+            .iter()
+            .all(|name| !para.contains(name)));
+        assert!(next(&["id", "c"]).contains("cp"));
+        assert!(next(&["id", "c", "p", "v"]).contains("va"));
+        assert!(!next(&["id", "p"]).contains("v"));
+        assert!(next(&["id", "im"]).contains("ior"));
+        assert!(!next(&["id", "c", "s1"]).contains("v"));
+
+        let footnote = next(&["id", "c", "p", "f"]);
+        assert!(footnote.contains("ft") && footnote.contains("nd"));
+        assert!(!footnote.contains("xt") && !footnote.contains("f") && !footnote.contains("p"));
+        assert!(next(&["id", "c", "p", "x", "xt"]).contains("xo"));
+        let span = next(&["id", "c", "p", "nd"]);
+        assert!(span.contains("f") && span.contains("w") && !span.contains("p"));
+        assert!(next(&["id", "c", "tr"]).contains("tc1"));
+        assert!(next(&["id", "c", "tr", "tc1"]).contains("v"));
+        assert!(next(&["id", "esb"]).contains("esbe"));
+        assert!(!next(&["id", "c", "p"]).contains("esbe"));
+
+        let markers = markers
+            .clone()
+            .update_from_str("\\marker ft\n\\category footnotechar\n\\occursunder x\n")
+            .expect("Extensions");
+        let footnote = markers
+            .valid_next_markers(&["id", "c", "p", "f"])
+            .into_iter()
+            .map(|marker| marker.name.as_str())
+            .collect::<Vec<_>>();
+        assert!(!footnote.contains(&"ft") && footnote.contains(&"fq"));
+    }
 }
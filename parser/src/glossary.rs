@@ -0,0 +1,166 @@
+//! Glossary entries from a GLO book, keyed by their `\k` keyword, and the
+//! `\w` words in the text that link to them.
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{
+    document::{Content, Document, Node, Span},
+    project::Project,
+    words::TaggedWord,
+};
+
+/// An entry, starting with a paragraph that opens with `\k keyword\k*` and
+/// taking in any following paragraphs without a keyword.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GlossaryEntry {
+    pub keyword: String,
+    /// The text of the entry after its keyword, one line per paragraph.
+    pub text: String,
+    /// Where the entry's first paragraph is in the source, for a document
+    /// parsed from USFM.
+    pub span: Option<Span>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Glossary {
+    entries: BTreeMap<String, GlossaryEntry>,
+    // Lower case keywords, for words capitalized differently.
+    folded: HashMap<String, String>,
+}
+
+impl Glossary {
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The entries in keyword order.
+    pub fn iter(&self) -> impl Iterator<Item = &GlossaryEntry> {
+        self.entries.values()
+    }
+
+    /// The entry for a keyword, matched exactly if possible and otherwise
+    /// ignoring case.
+    pub fn get(&self, keyword: &str) -> Option<&GlossaryEntry> {
+        let keyword = keyword.trim();
+        self.entries.get(keyword).or_else(|| {
+            let key = self.folded.get(&keyword.to_lowercase())?;
+            self.entries.get(key)
+        })
+    }
+
+    /// The entry a `\w` word links to, through its lemma as in
+    /// `\w Pharisees|Pharisee\w*` or otherwise the word itself.
+    pub fn resolve(&self, word: &TaggedWord) -> Option<&GlossaryEntry> {
+        self.get(word.lemma.as_deref().unwrap_or(&word.surface))
+    }
+
+    fn insert(&mut self, entry: GlossaryEntry) {
+        self.folded
+            .insert(entry.keyword.to_lowercase(), entry.keyword.clone());
+        self.entries.insert(entry.keyword.clone(), entry);
+    }
+}
+
+impl Node<'_> {
+    /// The keyword of a paragraph that starts with a `\k` span.
+    pub fn keyword(&self) -> Option<String> {
+        let first = self.content.iter().find(|item| match item {
+            Content::Text(text) => !text.as_str().trim().is_empty(),
+            _ => true,
+        })?;
+        match first {
+            Content::Char(node) if node.style == "k" => {
+                let keyword = node.text();
+                let keyword = keyword.trim();
+                (!keyword.is_empty()).then(|| keyword.to_owned())
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Document<'_> {
+    /// The entries of a glossary such as the GLO book. Paragraphs before
+    /// the first keyword are left out.
+    pub fn glossary(&self) -> Glossary {
+        let mut glossary = Glossary::default();
+        let mut current: Option<GlossaryEntry> = None;
+        for para in self.iter_paras() {
+            match para.keyword() {
+                Some(keyword) => {
+                    if let Some(entry) = current.take() {
+                        glossary.insert(entry);
+                    }
+                    let text = para.text();
+                    let text = text.trim_start();
+                    let rest = text.strip_prefix(keyword.as_str()).unwrap_or(text);
+                    current = Some(GlossaryEntry {
+                        keyword,
+                        text: rest.trim().to_owned(),
+                        span: para.span,
+                    });
+                }
+                None => {
+                    if let Some(entry) = &mut current {
+                        let text = para.text();
+                        if !text.trim().is_empty() {
+                            entry.text.push('\n');
+                            entry.text.push_str(text.trim());
+                        }
+                    }
+                }
+            }
+        }
+        if let Some(entry) = current {
+            glossary.insert(entry);
+        }
+        glossary
+    }
+}
+
+impl Project {
+    /// The glossary of the project's GLO book, empty if it has none.
+    pub fn glossary(&self) -> Glossary {
+        self.get("GLO").map(Document::glossary).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::document::Document;
+
+    #[test]
+    fn glossary() {
+        let glo: Document = "\\id GLO\n\\mt1 Glossary\n\\p \\k Pharisee\\k* A member of a party\n\
+                             \\p that kept the law strictly.\n\\p \\k Sabbath\\k* The day of rest.\n"
+            .parse()
+            .expect("Document");
+        let glossary = glo.glossary();
+        assert_eq!(
+            glossary
+                .iter()
+                .map(|e| e.keyword.as_str())
+                .collect::<Vec<_>>(),
+            ["Pharisee", "Sabbath"]
+        );
+        assert_eq!(
+            glossary.get("Pharisee").map(|e| e.text.as_str()),
+            Some("A member of a party\nthat kept the law strictly.")
+        );
+
+        let mrk: Document =
+            "\\id MRK\n\\c 2\n\\p \\v 24 The \\w Pharisees|Pharisee\\w* said, on the \\w sabbath\\w*\n"
+                .parse()
+                .expect("Document");
+        let resolved = mrk
+            .tagged_words()
+            .iter()
+            .map(|word| glossary.resolve(word).map(|e| e.keyword.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(resolved, [Some("Pharisee"), Some("Sabbath")]);
+    }
+}
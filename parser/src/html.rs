@@ -0,0 +1,406 @@
+//! Rendering documents as semantic HTML, with a `usfm-<marker>` class on
+//! every element for styling.
+
+use std::{
+    collections::HashMap,
+    fmt::{self, Display, Write},
+};
+
+use crate::{
+    document::{Content, Document, Node, State},
+    extension::{Category, Extensions},
+    usx::escape,
+};
+
+/// The tag and class an element is rendered with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Element {
+    pub tag: String,
+    pub class: String,
+}
+
+impl Element {
+    pub fn new(tag: impl Into<String>, class: impl Into<String>) -> Self {
+        Element {
+            tag: tag.into(),
+            class: class.into(),
+        }
+    }
+}
+
+pub struct Html<'d> {
+    doc: &'d Document<'d>,
+    elements: HashMap<String, Element>,
+}
+
+impl Document<'_> {
+    /// Paragraphs become `<p>`, headings `<h1>`–`<h3>`, character markers
+    /// `<span>` and tables real tables. Notes are `popover` spans following
+    /// a caller button, so they stay valid inside a paragraph. Header
+    /// paragraphs such as `\h` and `\toc1` are left out.
+    pub fn to_html(&self) -> Html<'_> {
+        Html {
+            doc: self,
+            elements: HashMap::new(),
+        }
+    }
+}
+
+impl Html<'_> {
+    /// Render a marker with a different tag or class than the default.
+    pub fn element(mut self, marker: &str, element: Element) -> Self {
+        self.elements.insert(marker.to_owned(), element);
+        self
+    }
+}
+
+impl Display for Html<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut writer = Writer {
+            out: f,
+            markers: State::usfm_ext(),
+            elements: &self.elements,
+            notes: 0,
+            endnotes: None,
+        };
+        match &self.doc.nodes {
+            Some(root) => writer.blocks(&root.content),
+            None => Ok(()),
+        }
+    }
+}
+
+struct Writer<'w, W> {
+    out: &'w mut W,
+    markers: &'static Extensions,
+    elements: &'w HashMap<String, Element>,
+    notes: usize,
+    /// Where notes go when writing XHTML with them at the end.
+    endnotes: Option<&'w mut Endnotes>,
+}
+
+/// The notes of a book collected while its chapters are written as XHTML
+/// for a publication, each linked to and from the place it was called.
+#[derive(Debug, Default)]
+pub(crate) struct Endnotes {
+    /// The file the notes are written to.
+    pub href: String,
+    /// The file being written.
+    pub back: String,
+    /// Each note as an `<aside>`.
+    pub notes: Vec<String>,
+}
+
+/// XHTML for content written into a publication file, with its notes
+/// moved to `endnotes`.
+pub(crate) fn xhtml(
+    content: &[Content],
+    elements: &HashMap<String, Element>,
+    endnotes: &mut Endnotes,
+) -> String {
+    let mut out = String::new();
+    let mut writer = Writer {
+        out: &mut out,
+        markers: State::usfm_ext(),
+        elements,
+        notes: endnotes.notes.len(),
+        endnotes: Some(endnotes),
+    };
+    let _ = writer.blocks(content);
+    out
+}
+
+// Generated callers run a, b, … z, aa, ab, …
+fn caller(mut n: usize) -> String {
+    let mut res = Vec::new();
+    loop {
+        res.push(b'a' + (n % 26) as u8);
+        n /= 26;
+        if n == 0 {
+            break;
+        }
+        n -= 1;
+    }
+    res.iter().rev().map(|&b| b as char).collect()
+}
+
+impl<W: Write> Writer<'_, W> {
+    fn category(&self, node: &Node) -> Category {
+        self.markers
+            .get(node.style.as_ref())
+            .map(|marker| marker.category)
+            .unwrap_or_default()
+    }
+
+    fn element(&self, node: &Node, tag: &str) -> Element {
+        self.elements
+            .get(node.style.as_ref())
+            .cloned()
+            .unwrap_or_else(|| Element::new(tag, format!("usfm-{}", node.style)))
+    }
+
+    fn open(&mut self, element: &Element, node: &Node, extra: &str) -> fmt::Result {
+        write!(
+            self.out,
+            "<{} class=\"{}\"",
+            element.tag,
+            escape(&element.class)
+        )?;
+        let mut attributes = node.attributes.iter().collect::<Vec<_>>();
+        attributes.sort();
+        for (name, value) in attributes {
+            write!(self.out, " data-{}=\"{}\"", escape(name), escape(value))?;
+        }
+        write!(self.out, "{extra}>")
+    }
+
+    fn wrap(
+        &mut self,
+        node: &Node,
+        tag: &str,
+        inner: impl FnOnce(&mut Self) -> fmt::Result,
+    ) -> fmt::Result {
+        let element = self.element(node, tag);
+        self.open(&element, node, "")?;
+        inner(self)?;
+        write!(self.out, "</{}>", element.tag)
+    }
+
+    fn blocks(&mut self, content: &[Content]) -> fmt::Result {
+        content.iter().try_for_each(|item| self.block(item))
+    }
+
+    fn block(&mut self, item: &Content) -> fmt::Result {
+        match item {
+            Content::Book(_) => Ok(()),
+            Content::Para(node) => {
+                let tag = match self.category(node) {
+                    Category::Header => return Ok(()),
+                    Category::Title if node.style.starts_with("mt") => "h1",
+                    Category::Title | Category::SectionPara if node.style.ends_with('2') => "h3",
+                    Category::Title | Category::SectionPara => "h2",
+                    Category::List => "li",
+                    _ => "p",
+                };
+                self.wrap(node, tag, |w| w.inlines(&node.content))?;
+                self.out.write_char('\n')
+            }
+            Content::Chapter(node) => {
+                let number = node
+                    .attributes
+                    .get("pubnumber")
+                    .or(node.attributes.get("number"))
+                    .map_or_else(String::new, |n| escape(n).into_owned());
+                self.wrap(node, "section", |w| {
+                    writeln!(w.out, "\n<h2 class=\"usfm-chapter-number\">{number}</h2>")?;
+                    w.blocks(&node.content)
+                })?;
+                self.out.write_char('\n')
+            }
+            Content::Table(node) => {
+                self.wrap(node, "table", |w| {
+                    w.out.write_char('\n')?;
+                    w.blocks(&node.content)
+                })?;
+                self.out.write_char('\n')
+            }
+            Content::Row(node) => {
+                self.wrap(node, "tr", |w| w.inlines(&node.content))?;
+                self.out.write_char('\n')
+            }
+            Content::List(node) => {
+                self.wrap(node, "ul", |w| {
+                    w.out.write_char('\n')?;
+                    w.blocks(&node.content)
+                })?;
+                self.out.write_char('\n')
+            }
+            Content::Stanza(node) | Content::Periph(node) | Content::Sidebar(node) => {
+                let tag = match item {
+                    Content::Sidebar(_) => "aside",
+                    Content::Periph(_) => "section",
+                    _ => "div",
+                };
+                self.wrap(node, tag, |w| {
+                    w.out.write_char('\n')?;
+                    w.blocks(&node.content)
+                })?;
+                self.out.write_char('\n')
+            }
+            Content::Unknown(node) => {
+                self.wrap(node, "div", |w| w.inlines(&node.content))?;
+                self.out.write_char('\n')
+            }
+            item => self.inline(item),
+        }
+    }
+
+    fn inlines(&mut self, content: &[Content]) -> fmt::Result {
+        content.iter().try_for_each(|item| self.inline(item))
+    }
+
+    fn inline(&mut self, item: &Content) -> fmt::Result {
+        match item {
+            Content::Text(text) => self.out.write_str(&escape(text.as_str())),
+            Content::OptBreak if self.endnotes.is_some() => self.out.write_str("<wbr/>"),
+            Content::OptBreak => self.out.write_str("<wbr>"),
+            Content::NoBreakSpace if self.endnotes.is_some() => self.out.write_str("&#160;"),
+            Content::NoBreakSpace => self.out.write_str("&nbsp;"),
+            Content::Verse(node) => {
+                let number = node
+                    .attributes
+                    .get("pubnumber")
+                    .or(node.attributes.get("number"))
+                    .map_or_else(String::new, |n| escape(n).into_owned());
+                self.wrap(node, "sup", |w| w.out.write_str(&number))
+            }
+            Content::Note(node) => self.note(node),
+            Content::Cell(node) => {
+                let tag = if node.style.starts_with("th") {
+                    "th"
+                } else {
+                    "td"
+                };
+                self.wrap(node, tag, |w| w.inlines(&node.content))
+            }
+            Content::Figure(node) => {
+                let attribute = |name| {
+                    node.attributes
+                        .get(name)
+                        .map_or_else(String::new, |v| escape(v).into_owned())
+                };
+                let (src, alt) = (attribute("src"), attribute("alt"));
+                let close = if self.endnotes.is_some() { "/>" } else { ">" };
+                self.wrap(node, "figure", |w| {
+                    write!(w.out, "<img src=\"{src}\" alt=\"{alt}\"{close}")?;
+                    w.out.write_str("<figcaption>")?;
+                    w.inlines(&node.content)?;
+                    w.out.write_str("</figcaption>")
+                })
+            }
+            Content::Milestone(node) => self.wrap(node, "span", |_| Ok(())),
+            item => match item.node() {
+                Some(node) => self.wrap(node, "span", |w| w.inlines(&node.content)),
+                None => Ok(()),
+            },
+        }
+    }
+
+    fn note(&mut self, node: &Node) -> fmt::Result {
+        self.notes += 1;
+        let id = format!("note-{}", self.notes);
+        let caller = match node.attributes.get("caller").map(|c| c.as_ref()) {
+            Some("-") => None,
+            Some("+") | None => Some(caller(self.notes - 1)),
+            Some(caller) => Some(escape(caller).into_owned()),
+        };
+        if self.endnotes.is_some() {
+            return self.endnote(node, &id, caller);
+        }
+        if let Some(caller) = caller {
+            write!(
+                self.out,
+                "<button class=\"usfm-caller\" popovertarget=\"{id}\">{caller}</button>"
+            )?;
+        }
+        let element = self.element(node, "span");
+        self.open(
+            &element,
+            node,
+            &format!(" id=\"{id}\" role=\"note\" popover"),
+        )?;
+        self.inlines(&node.content)?;
+        write!(self.out, "</{}>", element.tag)
+    }
+
+    // A link to the note, which goes with the rest at the end. Notes
+    // without a caller are still listed there.
+    fn endnote(&mut self, node: &Node, id: &str, caller: Option<String>) -> fmt::Result {
+        let Some(endnotes) = self.endnotes.as_deref_mut() else {
+            return Ok(());
+        };
+        let (href, back) = (escape(&endnotes.href), escape(&endnotes.back));
+        if let Some(caller) = &caller {
+            write!(
+                self.out,
+                "<a class=\"usfm-caller\" epub:type=\"noteref\" id=\"ref-{id}\" \
+                 href=\"{href}#{id}\">{caller}</a>"
+            )?;
+        }
+        let mut aside = String::new();
+        let mut writer = Writer {
+            out: &mut aside,
+            markers: self.markers,
+            elements: self.elements,
+            notes: 0,
+            endnotes: Some(&mut Endnotes::default()),
+        };
+        let element = writer.element(node, "aside");
+        writer.open(
+            &element,
+            node,
+            &format!(" id=\"{id}\" epub:type=\"endnote\""),
+        )?;
+        if let Some(caller) = &caller {
+            write!(writer.out, "<a href=\"{back}#ref-{id}\">{caller}</a> ")?;
+        }
+        writer.inlines(&node.content)?;
+        write!(writer.out, "</{}>", element.tag)?;
+        if let Some(endnotes) = self.endnotes.as_deref_mut() {
+            endnotes.notes.push(aside);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{caller, Element};
+    use crate::document::Document;
+
+    #[test]
+    fn render() {
+        let doc: Document = "\\id MRK\n\
+                             \\h Mark\n\
+                             \\mt1 Mark\n\
+                             \\c 1\n\
+                             \\s1 The <Baptist>\n\
+                             \\p \\v 1 The \\nd Lord\\nd*\\f + \\fr 1.1 \\ft A & B\\f* \\w word|lemma=\"w\"\\w*\n\
+                             \\tr \\th1 A \\tcr2 B\n"
+            .parse()
+            .expect("Document");
+        assert_eq!(
+            doc.to_html().to_string(),
+            "<h1 class=\"usfm-mt1\">Mark</h1>\n\
+             <section class=\"usfm-c\" data-number=\"1\">\n\
+             <h2 class=\"usfm-chapter-number\">1</h2>\n\
+             <h2 class=\"usfm-s1\" data-level=\"1\">The &lt;Baptist&gt;</h2>\n\
+             <p class=\"usfm-p\"><sup class=\"usfm-v\" data-number=\"1\">1</sup>The \
+             <span class=\"usfm-nd\">Lord</span>\
+             <button class=\"usfm-caller\" popovertarget=\"note-1\">a</button>\
+             <span class=\"usfm-f\" data-caller=\"+\" id=\"note-1\" role=\"note\" popover>\
+             <span class=\"usfm-fr\">1.1 </span><span class=\"usfm-ft\">A &amp; B</span></span> \
+             <span class=\"usfm-w\" data-lemma=\"w\">word</span></p>\n\
+             <table class=\"usfm-table\">\n\
+             <tr class=\"usfm-tr\"><th class=\"usfm-th1\" data-align=\"start\">A </th>\
+             <td class=\"usfm-tcr2\" data-align=\"end\">B</td></tr>\n\
+             </table>\n\
+             </section>\n"
+        );
+        let html = doc
+            .to_html()
+            .element("nd", Element::new("strong", "divine-name"))
+            .element("p", Element::new("div", "para"))
+            .to_string();
+        assert!(html.contains("<div class=\"para\"><sup"));
+        assert!(html.contains("<strong class=\"divine-name\">Lord</strong>"));
+    }
+
+    #[test]
+    fn callers() {
+        assert_eq!(caller(0), "a");
+        assert_eq!(caller(25), "z");
+        assert_eq!(caller(26), "aa");
+        assert_eq!(caller(27), "ab");
+    }
+}
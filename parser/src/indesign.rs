@@ -0,0 +1,241 @@
+//! Exporting documents as Adobe InDesign tagged text, for placing straight
+//! into a layout. Each marker becomes a paragraph or character style of the
+//! same name, so a template only has to define styles such as `p`, `q1`,
+//! `nd` and `v`.
+
+use std::io::{self, Write};
+
+use crate::{
+    document::{Content, Document, Node, State},
+    extension::{Category, Extensions},
+};
+
+impl Document<'_> {
+    /// Write tagged text as InDesign expects it for Unicode: UTF-16LE with
+    /// a byte order mark and CRLF line ends. Paragraph markers become
+    /// paragraph styles and character markers character styles, going by
+    /// their category. Nested character markers take the innermost style,
+    /// notes become InDesign footnotes and table rows tab separated
+    /// paragraphs. Header paragraphs such as `\h` and `\toc1` are left out.
+    pub fn to_tagged_text<W: Write>(&self, mut w: W) -> io::Result<()> {
+        let mut writer = Writer {
+            markers: State::usfm_ext(),
+            out: String::new(),
+            chars: Vec::new(),
+        };
+        writer.out.push_str("<UNICODE-WIN>\r\n");
+        if let Some(root) = &self.nodes {
+            writer.blocks(&root.content);
+        }
+        w.write_all(&[0xff, 0xfe])?;
+        let bytes = writer
+            .out
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect::<Vec<_>>();
+        w.write_all(&bytes)
+    }
+}
+
+struct Writer {
+    markers: &'static Extensions,
+    out: String,
+    // The character styles currently open, innermost last.
+    chars: Vec<String>,
+}
+
+fn escape(text: &str) -> String {
+    let mut res = String::with_capacity(text.len());
+    let mut space = false;
+    for c in text.chars() {
+        match c {
+            '<' | '>' | '\\' => {
+                res.push('\\');
+                res.push(c);
+            }
+            c if c.is_whitespace() && c != '\u{a0}' => {
+                if !space {
+                    res.push(' ');
+                }
+                space = true;
+                continue;
+            }
+            c => res.push(c),
+        }
+        space = false;
+    }
+    res
+}
+
+impl Writer {
+    fn category(&self, node: &Node) -> Category {
+        self.markers
+            .get(node.style.as_ref())
+            .map(|marker| marker.category)
+            .unwrap_or_default()
+    }
+
+    fn paragraph(&mut self, style: &str, inner: impl FnOnce(&mut Self)) {
+        self.out.push_str(&format!("<ParaStyle:{style}>"));
+        let start = self.out.len();
+        inner(self);
+        let text = self.out[start..].trim().to_owned();
+        self.out.truncate(start);
+        self.out.push_str(&text);
+        self.out.push_str("\r\n");
+    }
+
+    fn blocks(&mut self, content: &[Content]) {
+        for item in content {
+            match item {
+                Content::Book(_) | Content::Milestone(_) => {}
+                Content::Para(node) if self.category(node) == Category::Header => {}
+                Content::Para(node) => {
+                    self.paragraph(&node.style, |w| w.inlines(&node.content));
+                }
+                Content::Unknown(node) if !self.inline_unknown(node) => {
+                    self.paragraph(&node.style, |w| w.inlines(&node.content));
+                }
+                Content::Chapter(node) => {
+                    let number = node
+                        .attributes
+                        .get("pubnumber")
+                        .or(node.attributes.get("number"))
+                        .map(|n| escape(n))
+                        .unwrap_or_default();
+                    self.paragraph(&node.style, |w| w.out.push_str(&number));
+                    self.blocks(&node.content);
+                }
+                Content::Row(node) => {
+                    self.paragraph(&node.style, |w| {
+                        for (n, cell) in node.content.iter().enumerate() {
+                            if n > 0 {
+                                w.out.push('\t');
+                            }
+                            w.inline(cell);
+                        }
+                    });
+                }
+                Content::Figure(node) => {
+                    self.paragraph(&node.style, |w| w.inlines(&node.content));
+                }
+                item => match item.node() {
+                    Some(node) => self.blocks(&node.content),
+                    None => self.paragraph("p", |w| w.inline(item)),
+                },
+            }
+        }
+    }
+
+    // Unknown markers met between paragraphs are taken for paragraphs
+    // unless they look like character markers.
+    fn inline_unknown(&self, node: &Node) -> bool {
+        matches!(
+            self.category(node),
+            Category::Char | Category::FootnoteChar | Category::CrossreferenceChar
+        )
+    }
+
+    fn open_char(&mut self, style: &str) {
+        self.chars.push(style.to_owned());
+        self.out.push_str(&format!("<CharStyle:{style}>"));
+    }
+
+    fn close_char(&mut self) {
+        self.chars.pop();
+        match self.chars.last() {
+            Some(outer) => self.out.push_str(&format!("<CharStyle:{outer}>")),
+            None => self.out.push_str("<CharStyle:>"),
+        }
+    }
+
+    fn inlines(&mut self, content: &[Content]) {
+        content.iter().for_each(|item| self.inline(item));
+    }
+
+    fn inline(&mut self, item: &Content) {
+        match item {
+            Content::Text(text) => self.out.push_str(&escape(text.as_str())),
+            Content::NoBreakSpace => self.out.push('\u{a0}'),
+            Content::Verse(node) => {
+                let number = node
+                    .attributes
+                    .get("pubnumber")
+                    .or(node.attributes.get("number"))
+                    .map(|n| escape(n))
+                    .unwrap_or_default();
+                self.open_char(&node.style);
+                self.out.push_str(&number);
+                self.close_char();
+                self.out.push(' ');
+            }
+            Content::Note(node) => {
+                // Footnotes start afresh, without the styles around them.
+                let outer = std::mem::take(&mut self.chars);
+                if !outer.is_empty() {
+                    self.out.push_str("<CharStyle:>");
+                }
+                self.out.push_str("<FootnoteStart:>");
+                self.out.push_str(&format!("<ParaStyle:{}>", node.style));
+                self.inlines(&node.content);
+                self.out.push_str("<FootnoteEnd:>");
+                self.chars = outer;
+                if let Some(style) = self.chars.last() {
+                    self.out.push_str(&format!("<CharStyle:{style}>"));
+                }
+            }
+            Content::Cell(node) => self.inlines(&node.content),
+            Content::Char(node) | Content::Unknown(node) => {
+                self.open_char(&node.style);
+                self.inlines(&node.content);
+                self.close_char();
+            }
+            Content::Figure(_) | Content::Milestone(_) | Content::OptBreak => {}
+            item => {
+                if let Some(node) = item.node() {
+                    self.inlines(&node.content);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::document::Document;
+
+    fn tagged(usfm: &str) -> String {
+        let doc: Document = usfm.parse().expect("Document");
+        let mut out = Vec::new();
+        doc.to_tagged_text(&mut out).expect("tagged text");
+        assert_eq!(out[..2], [0xff, 0xfe]);
+        let units = out[2..]
+            .chunks(2)
+            .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+            .collect::<Vec<_>>();
+        String::from_utf16(&units).expect("UTF-16")
+    }
+
+    #[test]
+    fn tagged_text() {
+        assert_eq!(
+            tagged(
+                "\\id MRK\n\
+                 \\h Mark\n\
+                 \\mt1 Mark\n\
+                 \\c 1\n\
+                 \\p \\v 1 The \\w \\+nd Lord\\+nd* <God>\\w*\\f + \\fr 1.1 \\ft Or\\f* came\n\
+                 \\q1 \\v 2 a  b\n\
+                 \\tr \\tc1 A \\tc2 B\n"
+            ),
+            "<UNICODE-WIN>\r\n\
+             <ParaStyle:mt1>Mark\r\n\
+             <ParaStyle:c>1\r\n\
+             <ParaStyle:p><CharStyle:v>1<CharStyle:> The <CharStyle:w><CharStyle:nd>Lord\
+             <CharStyle:w> \\<God\\><CharStyle:><FootnoteStart:><ParaStyle:f>\
+             <CharStyle:fr>1.1 <CharStyle:><CharStyle:ft>Or<CharStyle:><FootnoteEnd:> came\r\n\
+             <ParaStyle:q1><CharStyle:v>2<CharStyle:> a b\r\n\
+             <ParaStyle:tr>A \tB\r\n"
+        );
+    }
+}
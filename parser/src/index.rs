@@ -0,0 +1,282 @@
+//! An inverted index from words to the verses they are in, for
+//! concordances and word search across a project.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::{
+    document::{Content, Document, Node, State},
+    extension::{Category, Extensions},
+    project::Project,
+    reference::Reference,
+    versification::verse_range,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexOptions {
+    /// Keep "Lord" and "lord" apart rather than folding case.
+    pub case_sensitive: bool,
+    /// Characters that join the letters either side of them into one word,
+    /// by default apostrophes and hyphens.
+    pub joiners: Vec<char>,
+}
+
+impl Default for IndexOptions {
+    fn default() -> Self {
+        IndexOptions {
+            case_sensitive: false,
+            joiners: vec!['\'', '’', '-', '\u{2011}'],
+        }
+    }
+}
+
+/// The words of the body text of some books. Notes, figures and headings
+/// are left out, and a `\w` span is always a word of its own.
+#[derive(Debug, Clone, Default)]
+pub struct Index {
+    options: IndexOptions,
+    verses: Vec<Reference>,
+    // Each word's occurrences as indexes into `verses` and word positions
+    // within the verse.
+    words: HashMap<String, Vec<(usize, usize)>>,
+}
+
+impl Index {
+    pub fn new(options: IndexOptions) -> Self {
+        Index {
+            options,
+            ..Index::default()
+        }
+    }
+
+    /// Add the verses of a document.
+    pub fn add(&mut self, doc: &Document) {
+        let (Some(book), Some(root)) = (doc.book(), doc.root()) else {
+            return;
+        };
+        let mut collector = Collector {
+            markers: State::usfm_ext(),
+            reference: Reference::new(book, 0, None),
+            verses: Vec::new(),
+            open: false,
+        };
+        collector.content(&root.content);
+        for (reference, segments) in collector.verses {
+            let verse = self.verses.len();
+            self.verses.push(reference);
+            let words = segments
+                .iter()
+                .flat_map(|segment| self.tokens(segment))
+                .collect::<Vec<_>>();
+            for (position, word) in words.into_iter().enumerate() {
+                self.words.entry(word).or_default().push((verse, position));
+            }
+        }
+    }
+
+    /// The words of `text` as they are indexed.
+    pub fn tokens(&self, text: &str) -> Vec<String> {
+        let mut words = Vec::new();
+        let mut word = String::new();
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            let joiner = self.options.joiners.contains(&c)
+                && !word.is_empty()
+                && chars.peek().is_some_and(|c| c.is_alphanumeric());
+            if c.is_alphanumeric() || joiner {
+                word.push(c);
+            } else if !word.is_empty() {
+                words.push(self.fold(std::mem::take(&mut word)));
+            }
+        }
+        if !word.is_empty() {
+            words.push(self.fold(word));
+        }
+        words
+    }
+
+    fn fold(&self, word: String) -> String {
+        match self.options.case_sensitive {
+            true => word,
+            false => word.to_lowercase(),
+        }
+    }
+
+    /// The verses containing `word`, in the order they were added.
+    pub fn word(&self, word: &str) -> Vec<Reference> {
+        let word = self.fold(word.to_owned());
+        let mut verses = self
+            .words
+            .get(&word)
+            .into_iter()
+            .flatten()
+            .map(|&(verse, _)| verse)
+            .collect::<Vec<_>>();
+        verses.dedup();
+        verses.into_iter().map(|verse| self.verses[verse]).collect()
+    }
+
+    /// The verses containing the words of `phrase` next to each other, in
+    /// the order they were added. Punctuation in the phrase is ignored.
+    pub fn phrase(&self, phrase: &str) -> Vec<Reference> {
+        let words = self.tokens(phrase);
+        let Some((first, rest)) = words.split_first() else {
+            return Vec::new();
+        };
+        let following = rest
+            .iter()
+            .map(|word| {
+                self.words
+                    .get(word)
+                    .into_iter()
+                    .flatten()
+                    .collect::<HashSet<_>>()
+            })
+            .collect::<Vec<_>>();
+        let mut verses = self
+            .words
+            .get(first)
+            .into_iter()
+            .flatten()
+            .filter(|&&(verse, position)| {
+                following
+                    .iter()
+                    .enumerate()
+                    .all(|(n, occurrences)| occurrences.contains(&(verse, position + n + 1)))
+            })
+            .map(|&(verse, _)| verse)
+            .collect::<Vec<_>>();
+        verses.dedup();
+        verses.into_iter().map(|verse| self.verses[verse]).collect()
+    }
+
+    /// Every word with how often it occurs, in alphabetical order.
+    pub fn words(&self) -> BTreeMap<&str, usize> {
+        self.words
+            .iter()
+            .map(|(word, occurrences)| (word.as_str(), occurrences.len()))
+            .collect()
+    }
+}
+
+impl Project {
+    /// An index of every book, in canonical order.
+    pub fn index(&self, options: IndexOptions) -> Index {
+        let mut index = Index::new(options);
+        for (_, doc) in self.iter() {
+            index.add(doc);
+        }
+        index
+    }
+}
+
+struct Collector {
+    markers: &'static Extensions,
+    reference: Reference,
+    // The text of each verse, split where words must break.
+    verses: Vec<(Reference, Vec<String>)>,
+    // Whether text belongs to the last verse, which it stops doing at the
+    // next chapter.
+    open: bool,
+}
+
+impl Collector {
+    fn is_heading(&self, node: &Node) -> bool {
+        self.markers.get(node.style.as_ref()).is_some_and(|marker| {
+            matches!(
+                marker.category,
+                Category::SectionPara | Category::Title | Category::Header | Category::Introduction
+            )
+        })
+    }
+
+    fn push(&mut self, text: &str) {
+        let verse = self.verses.last_mut().filter(|_| self.open);
+        if let Some(segment) = verse.and_then(|(_, segments)| segments.last_mut()) {
+            segment.push_str(text);
+        }
+    }
+
+    fn boundary(&mut self) {
+        if let Some((_, segments)) = self.verses.last_mut().filter(|_| self.open) {
+            segments.push(String::new());
+        }
+    }
+
+    fn content(&mut self, content: &[Content]) {
+        for item in content {
+            match item {
+                Content::Text(text) => self.push(text.as_str()),
+                Content::Chapter(node) => {
+                    self.reference.chapter = node
+                        .attributes
+                        .get("number")
+                        .and_then(|n| n.parse().ok())
+                        .unwrap_or(self.reference.chapter);
+                    self.open = false;
+                    self.reference.verse = None;
+                    self.content(&node.content);
+                }
+                Content::Verse(node) => {
+                    let Some((first, _)) =
+                        node.attributes.get("number").and_then(|n| verse_range(n))
+                    else {
+                        continue;
+                    };
+                    self.reference.verse = Some(first);
+                    self.verses.push((self.reference, vec![String::new()]));
+                    self.open = true;
+                }
+                Content::Note(_) | Content::Figure(_) | Content::Book(_) => self.boundary(),
+                Content::Para(node) if self.is_heading(node) => self.boundary(),
+                Content::Char(node) if node.style == "w" => {
+                    self.boundary();
+                    self.content(&node.content);
+                    self.boundary();
+                }
+                Content::Char(node) | Content::Milestone(node) => self.content(&node.content),
+                item => {
+                    self.boundary();
+                    if let Some(node) = item.node() {
+                        self.content(&node.content);
+                        self.boundary();
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Index, IndexOptions};
+    use crate::{books, document::Document, reference::Reference};
+
+    #[test]
+    fn index() {
+        let doc: Document = "\\id JHN\n\\c 3\n\\s1 God's love\n\
+                             \\p \\v 16 For God so loved\\f + \\ft Or God loved\\f* the world\n\
+                             \\v 17 God's \\w Son\\w*s came into the\n\\q1 world\n"
+            .parse()
+            .expect("Document");
+        let mut index = Index::new(IndexOptions::default());
+        index.add(&doc);
+        let jhn = |verse| Reference::new(books::get("JHN").unwrap(), 3, Some(verse));
+
+        assert_eq!(index.word("GOD"), [jhn(16)]);
+        assert_eq!(index.word("god's"), [jhn(17)]);
+        assert_eq!(index.word("son"), [jhn(17)]);
+        assert_eq!(index.word("love"), []);
+        assert_eq!(index.phrase("so loved the world"), [jhn(16)]);
+        assert_eq!(index.phrase("the world"), [jhn(16), jhn(17)]);
+        assert_eq!(index.phrase("God loved"), []);
+        assert_eq!(index.words()["the"], 2);
+
+        let mut index = Index::new(IndexOptions {
+            case_sensitive: true,
+            ..IndexOptions::default()
+        });
+        index.add(&doc);
+        assert_eq!(index.word("god"), []);
+        assert_eq!(index.tokens("Son's—son-in-law"), ["Son's", "son-in-law"]);
+    }
+}
@@ -0,0 +1,270 @@
+//! A translation interleaved with its back translation verse by verse, for
+//! consultant checking. Either the back translation is written into the
+//! document as paragraphs after the text they translate, or the two are set
+//! side by side in an HTML table.
+
+use std::{collections::HashMap, fmt::Write, ops::ControlFlow};
+
+use crate::{
+    corpus::AlignedVerse,
+    diagnostic::{Code, Diagnostic},
+    document::{Content, Document, Node},
+    reference::Reference,
+    transform::Transform,
+    usx::escape,
+    versification::{verse_numbers, verse_range},
+    visit::VisitMut,
+};
+
+/// Add a `\lit` paragraph after each block of the document holding the back
+/// translation of the verses starting in it, each as `[3] text`. Verses
+/// missing from the back translation are reported as
+/// [`Code::MissingVerse`] warnings.
+///
+/// ```
+/// # use parser::{document::Document, interleave::Interleave, transform::Pipeline};
+/// let back: Document = "\\id MRK\n\\c 1\n\\p \\v 1 The start.\n".parse().unwrap();
+/// let mut doc: Document = "\\id MRK\n\\c 1\n\\p \\v 1 Archê.\n".parse().unwrap();
+/// Pipeline::new().pass(Interleave::new(&back)).run(&mut doc);
+/// assert_eq!(doc.to_string(), "\\id MRK\n\\c 1\n\\p\n\\v 1 Archê.\n\\lit [1] The start.\n");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Interleave {
+    marker: String,
+    back: HashMap<(u32, u32), String>,
+    chapter: u32,
+    missing: Vec<Diagnostic>,
+}
+
+impl Interleave {
+    pub fn new(back: &Document) -> Self {
+        Interleave {
+            marker: "lit".to_owned(),
+            back: back
+                .verse_texts()
+                .into_iter()
+                .filter_map(|(reference, text)| Some(((reference.chapter, reference.verse?), text)))
+                .collect(),
+            ..Interleave::default()
+        }
+    }
+
+    /// Use another paragraph marker for the back translation, such as a
+    /// project's own `\zbt`.
+    pub fn marker(mut self, marker: impl Into<String>) -> Self {
+        self.marker = marker.into();
+        self
+    }
+
+    // The back translation of the verses starting in `block`.
+    fn back_translation(&mut self, block: &Node) -> String {
+        let mut res = Vec::new();
+        for verse in block.iter_verses() {
+            let Some(number) = verse.attributes.get("number") else {
+                continue;
+            };
+            let text = verse_range(number)
+                .into_iter()
+                .flat_map(verse_numbers)
+                .filter_map(|n| self.back.get(&(self.chapter, n)))
+                .filter(|text| !text.is_empty())
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(" ");
+            if text.is_empty() {
+                self.missing.push(Diagnostic::warning(
+                    Code::MissingVerse,
+                    verse.span.unwrap_or_default(),
+                    format!("no back translation for {}:{number}", self.chapter),
+                ));
+            } else {
+                res.push(format!("[{number}] {text}"));
+            }
+        }
+        res.join(" ")
+    }
+}
+
+impl VisitMut for Interleave {
+    fn visit_node_mut(&mut self, node: &mut Node) -> ControlFlow<()> {
+        match node.style.as_ref() {
+            "c" => {
+                self.chapter = node
+                    .attributes
+                    .get("number")
+                    .and_then(|n| n.parse().ok())
+                    .unwrap_or(self.chapter);
+            }
+            "usfm" => {}
+            _ => return ControlFlow::Continue(()),
+        }
+        let mut content = Vec::with_capacity(node.content.len());
+        for item in std::mem::take(&mut node.content) {
+            if let Content::Chapter(mut chapter) = item {
+                self.visit_node_mut(&mut chapter)?;
+                content.push(Content::Chapter(chapter));
+                continue;
+            }
+            let text = match &item {
+                Content::Book(_) => String::new(),
+                item => item
+                    .node()
+                    .map_or_else(String::new, |block| self.back_translation(block)),
+            };
+            content.push(item);
+            if !text.is_empty() {
+                content.push(Content::Para(Node {
+                    style: self.marker.clone().into(),
+                    content: vec![text.into()],
+                    ..Node::default()
+                }));
+            }
+        }
+        node.content = content;
+        ControlFlow::Continue(())
+    }
+}
+
+impl Transform for Interleave {
+    fn start(&mut self, _doc: &Document) {
+        self.chapter = 0;
+    }
+
+    fn diagnostics(&mut self) -> Vec<Diagnostic> {
+        std::mem::take(&mut self.missing)
+    }
+}
+
+/// The verses of a translation next to those of its back translation, in
+/// the order of the translation followed by any found only in the back
+/// translation. Unlike [`align_verses`](crate::corpus::align_verses), a
+/// verse missing from either side is kept with empty text, so that the gap
+/// shows.
+pub fn interleave_verses(vernacular: &Document, back: &Document) -> Vec<AlignedVerse> {
+    let mut back = back.verse_texts();
+    let index = back
+        .iter()
+        .enumerate()
+        .map(|(n, (reference, _))| (*reference, n))
+        .collect::<HashMap<Reference, usize>>();
+    let mut taken = vec![false; back.len()];
+    let mut res = Vec::new();
+    for (reference, source) in vernacular.verse_texts() {
+        let target = match index.get(&reference) {
+            Some(&n) => {
+                taken[n] = true;
+                std::mem::take(&mut back[n].1)
+            }
+            None => String::new(),
+        };
+        res.push(AlignedVerse {
+            reference,
+            source,
+            target,
+        });
+    }
+    res.extend(back.into_iter().zip(taken).filter(|(_, taken)| !taken).map(
+        |((reference, target), _)| AlignedVerse {
+            reference,
+            source: String::new(),
+            target,
+        },
+    ));
+    res.retain(|verse| !verse.source.is_empty() || !verse.target.is_empty());
+    res
+}
+
+/// A `<table class="usfm-interleave">` with a row for each verse of
+/// [`interleave_verses`]: the reference, the translation and the back
+/// translation.
+pub fn interleave_html(vernacular: &Document, back: &Document) -> String {
+    let mut out = String::from("<table class=\"usfm-interleave\">\n");
+    for verse in interleave_verses(vernacular, back) {
+        let _ = writeln!(
+            out,
+            "<tr><th>{}</th><td>{}</td><td>{}</td></tr>",
+            verse.reference,
+            escape(&verse.source),
+            escape(&verse.target)
+        );
+    }
+    out.push_str("</table>\n");
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::{interleave_html, Interleave};
+    use crate::{
+        diagnostic::Code,
+        document::Document,
+        transform::Pipeline,
+        writer::{Options, Whitespace},
+    };
+
+    const VERNACULAR: &str = "\\id MRK\n\\c 1\n\\s1 Yohanes\n\\p\n\\v 1 Satu. \\v 2 Dua,\n\
+                              \\q1 tiga \\v 3 <Empat>\n\\c 2\n\\p \\v 1 Lima\n";
+    const BACK: &str = "\\id MRK\n\\c 1\n\\p \\v 1 One. \\v 2 Two, three\n\\c 2\n\
+                        \\p \\v 1 Five \\v 2 Six\n";
+
+    #[test]
+    fn document() {
+        let back: Document = BACK.parse().expect("Document");
+        let mut doc = VERNACULAR.parse::<Document>().expect("Document");
+        let missing = Pipeline::new()
+            .pass(Interleave::new(&back).marker("zbt"))
+            .run(&mut doc);
+        assert_eq!(
+            missing
+                .iter()
+                .map(|d| (d.code, d.message.as_str(), &VERNACULAR[d.span.range()]))
+                .collect::<Vec<_>>(),
+            [(Code::MissingVerse, "no back translation for 1:3", "\\v 3 ")]
+        );
+        let preserve = Options {
+            whitespace: Whitespace::Preserve,
+            ..Options::default()
+        };
+        assert_eq!(
+            doc.to_usfm(preserve).to_string(),
+            "\\id MRK\n\\c 1\n\\s1 Yohanes\n\\p\n\\v 1 Satu. \\v 2 Dua,\n\
+             \\zbt [1] One. [2] Two, three\n\\q1 tiga \\v 3 <Empat>\n\
+             \\c 2\n\\p\n\\v 1 Lima\n\\zbt [1] Five\n"
+        );
+    }
+
+    #[test]
+    fn long_ranges() {
+        let back: Document = BACK.parse().expect("Document");
+        let mut doc = "\\id MRK\n\\c 1\n\\p \\v 2-4000000000 Dua \\v 3-1 tiga\n"
+            .parse::<Document>()
+            .expect("Document");
+        let missing = Pipeline::new().pass(Interleave::new(&back)).run(&mut doc);
+        assert_eq!(
+            missing
+                .iter()
+                .map(|d| d.message.as_str())
+                .collect::<Vec<_>>(),
+            ["no back translation for 1:3-1"]
+        );
+        assert!(doc
+            .to_string()
+            .ends_with("\\lit [2-4000000000] Two, three\n"));
+    }
+
+    #[test]
+    fn html() {
+        let vernacular: Document = VERNACULAR.parse().expect("Document");
+        let back: Document = BACK.parse().expect("Document");
+        assert_eq!(
+            interleave_html(&vernacular, &back),
+            "<table class=\"usfm-interleave\">\n\
+             <tr><th>MRK 1:1</th><td>Satu.</td><td>One.</td></tr>\n\
+             <tr><th>MRK 1:2</th><td>Dua, tiga</td><td>Two, three</td></tr>\n\
+             <tr><th>MRK 1:3</th><td>&lt;Empat&gt;</td><td></td></tr>\n\
+             <tr><th>MRK 2:1</th><td>Lima</td><td>Five</td></tr>\n\
+             <tr><th>MRK 2:2</th><td></td><td>Six</td></tr>\n\
+             </table>\n"
+        );
+    }
+}
@@ -0,0 +1,340 @@
+//! Interlinear layers over the `\w` words of a text: a gloss, morphology,
+//! transliteration or any other value given word by word.
+//!
+//! A layer is kept in the text as an `x-` attribute of each word, as in
+//! `\w beginning|x-gloss="start" x-morph="N-DSF"\w*`, or in a layer file
+//! beside it. Words are keyed by verse, surface text and occurrence, the
+//! count of that surface in the verse so far, as in the `x-occurrence`
+//! attribute of aligned texts. A layer file has a reference, word,
+//! occurrence and value on each line, separated by tabs.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    io,
+};
+
+use crate::{
+    document::{Content, Document, Node},
+    reference::Reference,
+    usx::invalid,
+    versification::verse_range,
+};
+
+/// The values of one layer for the words they belong to.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Layer {
+    pub name: String,
+    values: HashMap<(Reference, String, u32), String>,
+}
+
+impl Layer {
+    pub fn new(name: impl Into<String>) -> Self {
+        Layer {
+            name: name.into(),
+            values: HashMap::new(),
+        }
+    }
+
+    /// Read a layer file. Blank lines and lines starting with `#` are
+    /// skipped.
+    pub fn from_tsv(name: impl Into<String>, text: &str) -> io::Result<Self> {
+        let mut layer = Layer::new(name);
+        for (n, line) in text.lines().enumerate() {
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |e| invalid(format!("line {}: {e}", n + 1));
+            let fields = line.splitn(4, '\t').collect::<Vec<_>>();
+            let [reference, word, occurrence, value] = fields[..] else {
+                return Err(error(
+                    "expected reference, word, occurrence and value".into(),
+                ));
+            };
+            let reference = reference
+                .trim()
+                .parse()
+                .map_err(|e: io::Error| error(e.to_string()))?;
+            let occurrence = occurrence
+                .trim()
+                .parse()
+                .map_err(|_| error(format!("bad occurrence {occurrence:?}")))?;
+            layer.insert(reference, word.trim(), occurrence, value.trim());
+        }
+        Ok(layer)
+    }
+
+    pub fn insert(
+        &mut self,
+        reference: Reference,
+        word: &str,
+        occurrence: u32,
+        value: impl Into<String>,
+    ) -> Option<String> {
+        self.values
+            .insert((reference, word.to_owned(), occurrence), value.into())
+    }
+
+    pub fn get(&self, reference: Reference, word: &str, occurrence: u32) -> Option<&str> {
+        self.values
+            .get(&(reference, word.to_owned(), occurrence))
+            .map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+/// A `\w` word with the value of each layer it has.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub surface: String,
+    /// Which occurrence of the surface in its verse this is, from 1.
+    pub occurrence: u32,
+    /// Layer values by name, from the word's `x-` attributes with the
+    /// prefix dropped, its `lemma` and `strong` attributes, and the layers
+    /// given to [`Document::interlinear`].
+    pub layers: BTreeMap<String, String>,
+}
+
+/// The words of a verse, in order, for showing line under line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InterlinearVerse {
+    pub reference: Reference,
+    pub tokens: Vec<Token>,
+}
+
+impl InterlinearVerse {
+    /// One layer across the verse, with `None` for words it lacks.
+    pub fn line<'v>(&'v self, layer: &str) -> Vec<Option<&'v str>> {
+        self.tokens
+            .iter()
+            .map(|token| token.layers.get(layer).map(String::as_str))
+            .collect()
+    }
+}
+
+impl Document<'_> {
+    /// The `\w` words of each verse outside notes, with their layers. A
+    /// value in one of `layers` takes the place of one in the text.
+    pub fn interlinear(&self, layers: &[Layer]) -> Vec<InterlinearVerse> {
+        let (Some(book), Some(root)) = (self.book(), &self.nodes) else {
+            return Vec::new();
+        };
+        let mut words = Words::new(Reference::new(book, 0, None));
+        let mut verses = Vec::<InterlinearVerse>::new();
+        words.content(&root.content, &mut |reference, occurrence, node| {
+            let surface = node.text();
+            let mut token = Token {
+                layers: inline_layers(node),
+                occurrence,
+                surface,
+            };
+            for layer in layers {
+                if let Some(value) = layer.get(reference, &token.surface, occurrence) {
+                    token.layers.insert(layer.name.clone(), value.to_owned());
+                }
+            }
+            match verses.last_mut() {
+                Some(verse) if verse.reference == reference => verse.tokens.push(token),
+                _ => verses.push(InterlinearVerse {
+                    reference,
+                    tokens: vec![token],
+                }),
+            }
+        });
+        verses
+    }
+
+    /// Store a layer in the text as an `x-` attribute of each word it has a
+    /// value for, returning how many words it was given to.
+    pub fn attach_layer(&mut self, layer: &Layer) -> usize {
+        let (Some(book), Some(root)) = (self.book(), self.nodes.as_mut()) else {
+            return 0;
+        };
+        let name = format!("x-{}", layer.name);
+        let mut words = Words::new(Reference::new(book, 0, None));
+        let mut count = 0;
+        words.content_mut(&mut root.content, &mut |reference, occurrence, node| {
+            let value = layer.get(reference, &node.text(), occurrence)?;
+            node.set_attribute(name.clone(), value.to_owned());
+            count += 1;
+            Some(())
+        });
+        if count > 0 {
+            root.span = None;
+        }
+        count
+    }
+}
+
+fn inline_layers(node: &Node) -> BTreeMap<String, String> {
+    node.attributes
+        .iter()
+        .filter_map(|(name, value)| {
+            let name = match name.strip_prefix("x-") {
+                Some("occurrence" | "occurrences") => return None,
+                Some(name) => name,
+                None if matches!(name.as_ref(), "lemma" | "strong") => name,
+                None => return None,
+            };
+            Some((name.to_owned(), value.trim().to_owned()))
+        })
+        .collect()
+}
+
+// Walks the words of a document, keeping track of the verse and how many
+// times each surface has come up in it.
+struct Words {
+    reference: Reference,
+    counts: HashMap<String, u32>,
+}
+
+impl Words {
+    fn new(reference: Reference) -> Self {
+        Words {
+            reference,
+            counts: HashMap::new(),
+        }
+    }
+
+    fn chapter(&mut self, node: &Node) {
+        self.reference.chapter = node
+            .attributes
+            .get("number")
+            .and_then(|n| n.parse().ok())
+            .unwrap_or(self.reference.chapter);
+        self.reference.verse = None;
+        self.counts.clear();
+    }
+
+    fn verse(&mut self, node: &Node) {
+        if let Some((first, _)) = node.attributes.get("number").and_then(|n| verse_range(n)) {
+            self.reference.verse = Some(first);
+            self.counts.clear();
+        }
+    }
+
+    fn occurrence(&mut self, node: &Node) -> u32 {
+        let count = self.counts.entry(node.text()).or_default();
+        *count += 1;
+        *count
+    }
+
+    fn content<'d>(
+        &mut self,
+        content: &'d [Content],
+        f: &mut impl FnMut(Reference, u32, &'d Node),
+    ) {
+        for item in content {
+            match item {
+                Content::Chapter(node) => {
+                    self.chapter(node);
+                    self.content(&node.content, f);
+                }
+                Content::Verse(node) => self.verse(node),
+                Content::Char(node) if node.style == "w" => {
+                    let occurrence = self.occurrence(node);
+                    f(self.reference, occurrence, node);
+                }
+                Content::Note(_) => {}
+                item => {
+                    if let Some(node) = item.node() {
+                        self.content(&node.content, f);
+                    }
+                }
+            }
+        }
+    }
+
+    // Returns whether `f` changed any word, having dropped the spans of the
+    // nodes above those it did.
+    fn content_mut(
+        &mut self,
+        content: &mut [Content],
+        f: &mut impl FnMut(Reference, u32, &mut Node) -> Option<()>,
+    ) -> bool {
+        let mut changed = false;
+        for item in content {
+            match item {
+                Content::Chapter(node) => {
+                    self.chapter(node);
+                    if self.content_mut(&mut node.content, f) {
+                        node.span = None;
+                        changed = true;
+                    }
+                }
+                Content::Verse(node) => self.verse(node),
+                Content::Char(node) if node.style == "w" => {
+                    let occurrence = self.occurrence(node);
+                    changed |= f(self.reference, occurrence, node).is_some();
+                }
+                Content::Note(_) => {}
+                item => {
+                    if let Some(node) = item.node_mut() {
+                        if self.content_mut(&mut node.content, f) {
+                            node.span = None;
+                            changed = true;
+                        }
+                    }
+                }
+            }
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Layer;
+    use crate::{document::Document, reference::Reference};
+
+    #[test]
+    fn interlinear() {
+        let mut doc = "\\id GEN\n\\c 1\n\\p \\v 1 \\w In|x-morph=\"P\"\\w* \\w the\\w* \
+             \\w beginning|strong=\"H7225\" x-gloss=\"head\"\\w*, \\w the\\w*\n\
+             \\v 2 \\nd \\+w Lord\\+w*\\nd*\\f + \\w note\\w*\\f*\n"
+            .parse::<Document>()
+            .expect("Document");
+        let gloss = Layer::from_tsv(
+            "gloss",
+            "# reference\tword\toccurrence\tgloss\n\
+             GEN 1:1\tbeginning\t1\tstart\nGEN 1:1\tthe\t2\tthe (2)\nGEN 1:2\tLord\t1\tYHWH\n",
+        )
+        .expect("Layer");
+        assert_eq!(gloss.len(), 3);
+
+        let verses = doc.interlinear(std::slice::from_ref(&gloss));
+        assert_eq!(verses.len(), 2);
+        assert_eq!(verses[0].reference, "GEN 1:1".parse::<Reference>().unwrap());
+        assert_eq!(
+            verses[0]
+                .tokens
+                .iter()
+                .map(|t| (t.surface.as_str(), t.occurrence))
+                .collect::<Vec<_>>(),
+            [("In", 1), ("the", 1), ("beginning", 1), ("the", 2)]
+        );
+        assert_eq!(
+            verses[0].line("gloss"),
+            [None, None, Some("start"), Some("the (2)")]
+        );
+        assert_eq!(verses[0].line("morph"), [Some("P"), None, None, None]);
+        assert_eq!(verses[0].tokens[2].layers["strong"], "H7225");
+        assert_eq!(verses[1].line("gloss"), [Some("YHWH")]);
+
+        assert_eq!(doc.attach_layer(&gloss), 3);
+        let usfm = doc.to_string();
+        assert!(usfm.contains("\\w the|x-gloss=\"the (2)\"\\w*\n"));
+        assert!(usfm.contains("\\+w Lord|x-gloss=\"YHWH\"\\+w*"));
+        let doc: Document = usfm.parse().expect("round trip");
+        assert_eq!(doc.interlinear(&[]), verses);
+
+        let err = Layer::from_tsv("gloss", "GEN 1:1\tthe\tfirst\tthe\n").expect_err("occurrence");
+        assert_eq!(err.to_string(), "line 1: bad occurrence \"first\"");
+    }
+}
@@ -0,0 +1,171 @@
+//! Walking a document tree.
+
+use std::slice;
+
+use crate::{
+    document::{Content, Document, Node, State},
+    extension::Category,
+};
+
+/// Depth-first, pre-order traversal of everything below a node.
+#[derive(Debug, Clone)]
+pub struct Iter<'d> {
+    stack: Vec<slice::Iter<'d, Content<'d>>>,
+}
+
+impl<'d> Iter<'d> {
+    fn new(content: &'d [Content<'d>]) -> Self {
+        Iter {
+            stack: vec![content.iter()],
+        }
+    }
+}
+
+impl<'d> Iterator for Iter<'d> {
+    type Item = &'d Content<'d>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let item = match self.stack.last_mut()?.next() {
+                Some(item) => item,
+                None => {
+                    self.stack.pop();
+                    continue;
+                }
+            };
+            if let Some(node) = item.node() {
+                self.stack.push(node.content.iter());
+            }
+            return Some(item);
+        }
+    }
+}
+
+impl Node<'_> {
+    pub fn iter(&self) -> Iter<'_> {
+        Iter::new(&self.content)
+    }
+
+    pub fn iter_paras(&self) -> impl Iterator<Item = &Node<'_>> {
+        self.iter().filter_map(|item| match item {
+            Content::Para(node) => Some(node),
+            _ => None,
+        })
+    }
+
+    pub fn iter_verses(&self) -> impl Iterator<Item = &Node<'_>> {
+        self.iter().filter_map(|item| match item {
+            Content::Verse(node) => Some(node),
+            _ => None,
+        })
+    }
+
+    /// Every node below this one with the given marker.
+    pub fn find_all<'n>(&'n self, style: &'n str) -> impl Iterator<Item = &'n Node<'n>> {
+        self.iter()
+            .filter_map(Content::node)
+            .filter(move |node| node.style == style)
+    }
+
+    /// The direct children whose marker has the given category in the
+    /// bundled USFM marker set.
+    pub fn children_of_category(&self, category: Category) -> impl Iterator<Item = &Node<'_>> {
+        let markers = State::usfm_ext();
+        self.content
+            .iter()
+            .filter_map(Content::node)
+            .filter(move |node| markers.category(node.style.as_ref()) == Some(category))
+    }
+}
+
+impl Document<'_> {
+    /// The `usfm` node holding the whole document.
+    pub fn root(&self) -> Option<&Node<'_>> {
+        self.nodes.as_ref()
+    }
+
+    pub fn iter(&self) -> Iter<'_> {
+        Iter::new(self.root().map_or(&[], |root| &root.content))
+    }
+
+    pub fn iter_paras(&self) -> impl Iterator<Item = &Node<'_>> {
+        self.root().into_iter().flat_map(Node::iter_paras)
+    }
+
+    pub fn iter_verses(&self) -> impl Iterator<Item = &Node<'_>> {
+        self.root().into_iter().flat_map(Node::iter_verses)
+    }
+
+    pub fn find_all<'d>(&'d self, style: &'d str) -> impl Iterator<Item = &'d Node<'d>> {
+        self.root()
+            .into_iter()
+            .flat_map(move |root| root.find_all(style))
+    }
+
+    pub fn children_of_category(&self, category: Category) -> impl Iterator<Item = &Node<'_>> {
+        self.root()
+            .into_iter()
+            .flat_map(move |root| root.children_of_category(category))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        document::{Content, Document},
+        extension::Category,
+    };
+
+    const USFM: &str = "\\id MRK\n\
+                        \\c 1\n\
+                        \\s1 Heading\n\
+                        \\p \\v 1 In \\nd Lord\\nd*\\f + \\ft note\\f*\n\
+                        \\q1 \\v 2 and\n\
+                        \\c 2\n\
+                        \\p \\v 1 end\n";
+
+    #[test]
+    fn depth_first() {
+        let doc: Document = USFM.parse().expect("Document");
+        let styles = doc
+            .iter()
+            .map(|item| match item {
+                Content::Text(text) => text.as_str().trim(),
+                item => item.node().map_or("", |node| node.style.as_ref()),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            styles,
+            [
+                "id", "c", "s1", "Heading", "p", "v", "In", "nd", "Lord", "f", "ft", "note",
+                "stanza", "q1", "v", "and", "c", "p", "v", "end"
+            ]
+        );
+        assert_eq!(Document::default().iter().count(), 0);
+    }
+
+    #[test]
+    fn filters() {
+        let doc: Document = USFM.parse().expect("Document");
+        let paras = doc
+            .iter_paras()
+            .map(|node| node.style.as_ref())
+            .collect::<Vec<_>>();
+        assert_eq!(paras, ["s1", "p", "q1", "p"]);
+        let verses = doc
+            .iter_verses()
+            .map(|node| node.attributes["number"].as_ref())
+            .collect::<Vec<_>>();
+        assert_eq!(verses, ["1", "2", "1"]);
+        assert_eq!(doc.find_all("nd").count(), 1);
+        assert_eq!(doc.find_all("xt").count(), 0);
+
+        let chapter = doc.root().unwrap().find_all("c").next().unwrap();
+        let sections = chapter
+            .children_of_category(Category::SectionPara)
+            .map(|node| node.text())
+            .collect::<Vec<_>>();
+        assert_eq!(sections, ["Heading"]);
+        assert_eq!(chapter.children_of_category(Category::VersePara).count(), 1);
+    }
+}
@@ -1,7 +1,66 @@
 use nom::{error::VerboseError, IResult};
 
+pub mod alignment;
+#[cfg(feature = "arbitrary")]
+pub mod arbitrary;
+pub mod audio;
+pub mod books;
+pub mod builder;
+#[cfg(feature = "burrito")]
+pub mod burrito;
+pub mod checks;
+pub mod chunk;
+pub mod classify;
+#[cfg(feature = "conformance")]
+pub mod conformance;
+pub mod corpus;
+#[cfg(feature = "dbl")]
+pub mod dbl;
+pub mod diagnostic;
 pub mod document;
+pub mod edit;
+#[cfg(feature = "encoding_rs")]
+pub mod encoding;
+pub mod events;
 pub mod extension;
+pub mod glossary;
+pub mod html;
+pub mod indesign;
+pub mod index;
+pub mod interleave;
+pub mod interlinear;
+pub mod iter;
+#[cfg(feature = "lsp")]
+pub mod lsp;
+pub mod markdown;
+pub mod milestones;
+pub mod normalize;
+pub mod osis;
+pub mod plain;
+pub mod project;
+pub mod publish;
+pub mod reference;
+#[cfg(feature = "report")]
+pub mod report;
+pub mod search;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod stats;
 pub(crate) mod terminal;
+pub mod toc;
+pub mod tokens;
+pub mod transform;
+#[cfg(feature = "usj")]
+pub mod usj;
+pub mod usx;
+pub mod validate;
+pub mod verses;
+pub mod versification;
+pub mod visit;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod words;
+pub mod writer;
+pub(crate) mod xml;
 
 type Result<'i, O> = IResult<&'i str, O, VerboseError<&'i str>>;
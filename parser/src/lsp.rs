@@ -0,0 +1,449 @@
+//! The USFM side of a language server: diagnostics, document symbols,
+//! hover and completion. The types serialize to the JSON of the Language
+//! Server Protocol, leaving the transport to whichever server framework is
+//! used. Positions follow the protocol in counting UTF-16 code units from
+//! zero.
+
+use std::ops;
+
+use serde::Serialize;
+
+use crate::{
+    diagnostic::{self, Severity},
+    document::{Content, Document, Node},
+    extension::{Category, Extensions},
+    tokens::{tokens, TokenKind},
+};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct Position {
+    pub line: u32,
+    pub character: u32,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Range {
+    pub start: Position,
+    pub end: Position,
+}
+
+/// Converts between byte offsets and protocol positions.
+#[derive(Debug, Clone)]
+pub struct LineIndex<'s> {
+    source: &'s str,
+    lines: Vec<usize>,
+}
+
+impl<'s> LineIndex<'s> {
+    pub fn new(source: &'s str) -> Self {
+        let breaks = source.match_indices('\n').map(|(n, _)| n + 1);
+        LineIndex {
+            source,
+            lines: [0].into_iter().chain(breaks).collect(),
+        }
+    }
+
+    pub fn position(&self, offset: usize) -> Position {
+        let line = self.lines.partition_point(|&start| start <= offset) - 1;
+        let start = self.lines[line];
+        let character = self.source[start..offset].encode_utf16().count();
+        Position {
+            line: line as u32,
+            character: character as u32,
+        }
+    }
+
+    pub fn range(&self, range: ops::Range<usize>) -> Range {
+        Range {
+            start: self.position(range.start),
+            end: self.position(range.end),
+        }
+    }
+
+    /// The byte offset of a position, clamped to the end of its line.
+    pub fn offset(&self, position: Position) -> usize {
+        let Some(&start) = self.lines.get(position.line as usize) else {
+            return self.source.len();
+        };
+        let line = &self.source[start..];
+        let line = &line[..line.find('\n').unwrap_or(line.len())];
+        let mut units = 0;
+        for (n, c) in line.char_indices() {
+            if units >= position.character as usize {
+                return start + n;
+            }
+            units += c.len_utf16();
+        }
+        start + line.len()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(into = "u8")]
+pub enum DiagnosticSeverity {
+    Error = 1,
+    Warning = 2,
+    Information = 3,
+}
+
+impl From<DiagnosticSeverity> for u8 {
+    fn from(value: DiagnosticSeverity) -> Self {
+        value as u8
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Diagnostic {
+    pub range: Range,
+    pub severity: DiagnosticSeverity,
+    pub code: &'static str,
+    pub source: &'static str,
+    pub message: String,
+}
+
+/// The parameters of a `textDocument/publishDiagnostics` notification.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PublishDiagnosticsParams {
+    pub uri: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<i32>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+pub fn publish_diagnostics(
+    uri: impl Into<String>,
+    version: Option<i32>,
+    source: &str,
+    diagnostics: &[diagnostic::Diagnostic],
+) -> PublishDiagnosticsParams {
+    let index = LineIndex::new(source);
+    PublishDiagnosticsParams {
+        uri: uri.into(),
+        version,
+        diagnostics: diagnostics
+            .iter()
+            .map(|d| Diagnostic {
+                range: index.range(d.span.range()),
+                severity: match d.severity {
+                    Severity::Error => DiagnosticSeverity::Error,
+                    Severity::Warning => DiagnosticSeverity::Warning,
+                    Severity::Info => DiagnosticSeverity::Information,
+                },
+                code: d.code.as_str(),
+                source: "usfm",
+                message: d.message.clone(),
+            })
+            .collect(),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(into = "u8")]
+pub enum SymbolKind {
+    Namespace = 3,
+    Class = 5,
+    Number = 16,
+}
+
+impl From<SymbolKind> for u8 {
+    fn from(value: SymbolKind) -> Self {
+        value as u8
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentSymbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    pub range: Range,
+    pub selection_range: Range,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<DocumentSymbol>,
+}
+
+/// Chapters holding their section headings and verses, with verses before
+/// the first heading of a chapter directly under it. The document must
+/// have been parsed from USFM, so that it has source spans.
+pub fn document_symbols(doc: &Document, markers: &Extensions) -> Vec<DocumentSymbol> {
+    let index = LineIndex::new(doc.source());
+    doc.root()
+        .into_iter()
+        .flat_map(|root| &root.content)
+        .filter_map(|item| match item {
+            Content::Chapter(chapter) => chapter_symbol(chapter, markers, &index),
+            _ => None,
+        })
+        .collect()
+}
+
+fn chapter_symbol(
+    chapter: &Node,
+    markers: &Extensions,
+    index: &LineIndex,
+) -> Option<DocumentSymbol> {
+    let span = chapter.span?;
+    let number = chapter.attributes.get("number").map_or("", |n| n.as_ref());
+    let text = &index.source[span.range()];
+    let line = text.find(['\r', '\n']).unwrap_or(text.len());
+    let mut symbol = DocumentSymbol {
+        name: format!("Chapter {number}"),
+        kind: SymbolKind::Namespace,
+        range: index.range(span.range()),
+        selection_range: index.range(span.start.offset..span.start.offset + line),
+        children: Vec::new(),
+    };
+
+    // Headings and verses in order, each running to the start of the next
+    // of its kind or of a heading, or to the end of the chapter.
+    let items = chapter
+        .iter()
+        .filter_map(|item| match item {
+            Content::Para(node)
+                if markers.category(node.style.as_ref()) == Some(Category::SectionPara) =>
+            {
+                Some((true, node))
+            }
+            Content::Verse(node) => Some((false, node)),
+            _ => None,
+        })
+        .filter_map(|(heading, node)| Some((heading, node, node.span?)))
+        .collect::<Vec<_>>();
+    for (n, &(heading, node, span)) in items.iter().enumerate() {
+        let end = items[n + 1..]
+            .iter()
+            .find(|(next, ..)| *next || !heading)
+            .map_or(chapter.span?.end.offset, |(_, _, next)| next.start.offset);
+        let name = match heading {
+            true => node.text().trim().to_owned(),
+            false => node
+                .attributes
+                .get("number")
+                .map_or("", |n| n.as_ref())
+                .to_owned(),
+        };
+        let child = DocumentSymbol {
+            name,
+            kind: if heading {
+                SymbolKind::Class
+            } else {
+                SymbolKind::Number
+            },
+            range: index.range(span.start.offset..end),
+            selection_range: index.range(span.range()),
+            children: Vec::new(),
+        };
+        match symbol.children.last_mut() {
+            Some(section) if !heading && section.kind == SymbolKind::Class => {
+                section.children.push(child)
+            }
+            _ => symbol.children.push(child),
+        }
+    }
+    Some(symbol)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MarkupContent {
+    pub kind: &'static str,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Hover {
+    pub contents: MarkupContent,
+    pub range: Range,
+}
+
+// The marker name a token refers to, without its backslash, `+` or `*`.
+fn marker_name(token: &str) -> &str {
+    token
+        .trim_start_matches('\\')
+        .trim_start_matches('+')
+        .trim_end_matches('*')
+}
+
+fn documentation(marker: &crate::extension::Marker) -> String {
+    let mut res = format!("**\\\\{}** ({})", marker.name, marker.category);
+    if let Some(description) = &marker.description {
+        res.push_str("\n\n");
+        res.push_str(description);
+    }
+    res
+}
+
+/// Describe the marker under the cursor.
+pub fn hover(source: &str, position: Position, markers: &Extensions) -> Option<Hover> {
+    let index = LineIndex::new(source);
+    let offset = index.offset(position);
+    let token = tokens(source).find(|token| {
+        matches!(token.kind, TokenKind::MarkerTag | TokenKind::MarkerEnd)
+            && token.range.contains(&offset)
+    })?;
+    let marker = markers.get(marker_name(&source[token.range.clone()]))?;
+    Some(Hover {
+        contents: MarkupContent {
+            kind: "markdown",
+            value: documentation(marker),
+        },
+        range: index.range(token.range),
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(into = "u8")]
+pub enum CompletionItemKind {
+    Keyword = 14,
+}
+
+impl From<CompletionItemKind> for u8 {
+    fn from(value: CompletionItemKind) -> Self {
+        value as u8
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CompletionItem {
+    pub label: String,
+    pub kind: CompletionItemKind,
+    pub detail: String,
+    pub documentation: MarkupContent,
+}
+
+/// The markers whose names start with the partial one before the cursor,
+/// or nothing when the cursor does not follow a backslash.
+pub fn completion(source: &str, position: Position, markers: &Extensions) -> Vec<CompletionItem> {
+    let offset = LineIndex::new(source).offset(position);
+    let before = &source[..offset];
+    let head = before.trim_end_matches(|c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !head.ends_with('\\') && !head.ends_with("\\+") {
+        return Vec::new();
+    }
+    let prefix = &before[head.len()..];
+    let mut res = markers
+        .values()
+        .filter(|marker| marker.name.starts_with(prefix))
+        .map(|marker| CompletionItem {
+            label: marker.name.clone(),
+            kind: CompletionItemKind::Keyword,
+            detail: marker.category.to_string(),
+            documentation: MarkupContent {
+                kind: "markdown",
+                value: documentation(marker),
+            },
+        })
+        .collect::<Vec<_>>();
+    res.sort_by(|a, b| a.label.cmp(&b.label));
+    res
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::{completion, document_symbols, hover, publish_diagnostics, LineIndex, Position};
+    use crate::{document::Document, extension::Extensions};
+
+    fn markers() -> &'static Extensions {
+        Extensions::usfm(Default::default())
+    }
+
+    #[test]
+    fn positions() {
+        let index = LineIndex::new("\\id MRK\n\\p 𝔄b\n");
+        let position = index.position("\\id MRK\n\\p 𝔄".len());
+        assert_eq!(
+            position,
+            Position {
+                line: 1,
+                character: 5
+            }
+        );
+        assert_eq!(index.offset(position), "\\id MRK\n\\p 𝔄".len());
+        assert_eq!(
+            index.offset(Position {
+                line: 0,
+                character: 99
+            }),
+            7
+        );
+    }
+
+    #[test]
+    fn diagnostics() {
+        let source = "\\id MRK\n\\c 1\n\\p \\v 1 text \\bogus\n";
+        let (_, diagnostics) = Document::from_str_lenient(source);
+        let params = publish_diagnostics("file:///MRK.SFM", Some(3), source, &diagnostics);
+        assert_eq!(
+            serde_json::to_value(params).unwrap(),
+            json!({
+                "uri": "file:///MRK.SFM",
+                "version": 3,
+                "diagnostics": [{
+                    "range": {
+                        "start": {"line": 2, "character": 13},
+                        "end": {"line": 3, "character": 0}
+                    },
+                    "severity": 1,
+                    "code": "unknown-marker",
+                    "source": "usfm",
+                    "message": diagnostics[0].message,
+                }]
+            })
+        );
+    }
+
+    #[test]
+    fn symbols() {
+        let source = "\\id MRK\n\\c 1\n\\p \\v 1 one\n\\s1 Heading\n\\p \\v 2 two \\v 3 three\n\\c 2\n\\p \\v 1 four\n";
+        let doc = source.parse::<Document>().unwrap();
+        let symbols = document_symbols(&doc, markers());
+        let outline = |symbols: &[super::DocumentSymbol]| {
+            symbols
+                .iter()
+                .map(|s| (s.name.clone(), s.children.len()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            outline(&symbols),
+            [("Chapter 1".into(), 2), ("Chapter 2".into(), 1)]
+        );
+        assert_eq!(
+            outline(&symbols[0].children),
+            [("1".into(), 0), ("Heading".into(), 2)]
+        );
+        let verse = &symbols[0].children[1].children[0];
+        assert_eq!(verse.name, "2");
+        assert_eq!(
+            &source[LineIndex::new(source).offset(verse.range.start)
+                ..LineIndex::new(source).offset(verse.range.end)],
+            "\\v 2 two "
+        );
+        assert_eq!(
+            serde_json::to_value(&symbols[1]).unwrap()["selectionRange"],
+            json!({"start": {"line": 5, "character": 0}, "end": {"line": 5, "character": 4}})
+        );
+    }
+
+    #[test]
+    fn hover_and_completion() {
+        let source = "\\id MRK\n\\p \\v 1 the \\nd Lord\\nd* \\+w";
+        let at = |line, character| Position { line, character };
+        let hovered = hover(source, at(1, 13), markers()).expect("hover");
+        assert!(hovered.contents.value.starts_with("**\\\\nd** (char)"));
+        assert_eq!(hovered.range.start, at(1, 12));
+        assert_eq!(hover(source, at(1, 10), markers()), None);
+
+        let labels = |line, character| {
+            completion(source, at(line, character), markers())
+                .into_iter()
+                .map(|item| item.label)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(labels(1, 15), ["nd", "ndx"]);
+        assert!(labels(1, 28).contains(&"w".to_owned()));
+        assert!(labels(1, 1).len() > 100);
+        assert_eq!(labels(1, 9), Vec::<String>::new());
+    }
+}
@@ -0,0 +1,277 @@
+//! Exporting documents as Markdown, for publishing drafts on wikis and
+//! static sites. Footnotes and tables use the common GitHub extensions.
+
+use crate::{
+    document::{Content, Document, Node, State},
+    extension::{Category, Extensions},
+};
+
+impl Document<'_> {
+    /// Titles and headings become `#` headings, with chapter numbers at
+    /// level two and sections below them. Poetry is quoted, one line per
+    /// poetic line, notes become numbered footnotes and figures images.
+    /// Header paragraphs such as `\h` and `\toc1` are left out.
+    pub fn to_markdown(&self) -> String {
+        let mut writer = Writer {
+            markers: State::usfm_ext(),
+            out: String::new(),
+            group: None,
+            notes: Vec::new(),
+        };
+        if let Some(root) = &self.nodes {
+            writer.blocks(&root.content);
+        }
+        for (n, note) in writer.notes.iter().enumerate() {
+            writer.out.push_str(&format!("\n\n[^{}]: {note}", n + 1));
+        }
+        if !writer.out.is_empty() {
+            writer.out.push('\n');
+        }
+        writer.out
+    }
+}
+
+// Consecutive blocks of these kinds are written line by line rather than
+// as separate paragraphs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Group {
+    Poetry,
+    List,
+    Table,
+}
+
+struct Writer {
+    markers: &'static Extensions,
+    out: String,
+    group: Option<Group>,
+    notes: Vec<String>,
+}
+
+fn level(style: &str) -> usize {
+    let digits = style.trim_start_matches(|c: char| !c.is_ascii_digit());
+    digits.parse().unwrap_or(1)
+}
+
+fn escape(text: &str) -> String {
+    let mut res = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '*' | '_' | '[' | ']' | '<' | '>' | '`' | '|') {
+            res.push('\\');
+        }
+        res.push(c);
+    }
+    res
+}
+
+fn collapse(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+impl Writer {
+    fn category(&self, node: &Node) -> Category {
+        self.markers
+            .get(node.style.as_ref())
+            .map(|marker| marker.category)
+            .unwrap_or_default()
+    }
+
+    fn start(&mut self, group: Option<Group>) {
+        if !self.out.is_empty() {
+            self.out.push_str(match (self.group, group) {
+                (Some(Group::Poetry), Some(Group::Poetry)) => "\\\n",
+                (Some(previous), Some(next)) if previous == next => "\n",
+                _ => "\n\n",
+            });
+        }
+        self.group = group;
+    }
+
+    fn write_block(&mut self, group: Option<Group>, prefix: &str, text: &str) {
+        let text = collapse(text);
+        if text.is_empty() {
+            self.group = None;
+            return;
+        }
+        self.start(group);
+        self.out.push_str(prefix);
+        self.out.push_str(&text);
+    }
+
+    fn blocks(&mut self, content: &[Content]) {
+        for item in content {
+            match item {
+                Content::Book(_) | Content::Milestone(_) => {}
+                Content::Para(node) => self.para(node),
+                Content::Chapter(node) => {
+                    let number = node
+                        .attributes
+                        .get("pubnumber")
+                        .or(node.attributes.get("number"))
+                        .map(|n| n.to_string())
+                        .unwrap_or_default();
+                    self.write_block(None, "## ", &escape(&number));
+                    self.blocks(&node.content);
+                }
+                Content::Table(node) => {
+                    self.group = None;
+                    for (n, row) in node.content.iter().enumerate() {
+                        let Some(row) = row.node() else { continue };
+                        let cells = row
+                            .content
+                            .iter()
+                            .map(|cell| {
+                                let mut text = String::new();
+                                self.inlines(std::slice::from_ref(cell), &mut text);
+                                collapse(&text)
+                            })
+                            .collect::<Vec<_>>();
+                        self.write_block(
+                            Some(Group::Table),
+                            "",
+                            &format!("| {} |", cells.join(" | ")),
+                        );
+                        if n == 0 {
+                            self.out.push('\n');
+                            self.out.push_str(&"|---".repeat(cells.len()));
+                            self.out.push('|');
+                        }
+                    }
+                    self.group = None;
+                }
+                Content::Unknown(node) => {
+                    let mut text = String::new();
+                    self.inlines(&node.content, &mut text);
+                    self.write_block(None, "", &text);
+                }
+                item => match item.node() {
+                    Some(node) => self.blocks(&node.content),
+                    None => {
+                        let mut text = String::new();
+                        self.inlines(std::slice::from_ref(item), &mut text);
+                        self.write_block(None, "", &text);
+                    }
+                },
+            }
+        }
+    }
+
+    fn para(&mut self, node: &Node) {
+        let style = node.style.as_ref();
+        let (group, prefix) = match self.category(node) {
+            Category::Header => return,
+            Category::Title => (None, format!("{} ", "#".repeat(level(style).min(6)))),
+            Category::SectionPara => (None, format!("{} ", "#".repeat((level(style) + 2).min(6)))),
+            Category::List => (Some(Group::List), "- ".to_owned()),
+            Category::VersePara if style.starts_with('q') => {
+                let indent = "\u{2003}".repeat(level(style).saturating_sub(1));
+                (Some(Group::Poetry), format!("> {indent}"))
+            }
+            _ => (None, String::new()),
+        };
+        let mut text = String::new();
+        self.inlines(&node.content, &mut text);
+        self.write_block(group, &prefix, &text);
+    }
+
+    fn inlines(&mut self, content: &[Content], out: &mut String) {
+        for item in content {
+            match item {
+                Content::Text(text) => out.push_str(&escape(text.as_str())),
+                Content::NoBreakSpace => out.push('\u{a0}'),
+                Content::Verse(node) => {
+                    if let Some(number) = node
+                        .attributes
+                        .get("pubnumber")
+                        .or(node.attributes.get("number"))
+                    {
+                        out.push_str(&format!(" <sup>{}</sup> ", escape(number)));
+                    }
+                }
+                Content::Note(node) => {
+                    let mut text = String::new();
+                    self.inlines(&node.content, &mut text);
+                    self.notes.push(collapse(&text));
+                    out.push_str(&format!("[^{}]", self.notes.len()));
+                }
+                Content::Char(node) if matches!(node.style.as_ref(), "fr" | "xo") => {}
+                Content::Char(node) => {
+                    let emphasis = match node.style.as_ref() {
+                        "bd" => "**",
+                        "it" | "em" => "*",
+                        "bdit" => "***",
+                        _ => "",
+                    };
+                    let mut text = String::new();
+                    self.inlines(&node.content, &mut text);
+                    // Emphasis may not start or end with whitespace.
+                    let trimmed = text.trim();
+                    if emphasis.is_empty() || trimmed.is_empty() {
+                        out.push_str(&text);
+                    } else {
+                        let start = text.len() - text.trim_start().len();
+                        out.push_str(&text[..start]);
+                        out.push_str(&format!("{emphasis}{trimmed}{emphasis}"));
+                        out.push_str(&text[start + trimmed.len()..]);
+                    }
+                }
+                Content::Figure(node) => {
+                    let caption = collapse(&node.text());
+                    let src = node.attributes.get("src").map_or("", |s| s.as_ref());
+                    out.push_str(&format!(
+                        " ![{}]({}) ",
+                        escape(&caption),
+                        src.replace(' ', "%20")
+                    ));
+                }
+                Content::Milestone(_) | Content::OptBreak => {}
+                item => {
+                    if let Some(node) = item.node() {
+                        self.inlines(&node.content, out);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::document::Document;
+
+    #[test]
+    fn to_markdown() {
+        let doc: Document = "\\id PSA\n\
+                             \\h Psalms\n\
+                             \\mt1 Psalms\n\
+                             \\c 1\n\
+                             \\s1 The *two* ways\n\
+                             \\q1 \\v 1 Blessed is the \\bd man \\bd*\n\
+                             \\q2 who walks not\\f + \\fr 1.1 \\ft Or \\it goes\\it*\\f*\n\
+                             \\b\n\
+                             \\q1 \\v 2 but his delight\n\
+                             \\p \\fig A tree|src=\"tree 1.jpg\" size=\"col\"\\fig*\n\
+                             \\li1 one\n\
+                             \\li1 two\n\
+                             \\tr \\th1 A \\th2 B\n\
+                             \\tr \\tc1 1 \\tc2 2\n"
+            .parse()
+            .expect("Document");
+        assert_eq!(
+            doc.to_markdown(),
+            "# Psalms\n\n\
+             ## 1\n\n\
+             ### The \\*two\\* ways\n\n\
+             > <sup>1</sup> Blessed is the **man**\\\n\
+             > \u{2003}who walks not[^1]\n\n\
+             > <sup>2</sup> but his delight\n\n\
+             ![A tree](tree%201.jpg)\n\n\
+             - one\n\
+             - two\n\n\
+             | A | B |\n\
+             |---|---|\n\
+             | 1 | 2 |\n\n\
+             [^1]: Or *goes*\n"
+        );
+        assert_eq!(Document::default().to_markdown(), "");
+    }
+}
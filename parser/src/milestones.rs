@@ -0,0 +1,258 @@
+//! Start and end milestones, such as `\qt-s` and `\qt-e`, paired up so the
+//! text between them can be treated as one span even where it runs across
+//! paragraphs.
+
+use std::ops::Range;
+
+use crate::{
+    diagnostic::{Code, Diagnostic},
+    document::{Content, Document, Node, Span, State},
+    extension::Extensions,
+};
+
+/// A start milestone and the end milestone matched with it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MilestoneSpan<'d> {
+    pub start: &'d Node<'d>,
+    pub end: &'d Node<'d>,
+    /// The start's `sid`, if it has one.
+    pub id: Option<&'d str>,
+    /// The start and end milestones and everything between them, as
+    /// indexes into [`Milestones::items`].
+    pub range: Range<usize>,
+}
+
+impl MilestoneSpan<'_> {
+    /// Where the pair is in the source, from the start of the start
+    /// milestone to the end of the end one. `None` for documents not
+    /// parsed from USFM, or edited since.
+    pub fn span(&self) -> Option<Span> {
+        Some(Span {
+            start: self.start.span?.start,
+            end: self.end.span?.end,
+        })
+    }
+}
+
+/// The milestone pairs of a document, in the order they start.
+#[derive(Debug, Clone, Default)]
+pub struct Milestones<'d> {
+    pub spans: Vec<MilestoneSpan<'d>>,
+    /// End milestones without a start, starts never ended, and pairs that
+    /// overlap without one nesting inside the other.
+    pub diagnostics: Vec<Diagnostic>,
+    items: Vec<&'d Content<'d>>,
+    // The index just past the last descendant of each item.
+    ends: Vec<usize>,
+}
+
+impl<'d> Milestones<'d> {
+    /// Every content item in the document, parents before their children.
+    pub fn items(&self) -> &[&'d Content<'d>] {
+        &self.items
+    }
+
+    /// The items between a pair's start and end milestones.
+    pub fn content(&self, span: &MilestoneSpan) -> &[&'d Content<'d>] {
+        &self.items[span.range.start + 1..span.range.end - 1]
+    }
+
+    /// The text between a pair's start and end milestones, leaving out
+    /// notes and starting a new line for each paragraph.
+    pub fn text(&self, span: &MilestoneSpan) -> String {
+        let mut text = String::new();
+        let mut n = span.range.start + 1;
+        while n < span.range.end - 1 {
+            match self.items[n] {
+                Content::Note(_) => n = self.ends[n],
+                Content::Para(_) if !text.is_empty() => {
+                    text.push('\n');
+                    n += 1;
+                }
+                Content::Text(t) => {
+                    text.push_str(t.as_str());
+                    n += 1;
+                }
+                _ => n += 1,
+            }
+        }
+        text
+    }
+}
+
+impl Document<'_> {
+    /// Pair every start milestone with its end, by `sid` and `eid` where
+    /// the milestones have them and otherwise by nesting.
+    pub fn resolve_milestones(&self) -> Milestones<'_> {
+        let mut resolver = Resolver {
+            markers: State::usfm_ext(),
+            open: Vec::new(),
+            res: Milestones::default(),
+        };
+        if let Some(root) = self.root() {
+            resolver.content(&root.content);
+        }
+        for (_, node, _) in std::mem::take(&mut resolver.open) {
+            let end = resolver.markers.closed_by(&node.style).map_or_else(
+                || format!("{}-e", node.style.trim_end_matches("-s")),
+                str::to_owned,
+            );
+            resolver.res.diagnostics.push(Diagnostic::error(
+                Code::MissingEndmarker,
+                node.span.unwrap_or_default(),
+                format!("\\{} is missing its \\{end}", node.style),
+            ));
+        }
+        resolver.res.spans.sort_by_key(|span| span.range.start);
+        resolver.res
+    }
+}
+
+struct Resolver<'d> {
+    markers: &'static Extensions,
+    // Start milestones awaiting their end, with their item index.
+    open: Vec<(usize, &'d Node<'d>, Option<&'d str>)>,
+    res: Milestones<'d>,
+}
+
+impl<'d> Resolver<'d> {
+    fn content(&mut self, content: &'d [Content<'d>]) {
+        for item in content {
+            let n = self.res.items.len();
+            self.res.items.push(item);
+            self.res.ends.push(n + 1);
+            match item {
+                Content::Milestone(node) => self.milestone(n, node),
+                item => {
+                    if let Some(node) = item.node() {
+                        self.content(&node.content);
+                    }
+                }
+            }
+            self.res.ends[n] = self.res.items.len();
+        }
+    }
+
+    // Markers missing from the marker set pair up by their -s and -e
+    // suffixes.
+    fn milestone(&mut self, n: usize, node: &'d Node<'d>) {
+        let style = node.style.as_ref();
+        let marker = self.markers.get(style);
+        let opens = marker.map_or(style.ends_with("-s"), |m| m.closedby.is_some());
+        if opens {
+            let sid = node.attributes.get("sid").map(|sid| sid.as_ref());
+            self.open.push((n, node, sid));
+            return;
+        }
+        let closes = match marker {
+            Some(marker) => marker.closes.clone(),
+            None => style.strip_suffix("-e").map(|base| format!("{base}-s")),
+        };
+        let Some(start) = closes else {
+            return;
+        };
+        let eid = node.attributes.get("eid").map(|eid| eid.as_ref());
+        let found = self
+            .open
+            .iter()
+            .rposition(|(_, s, sid)| s.style == start && (eid.is_none() || *sid == eid));
+        let Some(found) = found else {
+            let message = match eid {
+                Some(eid) => format!("\\{style} has no \\{start} with sid {eid}"),
+                None => format!("\\{style} without \\{start}"),
+            };
+            self.res.diagnostics.push(Diagnostic::error(
+                Code::UnmatchedEndmarker,
+                node.span.unwrap_or_default(),
+                message,
+            ));
+            return;
+        };
+        let (begin, start, id) = self.open.remove(found);
+        for (_, other, _) in &self.open[found..] {
+            self.res.diagnostics.push(Diagnostic::warning(
+                Code::CrossingMilestones,
+                node.span.unwrap_or_default(),
+                format!(
+                    "\\{} and \\{} overlap without nesting",
+                    start.style, other.style
+                ),
+            ));
+        }
+        self.res.spans.push(MilestoneSpan {
+            start,
+            end: node,
+            id,
+            range: begin..n + 1,
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{diagnostic::Code, document::Document};
+
+    #[test]
+    fn resolve_milestones() {
+        let source =
+            "\\id MRK\n\\usfm 3.0\n\\c 1\n\\p \\v 1 \\qt-s |who=\"Jesus\"\\*Come\\f + \\ft note\\f*,\n\
+                      \\p follow me.\\qt-e\\* \\ts-s\\*Then \\qt-s |sid=\"b\"\\*they\\ts-e\\* \
+                      went.\\qt-e |eid=\"b\"\\*\n";
+        let doc = source.parse::<Document>().expect("Document");
+        let milestones = doc.resolve_milestones();
+        let spans = milestones
+            .spans
+            .iter()
+            .map(|span| {
+                (
+                    span.start.style.as_ref(),
+                    span.id,
+                    milestones.text(span),
+                    &source[span.span().expect("span").range()],
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            spans,
+            [
+                (
+                    "qt-s",
+                    Some("qt-1"),
+                    "Come,\nfollow me.".to_owned(),
+                    "\\qt-s |who=\"Jesus\"\\*Come\\f + \\ft note\\f*,\n\\p follow me.\\qt-e\\*"
+                ),
+                (
+                    "ts-s",
+                    Some("ts-2"),
+                    "Then they".to_owned(),
+                    "\\ts-s\\*Then \\qt-s |sid=\"b\"\\*they\\ts-e\\*"
+                ),
+                (
+                    "qt-s",
+                    Some("b"),
+                    "they went.".to_owned(),
+                    "\\qt-s |sid=\"b\"\\*they\\ts-e\\* went.\\qt-e |eid=\"b\"\\*"
+                ),
+            ]
+        );
+        assert_eq!(milestones.diagnostics.len(), 1);
+        assert_eq!(milestones.diagnostics[0].code, Code::CrossingMilestones);
+
+        let doc: Document = "\\id MRK\n\\usfm 3.0\n\\c 1\n\\p \\v 1 \\qt-e\\* \\qt-s\\*Open\n"
+            .parse()
+            .expect("Document");
+        let milestones = doc.resolve_milestones();
+        assert!(milestones.spans.is_empty());
+        assert_eq!(
+            milestones
+                .diagnostics
+                .iter()
+                .map(|d| (d.code, d.message.as_str()))
+                .collect::<Vec<_>>(),
+            [
+                (Code::UnmatchedEndmarker, "\\qt-e without \\qt-s"),
+                (Code::MissingEndmarker, "\\qt-s is missing its \\qt-e"),
+            ]
+        );
+    }
+}
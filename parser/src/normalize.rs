@@ -0,0 +1,196 @@
+//! Putting a document into canonical form, so that writing it out gives
+//! the same USFM whatever the layout of the source.
+
+use std::{borrow::Cow, ops::ControlFlow};
+
+use crate::{
+    document::{Content, Document, Node, State, Text},
+    extension::Category,
+    visit::{walk_content_mut, walk_node_mut, VisitMut},
+};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct NormalizeOptions {
+    /// Put the header paragraphs after `\id` in the standard order:
+    /// `\ide`, `\sts`, `\rem`, `\h`, `\toc1`–`\toc3`, `\toca1`–`\toca3`.
+    pub reorder_headers: bool,
+}
+
+impl Document<'_> {
+    /// Collapse runs of whitespace in text to a single space and trim it at
+    /// the start and end of each paragraph, and optionally reorder the
+    /// header. Written out, the result has each paragraph and verse on its
+    /// own line, no blank lines and every attribute quoted.
+    ///
+    /// Source spans are dropped, as they no longer match what the document
+    /// holds.
+    pub fn normalize(&mut self, options: NormalizeOptions) {
+        let Some(root) = &mut self.nodes else {
+            return;
+        };
+        let _ = Normalizer.visit_node_mut(root);
+        if options.reorder_headers {
+            reorder_headers(&mut root.content);
+        }
+        self.set_source(String::new());
+    }
+
+    /// Whether two documents hold the same content once normalized, without
+    /// reordering the header. Source spans and the layout of whitespace are
+    /// ignored, so a document written out and parsed again is canonically
+    /// equal to the original.
+    pub fn canonical_eq(&self, other: &Document) -> bool {
+        let canonical = |doc: &Document| {
+            let mut doc = doc.clone().into_owned();
+            doc.normalize(NormalizeOptions::default());
+            doc.nodes
+        };
+        canonical(self) == canonical(other)
+    }
+}
+
+struct Normalizer;
+
+fn collapse(text: &str) -> Cow<'_, str> {
+    let collapsed = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    let start = if text.starts_with(char::is_whitespace) {
+        " "
+    } else {
+        ""
+    };
+    let end = if text.ends_with(char::is_whitespace) && !collapsed.is_empty() {
+        " "
+    } else {
+        ""
+    };
+    let res = format!("{start}{collapsed}{end}");
+    if res == text {
+        Cow::Borrowed(text)
+    } else {
+        Cow::Owned(res)
+    }
+}
+
+impl VisitMut for Normalizer {
+    fn visit_content_mut(&mut self, content: &mut Content) -> ControlFlow<()> {
+        if let Content::Para(node) | Content::Book(node) | Content::Cell(node) = content {
+            trim(node);
+        }
+        walk_content_mut(self, content)
+    }
+
+    fn visit_node_mut(&mut self, node: &mut Node) -> ControlFlow<()> {
+        node.span = None;
+        walk_node_mut(self, node)
+    }
+
+    fn visit_text_mut(&mut self, text: &mut Text) -> ControlFlow<()> {
+        text.span = None;
+        if let Cow::Owned(collapsed) = collapse(&text.text) {
+            text.text = Cow::Owned(collapsed);
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+// Drop whitespace at the edges of a paragraph's text.
+fn trim(node: &mut Node) {
+    if let Some(Content::Text(text)) = node.content.first_mut() {
+        if text.text.starts_with(char::is_whitespace) {
+            text.text = Cow::Owned(text.text.trim_start().to_owned());
+        }
+    }
+    if let Some(Content::Text(text)) = node.content.last_mut() {
+        if text.text.ends_with(char::is_whitespace) {
+            text.text = Cow::Owned(text.text.trim_end().to_owned());
+        }
+    }
+    node.content
+        .retain(|item| !matches!(item, Content::Text(text) if text.text.is_empty()));
+}
+
+fn header_rank(style: &str) -> usize {
+    const ORDER: [&str; 10] = [
+        "ide", "sts", "rem", "h", "toc1", "toc2", "toc3", "toca1", "toca2", "toca3",
+    ];
+    let style = match style {
+        "h1" => "h",
+        style => style,
+    };
+    ORDER
+        .iter()
+        .position(|s| *s == style)
+        .unwrap_or(ORDER.len())
+}
+
+// Sorts the run of header paragraphs following `\id`, keeping the relative
+// order of paragraphs with the same marker.
+fn reorder_headers(content: &mut [Content]) {
+    let markers = State::usfm_ext();
+    let is_header = |item: &Content| match item {
+        Content::Para(node) => {
+            header_rank(&node.style) < 10
+                || markers.category(node.style.as_ref()) == Some(Category::Header)
+        }
+        _ => false,
+    };
+    let start = content
+        .iter()
+        .position(|item| matches!(item, Content::Book(_)))
+        .map_or(0, |n| n + 1);
+    let len = content[start..]
+        .iter()
+        .take_while(|item| is_header(item))
+        .count();
+    content[start..start + len].sort_by_key(|item| match item {
+        Content::Para(node) => header_rank(&node.style),
+        _ => usize::MAX,
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::NormalizeOptions;
+    use crate::document::Document;
+
+    #[test]
+    fn normalize() {
+        let source = "\\id MRK  Good   News\n\
+                      \\toc1 The Gospel\n\
+                      \\h Mark\n\
+                      \\rem checked\n\
+                      \\ide UTF-8\n\n\n\
+                      \\c 1\n\
+                      \\p   \\v 1 The  beginning\n   \\nd of\\nd*\t the \\w word|grace\\w*\n\n\
+                      \\v 2 As  \n";
+        let mut doc = source.parse::<Document>().expect("Document");
+        doc.normalize(NormalizeOptions {
+            reorder_headers: true,
+        });
+        assert_eq!(
+            doc.to_string(),
+            "\\id MRK Good News\n\
+             \\ide UTF-8\n\
+             \\rem checked\n\
+             \\h Mark\n\
+             \\toc1 The Gospel\n\
+             \\c 1\n\
+             \\p\n\
+             \\v 1 The beginning \\nd of\\nd* the \\w word|lemma=\"grace\"\\w*\n\
+             \\v 2 As\n"
+        );
+
+        let mut doc: Document = "\\id MRK\n\\toc1 T\n\\h H\n".parse().expect("Document");
+        doc.normalize(NormalizeOptions::default());
+        assert_eq!(doc.to_string(), "\\id MRK\n\\toc1 T\n\\h H\n");
+
+        let spaced = "\\id MRK\n\\c 1\n\\p\n\\v 1  In \\nd the\\nd*\n beginning \n"
+            .parse::<Document>()
+            .expect("Document");
+        let plain: Document = "\\id MRK\n\\c 1\n\\p \\v 1 In \\nd the\\nd* beginning\n"
+            .parse()
+            .expect("Document");
+        assert!(spaced.canonical_eq(&plain));
+        assert!(!spaced.canonical_eq(&doc));
+    }
+}
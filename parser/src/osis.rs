@@ -0,0 +1,517 @@
+//! Exporting documents as OSIS XML, the format read by SWORD and its
+//! tools. Chapters and verses are milestoned so they can cross paragraphs.
+
+use std::io::{self, Write};
+
+use crate::{
+    document::{Content, Document, Node, State},
+    extension::{Category, Extensions},
+    usx::escape,
+    versification::{verse_numbers, verse_range},
+};
+
+const NAMESPACE: &str = "http://www.bibletechnologies.net/2003/OSIS/namespace";
+
+// USFM book codes and their OSIS names. Books without an OSIS name keep
+// their USFM code.
+const OSIS_BOOKS: [(&str, &str); 104] = [
+    ("GEN", "Gen"),
+    ("EXO", "Exod"),
+    ("LEV", "Lev"),
+    ("NUM", "Num"),
+    ("DEU", "Deut"),
+    ("JOS", "Josh"),
+    ("JDG", "Judg"),
+    ("RUT", "Ruth"),
+    ("1SA", "1Sam"),
+    ("2SA", "2Sam"),
+    ("1KI", "1Kgs"),
+    ("2KI", "2Kgs"),
+    ("1CH", "1Chr"),
+    ("2CH", "2Chr"),
+    ("EZR", "Ezra"),
+    ("NEH", "Neh"),
+    ("EST", "Esth"),
+    ("JOB", "Job"),
+    ("PSA", "Ps"),
+    ("PRO", "Prov"),
+    ("ECC", "Eccl"),
+    ("SNG", "Song"),
+    ("ISA", "Isa"),
+    ("JER", "Jer"),
+    ("LAM", "Lam"),
+    ("EZK", "Ezek"),
+    ("DAN", "Dan"),
+    ("HOS", "Hos"),
+    ("JOL", "Joel"),
+    ("AMO", "Amos"),
+    ("OBA", "Obad"),
+    ("JON", "Jonah"),
+    ("MIC", "Mic"),
+    ("NAM", "Nah"),
+    ("HAB", "Hab"),
+    ("ZEP", "Zeph"),
+    ("HAG", "Hag"),
+    ("ZEC", "Zech"),
+    ("MAL", "Mal"),
+    ("MAT", "Matt"),
+    ("MRK", "Mark"),
+    ("LUK", "Luke"),
+    ("JHN", "John"),
+    ("ACT", "Acts"),
+    ("ROM", "Rom"),
+    ("1CO", "1Cor"),
+    ("2CO", "2Cor"),
+    ("GAL", "Gal"),
+    ("EPH", "Eph"),
+    ("PHP", "Phil"),
+    ("COL", "Col"),
+    ("1TH", "1Thess"),
+    ("2TH", "2Thess"),
+    ("1TI", "1Tim"),
+    ("2TI", "2Tim"),
+    ("TIT", "Titus"),
+    ("PHM", "Phlm"),
+    ("HEB", "Heb"),
+    ("JAS", "Jas"),
+    ("1PE", "1Pet"),
+    ("2PE", "2Pet"),
+    ("1JN", "1John"),
+    ("2JN", "2John"),
+    ("3JN", "3John"),
+    ("JUD", "Jude"),
+    ("REV", "Rev"),
+    ("TOB", "Tob"),
+    ("JDT", "Jdt"),
+    ("ESG", "EsthGr"),
+    ("WIS", "Wis"),
+    ("SIR", "Sir"),
+    ("BAR", "Bar"),
+    ("LJE", "EpJer"),
+    ("S3Y", "PrAzar"),
+    ("SUS", "Sus"),
+    ("BEL", "Bel"),
+    ("1MA", "1Macc"),
+    ("2MA", "2Macc"),
+    ("3MA", "3Macc"),
+    ("4MA", "4Macc"),
+    ("1ES", "1Esd"),
+    ("2ES", "2Esd"),
+    ("MAN", "PrMan"),
+    ("PS2", "AddPs"),
+    ("ODA", "Odes"),
+    ("PSS", "PssSol"),
+    ("DAG", "AddDan"),
+    ("LAO", "EpLao"),
+    ("ENO", "1En"),
+    ("JUB", "Jub"),
+    ("1MQ", "1Meq"),
+    ("2MQ", "2Meq"),
+    ("3MQ", "3Meq"),
+    ("REP", "Reproof"),
+    ("4BA", "4Bar"),
+    ("2BA", "2Bar"),
+    ("LBA", "EpBar"),
+    ("JSA", "JoshA"),
+    ("JDB", "JudgB"),
+    ("TBS", "TobS"),
+    ("SST", "SusTh"),
+    ("DNT", "DanTh"),
+    ("BLT", "BelTh"),
+    ("EZA", "4Ezra"),
+];
+
+/// The OSIS name of a book, such as `Matt` for `MAT`.
+pub fn osis_book(code: &str) -> Option<&'static str> {
+    OSIS_BOOKS
+        .iter()
+        .find(|(usfm, _)| *usfm == code)
+        .map(|(_, osis)| *osis)
+}
+
+impl Document<'_> {
+    /// Header paragraphs such as `\h` and `\toc1` are left out, and
+    /// character styles OSIS has no element for become `<seg>` elements
+    /// typed `x-usfm-` and the marker.
+    pub fn to_osis<W: Write>(&self, w: W) -> io::Result<()> {
+        let mut writer = Writer {
+            out: w,
+            markers: State::usfm_ext(),
+            book: String::new(),
+            chapter: None,
+            verse: None,
+        };
+        writer.document(self.nodes.as_ref())
+    }
+}
+
+struct Writer<'m, W> {
+    out: W,
+    markers: &'m Extensions,
+    book: String,
+    chapter: Option<String>,
+    verse: Option<String>,
+}
+
+impl<W: Write> Writer<'_, W> {
+    fn document(&mut self, root: Option<&Node>) -> io::Result<()> {
+        writeln!(self.out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(self.out, r#"<osis xmlns="{NAMESPACE}">"#)?;
+        writeln!(
+            self.out,
+            r#"<osisText osisIDWork="Bible" osisRefWork="Bible">"#
+        )?;
+        writeln!(self.out, r#"<header><work osisWork="Bible" /></header>"#)?;
+        if let Some(root) = root {
+            let code = root.content.iter().find_map(|item| match item {
+                Content::Book(node) => node.attributes.get("code"),
+                _ => None,
+            });
+            self.book = code.map_or_else(String::new, |code| {
+                osis_book(code).map_or_else(|| code.to_string(), str::to_owned)
+            });
+            writeln!(
+                self.out,
+                r#"<div type="book" osisID="{}">"#,
+                escape(&self.book)
+            )?;
+            self.blocks(&root.content, None)?;
+            self.close_verse()?;
+            self.close_chapter()?;
+            writeln!(self.out, "</div>")?;
+        }
+        writeln!(self.out, "</osisText>")?;
+        writeln!(self.out, "</osis>")
+    }
+
+    fn category(&self, node: &Node) -> Category {
+        self.markers
+            .get(node.style.as_ref())
+            .map(|marker| marker.category)
+            .unwrap_or_default()
+    }
+
+    fn blocks(&mut self, content: &[Content], next: Option<&Content>) -> io::Result<()> {
+        for (n, item) in content.iter().enumerate() {
+            self.block(item, content.get(n + 1).or(next))?;
+        }
+        Ok(())
+    }
+
+    fn block(&mut self, item: &Content, next: Option<&Content>) -> io::Result<()> {
+        match item {
+            Content::Book(_) => Ok(()),
+            Content::Chapter(node) => {
+                self.close_verse()?;
+                self.close_chapter()?;
+                let number = node.attributes.get("number").map_or("", |n| n.as_ref());
+                let id = format!("{}.{number}", self.book);
+                writeln!(
+                    self.out,
+                    r#"<chapter sID="{id}" osisID="{id}" n="{}" />"#,
+                    escape(number),
+                    id = escape(&id)
+                )?;
+                self.chapter = Some(id);
+                self.blocks(&node.content, None)
+            }
+            Content::Para(node) => {
+                let style = node.style.as_ref();
+                let (open, close) = match self.category(node) {
+                    Category::Header => return Ok(()),
+                    Category::Title => (r#"<title type="main">"#.to_owned(), "</title>"),
+                    Category::SectionPara if style == "r" => {
+                        (r#"<title type="parallel">"#.to_owned(), "</title>")
+                    }
+                    Category::SectionPara if style == "d" => (
+                        r#"<title type="psalm" canonical="true">"#.to_owned(),
+                        "</title>",
+                    ),
+                    Category::SectionPara => ("<title>".to_owned(), "</title>"),
+                    Category::List => ("<item>".to_owned(), "</item>"),
+                    Category::VersePara if style.starts_with('q') => {
+                        let level = node.attributes.get("level").map_or("1", |l| l.as_ref());
+                        (format!(r#"<l level="{}">"#, escape(level)), "</l>")
+                    }
+                    _ if node.content.is_empty() => return Ok(()),
+                    _ => ("<p>".to_owned(), "</p>"),
+                };
+                self.out.write_all(open.as_bytes())?;
+                self.inlines(&node.content)?;
+                if !self.continues(next) {
+                    self.close_verse()?;
+                }
+                writeln!(self.out, "{close}")
+            }
+            Content::Unknown(node) => {
+                write!(self.out, "<p>")?;
+                self.inlines(&node.content)?;
+                writeln!(self.out, "</p>")
+            }
+            Content::Stanza(node) | Content::List(node) | Content::Table(node) => {
+                let (open, close) = match item {
+                    Content::Stanza(_) => ("<lg>", "</lg>"),
+                    Content::List(_) => ("<list>", "</list>"),
+                    _ => ("<table>", "</table>"),
+                };
+                writeln!(self.out, "{open}")?;
+                self.blocks(&node.content, next)?;
+                writeln!(self.out, "{close}")
+            }
+            Content::Row(node) => {
+                write!(self.out, "<row>")?;
+                for (n, cell) in node.content.iter().enumerate() {
+                    self.cell(cell, node.content.get(n + 1).or(next))?;
+                }
+                writeln!(self.out, "</row>")
+            }
+            Content::Periph(node) | Content::Sidebar(node) => {
+                let kind = match item {
+                    Content::Periph(_) => "x-periph",
+                    _ => "x-sidebar",
+                };
+                writeln!(self.out, r#"<div type="{kind}">"#)?;
+                self.blocks(&node.content, next)?;
+                writeln!(self.out, "</div>")
+            }
+            item => self.inline(item),
+        }
+    }
+
+    fn cell(&mut self, item: &Content, next: Option<&Content>) -> io::Result<()> {
+        let Content::Cell(node) = item else {
+            return self.inline(item);
+        };
+        match node.style.starts_with("th") {
+            true => write!(self.out, r#"<cell role="label">"#)?,
+            false => write!(self.out, "<cell>")?,
+        }
+        self.inlines(&node.content)?;
+        if !self.continues(next) {
+            self.close_verse()?;
+        }
+        write!(self.out, "</cell>")
+    }
+
+    fn inlines(&mut self, content: &[Content]) -> io::Result<()> {
+        content.iter().try_for_each(|item| self.inline(item))
+    }
+
+    fn inline(&mut self, item: &Content) -> io::Result<()> {
+        match item {
+            Content::Text(text) => write!(self.out, "{}", escape(text.as_str())),
+            Content::OptBreak => Ok(()),
+            Content::NoBreakSpace => write!(self.out, "\u{a0}"),
+            Content::Verse(node) => {
+                self.close_verse()?;
+                let chapter = self.chapter.clone().unwrap_or_else(|| self.book.clone());
+                let number = node.attributes.get("number").map_or("", |n| n.as_ref());
+                let ids = match verse_range(number) {
+                    Some((first, last)) if first <= last => verse_numbers((first, last))
+                        .map(|v| format!("{chapter}.{v}"))
+                        .collect::<Vec<_>>(),
+                    _ => vec![format!("{chapter}.{number}")],
+                };
+                write!(
+                    self.out,
+                    r#"<verse sID="{}" osisID="{}" n="{}" />"#,
+                    escape(&ids[0]),
+                    escape(&ids.join(" ")),
+                    escape(number)
+                )?;
+                self.verse = Some(ids[0].clone());
+                Ok(())
+            }
+            Content::Milestone(node) => write!(
+                self.out,
+                r#"<milestone type="x-usfm-{}" />"#,
+                escape(&node.style)
+            ),
+            Content::Figure(node) => {
+                write!(self.out, "<figure")?;
+                if let Some(src) = node.attributes.get("src") {
+                    write!(self.out, r#" src="{}""#, escape(src))?;
+                }
+                write!(self.out, "><caption>")?;
+                self.inlines(&node.content)?;
+                write!(self.out, "</caption></figure>")
+            }
+            Content::Note(node) => {
+                let kind = match (self.category(node), node.style.as_ref()) {
+                    (Category::Crossreference, _) => r#"type="crossReference""#,
+                    (_, "fe" | "ef") => r#"placement="end""#,
+                    _ => r#"placement="foot""#,
+                };
+                write!(self.out, "<note {kind}")?;
+                if let Some(verse) = &self.verse {
+                    write!(self.out, r#" osisRef="{}""#, escape(verse))?;
+                }
+                match node.attributes.get("caller").map(|c| c.as_ref()) {
+                    Some("+" | "-") | None => {}
+                    Some(caller) => write!(self.out, r#" n="{}""#, escape(caller))?,
+                }
+                write!(self.out, ">")?;
+                self.inlines(&node.content)?;
+                write!(self.out, "</note>")
+            }
+            Content::Char(node) | Content::Unknown(node) => {
+                let (open, close) = self.char_element(node);
+                self.out.write_all(open.as_bytes())?;
+                self.inlines(&node.content)?;
+                self.out.write_all(close.as_bytes())
+            }
+            block => self.block(block, None),
+        }
+    }
+
+    fn char_element(&self, node: &Node) -> (String, &'static str) {
+        let hi = |kind| (format!(r#"<hi type="{kind}">"#), "</hi>");
+        match node.style.as_ref() {
+            "bd" => hi("bold"),
+            "it" => hi("italic"),
+            "em" => hi("emphasis"),
+            "sc" => hi("small-caps"),
+            "sup" => hi("super"),
+            "no" => hi("normal"),
+            "nd" => ("<divineName>".to_owned(), "</divineName>"),
+            "add" => (r#"<transChange type="added">"#.to_owned(), "</transChange>"),
+            "wj" => (r#"<q who="Jesus" marker="">"#.to_owned(), "</q>"),
+            "tl" => ("<foreign>".to_owned(), "</foreign>"),
+            "fk" | "fq" => ("<catchWord>".to_owned(), "</catchWord>"),
+            "fqa" => (r#"<rdg type="alternate">"#.to_owned(), "</rdg>"),
+            "fr" | "xo" => (
+                r#"<reference type="annotateRef">"#.to_owned(),
+                "</reference>",
+            ),
+            "ft" | "xt" => (String::new(), ""),
+            "w" => {
+                let mut open = "<w".to_owned();
+                let strong = node.attributes.get("strong").map(|strong| {
+                    strong
+                        .split(',')
+                        .map(|s| format!("strong:{}", s.trim()))
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                });
+                let lemma = node.attributes.get("lemma").map(|l| format!("lemma:{l}"));
+                let lemma = strong.into_iter().chain(lemma).collect::<Vec<_>>();
+                if !lemma.is_empty() {
+                    open.push_str(&format!(r#" lemma="{}""#, escape(&lemma.join(" "))));
+                }
+                if let Some(morph) = node.attributes.get("x-morph") {
+                    open.push_str(&format!(r#" morph="{}""#, escape(morph)));
+                }
+                open.push('>');
+                (open, "</w>")
+            }
+            style => (
+                format!(r#"<seg type="x-usfm-{}">"#, escape(style)),
+                "</seg>",
+            ),
+        }
+    }
+
+    fn close_verse(&mut self) -> io::Result<()> {
+        match self.verse.take() {
+            Some(eid) => write!(self.out, r#"<verse eID="{}" />"#, escape(&eid)),
+            None => Ok(()),
+        }
+    }
+
+    fn close_chapter(&mut self) -> io::Result<()> {
+        match self.chapter.take() {
+            Some(eid) => writeln!(self.out, r#"<chapter eID="{}" />"#, escape(&eid)),
+            None => Ok(()),
+        }
+    }
+
+    // As for USX, a verse stays open into the next block when its text
+    // carries on there.
+    fn continues(&self, next: Option<&Content>) -> bool {
+        let starts_verse = |node: &Node| matches!(node.content.first(), Some(Content::Verse(_)));
+        match next {
+            Some(Content::Para(node)) => {
+                matches!(self.category(node), Category::VersePara | Category::List)
+                    && !starts_verse(node)
+            }
+            Some(Content::Cell(node)) => !starts_verse(node),
+            Some(Content::Stanza(node) | Content::List(node) | Content::Table(node))
+            | Some(Content::Row(node)) => self.continues(node.content.first()),
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::osis_book;
+    use crate::document::Document;
+
+    fn osis(usfm: &str) -> String {
+        let doc: Document = usfm.parse().expect("Document");
+        let mut out = Vec::new();
+        doc.to_osis(&mut out).expect("OSIS");
+        String::from_utf8(out).expect("UTF-8")
+    }
+
+    #[test]
+    fn books() {
+        assert_eq!(osis_book("MAT"), Some("Matt"));
+        assert_eq!(osis_book("1JN"), Some("1John"));
+        assert_eq!(osis_book("FRT"), None);
+    }
+
+    #[test]
+    fn write_osis() {
+        assert_eq!(
+            osis(
+                "\\id MRK\n\
+                 \\h Mark\n\
+                 \\mt1 Mark\n\
+                 \\c 1\n\
+                 \\s1 John\n\
+                 \\p \\v 1 The \\nd Lord\\nd* & \\w word|strong=\"G3056\"\\w*\\f + \\fr 1.1 \\ft Or \\fq this\\fq*\\f*\n\
+                 \\q1 continued\n\
+                 \\q2 \\v 2-3 \\bk Isaiah\\bk*\n\
+                 \\c 2\n\
+                 \\p \\v 1 Again\n"
+            ),
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <osis xmlns=\"http://www.bibletechnologies.net/2003/OSIS/namespace\">\n\
+             <osisText osisIDWork=\"Bible\" osisRefWork=\"Bible\">\n\
+             <header><work osisWork=\"Bible\" /></header>\n\
+             <div type=\"book\" osisID=\"Mark\">\n\
+             <title type=\"main\">Mark</title>\n\
+             <chapter sID=\"Mark.1\" osisID=\"Mark.1\" n=\"1\" />\n\
+             <title>John</title>\n\
+             <p><verse sID=\"Mark.1.1\" osisID=\"Mark.1.1\" n=\"1\" />The <divineName>Lord</divineName> &amp; \
+             <w lemma=\"strong:G3056\">word</w><note placement=\"foot\" osisRef=\"Mark.1.1\">\
+             <reference type=\"annotateRef\">1.1 </reference>Or <catchWord>this</catchWord></note></p>\n\
+             <lg>\n\
+             <l level=\"1\">continued<verse eID=\"Mark.1.1\" /></l>\n\
+             <l level=\"2\"><verse sID=\"Mark.1.2\" osisID=\"Mark.1.2 Mark.1.3\" n=\"2-3\" />\
+             <seg type=\"x-usfm-bk\">Isaiah</seg><verse eID=\"Mark.1.2\" /></l>\n\
+             </lg>\n\
+             <chapter eID=\"Mark.1\" />\n\
+             <chapter sID=\"Mark.2\" osisID=\"Mark.2\" n=\"2\" />\n\
+             <p><verse sID=\"Mark.2.1\" osisID=\"Mark.2.1\" n=\"1\" />Again<verse eID=\"Mark.2.1\" /></p>\n\
+             <chapter eID=\"Mark.2\" />\n\
+             </div>\n\
+             </osisText>\n\
+             </osis>\n"
+        );
+    }
+
+    #[test]
+    fn long_ranges() {
+        let out = osis("\\id MRK\n\\c 1\n\\p \\v 2-4000000000 Dua \\v 3-1 tiga\n");
+        let ids = (2..=200)
+            .map(|v| format!("Mark.1.{v}"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        assert!(out.contains(&format!(
+            "<verse sID=\"Mark.1.2\" osisID=\"{ids}\" n=\"2-4000000000\" />Dua "
+        )));
+        assert!(out.contains("<verse sID=\"Mark.1.3-1\" osisID=\"Mark.1.3-1\" n=\"3-1\" />tiga"));
+    }
+}
@@ -0,0 +1,270 @@
+//! Extracting the readable text of a document, for search indexing,
+//! text-to-speech and the like.
+
+use crate::{
+    document::{Content, Document, Node, State},
+    extension::{Category, Extensions},
+};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum VerseNumbers {
+    #[default]
+    Omit,
+    /// `3 In the beginning`
+    Plain,
+    /// `[3] In the beginning`
+    Bracketed,
+    /// `³ In the beginning`
+    Superscript,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ParagraphSeparator {
+    #[default]
+    Newline,
+    BlankLine,
+    /// Run the whole text together on one line.
+    Space,
+}
+
+impl ParagraphSeparator {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ParagraphSeparator::Newline => "\n",
+            ParagraphSeparator::BlankLine => "\n\n",
+            ParagraphSeparator::Space => " ",
+        }
+    }
+}
+
+/// What to include besides the body text. The default is the body text
+/// alone, one paragraph per line.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PlainTextOptions {
+    /// Footnotes appear in square brackets where they are anchored, without
+    /// their `\fr` origin reference.
+    pub footnotes: bool,
+    /// Cross references appear like footnotes, without their `\xo`.
+    pub crossrefs: bool,
+    /// Titles and section headings.
+    pub headings: bool,
+    /// The book introduction.
+    pub introduction: bool,
+    pub verse_numbers: VerseNumbers,
+    pub paragraph_separator: ParagraphSeparator,
+}
+
+impl Document<'_> {
+    /// The text of the document with markup removed and whitespace inside
+    /// each paragraph collapsed. Header material such as `\h`, `\toc1` and
+    /// `\rem`, figures and chapter numbers are never included.
+    pub fn to_plain_text(&self, options: PlainTextOptions) -> String {
+        let mut extractor = Extractor {
+            options,
+            markers: State::usfm_ext(),
+            paragraphs: Vec::new(),
+            current: String::new(),
+        };
+        if let Some(root) = &self.nodes {
+            extractor.blocks(&root.content);
+        }
+        extractor.end_paragraph();
+        extractor
+            .paragraphs
+            .join(options.paragraph_separator.as_str())
+    }
+}
+
+struct Extractor {
+    options: PlainTextOptions,
+    markers: &'static Extensions,
+    paragraphs: Vec<String>,
+    current: String,
+}
+
+fn superscript(c: char) -> char {
+    match c {
+        '0' => '⁰',
+        '1' => '¹',
+        '2' => '²',
+        '3' => '³',
+        '4' => '⁴',
+        '5' => '⁵',
+        '6' => '⁶',
+        '7' => '⁷',
+        '8' => '⁸',
+        '9' => '⁹',
+        '-' => '⁻',
+        c => c,
+    }
+}
+
+impl Extractor {
+    fn category(&self, node: &Node) -> Category {
+        self.markers
+            .get(node.style.as_ref())
+            .map(|marker| marker.category)
+            .unwrap_or_default()
+    }
+
+    fn wanted(&self, node: &Node) -> bool {
+        match self.category(node) {
+            Category::Header | Category::Internal => false,
+            Category::Title | Category::SectionPara => self.options.headings,
+            Category::Introduction => self.options.introduction,
+            Category::Footnote => self.options.footnotes,
+            Category::Crossreference => self.options.crossrefs,
+            _ => node.style != "rem",
+        }
+    }
+
+    fn end_paragraph(&mut self) {
+        let text = self
+            .current
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        if !text.is_empty() {
+            self.paragraphs.push(text);
+        }
+        self.current.clear();
+    }
+
+    fn blocks(&mut self, content: &[Content]) {
+        for item in content {
+            match item {
+                Content::Book(_) | Content::Figure(_) | Content::Milestone(_) => {}
+                Content::Para(node) | Content::Unknown(node) => {
+                    if self.wanted(node) {
+                        self.end_paragraph();
+                        self.inlines(&node.content);
+                        self.end_paragraph();
+                    }
+                }
+                Content::Row(node) => {
+                    self.end_paragraph();
+                    self.inlines(&node.content);
+                    self.end_paragraph();
+                }
+                item => match item.node() {
+                    Some(node) => self.blocks(&node.content),
+                    None => self.inlines(std::slice::from_ref(item)),
+                },
+            }
+        }
+    }
+
+    fn inlines(&mut self, content: &[Content]) {
+        for item in content {
+            match item {
+                Content::Text(text) => self.current.push_str(text.as_str()),
+                Content::NoBreakSpace => self.current.push('\u{a0}'),
+                Content::Verse(node) => self.verse(node),
+                Content::Note(node) => {
+                    if self.wanted(node) {
+                        self.current.push_str(" [");
+                        self.inlines(&node.content);
+                        self.current.push_str("] ");
+                    }
+                }
+                Content::Char(node) if node.style == "fr" || node.style == "xo" => {}
+                Content::Char(node) | Content::Unknown(node) if !self.wanted(node) => {}
+                Content::Cell(node) => {
+                    self.current.push(' ');
+                    self.inlines(&node.content);
+                    self.current.push(' ');
+                }
+                Content::Figure(_) | Content::Milestone(_) | Content::OptBreak => {}
+                item => {
+                    if let Some(node) = item.node() {
+                        self.inlines(&node.content);
+                    }
+                }
+            }
+        }
+    }
+
+    fn verse(&mut self, node: &Node) {
+        let Some(number) = node.attributes.get("number") else {
+            return;
+        };
+        self.current.push(' ');
+        match self.options.verse_numbers {
+            VerseNumbers::Omit => {}
+            VerseNumbers::Plain => self.current.push_str(number),
+            VerseNumbers::Bracketed => {
+                self.current.push('[');
+                self.current.push_str(number);
+                self.current.push(']');
+            }
+            VerseNumbers::Superscript => self.current.extend(number.chars().map(superscript)),
+        }
+        self.current.push(' ');
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ParagraphSeparator, PlainTextOptions, VerseNumbers};
+    use crate::document::Document;
+
+    const SOURCE: &str = "\\id MRK\n\
+                          \\h Mark\n\
+                          \\mt Mark\n\
+                          \\ip An \\bk introduction\\bk*.\n\
+                          \\c 1\n\
+                          \\s The Baptist\n\
+                          \\p \\v 1 The beginning\\f + \\fr 1.1 \\ft Some add \\fq Son\\fq*.\\f* of the\n\
+                          gospel. \\v 2 As written\\x - \\xo 1.2 \\xt Mal 3.1\\x* in Isaiah.\n\
+                          \\q1 \\v 3 A voice\n";
+
+    fn text(options: PlainTextOptions) -> String {
+        SOURCE
+            .parse::<Document>()
+            .expect("Document")
+            .to_plain_text(options)
+    }
+
+    #[test]
+    fn defaults() {
+        assert_eq!(
+            text(PlainTextOptions::default()),
+            "The beginning of the gospel. As written in Isaiah.\nA voice"
+        );
+    }
+
+    #[test]
+    fn inclusion() {
+        assert_eq!(
+            text(PlainTextOptions {
+                footnotes: true,
+                crossrefs: true,
+                headings: true,
+                introduction: true,
+                verse_numbers: VerseNumbers::Bracketed,
+                paragraph_separator: ParagraphSeparator::BlankLine,
+            }),
+            "Mark\n\n\
+             An introduction.\n\n\
+             The Baptist\n\n\
+             [1] The beginning [Some add Son.] of the gospel. \
+             [2] As written [Mal 3.1] in Isaiah.\n\n\
+             [3] A voice"
+        );
+        assert_eq!(
+            text(PlainTextOptions {
+                verse_numbers: VerseNumbers::Superscript,
+                paragraph_separator: ParagraphSeparator::Space,
+                ..Default::default()
+            }),
+            "¹ The beginning of the gospel. ² As written in Isaiah. ³ A voice"
+        );
+        assert_eq!(
+            text(PlainTextOptions {
+                verse_numbers: VerseNumbers::Plain,
+                ..Default::default()
+            }),
+            "1 The beginning of the gospel. 2 As written in Isaiah.\n3 A voice"
+        );
+    }
+}
@@ -0,0 +1,515 @@
+//! Loading every book of a project at once, from a plain directory of USFM
+//! files or a Paratext project folder.
+
+use std::{
+    collections::HashMap,
+    fmt::{self, Display},
+    fs::{self, File},
+    io::{self, Read},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+};
+
+use nom::{error::convert_error, Finish};
+
+use crate::{
+    books::{Book, BOOKS},
+    diagnostic::{Code, Diagnostic},
+    document::{Document, Span, State},
+    extension::{Extensions, Version},
+    reference::BookNames,
+    usx::invalid,
+    versification::Scheme,
+    xml::{self, Element, Xml},
+};
+
+/// The books found in a directory of USFM files, keyed by book code.
+#[derive(Debug)]
+pub struct Project {
+    books: HashMap<&'static str, Document<'static>>,
+    markers: Arc<Extensions>,
+    pub(crate) names: BookNames,
+    /// Present when loaded from a Paratext project.
+    pub settings: Option<Settings>,
+    /// Problems found in every file, in path order.
+    pub diagnostics: Vec<FileDiagnostic>,
+    /// Present when loaded from a Scripture Burrito.
+    #[cfg(feature = "burrito")]
+    pub burrito: Option<crate::burrito::Metadata>,
+}
+
+/// A [`Diagnostic`] together with the file it was found in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileDiagnostic {
+    pub path: PathBuf,
+    pub diagnostic: Diagnostic,
+}
+
+impl Display for FileDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.path.display(), self.diagnostic)
+    }
+}
+
+impl Project {
+    /// Parse every `.sfm` and `.usfm` file in a directory, leniently so one
+    /// damaged book does not stop the rest loading. With the `parallel`
+    /// feature the files are parsed on as many threads as there are cores.
+    /// Files without a known `\id`, or repeating a book already loaded, are
+    /// reported in [`Project::diagnostics`] and left out.
+    pub fn load_dir(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut files = Vec::new();
+        for entry in fs::read_dir(path)? {
+            let path = entry?.path();
+            let usfm = path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| {
+                    ext.eq_ignore_ascii_case("sfm") || ext.eq_ignore_ascii_case("usfm")
+                });
+            if usfm && path.is_file() {
+                files.push(path);
+            }
+        }
+        files.sort();
+        Self::load_default(files)
+    }
+
+    /// Load a Paratext project folder. `Settings.xml` gives the file names
+    /// of the books, markers in `custom.sty` are merged into the bundled
+    /// set, and the names in `BookNames.xml` are added to the English ones
+    /// understood by [`Project::names`]. The last two are optional.
+    pub fn load_paratext(path: impl AsRef<Path>) -> io::Result<Self> {
+        let dir = path.as_ref();
+        let settings = Settings::from_reader(File::open(dir.join("Settings.xml"))?)?;
+        let mut markers = Arc::clone(Extensions::usfm_shared(Version::default()));
+        if let Some(file) = open_optional(&dir.join("custom.sty"))? {
+            let custom = Extensions::from_sty_reader(file)?;
+            markers = Arc::new(State::usfm_ext().clone().update_from(custom));
+        }
+        let names = match open_optional(&dir.join("BookNames.xml"))? {
+            Some(file) => BookNames::from_paratext_xml(file)?,
+            None => BookNames::default(),
+        };
+        let files = BOOKS
+            .iter()
+            .map(|book| dir.join(settings.naming.file_name(book)))
+            .filter(|path| path.is_file())
+            .collect();
+        let mut project = Self::load_files(files, markers)?;
+        project.names = names;
+        project.settings = Some(settings);
+        Ok(project)
+    }
+
+    /// Parse the files with the bundled marker set.
+    pub(crate) fn load_default(files: Vec<PathBuf>) -> io::Result<Self> {
+        Self::load_files(
+            files,
+            Arc::clone(Extensions::usfm_shared(Version::default())),
+        )
+    }
+
+    fn load_files(files: Vec<PathBuf>, markers: Arc<Extensions>) -> io::Result<Self> {
+        let parsed = parse_all(&files, &markers);
+        Self::from_parsed(files.into_iter().zip(parsed), markers)
+    }
+
+    /// A project of books already parsed, each with the path it came from.
+    pub(crate) fn from_parsed(
+        parsed: impl IntoIterator<Item = (PathBuf, Parsed)>,
+        markers: Arc<Extensions>,
+    ) -> io::Result<Self> {
+        let mut project = Project {
+            books: HashMap::new(),
+            names: BookNames::default(),
+            settings: None,
+            diagnostics: Vec::new(),
+            #[cfg(feature = "burrito")]
+            burrito: None,
+            markers,
+        };
+        for (path, parsed) in parsed {
+            let (doc, diagnostics) = parsed?;
+            project
+                .diagnostics
+                .extend(diagnostics.into_iter().map(|diagnostic| FileDiagnostic {
+                    path: path.clone(),
+                    diagnostic,
+                }));
+            let message = match doc.book() {
+                Some(book) if project.books.contains_key(book.code) => {
+                    format!("{} is already in the project", book.code)
+                }
+                Some(book) => {
+                    project.books.insert(book.code, doc);
+                    continue;
+                }
+                None => "no book code".to_owned(),
+            };
+            project.diagnostics.push(FileDiagnostic {
+                path: path.clone(),
+                diagnostic: Diagnostic::error(Code::UnknownBook, Span::default(), message),
+            });
+        }
+        Ok(project)
+    }
+
+    pub fn get(&self, code: &str) -> Option<&Document<'static>> {
+        self.books.get(code)
+    }
+
+    pub fn get_mut(&mut self, code: &str) -> Option<&mut Document<'static>> {
+        self.books.get_mut(code)
+    }
+
+    /// The language of the text, from the Paratext settings or the
+    /// burrito metadata.
+    pub fn language(&self) -> Option<&str> {
+        let settings = self.settings.as_ref();
+        let language = settings.and_then(|settings| settings.language.as_deref());
+        #[cfg(feature = "burrito")]
+        let language = language.or_else(|| self.burrito.as_ref()?.language());
+        language
+    }
+
+    /// The copyright statement from the burrito metadata, as plain text.
+    #[cfg(feature = "burrito")]
+    pub fn copyright(&self) -> Option<String> {
+        self.burrito.as_ref()?.copyright()
+    }
+
+    /// The marker set the books were parsed with.
+    pub fn markers(&self) -> &Extensions {
+        &self.markers
+    }
+
+    /// The book names used by the project, for parsing references.
+    pub fn names(&self) -> &BookNames {
+        &self.names
+    }
+
+    /// The books in canonical order.
+    pub fn iter(&self) -> impl Iterator<Item = (&'static Book, &Document<'static>)> {
+        let mut books = self
+            .books
+            .values()
+            .filter_map(|doc| Some((doc.book()?, doc)))
+            .collect::<Vec<_>>();
+        books.sort_by_key(|(book, _)| book.number);
+        books.into_iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.books.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.books.is_empty()
+    }
+}
+
+/// What the loader needs from a Paratext `Settings.xml`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Settings {
+    /// The short project name.
+    pub name: Option<String>,
+    /// The language's ISO 639 code, without any script or region.
+    pub language: Option<String>,
+    /// The standard scheme, or none for an unknown or custom one.
+    pub versification: Option<Scheme>,
+    pub naming: Naming,
+}
+
+impl FromStr for Settings {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (_, root) = xml::document(s)
+            .finish()
+            .map_err(|e| invalid(convert_error(s, e)))?;
+        let mut res = Settings::default();
+        for element in root.children.iter().filter_map(|child| match child {
+            Xml::Element(element) => Some(element),
+            Xml::Text(_) => None,
+        }) {
+            match element.name {
+                "Name" => res.name = text(element),
+                "LanguageIsoCode" => {
+                    res.language = text(element)
+                        .and_then(|code| code.split(':').next().map(str::to_owned))
+                        .filter(|code| !code.is_empty())
+                }
+                "Versification" => {
+                    res.versification = match text(element).as_deref() {
+                        Some("1") => Some(Scheme::Original),
+                        Some("2") => Some(Scheme::Septuagint),
+                        Some("3") => Some(Scheme::Vulgate),
+                        Some("4") => Some(Scheme::English),
+                        Some("5") => Some(Scheme::RussianCanonical),
+                        _ => None,
+                    }
+                }
+                "Naming" => {
+                    let attribute = |name| element.attribute(name).unwrap_or_default().to_owned();
+                    res.naming = Naming {
+                        pre_part: attribute("PrePart"),
+                        post_part: attribute("PostPart"),
+                        book_name_form: attribute("BookNameForm"),
+                    }
+                }
+                _ => (),
+            }
+        }
+        Ok(res)
+    }
+}
+
+impl Settings {
+    #[inline]
+    pub fn from_reader<R: Read>(reader: R) -> io::Result<Self> {
+        io::read_to_string(reader)?.parse()
+    }
+}
+
+pub(crate) fn text(element: &Element) -> Option<String> {
+    let text = element
+        .children
+        .iter()
+        .filter_map(|child| match child {
+            Xml::Text(text) => Some(text.as_ref()),
+            Xml::Element(_) => None,
+        })
+        .collect::<String>();
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_owned())
+}
+
+/// How Paratext names book files: `PrePart`, then the book written as in
+/// `BookNameForm` where `41` stands for the Paratext file number and `MAT`
+/// for the book code, then `PostPart`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Naming {
+    pub pre_part: String,
+    pub post_part: String,
+    pub book_name_form: String,
+}
+
+impl Default for Naming {
+    fn default() -> Self {
+        Naming {
+            pre_part: String::new(),
+            post_part: ".SFM".into(),
+            book_name_form: "41MAT".into(),
+        }
+    }
+}
+
+impl Naming {
+    pub fn file_name(&self, book: &Book) -> String {
+        let form = self
+            .book_name_form
+            .replace("41", &file_number(book))
+            .replace("MAT", book.code);
+        format!("{}{form}{}", self.pre_part, self.post_part)
+    }
+}
+
+// Paratext skips 40 so the New Testament starts at 41, and numbers past 99
+// as A0, A1 and so on.
+fn file_number(book: &Book) -> String {
+    match book.number {
+        n @ 0..=39 => format!("{n:02}"),
+        n @ 40..=99 => (u32::from(n) + 1).to_string(),
+        n => {
+            let n = n - 100;
+            format!("{}{}", char::from(b'A' + n / 10), n % 10)
+        }
+    }
+}
+
+fn open_optional(path: &Path) -> io::Result<Option<File>> {
+    match File::open(path) {
+        Ok(file) => Ok(Some(file)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+pub(crate) type Parsed = io::Result<(Document<'static>, Vec<Diagnostic>)>;
+
+fn parse(path: &Path, markers: &Arc<Extensions>) -> Parsed {
+    Ok(parse_source(&fs::read_to_string(path)?, markers))
+}
+
+pub(crate) fn parse_source(
+    source: &str,
+    markers: &Arc<Extensions>,
+) -> (Document<'static>, Vec<Diagnostic>) {
+    let (doc, diagnostics) = State::from_markers(Arc::clone(markers)).parse_lenient(source);
+    (doc.into_owned(), diagnostics)
+}
+
+#[cfg(not(feature = "parallel"))]
+fn parse_all(files: &[PathBuf], markers: &Arc<Extensions>) -> Vec<Parsed> {
+    files.iter().map(|path| parse(path, markers)).collect()
+}
+
+#[cfg(feature = "parallel")]
+fn parse_all(files: &[PathBuf], markers: &Arc<Extensions>) -> Vec<Parsed> {
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk = files.len().div_ceil(threads).max(1);
+    std::thread::scope(|scope| {
+        let workers = files
+            .chunks(chunk)
+            .map(|files| {
+                scope.spawn(|| {
+                    files
+                        .iter()
+                        .map(|path| parse(path, markers))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect::<Vec<_>>();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().expect("parser thread panicked"))
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::{env, fs, path::PathBuf};
+
+    use super::{Naming, Project, Settings};
+    use crate::{books, diagnostic::Code, versification::Scheme};
+
+    fn project_dir(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = env::temp_dir().join(format!("usfm-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).expect("temp dir");
+        for (file, text) in files {
+            fs::write(dir.join(file), text).expect("write");
+        }
+        dir
+    }
+
+    #[test]
+    fn load_dir() {
+        let dir = project_dir(
+            "load",
+            &[
+                ("41MRKtest.SFM", "\\id MRK\n\\c 1\n\\p \\v 1 Mark\n"),
+                (
+                    "40MATtest.usfm",
+                    "\\id MAT\n\\c 1\n\\p \\v 1 Matthew \\bogus\n",
+                ),
+                ("copy.sfm", "\\id MAT\n\\c 1\n\\p \\v 1 Again\n"),
+                ("empty.sfm", ""),
+                ("notes.txt", "\\id LUK\n"),
+            ],
+        );
+        let project = Project::load_dir(&dir).expect("Project");
+        fs::remove_dir_all(&dir).expect("cleanup");
+
+        assert_eq!(project.len(), 2);
+        let codes = project
+            .iter()
+            .map(|(book, _)| book.code)
+            .collect::<Vec<_>>();
+        assert_eq!(codes, ["MAT", "MRK"]);
+        assert_eq!(
+            project.get("MRK").expect("MRK").to_string(),
+            "\\id MRK\n\\c 1\n\\p\n\\v 1 Mark\n"
+        );
+        assert!(project.get("LUK").is_none());
+
+        let problems = project
+            .diagnostics
+            .iter()
+            .map(|d| {
+                let file = d.path.file_name().unwrap().to_string_lossy();
+                (file.into_owned(), d.diagnostic.code)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            problems,
+            [
+                ("40MATtest.usfm".to_owned(), Code::UnknownMarker),
+                ("copy.sfm".to_owned(), Code::UnknownBook),
+                ("empty.sfm".to_owned(), Code::Syntax),
+                ("empty.sfm".to_owned(), Code::UnknownBook),
+            ]
+        );
+    }
+
+    #[test]
+    fn load_paratext() {
+        let dir = project_dir(
+            "paratext",
+            &[
+                (
+                    "Settings.xml",
+                    "<ScriptureText>\n\
+                     <Name>WEB</Name>\n\
+                     <LanguageIsoCode>es:::</LanguageIsoCode>\n\
+                     <Versification>4</Versification>\n\
+                     <Naming PrePart=\"\" PostPart=\"WEB.SFM\" BookNameForm=\"41MAT\" />\n\
+                     </ScriptureText>\n",
+                ),
+                (
+                    "custom.sty",
+                    "\\Marker zp\n\\OccursUnder c\n\\TextType VerseText\n\\StyleType Paragraph\n",
+                ),
+                (
+                    "BookNames.xml",
+                    "<BookNames><book code=\"JHN\" abbr=\"Jn\" short=\"Juan\" long=\"San Juan\" /></BookNames>",
+                ),
+                ("44JHNWEB.SFM", "\\id JHN\n\\c 1\n\\zp \\v 1 En el principio\n"),
+                ("45ACTWEB.SFM", "\\id ACT\n\\c 1\n\\p \\v 1 En el primer tratado\n"),
+                ("44JHN.SFM", "\\id JHN\n\\c 1\n\\p \\v 1 Not this one\n"),
+            ],
+        );
+        let project = Project::load_paratext(&dir).expect("Project");
+        fs::remove_dir_all(&dir).expect("cleanup");
+
+        assert_eq!(project.diagnostics, []);
+        assert_eq!(project.len(), 2);
+        assert_eq!(
+            project.get("JHN").expect("JHN").to_string(),
+            "\\id JHN\n\\c 1\n\\zp\n\\v 1 En el principio\n"
+        );
+        assert!(project.markers().contains_key("zp"));
+        let reference = project
+            .names()
+            .reference("San Juan 1:1")
+            .expect("reference");
+        assert_eq!(reference.book.code, "JHN");
+
+        let settings = project.settings.expect("Settings");
+        assert_eq!(settings.name.as_deref(), Some("WEB"));
+        assert_eq!(settings.language.as_deref(), Some("es"));
+        assert_eq!(settings.versification, Some(Scheme::English));
+    }
+
+    #[test]
+    fn naming() {
+        let naming = Naming::default();
+        let file_name = |code| naming.file_name(books::get(code).unwrap());
+        assert_eq!(file_name("GEN"), "01GEN.SFM");
+        assert_eq!(file_name("MAL"), "39MAL.SFM");
+        assert_eq!(file_name("MAT"), "41MAT.SFM");
+        assert_eq!(file_name("FRT"), "A0FRT.SFM");
+        assert_eq!(file_name("GLO"), "A9GLO.SFM");
+        let settings: Settings = "<ScriptureText><Naming PrePart=\"x\" PostPart=\".usfm\" BookNameForm=\"MAT\" /></ScriptureText>"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            settings.naming.file_name(books::get("REV").unwrap()),
+            "xREV.usfm"
+        );
+        assert_eq!(settings.versification, None);
+    }
+}
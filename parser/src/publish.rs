@@ -0,0 +1,540 @@
+//! Turning a project into a publication: the files of an EPUB 3 book, one
+//! XHTML file to a chapter with each book's notes gathered at its end,
+//! written with [`Document::to_html`]'s markup. The files can be zipped
+//! into an `.epub` with the `epub` feature or handed to a print pipeline.
+
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    books::Book,
+    document::{Content, Document},
+    html::{self, Element, Endnotes},
+    project::Project,
+    usx::escape,
+};
+
+/// The stylesheet used unless [`Epub::css`] gives another.
+pub const DEFAULT_CSS: &str = "\
+body { font-family: serif; line-height: 1.5; }
+h1.usfm-mt1 { text-align: center; }
+h2.usfm-chapter-number { font-size: 1.2em; }
+sup.usfm-v { font-size: 0.7em; padding-right: 0.2em; }
+.usfm-q1, .usfm-q { margin: 0 0 0 2em; text-indent: -1em; }
+.usfm-q2 { margin: 0 0 0 3em; text-indent: -1em; }
+.usfm-nd { font-variant: small-caps; }
+a.usfm-caller { font-size: 0.7em; vertical-align: super; }
+aside { font-size: 0.9em; margin: 0.5em 0; }
+";
+
+/// A page outside the books of scripture, such as a title page or preface.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Page {
+    /// The name of the file, without `.xhtml`.
+    pub id: String,
+    /// Its title in the table of contents.
+    pub title: String,
+    /// The XHTML of its `<body>`.
+    pub body: String,
+}
+
+/// A file of the publication, with its path inside the EPUB container.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct File {
+    pub path: String,
+    pub contents: String,
+}
+
+type FrontMatter<'p> = Box<dyn Fn(&Project) -> Vec<Page> + 'p>;
+
+/// Builds an EPUB from the books of a project in canonical order. Front
+/// matter comes from the peripheral books `FRT` and `INT` unless
+/// [`Epub::front_matter`] is given; other peripheral books, such as a
+/// glossary, follow the books of scripture.
+pub struct Epub<'p> {
+    project: &'p Project,
+    title: String,
+    language: String,
+    identifier: String,
+    modified: Option<String>,
+    css: String,
+    elements: HashMap<String, Element>,
+    front_matter: FrontMatter<'p>,
+}
+
+impl<'p> Epub<'p> {
+    pub fn new(project: &'p Project) -> Self {
+        let name = project
+            .settings
+            .as_ref()
+            .and_then(|settings| settings.name.clone());
+        Epub {
+            project,
+            title: name.clone().unwrap_or_else(|| "Scripture".to_owned()),
+            language: project.language().unwrap_or("und").to_owned(),
+            identifier: format!("urn:usfm:{}", name.as_deref().unwrap_or("scripture")),
+            modified: None,
+            css: DEFAULT_CSS.to_owned(),
+            elements: HashMap::new(),
+            front_matter: Box::new(peripheral_front_matter),
+        }
+    }
+
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.title = title.into();
+        self
+    }
+
+    /// The BCP 47 tag of the text's language.
+    pub fn language(mut self, language: impl Into<String>) -> Self {
+        self.language = language.into();
+        self
+    }
+
+    /// A unique identifier for the publication, such as a URN or ISBN.
+    pub fn identifier(mut self, identifier: impl Into<String>) -> Self {
+        self.identifier = identifier.into();
+        self
+    }
+
+    /// When the publication was last changed, as `YYYY-MM-DDThh:mm:ssZ`.
+    /// Defaults to the time the files are made.
+    pub fn modified(mut self, modified: impl Into<String>) -> Self {
+        self.modified = Some(modified.into());
+        self
+    }
+
+    /// Replace the stylesheet, [`DEFAULT_CSS`].
+    pub fn css(mut self, css: impl Into<String>) -> Self {
+        self.css = css.into();
+        self
+    }
+
+    /// Render a marker with a different tag or class, as
+    /// [`Html::element`](crate::html::Html::element) does.
+    pub fn element(mut self, marker: &str, element: Element) -> Self {
+        self.elements.insert(marker.to_owned(), element);
+        self
+    }
+
+    /// Make the pages before the books of scripture, in place of those
+    /// made from `FRT` and `INT`.
+    pub fn front_matter(mut self, pages: impl Fn(&Project) -> Vec<Page> + 'p) -> Self {
+        self.front_matter = Box::new(pages);
+        self
+    }
+
+    /// Every file of the EPUB, `mimetype` first as the container needs.
+    pub fn files(&self) -> Vec<File> {
+        let mut files = Vec::new();
+        let mut spine = Vec::new();
+        let mut toc = String::new();
+        let page = |page: Page, files: &mut Vec<File>, spine: &mut Vec<String>| {
+            let path = format!("{}.xhtml", page.id);
+            files.push(File {
+                path: format!("OEBPS/{path}"),
+                contents: xhtml(&page.title, &self.language, &page.body),
+            });
+            spine.push(path);
+            page.title
+        };
+
+        for front in (self.front_matter)(self.project) {
+            let id = front.id.clone();
+            let title = page(front, &mut files, &mut spine);
+            let _ = writeln!(
+                toc,
+                "<li><a href=\"{id}.xhtml\">{}</a></li>",
+                escape(&title)
+            );
+        }
+        let (back, scripture): (Vec<_>, Vec<_>) = self
+            .project
+            .iter()
+            .filter(|(book, _)| !matches!(book.code, "FRT" | "INT"))
+            .partition(|(book, _)| book.is_peripheral());
+        for (book, doc) in scripture.into_iter().chain(back) {
+            let title = book_title(book, doc);
+            let mut chapters = String::new();
+            let mut endnotes = Endnotes {
+                href: format!("{}-notes.xhtml", book.code),
+                ..Endnotes::default()
+            };
+            for (id, label, content) in split_chapters(book, doc) {
+                let path = format!("{id}.xhtml");
+                endnotes.back.clone_from(&path);
+                let mut body = String::new();
+                if label.is_empty() {
+                    body = html::xhtml(content, &self.elements, &mut endnotes);
+                } else {
+                    let _ = writeln!(chapters, "<li><a href=\"{path}\">{label}</a></li>");
+                    for item in content {
+                        body +=
+                            &html::xhtml(std::slice::from_ref(item), &self.elements, &mut endnotes);
+                    }
+                }
+                let heading = match label.as_str() {
+                    "" => title.clone(),
+                    label => format!("{title} {label}"),
+                };
+                page(
+                    Page {
+                        id,
+                        title: heading,
+                        body,
+                    },
+                    &mut files,
+                    &mut spine,
+                );
+            }
+            let first = format!("{}-0.xhtml", book.code);
+            let first = match spine.contains(&first) {
+                true => first,
+                false => format!("{}-1.xhtml", book.code),
+            };
+            let _ = write!(toc, "<li><a href=\"{first}\">{}</a>", escape(&title));
+            if !chapters.is_empty() {
+                let _ = write!(toc, "\n<ol>\n{chapters}</ol>\n");
+            }
+            toc.push_str("</li>\n");
+            if !endnotes.notes.is_empty() {
+                let mut body = String::from("<section epub:type=\"endnotes\">\n");
+                for note in &endnotes.notes {
+                    body += note;
+                    body.push('\n');
+                }
+                body.push_str("</section>\n");
+                page(
+                    Page {
+                        id: format!("{}-notes", book.code),
+                        title: format!("{title}: Notes"),
+                        body,
+                    },
+                    &mut files,
+                    &mut spine,
+                );
+            }
+        }
+
+        files.push(File {
+            path: "OEBPS/nav.xhtml".into(),
+            contents: xhtml(
+                &self.title,
+                &self.language,
+                &format!("<nav epub:type=\"toc\" id=\"toc\">\n<ol>\n{toc}</ol>\n</nav>\n"),
+            ),
+        });
+        files.push(File {
+            path: "OEBPS/style.css".into(),
+            contents: self.css.clone(),
+        });
+        files.push(File {
+            path: "OEBPS/content.opf".into(),
+            contents: self.package(&spine),
+        });
+        files.insert(
+            0,
+            File {
+                path: "META-INF/container.xml".into(),
+                contents: CONTAINER.into(),
+            },
+        );
+        files.insert(
+            0,
+            File {
+                path: "mimetype".into(),
+                contents: "application/epub+zip".into(),
+            },
+        );
+        files
+    }
+
+    fn package(&self, spine: &[String]) -> String {
+        let modified = self.modified.clone().unwrap_or_else(now);
+        let mut manifest = String::from(
+            "<item id=\"nav\" href=\"nav.xhtml\" media-type=\"application/xhtml+xml\" \
+             properties=\"nav\"/>\n\
+             <item id=\"css\" href=\"style.css\" media-type=\"text/css\"/>\n",
+        );
+        let mut itemrefs = String::new();
+        for (n, path) in spine.iter().enumerate() {
+            let _ = writeln!(
+                manifest,
+                "<item id=\"f{n}\" href=\"{path}\" media-type=\"application/xhtml+xml\"/>"
+            );
+            let _ = writeln!(itemrefs, "<itemref idref=\"f{n}\"/>");
+        }
+        format!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+             <package xmlns=\"http://www.idpf.org/2007/opf\" version=\"3.0\" \
+             unique-identifier=\"id\">\n\
+             <metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\">\n\
+             <dc:identifier id=\"id\">{}</dc:identifier>\n\
+             <dc:title>{}</dc:title>\n\
+             <dc:language>{}</dc:language>\n\
+             <meta property=\"dcterms:modified\">{}</meta>\n\
+             </metadata>\n\
+             <manifest>\n{manifest}</manifest>\n\
+             <spine>\n{itemrefs}</spine>\n\
+             </package>\n",
+            escape(&self.identifier),
+            escape(&self.title),
+            escape(&self.language),
+            escape(&modified),
+        )
+    }
+
+    /// Zip the files into an `.epub`.
+    #[cfg(feature = "epub")]
+    pub fn write_to<W: std::io::Write + std::io::Seek>(&self, w: W) -> std::io::Result<()> {
+        use std::io::Write;
+        use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+        let mut zip = ZipWriter::new(w);
+        for file in self.files() {
+            // Readers find the type of the container uncompressed at the start.
+            let method = match file.path.as_str() {
+                "mimetype" => CompressionMethod::Stored,
+                _ => CompressionMethod::Deflated,
+            };
+            let options = SimpleFileOptions::default().compression_method(method);
+            zip.start_file(file.path, options)
+                .map_err(crate::usx::invalid)?;
+            zip.write_all(file.contents.as_bytes())?;
+        }
+        zip.finish().map_err(crate::usx::invalid)?;
+        Ok(())
+    }
+}
+
+const CONTAINER: &str = "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+<container version=\"1.0\" xmlns=\"urn:oasis:names:tc:opendocument:xmlns:container\">\n\
+<rootfiles>\n\
+<rootfile full-path=\"OEBPS/content.opf\" media-type=\"application/oebps-package+xml\"/>\n\
+</rootfiles>\n\
+</container>\n";
+
+fn xhtml(title: &str, language: &str, body: &str) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+         <!DOCTYPE html>\n\
+         <html xmlns=\"http://www.w3.org/1999/xhtml\" \
+         xmlns:epub=\"http://www.idpf.org/2007/ops\" lang=\"{language}\" xml:lang=\"{language}\">\n\
+         <head>\n<title>{}</title>\n\
+         <link rel=\"stylesheet\" type=\"text/css\" href=\"style.css\"/>\n</head>\n\
+         <body>\n{body}</body>\n</html>\n",
+        escape(title),
+        language = escape(language),
+    )
+}
+
+fn book_title(book: &Book, doc: &Document) -> String {
+    doc.header_text("toc2")
+        .or_else(|| doc.running_header())
+        .unwrap_or_else(|| book.name.to_owned())
+}
+
+// A page for each of `FRT` and `INT` in the project.
+fn peripheral_front_matter(project: &Project) -> Vec<Page> {
+    ["FRT", "INT"]
+        .into_iter()
+        .filter_map(|code| {
+            let doc = project.get(code)?;
+            let book = doc.book()?;
+            let content = doc.root().map_or(&[][..], |root| &root.content[..]);
+            let mut endnotes = Endnotes {
+                href: format!("{code}.xhtml"),
+                back: format!("{code}.xhtml"),
+                ..Endnotes::default()
+            };
+            let mut body = html::xhtml(content, &HashMap::new(), &mut endnotes);
+            for note in endnotes.notes {
+                body += &note;
+                body.push('\n');
+            }
+            Some(Page {
+                id: code.to_owned(),
+                title: book_title(book, doc),
+                body,
+            })
+        })
+        .collect()
+}
+
+// The material before the first chapter, if any, as `CODE-0`, then each
+// chapter as `CODE-n` with its number as label.
+fn split_chapters<'d>(
+    book: &Book,
+    doc: &'d Document<'d>,
+) -> Vec<(String, String, &'d [Content<'d>])> {
+    let Some(root) = doc.root() else {
+        return Vec::new();
+    };
+    let start = root
+        .content
+        .iter()
+        .position(|item| matches!(item, Content::Chapter(_)))
+        .unwrap_or(root.content.len());
+    let mut res = Vec::new();
+    let intro = &root.content[..start];
+    if intro.iter().any(|item| !matches!(item, Content::Book(_))) {
+        res.push((format!("{}-0", book.code), String::new(), intro));
+    }
+    let mut n = 0;
+    for (index, item) in root.content.iter().enumerate().skip(start) {
+        let Content::Chapter(chapter) = item else {
+            continue;
+        };
+        n += 1;
+        let label = chapter
+            .attributes
+            .get("pubnumber")
+            .or(chapter.attributes.get("number"))
+            .map_or_else(|| n.to_string(), |n| escape(n).into_owned());
+        res.push((
+            format!("{}-{n}", book.code),
+            label,
+            &root.content[index..=index],
+        ));
+    }
+    res
+}
+
+// The current UTC time as `YYYY-MM-DDThh:mm:ssZ`.
+fn now() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let (days, secs) = (secs / 86400, secs % 86400);
+    // Days to a civil date, after Howard Hinnant's algorithm.
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use std::{path::PathBuf, sync::Arc};
+
+    use super::{Epub, Page};
+    use crate::{
+        document::Document,
+        extension::{Extensions, Version},
+        project::Project,
+    };
+
+    fn project(books: &[&str]) -> Project {
+        let parsed = books.iter().map(|usfm| {
+            let doc = usfm.parse::<Document>().map(|doc| (doc, Vec::new()));
+            (PathBuf::new(), doc)
+        });
+        let markers = Arc::clone(Extensions::usfm_shared(Version::default()));
+        Project::from_parsed(parsed, markers).expect("Project")
+    }
+
+    #[test]
+    fn epub() {
+        let project = project(&[
+            "\\id MRK\n\\h Mark\n\\mt1 Mark\n\\c 1\n\
+             \\p \\v 1 The beginning\\f + \\ft Or origin\\f* of the gospel.\n\
+             \\c 2\n\\p \\v 1 Again~he\n",
+            "\\id FRT\n\\periph Title Page|id=\"title\"\n\\mt1 The Gospel\n",
+            "\\id GLO\n\\h Glossary\n\\p \\k Gospel\\k* good news\n",
+        ]);
+        let epub = Epub::new(&project)
+            .title("Gospel & Glossary")
+            .language("en")
+            .identifier("urn:test")
+            .modified("2024-01-01T00:00:00Z")
+            .css("body { color: black; }");
+        let files = epub.files();
+        let paths = files.iter().map(|f| f.path.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            [
+                "mimetype",
+                "META-INF/container.xml",
+                "OEBPS/FRT.xhtml",
+                "OEBPS/MRK-0.xhtml",
+                "OEBPS/MRK-1.xhtml",
+                "OEBPS/MRK-2.xhtml",
+                "OEBPS/MRK-notes.xhtml",
+                "OEBPS/GLO-0.xhtml",
+                "OEBPS/nav.xhtml",
+                "OEBPS/style.css",
+                "OEBPS/content.opf",
+            ]
+        );
+        let file = |path: &str| {
+            files
+                .iter()
+                .find(|f| f.path == path)
+                .map(|f| f.contents.as_str())
+                .expect("file")
+        };
+        assert!(file("OEBPS/MRK-1.xhtml").contains(
+            "The beginning<a class=\"usfm-caller\" epub:type=\"noteref\" id=\"ref-note-1\" \
+             href=\"MRK-notes.xhtml#note-1\">a</a> of the gospel."
+        ));
+        assert!(file("OEBPS/MRK-2.xhtml").contains("Again&#160;he"));
+        assert!(file("OEBPS/MRK-notes.xhtml").contains(
+            "<aside class=\"usfm-f\" data-caller=\"+\" id=\"note-1\" epub:type=\"endnote\">\
+             <a href=\"MRK-1.xhtml#ref-note-1\">a</a> <span class=\"usfm-ft\">Or origin</span></aside>"
+        ));
+        assert!(file("OEBPS/FRT.xhtml").contains("<h1 class=\"usfm-mt1\">The Gospel</h1>"));
+        assert_eq!(file("OEBPS/style.css"), "body { color: black; }");
+        let opf = file("OEBPS/content.opf");
+        assert!(opf.contains("<dc:title>Gospel &amp; Glossary</dc:title>"));
+        assert!(opf.contains("<meta property=\"dcterms:modified\">2024-01-01T00:00:00Z</meta>"));
+        assert!(opf.contains("<itemref idref=\"f0\"/>\n<itemref idref=\"f1\"/>"));
+        assert!(file("OEBPS/nav.xhtml").contains(
+            "<li><a href=\"MRK-0.xhtml\">Mark</a>\n<ol>\n\
+             <li><a href=\"MRK-1.xhtml\">1</a></li>\n\
+             <li><a href=\"MRK-2.xhtml\">2</a></li>\n</ol>\n</li>"
+        ));
+
+        let custom = Epub::new(&project).front_matter(|project| {
+            vec![Page {
+                id: "title".into(),
+                title: "Title".into(),
+                body: format!("<h1>{} books</h1>\n", project.len()),
+            }]
+        });
+        let files = custom.files();
+        assert_eq!(files[2].path, "OEBPS/title.xhtml");
+        assert!(files[2].contents.contains("<h1>3 books</h1>"));
+        assert!(super::now().ends_with('Z'));
+    }
+
+    #[cfg(feature = "epub")]
+    #[test]
+    fn zip() {
+        use std::io::{Cursor, Read};
+
+        let project = project(&["\\id MRK\n\\c 1\n\\p \\v 1 The beginning\n"]);
+        let mut out = Cursor::new(Vec::new());
+        Epub::new(&project).write_to(&mut out).expect("epub");
+        let mut archive = zip::ZipArchive::new(out).expect("zip");
+        let mut mimetype = String::new();
+        let mut first = archive.by_index(0).expect("mimetype");
+        assert_eq!(first.name(), "mimetype");
+        assert_eq!(first.compression(), zip::CompressionMethod::Stored);
+        first.read_to_string(&mut mimetype).expect("read");
+        assert_eq!(mimetype, "application/epub+zip");
+    }
+}
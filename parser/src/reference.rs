@@ -0,0 +1,629 @@
+//! Scripture references such as `JHN 3:16`, `GEN 1:1-2:3` and
+//! `MAT 5:3,5-7`, with book names looked up through [`BookNames`].
+
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    fmt::{self, Display},
+    io::{self, Read},
+    str::FromStr,
+    sync::OnceLock,
+};
+
+use nom::{
+    character::complete::{char, one_of, space0, u32},
+    combinator::{eof, opt},
+    error::{context, convert_error},
+    multi::separated_list1,
+    sequence::{delimited, pair, preceded, terminated},
+    Finish, Parser,
+};
+
+use super::Result;
+use crate::{
+    books::{self, Book, BOOKS},
+    document::{Content, Document, Position, State},
+    extension::{Category, Extensions},
+    versification::verse_range,
+    xml::{self, Xml},
+};
+
+/// A chapter, or a verse within one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Reference {
+    pub book: &'static Book,
+    pub chapter: u32,
+    pub verse: Option<u32>,
+}
+
+/// An inclusive span of references. A chapter reference at the end covers
+/// the whole of that chapter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct RefRange {
+    pub start: Reference,
+    pub end: Reference,
+}
+
+impl Reference {
+    pub fn new(book: &'static Book, chapter: u32, verse: Option<u32>) -> Self {
+        Reference {
+            book,
+            chapter,
+            verse,
+        }
+    }
+}
+
+impl From<Reference> for RefRange {
+    fn from(reference: Reference) -> Self {
+        RefRange {
+            start: reference,
+            end: reference,
+        }
+    }
+}
+
+impl RefRange {
+    pub fn contains(&self, reference: &Reference) -> bool {
+        let key = |r: &Reference| (r.book, r.chapter, r.verse.unwrap_or(0));
+        let end = (
+            self.end.book,
+            self.end.chapter,
+            self.end.verse.unwrap_or(u32::MAX),
+        );
+        key(&self.start) <= key(reference)
+            && (
+                reference.book,
+                reference.chapter,
+                reference.verse.unwrap_or(u32::MAX),
+            ) <= end
+    }
+}
+
+impl Display for Reference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.book.code, self.chapter)?;
+        match self.verse {
+            Some(verse) => write!(f, ":{verse}"),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Display for RefRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (start, end) = (&self.start, &self.end);
+        write!(f, "{start}")?;
+        if start == end {
+            return Ok(());
+        }
+        if start.book != end.book {
+            return write!(f, "-{end}");
+        }
+        match (start.verse, end.verse) {
+            (Some(_), Some(verse)) if start.chapter == end.chapter => write!(f, "-{verse}"),
+            (_, Some(verse)) => write!(f, "-{}:{verse}", end.chapter),
+            (_, None) => write!(f, "-{}", end.chapter),
+        }
+    }
+}
+
+/// Book names, abbreviations and codes, matched ignoring case, spaces and
+/// full stops. A name that matches nothing exactly may still be a unique
+/// prefix of a known name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BookNames {
+    names: HashMap<String, &'static Book>,
+}
+
+fn key(name: &str) -> String {
+    name.chars()
+        .filter(|c| !c.is_whitespace() && *c != '.')
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+impl Default for BookNames {
+    /// The book codes and their English names.
+    fn default() -> Self {
+        let mut res = BookNames {
+            names: HashMap::new(),
+        };
+        for book in &BOOKS {
+            res.insert(book.code, book);
+            res.insert(book.name, book);
+        }
+        res
+    }
+}
+
+impl BookNames {
+    pub fn english() -> &'static Self {
+        static NAMES: OnceLock<BookNames> = OnceLock::new();
+        NAMES.get_or_init(BookNames::default)
+    }
+
+    pub fn insert(&mut self, name: &str, book: &'static Book) {
+        self.names.insert(key(name), book);
+    }
+
+    /// Read the localized names in a Paratext project's `BookNames.xml`, on
+    /// top of the defaults.
+    pub fn from_paratext_xml<R: Read>(reader: R) -> io::Result<Self> {
+        let input = io::read_to_string(reader)?;
+        let (_, root) = xml::document(&input).finish().map_err(|e| {
+            io::Error::new(io::ErrorKind::InvalidData, convert_error(input.as_str(), e))
+        })?;
+        let mut res = BookNames::default();
+        for child in &root.children {
+            let Xml::Element(element) = child else {
+                continue;
+            };
+            let Some(book) = element.attribute("code").and_then(books::get) else {
+                continue;
+            };
+            for name in ["abbr", "short", "long"]
+                .into_iter()
+                .filter_map(|attr| element.attribute(attr))
+                .filter(|name| !name.is_empty())
+            {
+                res.insert(name, book);
+            }
+        }
+        Ok(res)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&'static Book> {
+        let name = key(name);
+        if name.is_empty() {
+            return None;
+        }
+        if let Some(book) = self.names.get(&name) {
+            return Some(book);
+        }
+        let matches = self
+            .names
+            .iter()
+            .filter(|(k, _)| k.starts_with(&name))
+            .map(|(_, book)| *book)
+            .collect::<HashSet<_>>();
+        match matches.into_iter().collect::<Vec<_>>()[..] {
+            [book] => Some(book),
+            _ => None,
+        }
+    }
+
+    pub fn reference(&self, s: &str) -> io::Result<Reference> {
+        match self.range(s)? {
+            RefRange { start, end } if start == end => Ok(start),
+            _ => Err(invalid(format!("expected a single reference: {s}"))),
+        }
+    }
+
+    pub fn range(&self, s: &str) -> io::Result<RefRange> {
+        match self.ranges(s)?[..] {
+            [range] => Ok(range),
+            _ => Err(invalid(format!("expected a single range: {s}"))),
+        }
+    }
+
+    /// Parse a comma separated list such as `MAT 5:3,5-7`. Bare numbers
+    /// after a verse are further verses in the same chapter.
+    pub fn ranges(&self, s: &str) -> io::Result<Vec<RefRange>> {
+        let s = s.trim();
+        let (name, locations) = split_book(s).ok_or_else(|| invalid(format!("no chapter: {s}")))?;
+        let book = self
+            .get(name)
+            .ok_or_else(|| invalid(format!("unknown book: {name}")))?;
+        let (_, locations) = terminated(ranges, space0.and(eof))
+            .parse(locations)
+            .finish()
+            .map_err(|e| invalid(convert_error(locations, e)))?;
+
+        let mut res = Vec::with_capacity(locations.len());
+        let mut context = None;
+        for (first, last) in locations {
+            let start = match first {
+                (chapter, Some(verse)) => Reference::new(book, chapter, Some(verse)),
+                (verse, None) => match context {
+                    Some(chapter) => Reference::new(book, chapter, Some(verse)),
+                    None => Reference::new(book, verse, None),
+                },
+            };
+            let end = match last {
+                Some((chapter, Some(verse))) => Reference::new(book, chapter, Some(verse)),
+                Some((n, None)) if start.verse.is_some() => {
+                    Reference::new(book, start.chapter, Some(n))
+                }
+                Some((chapter, None)) => Reference::new(book, chapter, None),
+                None => start,
+            };
+            if end.chapter < start.chapter
+                || end.chapter == start.chapter && end.verse < start.verse
+            {
+                return Err(invalid(format!("range ends before it starts: {s}")));
+            }
+            context = end.verse.map(|_| end.chapter);
+            res.push(RefRange { start, end });
+        }
+        Ok(res)
+    }
+}
+
+impl FromStr for Reference {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        BookNames::english().reference(s)
+    }
+}
+
+impl FromStr for RefRange {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        BookNames::english().range(s)
+    }
+}
+
+impl Document<'_> {
+    /// The text of a verse, or a whole chapter, without notes, figures or
+    /// headings. Verses combined in the source, such as `\v 16-17`, are
+    /// returned whole.
+    pub fn verse_text(&self, reference: &Reference) -> Option<String> {
+        self.range_text(&RefRange::from(*reference))
+    }
+
+    pub fn range_text(&self, range: &RefRange) -> Option<String> {
+        let mut extractor = Extractor {
+            range,
+            book: self.book()?,
+            markers: State::usfm_ext(),
+            chapter: 0,
+            verses: (0, 0),
+            found: false,
+            text: String::new(),
+        };
+        extractor.content(&self.nodes.as_ref()?.content);
+        extractor.found.then(|| {
+            extractor
+                .text
+                .split_ascii_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+    }
+}
+
+impl Document<'_> {
+    /// The verse a byte offset of the source falls in, or the chapter
+    /// before its first verse, or the book, as chapter 0, before its first
+    /// chapter. Text between verses, such as a heading, belongs to the
+    /// verse before it. `None` for a document not parsed from USFM, which
+    /// has no source spans.
+    pub fn position_to_reference(&self, offset: usize) -> Option<Reference> {
+        let book = self.book()?;
+        self.root()?.span?;
+        let mut reference = Reference::new(book, 0, None);
+        for (start, found, _) in self.reference_starts() {
+            if start.offset > offset {
+                break;
+            }
+            reference = found;
+        }
+        Some(reference)
+    }
+
+    /// Where the `\v` marker of a verse starts, or the `\c` marker of a
+    /// chapter for a reference without a verse. A verse of a combined
+    /// range such as `\v 16-17` is at the start of the range. `None` for a
+    /// document not parsed from USFM.
+    pub fn reference_to_position(&self, reference: &Reference) -> Option<Position> {
+        if self.book()? != reference.book {
+            return None;
+        }
+        self.reference_starts()
+            .into_iter()
+            .find(|(_, found, last)| {
+                found.chapter == reference.chapter
+                    && match (found.verse, reference.verse) {
+                        (None, None) => true,
+                        (Some(first), Some(verse)) => (first..=*last).contains(&verse),
+                        _ => false,
+                    }
+            })
+            .map(|(start, _, _)| start)
+    }
+
+    // Where each chapter and verse starts, in source order, with the last
+    // verse of a combined range.
+    fn reference_starts(&self) -> Vec<(Position, Reference, u32)> {
+        let (Some(book), Some(root)) = (self.book(), self.root()) else {
+            return Vec::new();
+        };
+        let mut starts = Vec::new();
+        for chapter in root.content.iter().filter_map(|item| match item {
+            Content::Chapter(node) => Some(node),
+            _ => None,
+        }) {
+            let Some(number) = chapter
+                .attributes
+                .get("number")
+                .and_then(|n| n.parse().ok())
+            else {
+                continue;
+            };
+            if let Some(span) = chapter.span {
+                starts.push((span.start, Reference::new(book, number, None), 0));
+            }
+            for verse in chapter.iter_verses() {
+                let range = verse.attributes.get("number").and_then(|n| verse_range(n));
+                if let (Some((first, last)), Some(span)) = (range, verse.span) {
+                    starts.push((span.start, Reference::new(book, number, Some(first)), last));
+                }
+            }
+        }
+        starts
+    }
+}
+
+struct Extractor<'r> {
+    range: &'r RefRange,
+    book: &'static Book,
+    markers: &'static Extensions,
+    chapter: u32,
+    verses: (u32, u32),
+    found: bool,
+    text: String,
+}
+
+impl Extractor<'_> {
+    // Whether the current verses overlap the verses of this chapter that
+    // the range covers.
+    fn in_range(&self) -> bool {
+        let (first, last) = self.verses;
+        let (start, end) = (&self.range.start, &self.range.end);
+        let chapter = (self.book, self.chapter);
+        let range_first = match (start.book, start.chapter).cmp(&chapter) {
+            Ordering::Less => 0,
+            Ordering::Equal => start.verse.unwrap_or(0),
+            Ordering::Greater => return false,
+        };
+        let range_last = match (end.book, end.chapter).cmp(&chapter) {
+            Ordering::Greater => u32::MAX,
+            Ordering::Equal => end.verse.unwrap_or(u32::MAX),
+            Ordering::Less => return false,
+        };
+        first <= range_last && last >= range_first
+    }
+
+    fn is_heading(&self, style: &str) -> bool {
+        self.markers.get(style).is_some_and(|marker| {
+            matches!(
+                marker.category,
+                Category::SectionPara | Category::Title | Category::Header | Category::Introduction
+            )
+        })
+    }
+
+    fn content(&mut self, content: &[Content]) {
+        for item in content {
+            match item {
+                Content::Text(text) if self.in_range() => self.text.push_str(text.as_str()),
+                Content::NoBreakSpace if self.in_range() => self.text.push('\u{a0}'),
+                Content::Chapter(node) => {
+                    self.chapter = node
+                        .attributes
+                        .get("number")
+                        .and_then(|n| n.parse().ok())
+                        .unwrap_or(self.chapter);
+                    self.verses = (0, 0);
+                    self.content(&node.content);
+                }
+                Content::Verse(node) => {
+                    if let Some(verses) = node.attributes.get("number").and_then(|n| verse_range(n))
+                    {
+                        self.verses = verses;
+                    }
+                    if self.in_range() {
+                        self.found = true;
+                        self.text.push(' ');
+                    }
+                }
+                Content::Note(_) | Content::Figure(_) => {}
+                Content::Para(node) if self.is_heading(&node.style) => {}
+                Content::Para(node) => {
+                    self.text.push(' ');
+                    self.content(&node.content);
+                    self.text.push(' ');
+                }
+                item => {
+                    if let Some(node) = item.node() {
+                        self.content(&node.content);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// The book name runs up to the first number that follows a space, so that
+// names such as `1 John` keep their leading digit.
+fn split_book(s: &str) -> Option<(&str, &str)> {
+    s.char_indices()
+        .zip(s.chars().skip(1))
+        .find(|((_, c), next)| c.is_whitespace() && next.is_ascii_digit())
+        .map(|((i, _), _)| (&s[..i], s[i..].trim_start()))
+}
+
+type Location = (u32, Option<u32>);
+
+fn location(input: &str) -> Result<'_, Location> {
+    pair(u32, opt(preceded(char(':'), u32))).parse(input)
+}
+
+fn ranges(input: &str) -> Result<'_, Vec<(Location, Option<Location>)>> {
+    let separator = delimited(space0, char(','), space0);
+    let dash = delimited(space0, one_of("-\u{2013}"), space0);
+    context(
+        "chapter and verse",
+        separated_list1(separator, pair(location, opt(preceded(dash, location)))),
+    )
+    .parse(input)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{BookNames, RefRange, Reference};
+    use crate::{books, document::Document};
+
+    fn reference(code: &str, chapter: u32, verse: Option<u32>) -> Reference {
+        Reference::new(books::get(code).unwrap(), chapter, verse)
+    }
+
+    #[test]
+    fn parse_reference() {
+        assert_eq!(
+            "JHN 3:16".parse::<Reference>().unwrap(),
+            reference("JHN", 3, Some(16))
+        );
+        assert_eq!(
+            "1 John 2".parse::<Reference>().unwrap(),
+            reference("1JN", 2, None)
+        );
+        assert_eq!(
+            "Matt. 5:3".parse::<Reference>().unwrap(),
+            reference("MAT", 5, Some(3))
+        );
+        assert!("Jo 1:1".parse::<Reference>().is_err());
+        assert!("XYZ 1:1".parse::<Reference>().is_err());
+        assert!("JHN".parse::<Reference>().is_err());
+        assert!("JHN 3:16-17".parse::<Reference>().is_err());
+        assert!("JHN 3:x".parse::<Reference>().is_err());
+    }
+
+    #[test]
+    fn parse_ranges() {
+        let names = BookNames::english();
+        let range: RefRange = "GEN 1:1-2:3".parse().unwrap();
+        assert_eq!(range.start, reference("GEN", 1, Some(1)));
+        assert_eq!(range.end, reference("GEN", 2, Some(3)));
+        assert!(range.contains(&reference("GEN", 1, Some(31))));
+        assert!(!range.contains(&reference("GEN", 2, None)));
+        let chapters: RefRange = "GEN 1-2".parse().unwrap();
+        assert!(chapters.contains(&reference("GEN", 2, Some(25))));
+        assert!(chapters.contains(&reference("GEN", 2, None)));
+        assert!(!range.contains(&reference("GEN", 2, Some(4))));
+        assert!("GEN 2:3-1:1".parse::<RefRange>().is_err());
+
+        let ranges = names
+            .ranges("MAT 5:3, 5-7, 6:1")
+            .unwrap()
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        assert_eq!(ranges, ["MAT 5:3", "MAT 5:5-7", "MAT 6:1"]);
+
+        let ranges = names
+            .ranges("Psalms 23-24,1-2:3")
+            .unwrap()
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        assert_eq!(ranges, ["PSA 23-24", "PSA 1-2:3"]);
+    }
+
+    #[test]
+    fn localized_names() {
+        let names = BookNames::from_paratext_xml(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+             <BookNames>\n\
+               <book code=\"JHN\" abbr=\"Jn\" short=\"Juan\" long=\"San Juan\" />\n\
+               <book code=\"1JN\" abbr=\"1 Jn\" short=\"1 Juan\" long=\"\" />\n\
+             </BookNames>\n"
+                .as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(
+            names.reference("San Juan 3:16").unwrap(),
+            reference("JHN", 3, Some(16))
+        );
+        assert_eq!(
+            names.reference("1 Jn 1:9").unwrap(),
+            reference("1JN", 1, Some(9))
+        );
+        assert_eq!(names.reference("JHN 1").unwrap(), reference("JHN", 1, None));
+    }
+
+    #[test]
+    fn verse_text() {
+        let doc: Document = "\\id MAT\n\
+                             \\c 5\n\
+                             \\s1 The Beatitudes\n\
+                             \\p \\v 1 Seeing the crowds,\\f + \\fr 5:1 \\ft Or \\fq multitudes\\f* he went up.\n\
+                             \\q1 \\v 2 And he \\w taught|lemma=\"teach\"\\w* them,\n\
+                             \\q2 saying: \\v 3-4 Blessed\\x - \\xo 5:3 \\xt LUK 6:20\\x* are the poor.\n\
+                             \\c 6\n\
+                             \\p \\v 1 Beware.\n"
+            .parse()
+            .expect("Document");
+        let text = |s: &str| doc.range_text(&s.parse().unwrap());
+        assert_eq!(
+            text("MAT 5:1").as_deref(),
+            Some("Seeing the crowds, he went up.")
+        );
+        assert_eq!(
+            text("MAT 5:2").as_deref(),
+            Some("And he taught them, saying:")
+        );
+        assert_eq!(text("MAT 5:4").as_deref(), Some("Blessed are the poor."));
+        assert_eq!(
+            text("MAT 5:3-6:1").as_deref(),
+            Some("Blessed are the poor. Beware.")
+        );
+        assert_eq!(text("MAT 6").as_deref(), Some("Beware."));
+        assert_eq!(text("MAT 5:5"), None);
+        assert_eq!(text("MRK 5:1"), None);
+
+        let doc: Document = "\\id MAT\n\\c 5\n\\p \\v 1-4000000000 Long.\n"
+            .parse()
+            .expect("Document");
+        let text = |s: &str| doc.range_text(&s.parse().unwrap());
+        assert_eq!(text("MAT 5:3999999999").as_deref(), Some("Long."));
+        assert_eq!(text("MAT 4-5:2").as_deref(), Some("Long."));
+        assert_eq!(text("MAT 6"), None);
+    }
+
+    #[test]
+    fn positions() {
+        let source = "\\id MAT\n\\h Matthew\n\\c 5\n\\s1 The Beatitudes\n\\p \\v 1 Seeing\n\
+                      \\q1 \\v 2-3 And he\n\\s2 Salt\n\\p more\n\\c 6\n\\p \\v 1 Beware.\n";
+        let doc = source.parse::<Document>().expect("Document");
+        let at = |s: &str| doc.position_to_reference(source.find(s).unwrap());
+        assert_eq!(at("Matthew"), Some(reference("MAT", 0, None)));
+        assert_eq!(at("\\c 5"), Some(reference("MAT", 5, None)));
+        assert_eq!(at("Beatitudes"), Some(reference("MAT", 5, None)));
+        assert_eq!(at("Seeing"), Some(reference("MAT", 5, Some(1))));
+        assert_eq!(at("And he"), Some(reference("MAT", 5, Some(2))));
+        assert_eq!(at("Salt"), Some(reference("MAT", 5, Some(2))));
+        assert_eq!(at("Beware"), Some(reference("MAT", 6, Some(1))));
+
+        let position = |r: Reference| doc.reference_to_position(&r).map(|p| (p.line, p.column));
+        assert_eq!(position(reference("MAT", 5, None)), Some((3, 1)));
+        assert_eq!(position(reference("MAT", 5, Some(1))), Some((5, 4)));
+        assert_eq!(position(reference("MAT", 5, Some(3))), Some((6, 5)));
+        assert_eq!(position(reference("MAT", 6, Some(1))), Some((10, 4)));
+        assert_eq!(position(reference("MAT", 6, Some(2))), None);
+        assert_eq!(position(reference("MRK", 5, None)), None);
+
+        let mut plain: Document = source.parse().expect("Document");
+        plain.clear_spans();
+        assert_eq!(plain.position_to_reference(0), None);
+    }
+}
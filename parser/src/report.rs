@@ -0,0 +1,246 @@
+//! Diagnostics as reports for other tools: a SARIF 2.1.0 log for code
+//! scanning services, and JSON lines, one diagnostic to a line, for
+//! scripts and pipelines.
+//!
+//! Both give each diagnostic its code as a rule id, its file, its span as
+//! lines and columns counted in characters from 1, with the byte range
+//! beside them, and the fix it suggests if any.
+
+use std::io::{self, Write};
+
+use serde_json::{json, Value};
+
+use crate::{
+    diagnostic::{Code, Diagnostic, Severity},
+    document::{Position, Span},
+};
+
+const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+impl Diagnostic {
+    /// The diagnostic as a JSON object, found in the file at `path`.
+    pub fn to_json(&self, path: &str) -> Value {
+        let position =
+            |p: Position| json!({"offset": p.offset, "line": p.line, "column": p.column});
+        let mut json = json!({
+            "file": path,
+            "code": self.code.as_str(),
+            "severity": self.severity.to_string(),
+            "message": self.message,
+            "start": position(self.span.start),
+            "end": position(self.span.end),
+        });
+        if let Some(fix) = &self.fix {
+            json["fix"] = json!({
+                "start": position(fix.span.start),
+                "end": position(fix.span.end),
+                "replacement": fix.replacement,
+            });
+        }
+        json
+    }
+}
+
+/// Write the diagnostics of one file as JSON lines.
+pub fn write_json_lines<W: Write>(
+    mut out: W,
+    path: &str,
+    diagnostics: &[Diagnostic],
+) -> io::Result<()> {
+    for diagnostic in diagnostics {
+        writeln!(out, "{}", diagnostic.to_json(path))?;
+    }
+    Ok(())
+}
+
+/// A SARIF log of one run over the given files and their diagnostics. The
+/// rules are the codes that came up, in the order they first did.
+pub fn sarif<'d>(files: impl IntoIterator<Item = (&'d str, &'d [Diagnostic])>) -> Value {
+    let mut rules = Vec::<Code>::new();
+    let mut results = Vec::new();
+    for (path, diagnostics) in files {
+        for diagnostic in diagnostics {
+            let index = match rules.iter().position(|&code| code == diagnostic.code) {
+                Some(index) => index,
+                None => {
+                    rules.push(diagnostic.code);
+                    rules.len() - 1
+                }
+            };
+            let mut result = json!({
+                "ruleId": diagnostic.code.as_str(),
+                "ruleIndex": index,
+                "level": level(diagnostic.severity),
+                "message": {"text": diagnostic.message},
+                "locations": [{
+                    "physicalLocation": {
+                        "artifactLocation": {"uri": uri(path)},
+                        "region": region(diagnostic.span),
+                    },
+                }],
+            });
+            if let Some(fix) = &diagnostic.fix {
+                result["fixes"] = json!([{
+                    "description": {"text": format!("{fix}")},
+                    "artifactChanges": [{
+                        "artifactLocation": {"uri": uri(path)},
+                        "replacements": [{
+                            "deletedRegion": region(fix.span),
+                            "insertedContent": {"text": fix.replacement},
+                        }],
+                    }],
+                }]);
+            }
+            results.push(result);
+        }
+    }
+    let rules = rules
+        .iter()
+        .map(|code| {
+            json!({
+                "id": code.as_str(),
+                "shortDescription": {"text": code.description()},
+            })
+        })
+        .collect::<Vec<_>>();
+    json!({
+        "$schema": SARIF_SCHEMA,
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "usfm",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "rules": rules,
+                },
+            },
+            "columnKind": "unicodeCodePoints",
+            "results": results,
+        }],
+    })
+}
+
+fn region(span: Span) -> Value {
+    json!({
+        "startLine": span.start.line,
+        "startColumn": span.start.column,
+        "endLine": span.end.line,
+        "endColumn": span.end.column,
+        "byteOffset": span.start.offset,
+        "byteLength": span.end.offset - span.start.offset,
+    })
+}
+
+fn level(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+        Severity::Info => "note",
+    }
+}
+
+// A relative URI for a path, with forward slashes and anything outside the
+// unreserved characters percent encoded.
+fn uri(path: &str) -> String {
+    let mut uri = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        match byte {
+            b'\\' => uri.push('/'),
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                uri.push(byte as char)
+            }
+            _ => uri.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    uri
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::{sarif, write_json_lines};
+    use crate::{
+        diagnostic::{Code, Diagnostic},
+        document::{Position, Span},
+    };
+
+    #[test]
+    fn reports() {
+        let span = Span {
+            start: Position {
+                offset: 23,
+                line: 3,
+                column: 11,
+            },
+            end: Position {
+                offset: 27,
+                line: 3,
+                column: 15,
+            },
+        };
+        let unknown = Diagnostic::error(Code::UnknownMarker, span, "unknown marker \\xyz");
+        let warning = Diagnostic::warning(unknown.code, unknown.span, "again").with_fix(
+            Span {
+                start: span.end,
+                ..span
+            },
+            "*",
+        );
+
+        let mut out = Vec::new();
+        write_json_lines(&mut out, "book.usfm", &[unknown.clone(), warning.clone()])
+            .expect("write");
+        let out = String::from_utf8(out).expect("UTF-8");
+        let lines = out.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[1].contains(r#""replacement":"*""#), "{}", lines[1]);
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(lines[0]).expect("JSON"),
+            json!({
+                "file": "book.usfm",
+                "code": "unknown-marker",
+                "severity": "error",
+                "message": "unknown marker \\xyz",
+                "start": {"offset": 23, "line": 3, "column": 11},
+                "end": {"offset": 27, "line": 3, "column": 15},
+            })
+        );
+
+        let diagnostics = [unknown, warning];
+        let log = sarif([("my books\\MRK.usfm", &diagnostics[..])]);
+        let run = &log["runs"][0];
+        assert_eq!(log["version"], "2.1.0");
+        assert_eq!(
+            run["tool"]["driver"]["rules"],
+            json!([{
+                "id": "unknown-marker",
+                "shortDescription": {"text": "A marker not defined by the marker set."},
+            }])
+        );
+        let results = run["results"].as_array().expect("results");
+        assert_eq!(
+            results.iter().map(|r| &r["level"]).collect::<Vec<_>>(),
+            ["error", "warning"]
+        );
+        assert_eq!(results[1]["ruleIndex"], 0);
+        assert!(results[0].get("fixes").is_none());
+        assert_eq!(
+            results[1]["fixes"][0]["artifactChanges"][0]["replacements"][0]["insertedContent"],
+            json!({"text": "*"})
+        );
+        let location = &results[0]["locations"][0]["physicalLocation"];
+        assert_eq!(location["artifactLocation"]["uri"], "my%20books/MRK.usfm");
+        assert_eq!(
+            location["region"],
+            json!({
+                "startLine": 3,
+                "startColumn": 11,
+                "endLine": 3,
+                "endColumn": 15,
+                "byteOffset": 23,
+                "byteLength": 4,
+            })
+        );
+    }
+}
@@ -0,0 +1,228 @@
+//! Finding text in a document with the markers taken out, reporting where
+//! each hit is in the USFM source so an editor can highlight it.
+
+use std::ops::Range;
+
+#[cfg(feature = "regex")]
+use regex::Regex;
+
+use crate::{
+    checks::Passage,
+    document::{Document, Span, State},
+    extension::Category,
+};
+
+/// What to search besides the body text. The default is the body text
+/// alone, as for [`crate::plain::PlainTextOptions`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SearchOptions {
+    /// Footnotes and cross references.
+    pub notes: bool,
+    /// Titles and section headings.
+    pub headings: bool,
+    /// Match text patterns ignoring case. Regular expressions ask for this
+    /// themselves with `(?i)`.
+    pub case_insensitive: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Pattern<'p> {
+    Text(&'p str),
+    #[cfg(feature = "regex")]
+    Regex(&'p Regex),
+}
+
+impl<'p> From<&'p str> for Pattern<'p> {
+    fn from(text: &'p str) -> Self {
+        Pattern::Text(text)
+    }
+}
+
+#[cfg(feature = "regex")]
+impl<'p> From<&'p Regex> for Pattern<'p> {
+    fn from(regex: &'p Regex) -> Self {
+        Pattern::Regex(regex)
+    }
+}
+
+/// A match, which never runs from one paragraph or note into another.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hit {
+    /// The text matched, with the markers taken out.
+    pub text: String,
+    /// Where the text is in the source, markers inside it included. Only
+    /// set for documents parsed from USFM.
+    pub span: Option<Span>,
+}
+
+impl Document<'_> {
+    /// Every match of `pattern` in source order. Matches do not overlap.
+    pub fn search<'p>(&self, pattern: impl Into<Pattern<'p>>, options: SearchOptions) -> Vec<Hit> {
+        let pattern = pattern.into();
+        let located = self.root().is_some_and(|root| root.span.is_some());
+        let markers = State::usfm_ext();
+        let mut hits = Vec::new();
+        for passage in self.passages() {
+            let category = markers.category(&passage.style);
+            let included = match category {
+                Some(Category::Footnote | Category::Crossreference) => options.notes,
+                Some(Category::Title | Category::SectionPara) => options.headings,
+                _ => true,
+            };
+            if !included {
+                continue;
+            }
+            for range in matches(&passage, pattern, options) {
+                hits.push(Hit {
+                    text: passage.text[range.clone()].to_owned(),
+                    span: located.then(|| span(&passage, range)),
+                });
+            }
+        }
+        hits.sort_by_key(|hit| hit.span.map(|span| span.start));
+        hits
+    }
+}
+
+fn matches(passage: &Passage, pattern: Pattern, options: SearchOptions) -> Vec<Range<usize>> {
+    match pattern {
+        Pattern::Text("") => Vec::new(),
+        Pattern::Text(text) if !options.case_insensitive => passage
+            .text
+            .match_indices(text)
+            .map(|(n, s)| n..n + s.len())
+            .collect(),
+        Pattern::Text(text) => {
+            let mut res = Vec::new();
+            let mut from = 0;
+            while let Some(range) = find_folded(&passage.text, text, from) {
+                from = range.end;
+                res.push(range);
+            }
+            res
+        }
+        #[cfg(feature = "regex")]
+        Pattern::Regex(regex) => regex
+            .find_iter(&passage.text)
+            .filter(|m| !m.is_empty())
+            .map(|m| m.range())
+            .collect(),
+    }
+}
+
+// The source span of a match, ending just after its last character rather
+// than at whatever follows it, such as an end marker.
+fn span(passage: &Passage, range: Range<usize>) -> Span {
+    let (n, _) = passage.text[range.clone()]
+        .char_indices()
+        .last()
+        .unwrap_or_default();
+    Span {
+        start: passage.span(range.clone()).start,
+        end: passage.char_span(range.start + n).end,
+    }
+}
+
+// The first match of `pattern` in `text` at or after `from`, comparing
+// lower case. Folding a character at a time keeps byte offsets into the
+// original text.
+fn find_folded(text: &str, pattern: &str, from: usize) -> Option<Range<usize>> {
+    let pattern = pattern
+        .chars()
+        .flat_map(char::to_lowercase)
+        .collect::<Vec<_>>();
+    text[from..].char_indices().find_map(|(start, _)| {
+        let start = from + start;
+        let mut want = pattern.iter();
+        for (n, c) in text[start..].char_indices() {
+            for folded in c.to_lowercase() {
+                if want.next() != Some(&folded) {
+                    return None;
+                }
+            }
+            if want.len() == 0 {
+                return Some(start..start + n + c.len_utf8());
+            }
+        }
+        None
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::SearchOptions;
+    use crate::document::Document;
+
+    #[test]
+    fn search() {
+        let source = "\\id MRK\n\\c 1\n\\s1 The Lord's voice\n\
+                      \\p \\v 1 The voice of the \\nd Lord\\nd*,\\f + \\ft lord of all\\f* \
+                      LORD of all.\n";
+        let doc = source.parse::<Document>().expect("Document");
+        let found = |pattern, options| {
+            doc.search(pattern, options)
+                .into_iter()
+                .map(|hit| {
+                    let span = hit.span.expect("span");
+                    (hit.text, &source[span.range()])
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            found("Lord", SearchOptions::default()),
+            [("Lord".to_owned(), "Lord")]
+        );
+        assert_eq!(
+            found("the lord", SearchOptions::default()),
+            Vec::<(String, &str)>::new()
+        );
+        let all = SearchOptions {
+            notes: true,
+            headings: true,
+            case_insensitive: true,
+        };
+        assert_eq!(
+            found("the lord", all),
+            [
+                ("The Lord".to_owned(), "The Lord"),
+                ("the Lord".to_owned(), "the \\nd Lord"),
+            ]
+        );
+        assert_eq!(found("lord of", all).len(), 2);
+
+        let mut doc: Document = source.parse().expect("Document");
+        doc.clear_spans();
+        let hits = doc.search("voice", SearchOptions::default());
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].span, None);
+    }
+
+    #[test]
+    fn search_crlf() {
+        let source = "\\id MRK\r\n\\c 1\r\n\\p \\v 1 one\r\ntwo\r\nthree\r\n";
+        let doc = source.parse::<Document>().expect("Document");
+        let found = |pattern| {
+            doc.search(pattern, SearchOptions::default())
+                .into_iter()
+                .map(|hit| &source[hit.span.expect("span").range()])
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(found("three"), ["three"]);
+        assert_eq!(found("one\n"), ["one\r\n"]);
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn search_regex() {
+        let source = "\\id MRK\n\\c 1\n\\p \\v 1 one \\v 2 two three\n";
+        let doc = source.parse::<Document>().expect("Document");
+        let regex = regex::Regex::new(r"t\w+").expect("Regex");
+        let hits = doc.search(&regex, SearchOptions::default());
+        assert_eq!(
+            hits.iter()
+                .map(|hit| &source[hit.span.expect("span").range()])
+                .collect::<Vec<_>>(),
+            ["two", "three"]
+        );
+    }
+}
@@ -0,0 +1,281 @@
+//! Exporting a project to SQLite, for reader apps that query the text
+//! without parsing USFM themselves.
+//!
+//! The database has four tables, all keyed by book code and chapter and
+//! verse numbers:
+//!
+//! - `books(code, number, name, short_name, abbreviation)`
+//! - `chapters(book, chapter, verses)`
+//! - `verses(book, chapter, verse, reference, text)`
+//! - `notes(id, book, chapter, verse, reference, style, caller, text)`
+//!
+//! Verse text is plain, without notes or headings. The text of a combined
+//! verse such as `\v 16-17` is given under its first verse and the rest
+//! are left empty. Notes before the first verse of a chapter have a null
+//! verse.
+
+use rusqlite::{params, Connection};
+
+use crate::{
+    books::Book,
+    document::{Content, Document, Node},
+    project::Project,
+    reference::Reference,
+    versification::verse_range,
+};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS books (
+    code TEXT PRIMARY KEY,
+    number INTEGER NOT NULL,
+    name TEXT,
+    short_name TEXT,
+    abbreviation TEXT
+);
+CREATE TABLE IF NOT EXISTS chapters (
+    book TEXT NOT NULL REFERENCES books (code),
+    chapter INTEGER NOT NULL,
+    verses INTEGER NOT NULL,
+    PRIMARY KEY (book, chapter)
+);
+CREATE TABLE IF NOT EXISTS verses (
+    book TEXT NOT NULL REFERENCES books (code),
+    chapter INTEGER NOT NULL,
+    verse INTEGER NOT NULL,
+    reference TEXT NOT NULL,
+    text TEXT NOT NULL,
+    PRIMARY KEY (book, chapter, verse)
+);
+CREATE TABLE IF NOT EXISTS notes (
+    id INTEGER PRIMARY KEY,
+    book TEXT NOT NULL REFERENCES books (code),
+    chapter INTEGER NOT NULL,
+    verse INTEGER,
+    reference TEXT NOT NULL,
+    style TEXT NOT NULL,
+    caller TEXT,
+    text TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS notes_verse ON notes (book, chapter, verse);
+";
+
+impl Project {
+    /// Write every book into the database in one transaction, creating the
+    /// tables if they are missing. A book already in the database is
+    /// replaced.
+    pub fn to_sqlite(&self, conn: &mut Connection) -> rusqlite::Result<()> {
+        let tx = conn.transaction()?;
+        tx.execute_batch(SCHEMA)?;
+        for (book, doc) in self.iter() {
+            for table in ["notes", "verses", "chapters", "books"] {
+                let column = if table == "books" { "code" } else { "book" };
+                tx.execute(
+                    &format!("DELETE FROM {table} WHERE {column} = ?1"),
+                    [book.code],
+                )?;
+            }
+            write_book(&tx, book, doc)?;
+        }
+        tx.commit()
+    }
+}
+
+fn write_book(conn: &Connection, book: &'static Book, doc: &Document) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO books (code, number, name, short_name, abbreviation)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![
+            book.code,
+            book.number,
+            doc.header_text("toc1").or_else(|| doc.running_header()),
+            doc.header_text("toc2").or_else(|| doc.running_header()),
+            doc.header_text("toc3"),
+        ],
+    )?;
+
+    let mut verse = conn.prepare(
+        "INSERT OR IGNORE INTO verses (book, chapter, verse, reference, text)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+    )?;
+    let mut chapters = Vec::<(u32, u32)>::new();
+    for (reference, text) in doc.verse_texts() {
+        let Some(number) = reference.verse else {
+            continue;
+        };
+        match chapters.last_mut() {
+            Some((chapter, count)) if *chapter == reference.chapter => *count += 1,
+            _ => chapters.push((reference.chapter, 1)),
+        }
+        verse.execute(params![
+            book.code,
+            reference.chapter,
+            number,
+            reference.to_string(),
+            text
+        ])?;
+    }
+    let mut chapter =
+        conn.prepare("INSERT OR IGNORE INTO chapters (book, chapter, verses) VALUES (?1, ?2, ?3)")?;
+    for (number, verses) in chapters {
+        chapter.execute(params![book.code, number, verses])?;
+    }
+
+    let mut note = conn.prepare(
+        "INSERT INTO notes (book, chapter, verse, reference, style, caller, text)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+    )?;
+    let mut notes = Notes::default();
+    if let Some(root) = doc.root() {
+        notes.content(&root.content);
+    }
+    for (chapter, verse, node) in notes.notes {
+        let reference = Reference {
+            book,
+            chapter,
+            verse,
+        };
+        note.execute(params![
+            book.code,
+            chapter,
+            verse,
+            reference.to_string(),
+            node.style.as_ref(),
+            node.attributes.get("caller").map(|caller| caller.as_ref()),
+            note_text(node),
+        ])?;
+    }
+    Ok(())
+}
+
+// The notes of a book with the chapter and verse they are in.
+#[derive(Default)]
+struct Notes<'d> {
+    chapter: u32,
+    verse: Option<u32>,
+    notes: Vec<(u32, Option<u32>, &'d Node<'d>)>,
+}
+
+impl<'d> Notes<'d> {
+    fn content(&mut self, content: &'d [Content<'d>]) {
+        for item in content {
+            match item {
+                Content::Chapter(node) => {
+                    self.chapter = node
+                        .attributes
+                        .get("number")
+                        .and_then(|n| n.parse().ok())
+                        .unwrap_or(self.chapter);
+                    self.verse = None;
+                    self.content(&node.content);
+                }
+                Content::Verse(node) => {
+                    self.verse = node
+                        .attributes
+                        .get("number")
+                        .and_then(|n| verse_range(n))
+                        .map(|(first, _)| first);
+                }
+                Content::Note(node) => self.notes.push((self.chapter, self.verse, node)),
+                item => {
+                    if let Some(node) = item.node() {
+                        self.content(&node.content);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// The text of a note without the reference it starts with.
+fn note_text(note: &Node) -> String {
+    let text = note
+        .content
+        .iter()
+        .map(|item| match item {
+            Content::Text(text) => text.as_str().to_owned(),
+            Content::NoBreakSpace => "\u{a0}".to_owned(),
+            Content::Char(node) if matches!(node.style.as_ref(), "fr" | "xo") => String::new(),
+            item => item.node().map(Node::text).unwrap_or_default(),
+        })
+        .collect::<String>();
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod test {
+    use std::{path::PathBuf, sync::Arc};
+
+    use rusqlite::Connection;
+
+    use crate::{
+        document::Document,
+        extension::{Extensions, Version},
+        project::Project,
+    };
+
+    #[test]
+    fn export() {
+        let books = [
+            "\\id MRK\n\\h Mark\n\\toc1 The Gospel of Mark\n\\toc3 Mk\n\\c 1\n\
+             \\p \\v 1 The beginning\\f + \\fr 1:1 \\ft Some add \\fq Son of God\\f*.\n\
+             \\v 2-3 As it is written\n\\c 2\n\\s1 Healing\\x - \\xo 2:0 \\xt Luke 5\\x*\n\
+             \\p \\v 1 Again\n",
+            "\\id JHN\n\\c 1\n\\p \\v 1 In the beginning\n",
+        ];
+        let parsed = books.iter().map(|usfm| {
+            let doc = usfm.parse::<Document>().map(|doc| (doc, Vec::new()));
+            (PathBuf::new(), doc)
+        });
+        let markers = Arc::clone(Extensions::usfm_shared(Version::default()));
+        let project = Project::from_parsed(parsed, markers).expect("Project");
+
+        let mut conn = Connection::open_in_memory().expect("SQLite");
+        project.to_sqlite(&mut conn).expect("export");
+        // Exporting again replaces the books.
+        project.to_sqlite(&mut conn).expect("export");
+
+        let rows = |sql: &str| {
+            let mut statement = conn.prepare(sql).expect("statement");
+            let count = statement.column_count();
+            statement
+                .query_map([], |row| {
+                    (0..count)
+                        .map(|n| {
+                            let value: rusqlite::types::Value = row.get(n)?;
+                            Ok(match value {
+                                rusqlite::types::Value::Null => "NULL".to_owned(),
+                                rusqlite::types::Value::Integer(n) => n.to_string(),
+                                rusqlite::types::Value::Text(s) => s,
+                                value => format!("{value:?}"),
+                            })
+                        })
+                        .collect::<rusqlite::Result<Vec<_>>>()
+                        .map(|row| row.join("|"))
+                })
+                .expect("query")
+                .collect::<rusqlite::Result<Vec<_>>>()
+                .expect("rows")
+        };
+        assert_eq!(
+            rows("SELECT * FROM books ORDER BY number"),
+            ["MRK|41|The Gospel of Mark|Mark|Mk", "JHN|43|NULL|NULL|NULL"]
+        );
+        assert_eq!(
+            rows("SELECT * FROM chapters WHERE book = 'MRK'"),
+            ["MRK|1|3", "MRK|2|1"]
+        );
+        assert_eq!(
+            rows("SELECT reference, text FROM verses WHERE book = 'MRK'"),
+            [
+                "MRK 1:1|The beginning.",
+                "MRK 1:2|As it is written",
+                "MRK 1:3|",
+                "MRK 2:1|Again"
+            ]
+        );
+        assert_eq!(
+            rows("SELECT reference, verse, style, caller, text FROM notes"),
+            ["MRK 1:1|1|f|+|Some add Son of God", "MRK 2|NULL|x|-|Luke 5"]
+        );
+    }
+}
@@ -0,0 +1,345 @@
+//! Counts of words, verses, notes and markers, as translation consultants
+//! ask for them, and where in a project each marker is used, as project
+//! administrators ask for it.
+
+use std::collections::BTreeMap;
+
+use crate::{
+    books::Book,
+    document::{is_custom, Content, Document, State},
+    extension::{Category, Extensions},
+    plain::PlainTextOptions,
+    project::Project,
+    reference::Reference,
+    versification::verse_range,
+};
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Stats {
+    /// Words of the body text, headings and introduction, not counting
+    /// notes or header material such as `\toc1`.
+    pub words: usize,
+    /// Characters of the same text, not counting whitespace.
+    pub characters: usize,
+    /// The number of verses in each chapter, keyed by chapter reference.
+    /// A combined verse such as `\v 16-17` counts as two.
+    pub verses: BTreeMap<Reference, usize>,
+    pub footnotes: usize,
+    pub crossreferences: usize,
+    /// How often each marker is used, including `\c` and `\v`.
+    pub markers: BTreeMap<String, usize>,
+}
+
+impl Stats {
+    /// The number of verses over every chapter.
+    pub fn verse_count(&self) -> usize {
+        self.verses.values().sum()
+    }
+
+    /// Add the counts of another document.
+    pub fn merge(&mut self, other: &Stats) {
+        self.words += other.words;
+        self.characters += other.characters;
+        for (chapter, count) in &other.verses {
+            *self.verses.entry(*chapter).or_default() += count;
+        }
+        self.footnotes += other.footnotes;
+        self.crossreferences += other.crossreferences;
+        for (marker, count) in &other.markers {
+            *self.markers.entry(marker.clone()).or_default() += count;
+        }
+    }
+}
+
+impl Document<'_> {
+    /// The document's counts, telling footnotes from cross references by
+    /// the bundled marker set.
+    pub fn stats(&self) -> Stats {
+        self.stats_with(State::usfm_ext())
+    }
+
+    /// As [`Document::stats`], with the categories of `markers`.
+    pub fn stats_with(&self, markers: &Extensions) -> Stats {
+        let text = self.to_plain_text(PlainTextOptions {
+            headings: true,
+            introduction: true,
+            ..PlainTextOptions::default()
+        });
+        let mut stats = Stats {
+            words: text.split_whitespace().count(),
+            characters: text.chars().filter(|c| !c.is_whitespace()).count(),
+            ..Stats::default()
+        };
+        if let (Some(book), Some(root)) = (self.book(), &self.nodes) {
+            let mut chapter = None;
+            count(&root.content, markers, book, &mut chapter, &mut stats);
+        }
+        stats
+    }
+}
+
+fn count(
+    content: &[Content],
+    markers: &Extensions,
+    book: &'static Book,
+    chapter: &mut Option<Reference>,
+    stats: &mut Stats,
+) {
+    for item in content {
+        let Some(node) = item.node() else {
+            continue;
+        };
+        match item {
+            // Wrappers the parser adds around runs of list items, poetry
+            // and table rows rather than markers in the source.
+            Content::List(_) | Content::Stanza(_) | Content::Table(_) => {}
+            _ => *stats.markers.entry(node.style.to_string()).or_default() += 1,
+        }
+        match item {
+            Content::Chapter(node) => {
+                *chapter = node
+                    .attributes
+                    .get("number")
+                    .and_then(|n| n.parse().ok())
+                    .map(|n| Reference::new(book, n, None));
+            }
+            Content::Verse(node) => {
+                let range = node.attributes.get("number").and_then(|n| verse_range(n));
+                // A reversed range such as `5-3` is left uncounted.
+                let verses = range.and_then(|(first, last)| last.checked_sub(first));
+                if let (Some(chapter), Some(verses)) = (*chapter, verses) {
+                    *stats.verses.entry(chapter).or_default() +=
+                        (verses as usize).saturating_add(1);
+                }
+            }
+            Content::Note(node) => match markers.category(node.style.as_ref()) {
+                Some(Category::Crossreference) => stats.crossreferences += 1,
+                _ => stats.footnotes += 1,
+            },
+            _ => {}
+        }
+        count(&node.content, markers, book, chapter, stats);
+    }
+}
+
+impl Project {
+    /// The counts of every book added together, by the project's marker
+    /// set.
+    pub fn stats(&self) -> Stats {
+        let mut stats = Stats::default();
+        for (_, doc) in self.iter() {
+            stats.merge(&doc.stats_with(self.markers()));
+        }
+        stats
+    }
+}
+
+/// How one marker is used over a project, see [`Project::marker_usage`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MarkerUsage {
+    pub marker: String,
+    pub count: usize,
+    /// Where the marker is first used: the verse it is in, or the chapter
+    /// before the first verse, or the book before the first chapter.
+    pub first: Option<Reference>,
+    /// Where the marker is last used, in the same way.
+    pub last: Option<Reference>,
+    /// Used in the text but not defined by the project's marker set.
+    pub undefined: bool,
+    /// A custom `\z` marker the marker set defines but no book uses.
+    pub unused: bool,
+}
+
+impl Project {
+    /// Every marker the books use, and every custom marker defined for
+    /// them, in name order.
+    pub fn marker_usage(&self) -> Vec<MarkerUsage> {
+        let mut usage = BTreeMap::<String, MarkerUsage>::new();
+        for (book, doc) in self.iter() {
+            if let Some(root) = &doc.nodes {
+                let mut reference = Reference::new(book, 0, None);
+                uses(&root.content, &mut reference, &mut usage);
+            }
+        }
+        for (name, usage) in &mut usage {
+            usage.undefined = !self.markers().contains(name);
+        }
+        for name in self.markers().keys().filter(|name| is_custom(name)) {
+            usage.entry(name.clone()).or_insert_with(|| MarkerUsage {
+                marker: name.clone(),
+                count: 0,
+                first: None,
+                last: None,
+                undefined: false,
+                unused: true,
+            });
+        }
+        usage.into_values().collect()
+    }
+}
+
+fn uses(content: &[Content], reference: &mut Reference, usage: &mut BTreeMap<String, MarkerUsage>) {
+    for item in content {
+        let Some(node) = item.node() else {
+            continue;
+        };
+        match item {
+            Content::Chapter(node) => {
+                reference.chapter = node
+                    .attributes
+                    .get("number")
+                    .and_then(|n| n.parse().ok())
+                    .unwrap_or(reference.chapter);
+                reference.verse = None;
+            }
+            Content::Verse(node) => {
+                if let Some((first, _)) = node.attributes.get("number").and_then(|n| verse_range(n))
+                {
+                    reference.verse = Some(first);
+                }
+            }
+            _ => {}
+        }
+        if !matches!(
+            item,
+            Content::List(_) | Content::Stanza(_) | Content::Table(_)
+        ) {
+            let marker = usage
+                .entry(node.style.to_string())
+                .or_insert_with(|| MarkerUsage {
+                    marker: node.style.to_string(),
+                    count: 0,
+                    first: Some(*reference),
+                    last: None,
+                    undefined: false,
+                    unused: false,
+                });
+            marker.count += 1;
+            marker.last = Some(*reference);
+        }
+        uses(&node.content, reference, usage);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{path::PathBuf, sync::Arc};
+
+    use crate::{
+        books,
+        document::{Document, ParseOptions, State, UnknownMarkers},
+        extension::Extensions,
+        project::Project,
+        reference::Reference,
+    };
+
+    #[test]
+    fn stats() {
+        let doc: Document = "\\id MRK\n\\h Mark\n\\c 1\n\\s1 The Start\n\
+                             \\p \\v 1 The \\nd Lord\\nd* said,\\f + \\ft A note\\f*\n\
+                             \\q1 \\v 2-3 come\\x - \\xt Isa 40:3\\x*\n\\c 2\n\\p \\v 1 Again\n"
+            .parse()
+            .expect("Document");
+        let stats = doc.stats();
+        assert_eq!(stats.words, 7);
+        assert_eq!(stats.characters, 29);
+        let mrk = books::get("MRK").unwrap();
+        assert_eq!(
+            stats.verses.into_iter().collect::<Vec<_>>(),
+            [
+                (Reference::new(mrk, 1, None), 3),
+                (Reference::new(mrk, 2, None), 1)
+            ]
+        );
+        assert_eq!((stats.footnotes, stats.crossreferences), (1, 1));
+        assert_eq!(stats.markers["v"], 3);
+        assert_eq!(stats.markers["nd"], 1);
+        assert_eq!(stats.markers["q1"], 1);
+        assert!(!stats.markers.contains_key("stanza"));
+    }
+
+    #[test]
+    fn stats_with() {
+        let doc: Document = "\\id MRK\n\\c 1\n\\p \\v 1 a \\v 5-3 b \\v 6-7 c\n"
+            .parse()
+            .expect("Document");
+        let mrk = books::get("MRK").unwrap();
+        assert_eq!(doc.stats().verses[&Reference::new(mrk, 1, None)], 3);
+
+        let markers = Arc::new(
+            State::usfm_ext()
+                .clone()
+                .update_from_str("\\marker zx\n\\category crossreference\n")
+                .expect("markers"),
+        );
+        let source = "\\id MRK\n\\c 1\n\\p \\v 1 a\\zx - \\xt Isa 40:3\\zx*\n";
+        let doc =
+            Document::from_str_with_markers(source, Arc::clone(&markers), ParseOptions::default())
+                .expect("Document")
+                .into_owned();
+        let stats = doc.stats_with(&markers);
+        assert_eq!((stats.footnotes, stats.crossreferences), (0, 1));
+        let project = Project::from_parsed([(PathBuf::new(), Ok((doc, Vec::new())))], markers)
+            .expect("Project");
+        assert_eq!(project.stats().crossreferences, 1);
+    }
+
+    #[test]
+    fn marker_usage() {
+        let custom = Extensions::from_sty_str(
+            "\\Marker zfoo\n\\TextType VerseText\n\\StyleType Character\n\n\
+             \\Marker zbar\n\\TextType VerseText\n\\StyleType Character\n",
+        )
+        .expect("custom.sty");
+        let markers = Arc::new(State::usfm_ext().clone().update_from(custom));
+        let parsed = [
+            "\\id MRK\n\\h Mark\n\\c 1\n\\s1 Start\n\\p \\v 1 The \\nd Lord\\nd*\n\
+             \\q1 \\v 2-3 \\zfoo come\\zfoo*\\qt-s |who=\"X\"\\*cry\\qt-e\\*\n\\c 2\n\
+             \\tr \\tc1 cell\n\\p \\v 1 \\xyz again \\fig A|src=\"a.png\" size=\"col\"\\fig*\n",
+            "\\id GEN\n\\c 1\n\\p \\v 5 \\nd God\\nd*\n",
+        ]
+        .map(|usfm| {
+            let doc = Document::from_str_with(
+                usfm,
+                ParseOptions {
+                    unknown_markers: UnknownMarkers::Heuristic,
+                    ..ParseOptions::default()
+                },
+            );
+            (
+                PathBuf::new(),
+                doc.map(|doc| (doc.into_owned(), Vec::new())),
+            )
+        });
+        let project = Project::from_parsed(parsed, markers).expect("Project");
+        let usage = project.marker_usage();
+        let get = |name: &str| usage.iter().find(|u| u.marker == name).expect(name);
+        let reference = |r: &str| Some(r.parse::<Reference>().unwrap());
+
+        let nd = get("nd");
+        assert_eq!(nd.count, 2);
+        assert_eq!(
+            (nd.first, nd.last),
+            (reference("GEN 1:5"), reference("MRK 1:1"))
+        );
+        assert_eq!(get("h").first, reference("MRK 0"));
+        assert_eq!(get("s1").first, reference("MRK 1"));
+        assert_eq!(get("tc1").last, reference("MRK 2"));
+        assert_eq!(get("zfoo").first, reference("MRK 1:2"));
+        assert_eq!(get("v").count, 4);
+        assert!(!usage
+            .iter()
+            .any(|u| u.marker == "table" || u.marker == "usfm"));
+        assert_eq!(
+            usage
+                .iter()
+                .filter(|u| u.undefined)
+                .map(|u| u.marker.as_str())
+                .collect::<Vec<_>>(),
+            ["xyz"]
+        );
+        let zbar = get("zbar");
+        assert!(zbar.unused && zbar.count == 0 && zbar.first.is_none());
+        assert!(!get("zfoo").unused);
+    }
+}
@@ -1,72 +1,185 @@
 #![allow(dead_code)]
+use std::borrow::Cow;
+
 use super::Result;
 use nom::{
-    bytes::complete::{is_not, take_while1},
+    bytes::complete::{tag, take_while1},
     character::{
-        complete::{self as character, char, none_of, one_of},
+        complete::{self as character, char},
         is_alphanumeric,
     },
-    combinator::{eof, opt, recognize, value},
+    combinator::{opt, value},
     error::context,
-    multi::{many0_count, many1_count},
-    sequence::{delimited, preceded},
+    multi::many1_count,
+    sequence::delimited,
     Parser,
 };
 
 #[inline]
-pub(crate) fn bom(input: &str) -> Result<bool> {
+pub(crate) fn bom(input: &str) -> Result<'_, bool> {
     opt(char('\u{FEFF}')).map(|opt| opt.is_some()).parse(input)
 }
 
 #[inline] // NL
-pub(crate) fn line_ending(input: &str) -> Result<&str> {
+pub(crate) fn line_ending(input: &str) -> Result<'_, &str> {
     value("\n", character::line_ending).parse(input)
 }
 
 #[inline] // NL
-pub(crate) fn line_ending1(input: &str) -> Result<&str> {
+pub(crate) fn line_ending1(input: &str) -> Result<'_, &str> {
     value("\n", many1_count(character::line_ending)).parse(input)
 }
 
+/// A line ending as [`line_ending`] does, or unless `strict` a lone `\r` as
+/// written by classic Mac OS tools.
+#[inline] // NL
+pub(crate) fn line_break(strict: bool) -> impl Fn(&str) -> Result<&str> {
+    move |input| match strict {
+        true => line_ending(input),
+        false => value("\n", character::line_ending.or(tag("\r"))).parse(input),
+    }
+}
+
+#[inline] // NL
+pub(crate) fn line_breaks(strict: bool) -> impl Fn(&str) -> Result<&str> {
+    move |input| value("\n", many1_count(line_break(strict))).parse(input)
+}
+
+/// Replace `\r\n` and lone `\r` line endings with `\n`, borrowing when
+/// there are none.
+pub(crate) fn normalize_line_endings(text: &str) -> Cow<'_, str> {
+    match text.contains('\r') {
+        true => Cow::Owned(text.replace("\r\n", "\n").replace('\r', "\n")),
+        false => Cow::Borrowed(text),
+    }
+}
+
 fn reduce_space(spaces: &str) -> &str {
     match spaces {
         "" => "",
-        ws if ws.contains('\n') => "\n",
+        ws if ws.contains(['\n', '\r']) => "\n",
         _ => " ",
     }
 }
 
 #[inline] // NL
-pub(crate) fn multispace0(input: &str) -> Result<&str> {
+pub(crate) fn multispace0(input: &str) -> Result<'_, &str> {
     character::multispace0.map(reduce_space).parse(input)
 }
 
 #[inline] // NL
-pub(crate) fn multispace1(input: &str) -> Result<&str> {
+pub(crate) fn multispace1(input: &str) -> Result<'_, &str> {
     character::multispace1.map(reduce_space).parse(input)
 }
 
+/// Whitespace between inline items: the run as written when preserving,
+/// otherwise a single space.
+pub(crate) fn inline_space(preserve: bool) -> impl Fn(&str) -> Result<&str> {
+    move |input| match preserve {
+        true => character::multispace1(input),
+        // A single space borrows from the input, so that text either
+        // side of it can too.
+        false => character::multispace1
+            .map(|s| if s == " " { s } else { " " })
+            .parse(input),
+    }
+}
+
+/// Collapse every run of whitespace in text to a single space, borrowing
+/// when there is nothing to change.
+pub(crate) fn reduce_text(text: &str) -> Cow<'_, str> {
+    let mut prev = 'x';
+    let reduced = text.chars().all(|c| {
+        let single = !c.is_whitespace() || c == ' ' && !prev.is_whitespace();
+        prev = c;
+        single
+    });
+    if reduced {
+        return Cow::Borrowed(text);
+    }
+    let mut res = String::with_capacity(text.len());
+    let mut space = false;
+    for c in text.chars() {
+        if c.is_whitespace() {
+            space = true;
+            continue;
+        }
+        if std::mem::take(&mut space) {
+            res.push(' ');
+        }
+        res.push(c);
+    }
+    if space {
+        res.push(' ');
+    }
+    match res == text {
+        true => Cow::Borrowed(text),
+        false => Cow::Owned(res),
+    }
+}
+
 #[inline] // NL
-pub(crate) fn space0(input: &str) -> Result<&str> {
+pub(crate) fn space0(input: &str) -> Result<'_, &str> {
     character::space0.map(reduce_space).parse(input)
 }
 
 #[inline] // NL
-pub(crate) fn space1(input: &str) -> Result<&str> {
+pub(crate) fn space1(input: &str) -> Result<'_, &str> {
     value(" ", character::space1).parse(input)
 }
 
-pub(crate) fn name(input: &str) -> Result<&str> {
+pub(crate) fn name(input: &str) -> Result<'_, &str> {
     take_while1(|c| is_alphanumeric(c as u8) || c == '-' || c == '_').parse(input)
 }
 
-pub(crate) fn text(input: &str) -> Result<&str> {
-    let escape_sequence = preceded(char('\\'), one_of(r#"/~\|"#));
-    let non_break = preceded(char('/'), none_of("\\/"));
-    let medial_line_ending = preceded(multispace1, none_of("\\/"));
-    let specials = recognize(medial_line_ending.or(non_break).or(escape_sequence));
-    let runs = is_not("\\/\r\n").or(specials);
-    recognize(many0_count(runs)).or(eof).parse(input)
+/// Text up to the next marker, `//` or line break before one of them.
+/// Escape sequences such as `\\` and lone `/` and `|` are part of the text,
+/// as are line breaks with more text after them.
+///
+/// Every paragraph's text goes through here, so it scans the bytes once
+/// rather than trying each alternative at every run.
+#[inline]
+pub(crate) fn text(input: &str) -> Result<'_, &str> {
+    scan(input, false)
+}
+
+/// As [`text`], but also stopping at a `|`, which within a character span,
+/// figure or peripheral starts its attributes.
+#[inline]
+pub(crate) fn attributed_text(input: &str) -> Result<'_, &str> {
+    scan(input, true)
+}
+
+fn scan(input: &str, attributes: bool) -> Result<'_, &str> {
+    let bytes = input.as_bytes();
+    // Whether the text stops at the byte starting at `at`.
+    let stops = |at: usize| match bytes.get(at) {
+        None | Some(b'\\') => true,
+        Some(b'/') => bytes.get(at + 1) == Some(&b'/'),
+        Some(b'|') => attributes,
+        Some(_) => false,
+    };
+    // The length of the character starting at `at`, which is not the end.
+    let char_len = |at: usize| input[at..].chars().next().map_or(1, char::len_utf8);
+    let mut end = 0;
+    while let Some(&b) = bytes.get(end) {
+        end += match b {
+            b'\\' if matches!(bytes.get(end + 1), Some(b'/' | b'~' | b'\\' | b'|')) => 2,
+            b'\r' | b'\n' => {
+                let space = bytes[end..]
+                    .iter()
+                    .take_while(|b| matches!(b, b' ' | b'\t' | b'\r' | b'\n'))
+                    .count();
+                if stops(end + space) || bytes.get(end + space) == Some(&b'/') {
+                    break;
+                }
+                space + char_len(end + space)
+            }
+            _ if stops(end) => break,
+            _ => char_len(end),
+        };
+    }
+    Ok((&input[end..], &input[..end]))
 }
 
 pub(crate) mod marker {
@@ -81,7 +194,7 @@ pub(crate) mod marker {
         Parser,
     };
 
-    pub fn end(input: &str) -> Result<()> {
+    pub fn end(input: &str) -> Result<'_, ()> {
         let parser = value((), alt((multispace1, peek(recognize(one_of("\\|"))), eof)));
         context("end of tag name", parser).parse(input)
     }
@@ -91,27 +204,110 @@ pub(crate) mod marker {
     }
 }
 
-pub(crate) fn marker(input: &str) -> Result<&str> {
+pub(crate) fn marker(input: &str) -> Result<'_, &str> {
     context("marker", delimited(char('\\'), self::name, marker::end)).parse(input)
 }
 
+pub(crate) fn nested_marker(input: &str) -> Result<'_, &str> {
+    context(
+        "nested marker",
+        delimited(tag("\\+"), self::name, marker::end),
+    )
+    .parse(input)
+}
+
+pub(crate) fn nested_endmarker(id: &str) -> impl Fn(&str) -> Result<&str> + '_ {
+    move |input| context("nested end tag", delimited(tag("\\+"), tag(id), char('*'))).parse(input)
+}
+
+pub(crate) fn milestone_end(input: &str) -> Result<'_, &str> {
+    context("milestone end", tag("\\*")).parse(input)
+}
+
+pub(crate) fn endmarker(id: &str) -> impl Fn(&str) -> Result<&str> + '_ {
+    move |input| context("end tag", delimited(char('\\'), tag(id), char('*'))).parse(input)
+}
+
 pub(crate) mod attrib {
-    use super::Result;
+    use super::{multispace0, multispace1, space0, Result};
     use nom::{
-        bytes::complete::{escaped, is_not},
-        character::complete::one_of,
+        branch::alt,
+        bytes::complete::{escaped, is_not, tag},
+        character::complete::{char, none_of, one_of},
+        combinator::{opt, peek, recognize},
+        error::context,
+        multi::{many1_count, separated_list1},
+        sequence::{delimited, preceded, separated_pair, terminated},
         Parser,
     };
+    use std::borrow::Cow;
+
+    #[derive(Debug, PartialEq, Eq, Clone)]
+    pub enum Attributes<'i> {
+        Default(&'i str),
+        Named(Vec<(&'i str, &'i str)>),
+    }
+
+    fn text(input: &str) -> Result<'_, &str> {
+        escaped(is_not("\\\""), '\\', one_of(r#""\=~/|"#)).parse(input)
+    }
+
+    fn value(input: &str) -> Result<'_, &str> {
+        delimited(
+            char('"'),
+            opt(text).map(Option::unwrap_or_default),
+            char('"'),
+        )
+        .parse(input)
+    }
 
-    fn text(input: &str) -> Result<&str> {
-        escaped(is_not("\\ \t?"), '\\', one_of(r#""\=~/|"#)).parse(input)
+    fn name(input: &str) -> Result<'_, &str> {
+        super::name(input)
+    }
+
+    fn attribute(input: &str) -> Result<'_, (&str, &str)> {
+        separated_pair(name, delimited(space0, char('='), space0), value).parse(input)
+    }
+
+    fn default(input: &str) -> Result<'_, &str> {
+        let run = recognize(many1_count(alt((recognize(none_of("\\=|")), tag("\\=")))));
+        terminated(run, peek(char('\\')))
+            .map(str::trim_end)
+            .parse(input)
+    }
+
+    pub fn attributes(input: &str) -> Result<'_, Attributes<'_>> {
+        let named = separated_list1(multispace1, attribute).map(Attributes::Named);
+        let list = alt((named, default.map(Attributes::Default)));
+        context(
+            "attributes",
+            delimited(preceded(char('|'), space0), list, multispace0),
+        )
+        .parse(input)
+    }
+
+    pub fn unescape(value: &str) -> Cow<'_, str> {
+        if !value.contains('\\') {
+            return Cow::Borrowed(value);
+        }
+        let mut res = String::with_capacity(value.len());
+        let mut chars = value.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => res.extend(chars.next()),
+                _ => res.push(c),
+            }
+        }
+        Cow::Owned(res)
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::{
-        line_ending, line_ending1, marker, multispace0, multispace1, space0, space1, text,
+        attributed_text, endmarker, line_break, line_breaks, line_ending, line_ending1, marker,
+        multispace0, multispace1, nested_endmarker, nested_marker, normalize_line_endings, space0,
+        space1, text,
     };
 
     use nom::{
@@ -177,6 +373,18 @@ mod test {
         assert_eq!(line_ending("\u{000A}\u{000D}") as Result, Ok(("\r", "\n")));
     }
 
+    #[test]
+    fn lenient_newline_terminals() {
+        assert_eq!(line_break(false)("\u{000D}") as Result, Ok(("", "\n")));
+        assert_eq!(
+            line_break(false)("\u{000D}\u{000A}") as Result,
+            Ok(("", "\n"))
+        );
+        assert_eq!(line_breaks(false)("\r\r\n\nx") as Result, Ok(("x", "\n")));
+        assert!(line_break(true)("\u{000D}").is_err());
+        assert_eq!(normalize_line_endings("a\r\nb\rc\n"), "a\nb\nc\n");
+    }
+
     #[test]
     fn marker_parser() {
         assert_eq!(marker::tag("c")(r"\c 1"), Ok(("1", "c")));
@@ -220,32 +428,73 @@ mod test {
             text(r#"Some text \\ \~ \/"#) as Result,
             Ok((r"", r#"Some text \\ \~ \/"#))
         );
+        assert_eq!(
+            text("and/or\n  then\n") as Result,
+            Ok(("\n", "and/or\n  then"))
+        );
+        assert_eq!(text("a/\nb/") as Result, Ok(("", "a/\nb/")));
+        assert_eq!(text("Ἐν ἀρχῇ\n/ἦν") as Result, Ok(("\n/ἦν", "Ἐν ἀρχῇ")));
+        assert_eq!(text("\\x") as Result, Ok(("\\x", "")));
+        assert_eq!(text("") as Result, Ok(("", "")));
     }
 
-    // #[test]
-    // fn end_marker_parser() {
-    //     assert_eq!(endmarker("f")(r"\f* text") as Result, Ok((" text", "f")));
-    //     assert_eq!(endmarker("f")(r"\f*text") as Result, Ok(("text", "f")));
-    //     assert_eq!(endmarker("f")(r"\f*\v 1") as Result, Ok(("\\v 1", "f")));
-    //     assert_eq!(
-    //         endmarker("f")(r"\w\f*").finish(),
-    //         Err(VerboseError {
-    //             errors: vec![
-    //                 ("w\\f*", Nom(ErrorKind::Tag)),
-    //                 ("\\w\\f*", Context("end tag"))
-    //             ]
-    //         })
-    //     );
-    //     assert_eq!(
-    //         endmarker("f")(r"f* text").finish(),
-    //         Err(VerboseError {
-    //             errors: vec![("f* text", Char('\\')), ("f* text", Context("end tag"))]
-    //         })
-    //     );
+    #[test]
+    fn text_breaks_and_escapes() {
+        assert_eq!(text("a//b") as Result, Ok(("//b", "a")));
+        assert_eq!(text("a //\nb") as Result, Ok(("//\nb", "a ")));
+        assert_eq!(text("a\n//b") as Result, Ok(("\n//b", "a")));
+        assert_eq!(text("and/") as Result, Ok(("", "and/")));
+        assert_eq!(text("and/\\v 2") as Result, Ok(("\\v 2", "and/")));
+        assert_eq!(text("a/|b") as Result, Ok(("", "a/|b")));
+        assert_eq!(attributed_text("a/|b") as Result, Ok(("|b", "a/")));
+        assert_eq!(text(r"a \\ b\v 1") as Result, Ok(("\\v 1", r"a \\ b")));
+        assert_eq!(text(r"end\\") as Result, Ok(("", r"end\\")));
+        assert_eq!(text(r"\\\v 1") as Result, Ok(("\\v 1", r"\\")));
+        assert_eq!(
+            text("ἀρχῇ/λόγος//θεός") as Result,
+            Ok(("//θεός", "ἀρχῇ/λόγος"))
+        );
+        assert_eq!(text("日本\n語\\v 2") as Result, Ok(("\\v 2", "日本\n語")));
+        assert_eq!(text("λόγος/") as Result, Ok(("", "λόγος/")));
+    }
 
-    //     // let inlinetag = |t| flat_map(marker(t), |opener| preceded(text, endmarker(opener)));
-    //     // assert_eq!(inlinetag("f")(r"\f text\f*"), Ok(("", "f")));
-    // }
+    #[test]
+    fn end_marker_parser() {
+        assert_eq!(endmarker("f")(r"\f* text") as Result, Ok((" text", "f")));
+        assert_eq!(endmarker("f")(r"\f*text") as Result, Ok(("text", "f")));
+        assert_eq!(endmarker("f")(r"\f*\v 1") as Result, Ok(("\\v 1", "f")));
+        assert_eq!(
+            endmarker("f")(r"\w\f*").finish(),
+            Err(VerboseError {
+                errors: vec![("w\\f*", Nom(Tag)), ("\\w\\f*", Context("end tag"))]
+            })
+        );
+        assert_eq!(
+            endmarker("f")(r"f* text").finish(),
+            Err(VerboseError {
+                errors: vec![("f* text", Char('\\')), ("f* text", Context("end tag"))]
+            })
+        );
+    }
+
+    #[test]
+    fn nested_marker_parser() {
+        assert_eq!(nested_marker(r"\+nd Lord") as Result, Ok(("Lord", "nd")));
+        assert_eq!(
+            nested_marker(r"\nd Lord").finish(),
+            Err(VerboseError {
+                errors: vec![
+                    ("\\nd Lord", Nom(Tag)),
+                    ("\\nd Lord", Context("nested marker"))
+                ]
+            })
+        );
+        assert_eq!(
+            nested_endmarker("nd")(r"\+nd* text") as Result,
+            Ok((" text", "nd"))
+        );
+        assert!(nested_endmarker("nd")(r"\nd* text").is_err());
+    }
 
     // #[test]
     // fn pmarker_parser() {
@@ -290,4 +539,48 @@ mod test {
     //         })
     //     );
     // }
+
+    #[test]
+    fn attribute_parser() {
+        use super::attrib::{attributes, unescape, Attributes};
+
+        assert_eq!(
+            attributes(r#"|lemma="grace" strong="H1234"\w*"#) as Result<Attributes>,
+            Ok((
+                "\\w*",
+                Attributes::Named(vec![("lemma", "grace"), ("strong", "H1234")])
+            ))
+        );
+        assert_eq!(
+            attributes(r#"| x-note = "a \"quoted\" word" \w*"#) as Result<Attributes>,
+            Ok((
+                "\\w*",
+                Attributes::Named(vec![("x-note", r#"a \"quoted\" word"#)])
+            ))
+        );
+        assert_eq!(
+            attributes(r#"|grace \w*"#) as Result<Attributes>,
+            Ok(("\\w*", Attributes::Default("grace")))
+        );
+        assert_eq!(
+            attributes(r#"|sid="ts1"\*"#) as Result<Attributes>,
+            Ok(("\\*", Attributes::Named(vec![("sid", "ts1")])))
+        );
+        assert!(attributes(r#"lemma="grace"\w*"#).is_err());
+        assert_eq!(unescape(r#"a \"quoted\" \\word"#), r#"a "quoted" \word"#);
+    }
+
+    #[test]
+    fn text_stops_at_attributes() {
+        assert_eq!(
+            attributed_text("gracious|lemma") as Result,
+            Ok(("|lemma", "gracious"))
+        );
+        assert_eq!(
+            attributed_text(r"a \| b|c") as Result,
+            Ok(("|c", r"a \| b"))
+        );
+        assert_eq!(text("a | b\\v 2") as Result, Ok(("\\v 2", "a | b")));
+        assert_eq!(text("a\n| b") as Result, Ok(("", "a\n| b")));
+    }
 }
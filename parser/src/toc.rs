@@ -0,0 +1,77 @@
+//! Book names from the `\toc` and `\toca` markers, for the menus of
+//! reader apps.
+
+use crate::{books::Book, document::Document, project::Project};
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TableOfContents {
+    /// `\toc1`, such as "The Gospel according to Mark".
+    pub long_name: Option<String>,
+    /// `\toc2`, such as "Mark".
+    pub short_name: Option<String>,
+    /// `\toc3`, such as "Mrk".
+    pub abbreviation: Option<String>,
+    /// `\toca1`, the long name in an alternative language.
+    pub alt_long_name: Option<String>,
+    /// `\toca2`
+    pub alt_short_name: Option<String>,
+    /// `\toca3`
+    pub alt_abbreviation: Option<String>,
+}
+
+impl TableOfContents {
+    pub fn is_empty(&self) -> bool {
+        *self == TableOfContents::default()
+    }
+}
+
+impl Document<'_> {
+    pub fn toc(&self) -> TableOfContents {
+        let field = |style| self.header_text(style);
+        TableOfContents {
+            long_name: field("toc1"),
+            short_name: field("toc2"),
+            abbreviation: field("toc3"),
+            alt_long_name: field("toca1"),
+            alt_short_name: field("toca2"),
+            alt_abbreviation: field("toca3"),
+        }
+    }
+}
+
+impl Project {
+    /// The table of contents of every book in canonical order.
+    pub fn toc(&self) -> Vec<(&'static Book, TableOfContents)> {
+        self.iter().map(|(book, doc)| (book, doc.toc())).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::TableOfContents;
+    use crate::document::Document;
+
+    #[test]
+    fn toc() {
+        let doc: Document = "\\id MRK\n\\usfm 3.0\n\\h Mark\n\\toc1 The Gospel according to Mark\n\
+                             \\toc2 Mark \n\\toc3 Mrk\n\\toca2 Marc\n\\mt1 Mark\n\\c 1\n\
+                             \\p \\v 1 Text\n"
+            .parse()
+            .expect("Document");
+        assert_eq!(
+            doc.toc(),
+            TableOfContents {
+                long_name: Some("The Gospel according to Mark".into()),
+                short_name: Some("Mark".into()),
+                abbreviation: Some("Mrk".into()),
+                alt_short_name: Some("Marc".into()),
+                ..TableOfContents::default()
+            }
+        );
+        let doc: Document = "\\id MRK\n\\c 1\n\\p \\v 1 Text\n"
+            .parse()
+            .expect("Document");
+        assert!(doc.toc().is_empty());
+    }
+}
@@ -0,0 +1,239 @@
+//! Classifying source text for syntax highlighting, without parsing it.
+//!
+//! The lexer only looks at the text itself, so it never fails and knows
+//! nothing of which markers exist or where they may occur.
+
+use std::ops::Range;
+
+use crate::terminal;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TokenKind {
+    /// A marker such as `\p`, `\v` or `\+nd`.
+    MarkerTag,
+    /// An end marker such as `\nd*`, or the `\*` closing a milestone.
+    MarkerEnd,
+    AttributeKey,
+    /// An attribute value with its quotes, or a default attribute.
+    AttributeValue,
+    VerseNumber,
+    ChapterNumber,
+    /// A run of text within a line, without leading or trailing whitespace.
+    Text,
+    /// A backslash escape such as `\\` or `\|`.
+    Escape,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Token {
+    pub kind: TokenKind,
+    /// Byte offsets into the source.
+    pub range: Range<usize>,
+}
+
+/// Split USFM source into classified tokens, in source order. Whitespace
+/// and the `|` and `=` punctuating attributes are left out.
+pub fn tokens(source: &str) -> Tokens<'_> {
+    Tokens {
+        source,
+        offset: 0,
+        state: State::Text,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Text,
+    Number(TokenKind),
+    Attributes,
+}
+
+#[derive(Debug, Clone)]
+pub struct Tokens<'s> {
+    source: &'s str,
+    offset: usize,
+    state: State,
+}
+
+impl Tokens<'_> {
+    fn token(&mut self, kind: TokenKind, len: usize) -> Option<Token> {
+        let start = self.offset;
+        self.offset += len;
+        Some(Token {
+            kind,
+            range: start..self.offset,
+        })
+    }
+
+    fn skip(&mut self, pred: impl Fn(char) -> bool) {
+        let rest = &self.source[self.offset..];
+        self.offset += rest.find(|c| !pred(c)).unwrap_or(rest.len());
+    }
+
+    fn marker(&mut self) -> Option<Token> {
+        let rest = &self.source[self.offset..];
+        let after = rest[1..].strip_prefix('+').unwrap_or(&rest[1..]);
+        let name = match terminal::name(after) {
+            Ok((_, name)) => name,
+            Err(_) if after.starts_with('*') => {
+                return self.token(TokenKind::MarkerEnd, rest.len() - after.len() + 1)
+            }
+            Err(_) => return self.token(TokenKind::Text, 1),
+        };
+        let len = rest.len() - after.len() + name.len();
+        if rest[len..].starts_with('*') {
+            return self.token(TokenKind::MarkerEnd, len + 1);
+        }
+        match name {
+            "v" | "va" => self.state = State::Number(TokenKind::VerseNumber),
+            "c" | "ca" => self.state = State::Number(TokenKind::ChapterNumber),
+            _ => (),
+        }
+        self.token(TokenKind::MarkerTag, len)
+    }
+
+    fn attribute(&mut self) -> Option<Token> {
+        let rest = &self.source[self.offset..];
+        if let Ok((after, key)) = terminal::name(rest) {
+            if after.trim_start().starts_with('=') {
+                return self.token(TokenKind::AttributeKey, key.len());
+            }
+        }
+        if let Some(quoted) = rest.strip_prefix('=') {
+            self.offset += rest.len() - quoted.trim_start().len();
+            return None;
+        }
+        if let Some(value) = rest.strip_prefix('"') {
+            let mut escaped = false;
+            let end = value
+                .find(|c| {
+                    let end = c == '"' && !escaped;
+                    escaped = c == '\\' && !escaped;
+                    end
+                })
+                .map_or(value.len(), |n| n + 1);
+            return self.token(TokenKind::AttributeValue, end + 1);
+        }
+        self.state = State::Text;
+        let end = rest.find('\\').unwrap_or(rest.len());
+        self.token(TokenKind::AttributeValue, rest[..end].trim_end().len())
+    }
+
+    fn text(&mut self) -> Option<Token> {
+        let rest = &self.source[self.offset..];
+        let end = rest.find(['\\', '|', '\r', '\n']).unwrap_or(rest.len());
+        self.token(TokenKind::Text, rest[..end].trim_end().len())
+    }
+}
+
+impl Iterator for Tokens<'_> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.state {
+                State::Text => self.skip(char::is_whitespace),
+                State::Number(_) | State::Attributes => self.skip(|c| c == ' ' || c == '\t'),
+            }
+            let rest = &self.source[self.offset..];
+            if rest.is_empty() {
+                return None;
+            }
+            let token = match self.state {
+                State::Number(kind) => {
+                    self.state = State::Text;
+                    let end = rest
+                        .find(|c: char| c.is_whitespace() || c == '\\')
+                        .unwrap_or(rest.len());
+                    match end {
+                        0 => None,
+                        end => self.token(kind, end),
+                    }
+                }
+                State::Attributes if rest.starts_with(['\\', '\r', '\n']) => {
+                    self.state = State::Text;
+                    None
+                }
+                State::Attributes => self.attribute(),
+                State::Text if rest.starts_with('|') => {
+                    self.offset += 1;
+                    self.state = State::Attributes;
+                    None
+                }
+                State::Text if rest.starts_with('\\') => match rest[1..].chars().next() {
+                    Some('\\' | '/' | '~' | '|') => self.token(TokenKind::Escape, 2),
+                    _ => self.marker(),
+                },
+                State::Text => self.text(),
+            };
+            if token.is_some() {
+                return token;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{tokens, TokenKind::*};
+
+    fn lex(source: &str) -> Vec<(super::TokenKind, &str)> {
+        tokens(source)
+            .map(|token| (token.kind, &source[token.range]))
+            .collect()
+    }
+
+    #[test]
+    fn markers_and_numbers() {
+        assert_eq!(
+            lex("\\c 1\n\\p\n\\v 2-3 In the \\+nd Lord\\+nd* a\\\\b \\qt-s\\*\n"),
+            [
+                (MarkerTag, "\\c"),
+                (ChapterNumber, "1"),
+                (MarkerTag, "\\p"),
+                (MarkerTag, "\\v"),
+                (VerseNumber, "2-3"),
+                (Text, "In the"),
+                (MarkerTag, "\\+nd"),
+                (Text, "Lord"),
+                (MarkerEnd, "\\+nd*"),
+                (Text, "a"),
+                (Escape, "\\\\"),
+                (Text, "b"),
+                (MarkerTag, "\\qt-s"),
+                (MarkerEnd, "\\*"),
+            ]
+        );
+        assert_eq!(lex("\\v\n"), [(MarkerTag, "\\v")]);
+        assert_eq!(lex(""), []);
+    }
+
+    #[test]
+    fn attributes() {
+        assert_eq!(
+            lex("\\w gracious|lemma=\"grace\" x-s = \"a\\\"b\"\\w* \\w word|default \\w*"),
+            [
+                (MarkerTag, "\\w"),
+                (Text, "gracious"),
+                (AttributeKey, "lemma"),
+                (AttributeValue, "\"grace\""),
+                (AttributeKey, "x-s"),
+                (AttributeValue, "\"a\\\"b\""),
+                (MarkerEnd, "\\w*"),
+                (MarkerTag, "\\w"),
+                (Text, "word"),
+                (AttributeValue, "default"),
+                (MarkerEnd, "\\w*"),
+            ]
+        );
+        assert_eq!(
+            lex("\\zms |sid=\"1\"\\*"),
+            [
+                (MarkerTag, "\\zms"),
+                (AttributeKey, "sid"),
+                (AttributeValue, "\"1\""),
+                (MarkerEnd, "\\*"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,441 @@
+//! Passes run over a document between parsing and writing, such as
+//! substituting quote marks, converting digits or renaming markers.
+//!
+//! Each pass is a [`VisitMut`]. A [`Pipeline`] runs them in turn and then
+//! drops the source span of everything they changed, so that writing with
+//! [`Whitespace::Preserve`](crate::writer::Whitespace::Preserve) regenerates
+//! those nodes and still copies the rest verbatim. A pass can also ask for
+//! a node to be regenerated by clearing its span.
+
+use std::{borrow::Cow, collections::HashMap, mem::discriminant, ops::ControlFlow};
+
+use crate::{
+    diagnostic::{Code, Diagnostic},
+    document::{Content, Document, Node, State, Text},
+    extension::Category,
+    reference::BookNames,
+    visit::{walk_content_mut, walk_node_mut, VisitMut},
+};
+
+/// A pass for a [`Pipeline`].
+pub trait Transform: VisitMut {
+    /// Called with the document before the pass walks it.
+    fn start(&mut self, _doc: &Document) {}
+
+    /// Problems found or changes worth reporting, taken after each run.
+    /// Their spans are in the document as it was before the pipeline ran.
+    fn diagnostics(&mut self) -> Vec<Diagnostic> {
+        Vec::new()
+    }
+}
+
+#[derive(Default)]
+pub struct Pipeline {
+    passes: Vec<Box<dyn Transform>>,
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Pipeline::default()
+    }
+
+    /// Add a pass, to run after those already added.
+    pub fn pass(mut self, pass: impl Transform + 'static) -> Self {
+        self.passes.push(Box::new(pass));
+        self
+    }
+
+    /// Run every pass over `doc`, returning their diagnostics in the order
+    /// the passes ran. A pass breaking out of its walk stops only itself.
+    pub fn run(&mut self, doc: &mut Document) -> Vec<Diagnostic> {
+        let Some(before) = doc.nodes.clone() else {
+            return Vec::new();
+        };
+        let mut diagnostics = Vec::new();
+        for pass in &mut self.passes {
+            pass.start(doc);
+            let _ = doc.accept_mut(pass.as_mut());
+            diagnostics.extend(pass.diagnostics());
+        }
+        if let Some(root) = doc.root_mut() {
+            reconcile(&before, root);
+        }
+        diagnostics
+    }
+}
+
+// Drop the spans of whatever differs from `before`, along with those of the
+// nodes holding it, returning whether anything did.
+fn reconcile(before: &Node, after: &mut Node) -> bool {
+    if before.content.len() != after.content.len() {
+        let _ = ClearSpans.visit_node_mut(after);
+        return true;
+    }
+    let mut changed = before.style != after.style
+        || before.attributes != after.attributes
+        || before.nested != after.nested
+        || before.custom != after.custom
+        || (before.span.is_some() && after.span.is_none());
+    for (old, new) in before.content.iter().zip(&mut after.content) {
+        changed |= match (old, new) {
+            (Content::Text(old), Content::Text(new)) => {
+                let differs = old.text != new.text || (old.span.is_some() && new.span.is_none());
+                if differs {
+                    new.span = None;
+                }
+                differs
+            }
+            (old, new) if discriminant(old) != discriminant(new) => {
+                let _ = ClearSpans.visit_content_mut(new);
+                true
+            }
+            (old, new) => match (old.node(), new.node_mut()) {
+                (Some(old), Some(new)) => reconcile(old, new),
+                _ => false,
+            },
+        };
+    }
+    if changed {
+        after.span = None;
+    }
+    changed
+}
+
+pub(crate) struct ClearSpans;
+
+impl Document<'_> {
+    /// Drop every source span, so that the tree compares equal to one built
+    /// or read from USX without them, and is written afresh rather than
+    /// copied from the source.
+    pub fn clear_spans(&mut self) {
+        let _ = self.accept_mut(&mut ClearSpans);
+    }
+}
+
+impl VisitMut for ClearSpans {
+    fn visit_node_mut(&mut self, node: &mut Node) -> ControlFlow<()> {
+        node.span = None;
+        walk_node_mut(self, node)
+    }
+
+    fn visit_text_mut(&mut self, text: &mut Text) -> ControlFlow<()> {
+        text.span = None;
+        ControlFlow::Continue(())
+    }
+}
+
+/// Replace strings in text, in the order given, for punctuation and quote
+/// marks.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Substitute {
+    pairs: Vec<(String, String)>,
+}
+
+impl Substitute {
+    pub fn new<F, T>(pairs: impl IntoIterator<Item = (F, T)>) -> Self
+    where
+        F: Into<String>,
+        T: Into<String>,
+    {
+        Substitute {
+            pairs: pairs
+                .into_iter()
+                .map(|(from, to)| (from.into(), to.into()))
+                .filter(|(from, _)| !from.is_empty())
+                .collect(),
+        }
+    }
+
+    /// The angle brackets keyboards stand in for guillemets with: `<<` and
+    /// `>>` to « and », then `<` and `>` to ‹ and ›.
+    pub fn angle_quotes() -> Self {
+        Substitute::new([("<<", "«"), (">>", "»"), ("<", "‹"), (">", "›")])
+    }
+}
+
+impl VisitMut for Substitute {
+    fn visit_text_mut(&mut self, text: &mut Text) -> ControlFlow<()> {
+        for (from, to) in &self.pairs {
+            if text.text.contains(from.as_str()) {
+                text.text = Cow::Owned(text.text.replace(from.as_str(), to));
+            }
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+impl Transform for Substitute {}
+
+/// Write the digits 0 to 9 in text in another script, given its zero, such
+/// as `'०'` for Devanagari. Chapter and verse numbers are left alone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Digits {
+    pub zero: char,
+}
+
+impl VisitMut for Digits {
+    fn visit_text_mut(&mut self, text: &mut Text) -> ControlFlow<()> {
+        if text.text.contains(|c: char| c.is_ascii_digit()) {
+            let converted = text
+                .text
+                .chars()
+                .map(|c| match c.to_digit(10) {
+                    Some(d) if c.is_ascii_digit() => {
+                        char::from_u32(self.zero as u32 + d).unwrap_or(c)
+                    }
+                    _ => c,
+                })
+                .collect();
+            text.text = Cow::Owned(converted);
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+impl Transform for Digits {}
+
+/// Rename markers, such as a project's `\s` to `\s1`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Rename {
+    pub markers: HashMap<String, String>,
+}
+
+impl Rename {
+    pub fn new<F, T>(markers: impl IntoIterator<Item = (F, T)>) -> Self
+    where
+        F: Into<String>,
+        T: Into<String>,
+    {
+        Rename {
+            markers: markers
+                .into_iter()
+                .map(|(from, to)| (from.into(), to.into()))
+                .collect(),
+        }
+    }
+}
+
+impl VisitMut for Rename {
+    fn visit_node_mut(&mut self, node: &mut Node) -> ControlFlow<()> {
+        if let Some(to) = self.markers.get(node.style.as_ref()) {
+            node.style = Cow::Owned(to.clone());
+        }
+        walk_node_mut(self, node)
+    }
+}
+
+impl Transform for Rename {}
+
+/// Upgrade constructs from before USFM 3, reporting each change as a
+/// [`Code::Deprecated`] diagnostic:
+///
+/// - figures in the `\fig DESC|FILE|SIZE|LOC|COPY|CAP|REF\fig*` field form
+///   are written with attributes,
+/// - `\xt` references get a `link-href` attribute,
+/// - `\ps` and `\psi` become `\p` and `\pi`, and a following `\p` becomes
+///   `\nb`, even across a chapter break,
+/// - `\ph` becomes `\li`,
+/// - and the document is declared `\usfm 3.0`.
+#[derive(Debug, Clone, Default)]
+pub struct Migrate {
+    source: String,
+    continued: bool,
+    changes: Vec<Diagnostic>,
+}
+
+impl Migrate {
+    const RENAMED: [(&'static str, &'static str); 4] =
+        [("ph", "li"), ("ph1", "li1"), ("ph2", "li2"), ("ph3", "li3")];
+
+    pub fn new() -> Self {
+        Migrate::default()
+    }
+
+    fn report(&mut self, node: &Node, message: String) {
+        self.changes.push(Diagnostic::info(
+            Code::Deprecated,
+            node.span.unwrap_or_default(),
+            message,
+        ));
+    }
+
+    fn version(&mut self, root: &mut Node) {
+        let version = root.attributes.get("version");
+        let declared =
+            version.is_some_and(|v| v.contains('.') && v.parse().is_ok_and(|v: f32| v >= 3.0));
+        if declared {
+            return;
+        }
+        let message = match version {
+            Some(v) if v != "3" => format!("\\usfm {v} raised to 3.0"),
+            _ => "\\usfm 3.0 added".to_owned(),
+        };
+        root.attributes.insert("version".into(), "3.0".into());
+        if let Some(Content::Book(book)) = root
+            .content
+            .iter_mut()
+            .find(|c| matches!(c, Content::Book(_)))
+        {
+            self.report(book, message);
+            book.span = None;
+        }
+    }
+
+    fn paragraph(&mut self, node: &mut Node) {
+        let markers = State::usfm_ext();
+        let heading = matches!(
+            markers.category(node.style.as_ref()),
+            Some(Category::SectionPara | Category::Title | Category::Header)
+        );
+        if heading {
+            return;
+        }
+        if std::mem::take(&mut self.continued) && node.style == "p" {
+            self.report(node, "\\p after \\ps changed to \\nb".into());
+            node.style = "nb".into();
+        }
+        let renamed = match node.style.as_ref() {
+            "ps" => Some("p"),
+            "psi" => Some("pi"),
+            style => Self::RENAMED
+                .iter()
+                .find(|(from, _)| *from == style)
+                .map(|(_, to)| *to),
+        };
+        if let Some(to) = renamed {
+            self.continued = matches!(node.style.as_ref(), "ps" | "psi");
+            self.report(node, format!("\\{} changed to \\{to}", node.style));
+            node.style = to.into();
+        }
+    }
+
+    fn figure(&mut self, node: &mut Node) {
+        let Some(span) = node.span else {
+            return;
+        };
+        let source = self.source.get(span.range()).unwrap_or_default();
+        if !source.contains('=') && source.matches('|').count() == 6 {
+            self.report(node, "\\fig fields written as attributes".into());
+            node.span = None;
+        }
+    }
+
+    fn cross_reference(&mut self, node: &mut Node) {
+        if node.attributes.contains_key("link-href") {
+            return;
+        }
+        let text = node.text();
+        let Some(range) = BookNames::english()
+            .ranges(text.trim())
+            .ok()
+            .and_then(|ranges| ranges.into_iter().next())
+        else {
+            return;
+        };
+        self.report(node, format!("\\xt {} linked to {range}", text.trim()));
+        node.set_attribute("link-href", range.to_string());
+    }
+}
+
+impl VisitMut for Migrate {
+    fn visit_content_mut(&mut self, content: &mut Content) -> ControlFlow<()> {
+        match content {
+            Content::Para(node) => self.paragraph(node),
+            Content::Figure(node) => self.figure(node),
+            Content::Char(node) if node.style == "xt" => self.cross_reference(node),
+            _ => {}
+        }
+        walk_content_mut(self, content)
+    }
+
+    fn visit_node_mut(&mut self, node: &mut Node) -> ControlFlow<()> {
+        if node.style == "usfm" {
+            self.version(node);
+        }
+        walk_node_mut(self, node)
+    }
+}
+
+impl Transform for Migrate {
+    fn start(&mut self, doc: &Document) {
+        self.source = doc.source().to_owned();
+        self.continued = false;
+    }
+
+    fn diagnostics(&mut self) -> Vec<Diagnostic> {
+        std::mem::take(&mut self.changes)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Digits, Migrate, Pipeline, Rename, Substitute};
+    use crate::{
+        diagnostic::Severity,
+        document::Document,
+        writer::{Options, Whitespace},
+    };
+
+    #[test]
+    fn pipeline() {
+        let source = "\\id MRK\n\\c 1\n\\s The  beginning\n\
+                      \\p \\v 1 He said, <<Come  in 3 days.>>\n\
+                      \\p \\v 2 Unchanged   spacing.\n";
+        let mut doc = source.parse::<Document>().expect("Document");
+        let diagnostics = Pipeline::new()
+            .pass(Substitute::angle_quotes())
+            .pass(Digits { zero: '०' })
+            .pass(Rename::new([("s", "s1")]))
+            .run(&mut doc);
+        assert!(diagnostics.is_empty());
+        let preserve = Options {
+            whitespace: Whitespace::Preserve,
+            ..Options::default()
+        };
+        assert_eq!(
+            doc.to_usfm(preserve).to_string(),
+            "\\id MRK\n\\c 1\n\\s1 The  beginning\n\
+             \\p\n\\v 1 He said, «Come  in ३ days.»\n\
+             \\p \\v 2 Unchanged   spacing.\n"
+        );
+        let p = &doc.root().unwrap().content[1].node().unwrap().content;
+        assert!(p[1].node().unwrap().span.is_none());
+        assert!(p[2].node().unwrap().span.is_some());
+    }
+
+    #[test]
+    fn migrate() {
+        let source = "\\id MRK\n\\usfm 2.4\n\\c 1\n\
+                      \\ps \\v 1 Text\\x - \\xo 1:1 \\xt Mat 3:1\\xt*\\x*\n\
+                      \\fig Map|map.png|col|||The land|1:1\\fig*\n\
+                      \\c 2\n\\p \\v 1 continued\n\\ph1 Hanging\n\
+                      \\p Unchanged  here\n";
+        let mut doc = source.parse::<Document>().expect("Document");
+        let changes = Pipeline::new().pass(Migrate::new()).run(&mut doc);
+        assert!(changes.iter().all(|c| c.severity == Severity::Info));
+        assert_eq!(
+            changes
+                .iter()
+                .map(|c| c.message.as_str())
+                .collect::<Vec<_>>(),
+            [
+                "\\usfm 2.4 raised to 3.0",
+                "\\ps changed to \\p",
+                "\\xt Mat 3:1 linked to MAT 3:1",
+                "\\fig fields written as attributes",
+                "\\p after \\ps changed to \\nb",
+                "\\ph1 changed to \\li1",
+            ]
+        );
+        let preserve = Options {
+            whitespace: Whitespace::Preserve,
+            ..Options::default()
+        };
+        assert_eq!(
+            doc.to_usfm(preserve).to_string(),
+            "\\id MRK\n\\usfm 3.0\n\\c 1\n\\p\n\\v 1 Text\\x - \\xo 1:1 \\xt Mat 3:1|link-href=\"MAT 3:1\"\\xt*\\x* \
+             \\fig The land|alt=\"Map\" ref=\"1:1\" size=\"col\" src=\"map.png\"\\fig*\n\
+             \\c 2\n\\nb\n\\v 1 continued\n\\li1 Hanging\n\\p Unchanged  here\n"
+        );
+    }
+}
@@ -0,0 +1,259 @@
+use std::{borrow::Cow, io};
+
+use serde_json::{Map, Value};
+
+use crate::{
+    document::{Content, Document, Node},
+    usx::{invalid, Reader},
+    xml::{Element, Xml},
+};
+
+const VERSION: &str = "3.0";
+
+impl Document<'_> {
+    pub fn to_usj(&self) -> Value {
+        let mut writer = Writer::default();
+        let mut content = Vec::new();
+        if let Some(root) = &self.nodes {
+            writer.blocks(&root.content, &mut content);
+        }
+        let mut usj = Map::new();
+        usj.insert("type".into(), "USJ".into());
+        usj.insert("version".into(), VERSION.into());
+        usj.insert("content".into(), content.into());
+        usj.into()
+    }
+
+    pub fn from_usj(usj: &Value) -> io::Result<Self> {
+        let Xml::Element(root) = element(usj)? else {
+            return Err(invalid("expected a USJ object"));
+        };
+        let mut doc = Document::default();
+        doc.nodes = Some(Reader::new().document(root)?);
+        Ok(doc)
+    }
+}
+
+#[derive(Default)]
+struct Writer {
+    book: String,
+    chapter: String,
+}
+
+impl Writer {
+    fn blocks(&mut self, content: &[Content], out: &mut Vec<Value>) {
+        for item in content {
+            match item {
+                Content::Book(node) => {
+                    self.book = node
+                        .attributes
+                        .get("code")
+                        .map_or_else(String::new, |s| s.to_string());
+                    out.push(self.object("book", node, &[]));
+                }
+                Content::Chapter(node) => {
+                    self.chapter = node
+                        .attributes
+                        .get("number")
+                        .map_or_else(String::new, |s| s.to_string());
+                    let mut chapter = object("chapter", node, &[]);
+                    let sid = format!("{} {}", self.book, self.chapter);
+                    chapter.insert("sid".into(), sid.into());
+                    out.push(chapter.into());
+                    self.blocks(&node.content, out);
+                }
+                Content::List(node) | Content::Stanza(node) => self.blocks(&node.content, out),
+                Content::Para(node) => out.push(self.object("para", node, &["level"])),
+                Content::Unknown(node) => out.push(unknown(self.object("para", node, &[]))),
+                Content::Table(node) => {
+                    let mut table = Map::new();
+                    table.insert("type".into(), "table".into());
+                    let rows = node.content.iter().map(|row| self.inline(row));
+                    table.insert("content".into(), rows.collect());
+                    out.push(table.into());
+                }
+                Content::Periph(node) => {
+                    let mut periph = object("periph", node, &[]);
+                    periph.remove("marker");
+                    let mut content = Vec::new();
+                    self.blocks(&node.content, &mut content);
+                    periph.insert("content".into(), content.into());
+                    out.push(periph.into());
+                }
+                Content::Sidebar(node) => {
+                    let mut sidebar = object("sidebar", node, &[]);
+                    let mut content = Vec::new();
+                    self.blocks(&node.content, &mut content);
+                    sidebar.insert("content".into(), content.into());
+                    out.push(sidebar.into());
+                }
+                item => out.push(self.inline(item)),
+            }
+        }
+    }
+
+    fn inline(&mut self, item: &Content) -> Value {
+        match item {
+            Content::Text(text) => text.as_str().into(),
+            Content::NoBreakSpace => "\u{a0}".into(),
+            Content::OptBreak => {
+                let mut optbreak = Map::new();
+                optbreak.insert("type".into(), "optbreak".into());
+                optbreak.into()
+            }
+            Content::Verse(node) => {
+                let mut verse = object("verse", node, &[]);
+                let number = node.attributes.get("number").map_or("", |s| s.as_ref());
+                let sid = format!("{} {}:{number}", self.book, self.chapter);
+                verse.insert("sid".into(), sid.into());
+                verse.into()
+            }
+            Content::Row(node) => self.object("table:row", node, &[]),
+            Content::Cell(node) => self.object("table:cell", node, &[]),
+            Content::Milestone(node) => self.object("ms", node, &[]),
+            Content::Char(node) => self.object("char", node, &[]),
+            Content::Unknown(node) => unknown(self.object("char", node, &[])),
+            Content::Note(node) => self.object("note", node, &[]),
+            Content::Figure(node) => {
+                let mut figure = object("figure", node, &["src"]);
+                if let Some(src) = node.attributes.get("src") {
+                    figure.insert("file".into(), src.as_ref().into());
+                }
+                let content = node.content.iter().map(|item| self.inline(item));
+                figure.insert("content".into(), content.collect());
+                figure.into()
+            }
+            block => {
+                let mut content = Vec::new();
+                self.blocks(std::slice::from_ref(block), &mut content);
+                content.pop().unwrap_or_default()
+            }
+        }
+    }
+
+    fn object(&mut self, kind: &str, node: &Node, skip: &[&str]) -> Value {
+        let mut res = object(kind, node, skip);
+        if !node.content.is_empty() {
+            let content = node.content.iter().map(|item| self.inline(item));
+            res.insert("content".into(), content.collect());
+        }
+        res.into()
+    }
+}
+
+fn object(kind: &str, node: &Node, skip: &[&str]) -> Map<String, Value> {
+    let mut res = Map::new();
+    res.insert("type".into(), kind.into());
+    res.insert("marker".into(), node.style.as_ref().into());
+    for (name, value) in &node.attributes {
+        if !skip.contains(&name.as_ref()) {
+            res.insert(name.to_string(), value.as_ref().into());
+        }
+    }
+    res
+}
+
+fn unknown(mut object: Value) -> Value {
+    object["status"] = "unknown".into();
+    object
+}
+
+// USJ is a direct transliteration of USX, so map each object back onto the
+// equivalent XML element and let the USX reader rebuild the tree.
+fn element(value: &Value) -> io::Result<Xml<'_>> {
+    let object = match value {
+        Value::String(text) => return Ok(Xml::Text(Cow::Borrowed(text))),
+        Value::Object(object) => object,
+        value => return Err(invalid(format!("unexpected USJ value {value}"))),
+    };
+    let name = match object.get("type").and_then(Value::as_str) {
+        Some("USJ") => "usx",
+        Some("table:row") => "row",
+        Some("table:cell") => "cell",
+        Some(kind) => kind,
+        None => return Err(invalid("USJ object without a type")),
+    };
+    let mut attributes = Vec::new();
+    for (key, value) in object {
+        let key = match key.as_str() {
+            "type" | "content" => continue,
+            "marker" => "style",
+            key => key,
+        };
+        match value {
+            Value::String(value) => attributes.push((key, Cow::Borrowed(value.as_str()))),
+            value => return Err(invalid(format!("unexpected {key} value {value}"))),
+        }
+    }
+    let children = match object.get("content") {
+        Some(Value::Array(content)) => content.iter().map(element).collect::<io::Result<_>>()?,
+        Some(value) => return Err(invalid(format!("unexpected content value {value}"))),
+        None => Vec::new(),
+    };
+    Ok(Xml::Element(Element {
+        name,
+        attributes,
+        children,
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use crate::document::Document;
+
+    #[test]
+    fn write_usj() {
+        let doc: Document = "\\id MRK\n\
+                             \\c 1\n\
+                             \\p \\v 1 The beginning \\nd of\\nd*\\f + \\ft Note\\f*\n"
+            .parse()
+            .expect("Document");
+        assert_eq!(
+            doc.to_usj(),
+            json!({
+                "type": "USJ",
+                "version": "3.0",
+                "content": [
+                    {"type": "book", "marker": "id", "code": "MRK"},
+                    {"type": "chapter", "marker": "c", "number": "1", "sid": "MRK 1"},
+                    {"type": "para", "marker": "p", "content": [
+                        {"type": "verse", "marker": "v", "number": "1", "sid": "MRK 1:1"},
+                        "The beginning ",
+                        {"type": "char", "marker": "nd", "content": ["of"]},
+                        {"type": "note", "marker": "f", "caller": "+", "content": [
+                            {"type": "char", "marker": "ft", "content": ["Note"]}
+                        ]}
+                    ]}
+                ]
+            })
+        );
+    }
+
+    #[test]
+    fn usj_round_trip() {
+        let doc: Document = "\\id NUM Numbers\n\
+                             \\usfm 3.0\n\
+                             \\mt1 Numbers\n\
+                             \\c 2\n\
+                             \\s1 Camp\n\
+                             \\p \\v 1 Census \\qt-s |who=\"Moses\"\\*count\\qt-e\\*\n\
+                             \\q1 in \\w tents|lemma=\"tent\"\\w*\n\
+                             \\q2 by tribe\n\
+                             \\tr \\th1 Tribe \\thr2 Number\n\
+                             \\tr \\tc1-2 Reuben\n\
+                             \\esb \\cat People\\cat*\n\
+                             \\p Sidebar\n\
+                             \\esbe\n\
+                             \\p \\v 2 \\fig Camp|src=\"camp.png\" size=\"col\"\\fig*\n"
+            .parse()
+            .expect("Document");
+        assert_eq!(
+            Document::from_usj(&doc.to_usj()).expect("from USJ").nodes,
+            doc.nodes
+        );
+        assert!(Document::from_usj(&json!({"content": []})).is_err());
+        assert!(Document::from_usj(&json!({"type": "USJ", "content": [1]})).is_err());
+    }
+}
@@ -0,0 +1,716 @@
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    io::{self, Read, Write},
+};
+
+use nom::{error::convert_error, Finish};
+
+use crate::{
+    document::{is_custom, merge_text, Content, Document, Node, State},
+    extension::{Category, Extensions},
+    xml::{self, Element, Xml},
+};
+
+const VERSION: &str = "3.0";
+
+impl Document<'_> {
+    pub fn to_usx<W: Write>(&self, w: W) -> io::Result<()> {
+        let mut writer = Writer {
+            out: w,
+            markers: State::usfm_ext(),
+            book: String::new(),
+            chapter: String::new(),
+            verse: None,
+        };
+        writer.document(self.nodes.as_ref())
+    }
+
+    pub fn from_usx<R: Read>(reader: R) -> io::Result<Self> {
+        let input = io::read_to_string(reader)?;
+        let (_, root) = xml::document(&input)
+            .finish()
+            .map_err(|e| invalid(convert_error(input.as_str(), e)))?;
+        let mut doc = Document::default();
+        doc.nodes = Some(Reader::new().document(root)?);
+        Ok(doc)
+    }
+}
+
+pub(crate) fn invalid<E: Into<Box<dyn std::error::Error + Send + Sync>>>(error: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+struct Writer<'m, W> {
+    out: W,
+    markers: &'m Extensions,
+    book: String,
+    chapter: String,
+    verse: Option<String>,
+}
+
+impl<W: Write> Writer<'_, W> {
+    fn document(&mut self, root: Option<&Node>) -> io::Result<()> {
+        writeln!(self.out, r#"<?xml version="1.0" encoding="utf-8"?>"#)?;
+        writeln!(self.out, r#"<usx version="{VERSION}">"#)?;
+        if let Some(root) = root {
+            self.blocks(&root.content, None)?;
+        }
+        writeln!(self.out, "</usx>")
+    }
+
+    fn blocks(&mut self, content: &[Content], next: Option<&Content>) -> io::Result<()> {
+        for (n, item) in content.iter().enumerate() {
+            self.block(item, content.get(n + 1).or(next))?;
+        }
+        Ok(())
+    }
+
+    fn block(&mut self, item: &Content, next: Option<&Content>) -> io::Result<()> {
+        match item {
+            Content::Book(node) => {
+                self.book = node
+                    .attributes
+                    .get("code")
+                    .map_or_else(String::new, |s| s.to_string());
+                write!(
+                    self.out,
+                    r#"<book code="{}" style="id">"#,
+                    escape(&self.book)
+                )?;
+                self.inlines(&node.content)?;
+                writeln!(self.out, "</book>")
+            }
+            Content::Chapter(node) => {
+                self.chapter = node
+                    .attributes
+                    .get("number")
+                    .map_or_else(String::new, |s| s.to_string());
+                let sid = format!("{} {}", self.book, self.chapter);
+                write!(self.out, "<chapter")?;
+                self.attributes(node, &["number", "style", "altnumber", "pubnumber"])?;
+                writeln!(self.out, r#" sid="{}" />"#, escape(&sid))?;
+                self.blocks(&node.content, None)?;
+                writeln!(self.out, r#"<chapter eid="{}" />"#, escape(&sid))
+            }
+            Content::Para(node) | Content::Unknown(node) => {
+                write!(self.out, r#"<para style="{}""#, escape(&node.style))?;
+                if let Content::Unknown(_) = item {
+                    write!(self.out, r#" status="unknown""#)?;
+                }
+                write!(self.out, ">")?;
+                self.inlines(&node.content)?;
+                if !self.continues(next) {
+                    self.close_verse()?;
+                }
+                writeln!(self.out, "</para>")
+            }
+            Content::List(node) | Content::Stanza(node) => self.blocks(&node.content, next),
+            Content::Table(node) => {
+                writeln!(self.out, "<table>")?;
+                self.blocks(&node.content, next)?;
+                writeln!(self.out, "</table>")
+            }
+            Content::Row(node) => {
+                write!(self.out, r#"<row style="{}">"#, escape(&node.style))?;
+                for (n, cell) in node.content.iter().enumerate() {
+                    self.cell(cell, node.content.get(n + 1).or(next))?;
+                }
+                writeln!(self.out, "</row>")
+            }
+            Content::Periph(node) => {
+                write!(self.out, "<periph")?;
+                self.attributes(node, &["alt", "id"])?;
+                writeln!(self.out, ">")?;
+                self.blocks(&node.content, next)?;
+                writeln!(self.out, "</periph>")
+            }
+            Content::Sidebar(node) => {
+                write!(self.out, "<sidebar")?;
+                self.attributes(node, &["style", "category"])?;
+                writeln!(self.out, ">")?;
+                self.blocks(&node.content, next)?;
+                writeln!(self.out, "</sidebar>")
+            }
+            item => self.inline(item),
+        }
+    }
+
+    fn cell(&mut self, item: &Content, next: Option<&Content>) -> io::Result<()> {
+        let Content::Cell(node) = item else {
+            return self.inline(item);
+        };
+        write!(self.out, "<cell")?;
+        self.attributes(node, &["style", "align", "colspan"])?;
+        write!(self.out, ">")?;
+        self.inlines(&node.content)?;
+        if !self.continues(next) {
+            self.close_verse()?;
+        }
+        write!(self.out, "</cell>")
+    }
+
+    fn inlines(&mut self, content: &[Content]) -> io::Result<()> {
+        content.iter().try_for_each(|item| self.inline(item))
+    }
+
+    fn inline(&mut self, item: &Content) -> io::Result<()> {
+        match item {
+            Content::Text(text) => write!(self.out, "{}", escape(text.as_str())),
+            Content::OptBreak => write!(self.out, "<optbreak />"),
+            Content::NoBreakSpace => write!(self.out, "\u{a0}"),
+            Content::Verse(node) => {
+                self.close_verse()?;
+                let number = node.attributes.get("number").map_or("", |s| s.as_ref());
+                let sid = format!("{} {}:{number}", self.book, self.chapter);
+                write!(self.out, "<verse")?;
+                self.attributes(node, &["number", "style", "altnumber", "pubnumber"])?;
+                write!(self.out, r#" sid="{}" />"#, escape(&sid))?;
+                self.verse = Some(sid);
+                Ok(())
+            }
+            Content::Milestone(node) => {
+                write!(self.out, "<ms")?;
+                self.attributes(node, &["style"])?;
+                self.other_attributes(node, &["style"])?;
+                write!(self.out, " />")
+            }
+            Content::Figure(node) => {
+                write!(self.out, r#"<figure style="{}""#, escape(&node.style))?;
+                for (key, usx) in [
+                    ("alt", "alt"),
+                    ("src", "file"),
+                    ("size", "size"),
+                    ("loc", "loc"),
+                    ("copy", "copy"),
+                    ("ref", "ref"),
+                ] {
+                    if let Some(value) = node.attributes.get(key) {
+                        write!(self.out, r#" {usx}="{}""#, escape(value))?;
+                    }
+                }
+                write!(self.out, ">")?;
+                self.inlines(&node.content)?;
+                write!(self.out, "</figure>")
+            }
+            Content::Note(node) => {
+                write!(self.out, "<note")?;
+                self.attributes(node, &["caller", "style", "category"])?;
+                write!(self.out, ">")?;
+                self.inlines(&node.content)?;
+                write!(self.out, "</note>")
+            }
+            Content::Char(node) | Content::Unknown(node) => {
+                write!(self.out, "<char")?;
+                self.attributes(node, &["style"])?;
+                self.other_attributes(node, &["style"])?;
+                if let Content::Unknown(_) = item {
+                    write!(self.out, r#" status="unknown""#)?;
+                }
+                write!(self.out, ">")?;
+                self.inlines(&node.content)?;
+                write!(self.out, "</char>")
+            }
+            block => self.block(block, None),
+        }
+    }
+
+    fn close_verse(&mut self) -> io::Result<()> {
+        match self.verse.take() {
+            Some(eid) => write!(self.out, r#"<verse eid="{}" />"#, escape(&eid)),
+            None => Ok(()),
+        }
+    }
+
+    // Whether verse text carries on into the following block, so the
+    // current verse should stay open past the end of this paragraph.
+    fn continues(&self, next: Option<&Content>) -> bool {
+        let starts_verse = |node: &Node| matches!(node.content.first(), Some(Content::Verse(_)));
+        match next {
+            Some(Content::Para(node)) => {
+                let category = self.markers.category(node.style.as_ref());
+                matches!(category, Some(Category::VersePara | Category::List))
+                    && !starts_verse(node)
+            }
+            Some(Content::Cell(node)) => !starts_verse(node),
+            Some(Content::Stanza(node) | Content::List(node) | Content::Table(node))
+            | Some(Content::Row(node)) => self.continues(node.content.first()),
+            _ => false,
+        }
+    }
+
+    fn attributes(&mut self, node: &Node, names: &[&str]) -> io::Result<()> {
+        for &name in names {
+            let value = match name {
+                "style" => Some(&node.style),
+                name => node.attributes.get(name),
+            };
+            if let Some(value) = value {
+                write!(self.out, r#" {name}="{}""#, escape(value))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn other_attributes(&mut self, node: &Node, skip: &[&str]) -> io::Result<()> {
+        let mut attributes = node
+            .attributes
+            .iter()
+            .filter(|(k, _)| !skip.contains(&k.as_ref()))
+            .collect::<Vec<_>>();
+        attributes.sort();
+        for (name, value) in attributes {
+            write!(self.out, r#" {name}="{}""#, escape(value))?;
+        }
+        Ok(())
+    }
+}
+
+pub(crate) struct Reader {
+    state: State<'static>,
+}
+
+impl Reader {
+    pub(crate) fn new() -> Self {
+        Reader {
+            state: State::new(),
+        }
+    }
+
+    pub(crate) fn document(&self, root: Element) -> io::Result<Node<'static>> {
+        if root.name != "usx" {
+            return Err(invalid(format!(
+                "expected <usx> element, found <{}>",
+                root.name
+            )));
+        }
+        let version = root.attribute("version").unwrap_or(VERSION);
+        let version = version
+            .parse::<f32>()
+            .map_or_else(|_| version.to_owned(), |v| v.to_string());
+        Ok(Node {
+            style: "usfm".into(),
+            attributes: [("version".into(), version.into())].into(),
+            content: self.blocks(&root.children)?,
+            ..Node::default()
+        })
+    }
+
+    fn blocks(&self, children: &[Xml]) -> io::Result<Vec<Content<'static>>> {
+        let mut res = Vec::new();
+        for child in children {
+            let element = match child {
+                Xml::Element(element) => element,
+                Xml::Text(text) if text.trim().is_empty() => continue,
+                Xml::Text(text) => {
+                    return Err(invalid(format!("unexpected text {text:?} between blocks")))
+                }
+            };
+            let block = match element.name {
+                "chapter" if element.attribute("eid").is_some() => continue,
+                "chapter" => {
+                    res.push(Content::Chapter(Node {
+                        attributes: attributes(element, &["number", "altnumber", "pubnumber"]),
+                        ..self.node(element, "c")
+                    }));
+                    continue;
+                }
+                "periph" => {
+                    res.push(Content::Periph(Node {
+                        attributes: attributes(element, &["alt", "id"]),
+                        content: self.blocks(&element.children)?,
+                        ..self.node(element, "periph")
+                    }));
+                    continue;
+                }
+                "book" => Content::Book(Node {
+                    attributes: attributes(element, &["code"]),
+                    content: self.inlines(&element.children, false)?,
+                    ..self.node(element, "id")
+                }),
+                "para" => self.para(element)?,
+                "table" => Content::Table(Node {
+                    content: self.rows(&element.children)?,
+                    ..self.node(element, "table")
+                }),
+                "sidebar" => Content::Sidebar(Node {
+                    attributes: attributes(element, &["category"]),
+                    content: self.blocks(&element.children)?,
+                    ..self.node(element, "esb")
+                }),
+                name => return Err(invalid(format!("unexpected <{name}> element"))),
+            };
+            let target = match res.last_mut() {
+                Some(Content::Chapter(chapter)) => &mut chapter.content,
+                _ => &mut res,
+            };
+            self.push_block(target, block);
+        }
+        Ok(res)
+    }
+
+    // Regroup consecutive list items and poetic lines into the container
+    // nodes the USFM parser produces for them.
+    fn push_block(&self, content: &mut Vec<Content<'static>>, block: Content<'static>) {
+        let category = block
+            .node()
+            .and_then(|node| self.state.markers.get(node.style.as_ref()))
+            .map(|marker| marker.category);
+        match (content.last_mut(), block, category) {
+            (Some(Content::List(list)), block @ Content::Para(_), Some(Category::List)) => {
+                list.content.push(block)
+            }
+            (_, block @ Content::Para(_), Some(Category::List)) => {
+                content.push(Content::List(Node {
+                    style: "list".into(),
+                    content: vec![block],
+                    ..Node::default()
+                }))
+            }
+            (last, Content::Para(mut line), Some(Category::VersePara))
+                if line.style.starts_with('q') =>
+            {
+                let level = self.state.level(&line.style).unwrap_or("1").to_owned();
+                line.attributes.insert("level".into(), level.into());
+                match last {
+                    Some(Content::Stanza(stanza)) => stanza.content.push(Content::Para(line)),
+                    _ => content.push(Content::Stanza(Node {
+                        style: "stanza".into(),
+                        content: vec![Content::Para(line)],
+                        ..Node::default()
+                    })),
+                }
+            }
+            (_, Content::Para(mut heading), Some(Category::SectionPara)) => {
+                if let Some(level) = self.state.level(&heading.style) {
+                    let level = level.to_owned();
+                    heading.attributes.insert("level".into(), level.into());
+                }
+                content.push(Content::Para(heading))
+            }
+            (_, block, _) => content.push(block),
+        }
+    }
+
+    fn para(&self, element: &Element) -> io::Result<Content<'static>> {
+        let node = Node {
+            content: self.inlines(&element.children, false)?,
+            ..self.node(element, "p")
+        };
+        match element.attribute("status") {
+            Some("unknown") => Ok(Content::Unknown(node)),
+            _ => Ok(Content::Para(node)),
+        }
+    }
+
+    fn rows(&self, children: &[Xml]) -> io::Result<Vec<Content<'static>>> {
+        let mut res = Vec::new();
+        for child in children {
+            match child {
+                Xml::Element(row) if row.name == "row" => {
+                    let mut cells = Vec::new();
+                    for child in &row.children {
+                        match child {
+                            Xml::Element(cell) if cell.name == "cell" => {
+                                cells.push(Content::Cell(Node {
+                                    attributes: attributes(cell, &["align", "colspan"]),
+                                    content: self.inlines(&cell.children, false)?,
+                                    ..self.node(cell, "tc1")
+                                }))
+                            }
+                            Xml::Text(text) if text.trim().is_empty() => (),
+                            _ => return Err(invalid("expected <cell> element in <row>")),
+                        }
+                    }
+                    res.push(Content::Row(Node {
+                        content: cells,
+                        ..self.node(row, "tr")
+                    }));
+                }
+                Xml::Text(text) if text.trim().is_empty() => (),
+                _ => return Err(invalid("expected <row> element in <table>")),
+            }
+        }
+        Ok(res)
+    }
+
+    fn inlines(&self, children: &[Xml], in_char: bool) -> io::Result<Vec<Content<'static>>> {
+        let mut res = Vec::new();
+        for child in children {
+            let element = match child {
+                Xml::Text(text) => {
+                    res.push(Content::from(text.to_string()));
+                    continue;
+                }
+                Xml::Element(element) => element,
+            };
+            res.push(match element.name {
+                "verse" if element.attribute("eid").is_some() => continue,
+                "verse" => Content::Verse(Node {
+                    attributes: attributes(element, &["number", "altnumber", "pubnumber"]),
+                    ..self.node(element, "v")
+                }),
+                "optbreak" => Content::OptBreak,
+                "ms" => Content::Milestone(Node {
+                    attributes: other_attributes(element),
+                    ..self.node(element, "ms")
+                }),
+                "char" if element.attribute("status") == Some("unknown") => {
+                    Content::Unknown(Node {
+                        content: self.inlines(&element.children, true)?,
+                        ..self.node(element, "w")
+                    })
+                }
+                "char" => Content::Char(Node {
+                    attributes: other_attributes(element),
+                    nested: in_char,
+                    content: self.inlines(&element.children, true)?,
+                    ..self.node(element, "w")
+                }),
+                "note" => Content::Note(Node {
+                    attributes: attributes(element, &["caller", "category"]),
+                    content: self.inlines(&element.children, false)?,
+                    ..self.node(element, "f")
+                }),
+                "figure" => Content::Figure(Node {
+                    attributes: element
+                        .attributes
+                        .iter()
+                        .filter(|(k, _)| *k != "style")
+                        .map(|(k, v)| {
+                            let k = if *k == "file" { "src" } else { k };
+                            (k.to_owned().into(), v.clone().into_owned().into())
+                        })
+                        .collect(),
+                    content: self.inlines(&element.children, false)?,
+                    ..self.node(element, "fig")
+                }),
+                "ref" => {
+                    res.extend(self.inlines(&element.children, in_char)?);
+                    continue;
+                }
+                name => return Err(invalid(format!("unexpected <{name}> element"))),
+            });
+        }
+        Ok(merge_text(res))
+    }
+
+    fn node(&self, element: &Element, style: &str) -> Node<'static> {
+        let style = element.attribute("style").unwrap_or(style);
+        Node {
+            style: style.to_owned().into(),
+            custom: is_custom(style),
+            ..Node::default()
+        }
+    }
+}
+
+fn attributes(element: &Element, names: &[&str]) -> HashMap<Cow<'static, str>, Cow<'static, str>> {
+    names
+        .iter()
+        .filter_map(|&name| {
+            let value = element.attribute(name)?.to_owned();
+            Some((name.to_owned().into(), value.into()))
+        })
+        .collect()
+}
+
+fn other_attributes(element: &Element) -> HashMap<Cow<'static, str>, Cow<'static, str>> {
+    element
+        .attributes
+        .iter()
+        .filter(|(k, _)| *k != "style")
+        .map(|(k, v)| ((*k).to_owned().into(), v.clone().into_owned().into()))
+        .collect()
+}
+
+pub(crate) fn escape(text: &str) -> Cow<'_, str> {
+    if !text.contains(['&', '<', '>', '"']) {
+        return Cow::Borrowed(text);
+    }
+    let mut res = String::with_capacity(text.len() + 8);
+    for c in text.chars() {
+        match c {
+            '&' => res.push_str("&amp;"),
+            '<' => res.push_str("&lt;"),
+            '>' => res.push_str("&gt;"),
+            '"' => res.push_str("&quot;"),
+            c => res.push(c),
+        }
+    }
+    Cow::Owned(res)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::document::{Content, Document, Node, ParseOptions, UnknownMarkers};
+
+    fn usx(usfm: &str) -> String {
+        let doc: Document = usfm.parse().expect("Document");
+        let mut out = Vec::new();
+        doc.to_usx(&mut out).expect("USX");
+        String::from_utf8(out).expect("UTF-8")
+    }
+
+    #[test]
+    fn write_usx() {
+        assert_eq!(
+            usx("\\id MRK Good News\n\
+                 \\h Mark\n\
+                 \\c 1\n\
+                 \\s1 John the Baptist\n\
+                 \\p\n\
+                 \\v 1 The beginning \\nd of\\nd* the\n\
+                 \\v 2 As it is written\\f + \\fr 1.2 \\ft Isaiah & Malachi\\f*:\n\
+                 \\q1 “I will send\n\
+                 \\q2 my messenger.”\n\
+                 \\p \\v 3 Someone \\w shouting|lemma=\"βοάω\"\\w*\n"),
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<usx version="3.0">
+<book code="MRK" style="id">Good News</book>
+<para style="h">Mark</para>
+<chapter number="1" style="c" sid="MRK 1" />
+<para style="s1">John the Baptist</para>
+<para style="p"><verse number="1" style="v" sid="MRK 1:1" />The beginning <char style="nd">of</char> the <verse eid="MRK 1:1" /><verse number="2" style="v" sid="MRK 1:2" />As it is written<note caller="+" style="f"><char style="fr">1.2 </char><char style="ft">Isaiah &amp; Malachi</char></note>:</para>
+<para style="q1">“I will send</para>
+<para style="q2">my messenger.”<verse eid="MRK 1:2" /></para>
+<para style="p"><verse number="3" style="v" sid="MRK 1:3" />Someone <char style="w" lemma="βοάω">shouting</char><verse eid="MRK 1:3" /></para>
+<chapter eid="MRK 1" />
+</usx>
+"#
+        );
+    }
+
+    #[test]
+    fn write_usx_structures() {
+        assert_eq!(
+            usx("\\id NUM\n\
+                 \\usfm 3.0\n\
+                 \\c 2\n\
+                 \\p \\v 1 Census \\qt-s |who=\"Moses\"\\*count\\qt-e\\*\n\
+                 \\tr \\th1 Tribe \\thr2 Number\n\
+                 \\tr \\tc1 Reuben \\tcr2 46,500\n\
+                 \\p \\v 2 \\fig Camp|src=\"camp.png\" size=\"col\"\\fig*\n"),
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<usx version="3.0">
+<book code="NUM" style="id"></book>
+<chapter number="2" style="c" sid="NUM 2" />
+<para style="p"><verse number="1" style="v" sid="NUM 2:1" />Census <ms style="qt-s" sid="qt-1" who="Moses" />count<ms style="qt-e" eid="qt-1" /></para>
+<table>
+<row style="tr"><cell style="th1" align="start">Tribe </cell><cell style="thr2" align="end">Number</cell></row>
+<row style="tr"><cell style="tc1" align="start">Reuben </cell><cell style="tcr2" align="end">46,500<verse eid="NUM 2:1" /></cell></row>
+</table>
+<para style="p"><verse number="2" style="v" sid="NUM 2:2" /><figure style="fig" file="camp.png" size="col">Camp</figure><verse eid="NUM 2:2" /></para>
+<chapter eid="NUM 2" />
+</usx>
+"#
+        );
+    }
+
+    fn round_trip(usfm: &str) {
+        round_trip_with(usfm, ParseOptions::default())
+    }
+
+    fn round_trip_with(usfm: &str, options: ParseOptions) {
+        let doc = Document::from_str_with(usfm, options).expect("Document");
+        let mut out = Vec::new();
+        doc.to_usx(&mut out).expect("USX");
+        assert_eq!(
+            Document::from_usx(out.as_slice()).expect("from USX").nodes,
+            doc.nodes
+        );
+    }
+
+    #[test]
+    fn read_usx() {
+        let doc = Document::from_usx(
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<usx version="3.0">
+  <book code="JHN" style="id" />
+  <chapter number="3" style="c" sid="JHN 3" />
+  <para style="p">
+    <verse number="16" style="v" sid="JHN 3:16" />For God so loved &amp; gave<verse eid="JHN 3:16" />
+  </para>
+  <chapter eid="JHN 3" />
+</usx>
+"#
+            .as_bytes(),
+        )
+        .expect("from USX");
+        assert_eq!(
+            doc.nodes,
+            Some(Node {
+                style: "usfm".into(),
+                attributes: [("version".into(), "3".into())].into(),
+                content: vec![
+                    Content::Book(Node {
+                        style: "id".into(),
+                        attributes: [("code".into(), "JHN".into())].into(),
+                        ..Default::default()
+                    }),
+                    Content::Chapter(Node {
+                        style: "c".into(),
+                        attributes: [("number".into(), "3".into())].into(),
+                        content: vec![Content::Para(Node {
+                            style: "p".into(),
+                            content: vec![
+                                "\n    ".into(),
+                                Content::Verse(Node {
+                                    style: "v".into(),
+                                    attributes: [("number".into(), "16".into())].into(),
+                                    ..Default::default()
+                                }),
+                                "For God so loved & gave\n  ".into(),
+                            ],
+                            ..Default::default()
+                        })],
+                        ..Default::default()
+                    }),
+                ],
+                ..Default::default()
+            })
+        );
+        assert!(Document::from_usx("<usx><para style=\"p\"></usx>".as_bytes()).is_err());
+        assert!(Document::from_usx("<usfm />".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn usx_round_trip() {
+        round_trip(
+            "\\id MRK Good News\n\
+             \\usfm 3.0\n\
+             \\h Mark\n\
+             \\mt1 Mark\n\
+             \\c 1\n\
+             \\s1 John the Baptist\n\
+             \\p\n\
+             \\v 1 The beginning \\nd of \\+w Lord|strong=\"H3068\"\\+w*\\nd* the\n\
+             \\v 2 As it is written\\f + \\fr 1.2 \\ft Isaiah & Malachi\\f*:\n\
+             \\q1 “I will send\n\
+             \\q2 my messenger.”\n\
+             \\li1 first\n\
+             \\li2 second\n\
+             \\c 2 \\ca 3\\ca*\n\
+             \\p \\v 1 Census \\qt-s |who=\"Moses\"\\*count\\qt-e\\*\n\
+             \\tr \\th1 Tribe \\thr2 Number\n\
+             \\tr \\tc1-2 Reuben\n\
+             \\esb \\cat People\\cat*\n\
+             \\p Sidebar\n\
+             \\esbe\n\
+             \\p \\v 2 \\fig Camp|src=\"camp.png\" size=\"col\"\\fig*\n",
+        );
+        round_trip("\\id MRK\n\\c 1\n\\zpara \\v 1 In \\zw the\\zw* \\zms\\*\n");
+    }
+
+    #[test]
+    fn usx_round_trip_unknown() {
+        let options = ParseOptions {
+            unknown_markers: UnknownMarkers::Preserve,
+            ..ParseOptions::default()
+        };
+        round_trip_with(
+            "\\id MRK\n\\c 1\n\\p \\v 1 In \\xyz the\\xyz* \\abc start\n\\yy1 odd\n",
+            options,
+        );
+    }
+}
@@ -0,0 +1,634 @@
+//! Checks of markup against the marker definitions: where each marker may
+//! occur, its attributes and `\cat` categories, and, on the source text
+//! alone, whether spans, notes, milestones and sidebars are closed.
+
+use std::{collections::HashMap, ops::Range};
+
+use crate::{
+    diagnostic::{Code, Diagnostic, Fix},
+    document::{line_starts, Content, Document, Node, Position, Span},
+    extension::{Category, Extensions},
+    tokens::{tokens, TokenKind},
+};
+
+impl Document<'_> {
+    /// Check the parsed tree against the rules in `markers`, reporting nodes
+    /// placed under a marker their `occurs_under` list does not allow and
+    /// attributes that do not match the marker's schema. In a fragment the
+    /// markers at the top, whose real parents are unknown, are not checked
+    /// for placement. Spans are empty for documents not parsed from USFM.
+    pub fn validate(&self, markers: &Extensions) -> Vec<Diagnostic> {
+        self.validate_in(markers, None)
+    }
+
+    /// As [`Document::validate`], also reporting notes and sidebars whose
+    /// `\cat` category is not one of those a project allows.
+    pub fn validate_with_categories(
+        &self,
+        markers: &Extensions,
+        categories: &[&str],
+    ) -> Vec<Diagnostic> {
+        self.validate_in(markers, Some(categories))
+    }
+
+    fn validate_in(&self, markers: &Extensions, categories: Option<&[&str]>) -> Vec<Diagnostic> {
+        let mut validator = Validator {
+            markers,
+            categories,
+            ancestors: vec!["id"],
+            fragment: self.is_fragment(),
+            diagnostics: Vec::new(),
+        };
+        if let Some(root) = &self.nodes {
+            validator.content(&root.content);
+        }
+        validator.diagnostics
+    }
+}
+
+struct Validator<'d> {
+    markers: &'d Extensions,
+    categories: Option<&'d [&'d str]>,
+    ancestors: Vec<&'d str>,
+    fragment: bool,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'d> Validator<'d> {
+    fn content(&mut self, content: &'d [Content]) {
+        for item in content {
+            match item {
+                // Grouping nodes have no marker of their own.
+                Content::List(node) | Content::Stanza(node) | Content::Table(node) => {
+                    self.content(&node.content)
+                }
+                Content::Char(node) | Content::Milestone(node) | Content::Figure(node) => {
+                    self.attributes(node);
+                    self.node(node);
+                }
+                item => {
+                    if let Some(node) = item.node() {
+                        self.node(node);
+                    }
+                }
+            }
+        }
+    }
+
+    fn node(&mut self, node: &'d Node) {
+        self.occurs_under(node);
+        self.category(node);
+        self.ancestors.push(&node.style);
+        self.content(&node.content);
+        self.ancestors.pop();
+    }
+
+    // Nested character markers may sit inside other character markers, so
+    // any enclosing marker counts as a permitted parent.
+    fn occurs_under(&mut self, node: &Node) {
+        let Some(marker) = self.markers.get(node.style.as_ref()) else {
+            return;
+        };
+        let top = self
+            .ancestors
+            .iter()
+            .all(|style| matches!(*style, "id" | "c"));
+        if (self.fragment && top)
+            || marker.occurs_under.is_empty()
+            || self
+                .ancestors
+                .iter()
+                .any(|style| marker.occurs_under.contains(*style))
+        {
+            return;
+        }
+        let parent = self.ancestors.last().copied().unwrap_or_default();
+        self.diagnostics.push(Diagnostic::error(
+            Code::UnexpectedMarker,
+            node.span.unwrap_or_default(),
+            format!("\\{} cannot occur under \\{parent}", node.style),
+        ));
+    }
+
+    fn category(&mut self, node: &Node) {
+        let (Some(allowed), Some(category)) = (self.categories, node.attributes.get("category"))
+        else {
+            return;
+        };
+        if !allowed.contains(&category.as_ref()) {
+            self.diagnostics.push(Diagnostic::error(
+                Code::InvalidAttribute,
+                node.span.unwrap_or_default(),
+                format!("unknown category {category} on \\{}", node.style),
+            ));
+        }
+    }
+
+    // User defined x- attributes and the linking attributes are allowed on
+    // any marker.
+    fn attributes(&mut self, node: &Node) {
+        let Some(marker) = self.markers.get(node.style.as_ref()) else {
+            return;
+        };
+        let mut unknown = node
+            .attributes
+            .keys()
+            .filter(|name| marker.attribute(name).is_none())
+            .filter(|name| !name.starts_with("x-") && !name.starts_with("link-"))
+            .collect::<Vec<_>>();
+        unknown.sort_unstable();
+        let mut missing = marker
+            .attributes
+            .iter()
+            .filter(|(name, optional)| !**optional && !name.ends_with('*'))
+            .filter(|(name, _)| !node.attributes.contains_key(name.as_str()))
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>();
+        missing.sort_unstable();
+        let mut invalid = node
+            .attributes
+            .iter()
+            .filter_map(|(name, value)| {
+                let expected = marker.attribute_type(name).check(value).err()?;
+                Some((name, value, expected))
+            })
+            .collect::<Vec<_>>();
+        invalid.sort_unstable();
+        let span = node.span.unwrap_or_default();
+        for name in unknown {
+            self.diagnostics.push(Diagnostic::error(
+                Code::InvalidAttribute,
+                span,
+                format!("unknown attribute {name} on \\{}", node.style),
+            ));
+        }
+        for name in missing {
+            self.diagnostics.push(Diagnostic::error(
+                Code::InvalidAttribute,
+                span,
+                format!("missing required attribute {name} on \\{}", node.style),
+            ));
+        }
+        for (name, value, expected) in invalid {
+            self.diagnostics.push(Diagnostic::error(
+                Code::InvalidAttribute,
+                span,
+                format!(
+                    "invalid value \"{value}\" for attribute {name} on \\{}, expected {expected}",
+                    node.style
+                ),
+            ));
+        }
+    }
+}
+
+/// Check that spans, notes, milestones and sidebars in `source` are closed,
+/// reporting where each unbalanced start or end marker is. Character spans
+/// must end before the next paragraph or chapter, by their end marker or the
+/// marker named by `closedby`; note character spans may be left to end at
+/// the next one. Milestones pair up by `sid` and `eid`, or by nesting when
+/// these are missing. Works on the text alone, so it can run on sources the
+/// parser rejects.
+pub fn balance(source: &str, markers: &Extensions) -> Vec<Diagnostic> {
+    let mut balance = Balance {
+        markers,
+        lines: line_starts(source),
+        source,
+        spans: Vec::new(),
+        milestone: None,
+        milestones: Vec::new(),
+        sidebar: None,
+        closing: 0,
+        fixable: true,
+        diagnostics: Vec::new(),
+    };
+    let mut key = None;
+    for token in tokens(source) {
+        let text = &source[token.range.clone()];
+        balance.closing = token.range.start;
+        match token.kind {
+            TokenKind::MarkerTag => {
+                balance.end_milestone();
+                balance.start(&text[1..], token.range);
+            }
+            TokenKind::MarkerEnd if text == "\\*" => match balance.milestone.is_some() {
+                true => balance.end_milestone(),
+                false => balance.report(
+                    Code::UnmatchedEndmarker,
+                    token.range,
+                    "\\* without a milestone".into(),
+                ),
+            },
+            TokenKind::MarkerEnd => {
+                balance.end_milestone();
+                balance.end(&text[1..text.len() - 1], token.range);
+            }
+            TokenKind::AttributeKey => key = Some(text),
+            TokenKind::AttributeValue => {
+                // An unclosed quote swallows the markers after it.
+                if text.len() < 2 || !text.ends_with('"') {
+                    balance.fixable &= !text.starts_with('"');
+                }
+                if let Some((style, _, attributes)) = &mut balance.milestone {
+                    let name = key
+                        .map(str::to_owned)
+                        .or_else(|| markers.get(style.as_str()).and_then(|m| m.default.clone()));
+                    if let Some(name) = name {
+                        attributes.insert(name, text.trim_matches('"').to_owned());
+                    }
+                }
+                key = None;
+            }
+            _ => {}
+        }
+    }
+    balance.end_milestone();
+    balance.closing = source.len();
+    balance.close_spans();
+    for (style, range, _) in std::mem::take(&mut balance.milestones) {
+        balance.report(
+            Code::MissingEndmarker,
+            range,
+            format!("\\{style} is not closed"),
+        );
+    }
+    if let Some(range) = balance.sidebar.take() {
+        balance.report(Code::MissingEndmarker, range, "\\esb without \\esbe".into());
+    }
+    balance.diagnostics.sort_by_key(|d| d.span.start);
+    balance.diagnostics
+}
+
+struct OpenSpan<'s> {
+    tag: &'s str,
+    /// The marker ending the span, without its backslash.
+    end: String,
+    range: Range<usize>,
+    /// Whether the span has to be closed explicitly.
+    required: bool,
+}
+
+struct Balance<'s> {
+    markers: &'s Extensions,
+    source: &'s str,
+    lines: Vec<usize>,
+    spans: Vec<OpenSpan<'s>>,
+    /// A milestone whose attributes are still being read.
+    milestone: Option<(String, Range<usize>, HashMap<String, String>)>,
+    /// Open start milestones with their `sid`, if any.
+    milestones: Vec<(String, Range<usize>, Option<String>)>,
+    sidebar: Option<Range<usize>>,
+    /// Where the token that may close spans starts.
+    closing: usize,
+    /// Whether the text can be trusted enough to suggest fixes.
+    fixable: bool,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'s> Balance<'s> {
+    fn position(&self, offset: usize) -> Position {
+        let line = self.lines.partition_point(|&start| start <= offset);
+        let start = self.lines[line - 1];
+        Position {
+            offset,
+            line,
+            column: self.source[start..offset].chars().count() + 1,
+        }
+    }
+
+    fn report(&mut self, code: Code, range: Range<usize>, message: String) {
+        let span = Span {
+            start: self.position(range.start),
+            end: self.position(range.end),
+        };
+        self.diagnostics
+            .push(Diagnostic::error(code, span, message));
+    }
+
+    fn start(&mut self, tag: &'s str, range: Range<usize>) {
+        let style = tag.trim_start_matches('+');
+        let marker = self.markers.get(style);
+        if let Some(n) = self
+            .spans
+            .iter()
+            .rposition(|span| span.end != span.tag && span.end == style)
+        {
+            // A marker named by an open span's `closedby`.
+            self.close_to(n);
+            return;
+        }
+        match (marker.map(|m| m.category), style) {
+            (_, "esb") => {
+                self.close_spans();
+                if let Some(open) = self.sidebar.replace(range.clone()) {
+                    self.report(Code::MissingEndmarker, open, "\\esb without \\esbe".into());
+                }
+            }
+            (_, "esbe") => {
+                self.close_spans();
+                if self.sidebar.take().is_none() {
+                    self.report(
+                        Code::UnmatchedEndmarker,
+                        range,
+                        "\\esbe without \\esb".into(),
+                    );
+                }
+            }
+            (Some(Category::Milestone), _) => {
+                self.milestone = Some((style.to_owned(), range, HashMap::new()))
+            }
+            (None, _) if style.ends_with("-s") || style.ends_with("-e") => {
+                self.milestone = Some((style.to_owned(), range, HashMap::new()))
+            }
+            (
+                Some(
+                    Category::Char
+                    | Category::ListChar
+                    | Category::IntroChar
+                    | Category::Footnote
+                    | Category::Crossreference,
+                ),
+                _,
+            )
+            | (Some(Category::Internal), "ca" | "va" | "vp" | "fig" | "cat") => {
+                self.push(tag, style, range, true)
+            }
+            (Some(Category::FootnoteChar | Category::CrossreferenceChar), _) => {
+                // Unnested note character spans end at the next one.
+                if !tag.starts_with('+') {
+                    while self.spans.last().is_some_and(|span| !span.required) {
+                        self.spans.pop();
+                    }
+                }
+                self.push(tag, style, range, false)
+            }
+            (Some(Category::Internal), "v") | (None, _) => {}
+            // Paragraphs, cells, chapters and the like.
+            _ => self.close_spans(),
+        }
+    }
+
+    fn push(&mut self, tag: &'s str, style: &'s str, range: Range<usize>, required: bool) {
+        let end = self
+            .markers
+            .closed_by(style)
+            .map_or_else(|| tag.to_owned(), str::to_owned);
+        self.spans.push(OpenSpan {
+            tag,
+            end,
+            range,
+            required,
+        });
+    }
+
+    fn end(&mut self, tag: &str, range: Range<usize>) {
+        match self.spans.iter().rposition(|span| span.end == tag) {
+            Some(n) => self.close_to(n),
+            None => self.report(
+                Code::UnmatchedEndmarker,
+                range,
+                format!("\\{tag}* without \\{tag}"),
+            ),
+        }
+    }
+
+    /// Close the span at `n` and report those opened inside it and not
+    /// closed.
+    fn close_to(&mut self, n: usize) {
+        for span in self.spans.split_off(n).into_iter().skip(1) {
+            if span.required {
+                self.missing(span);
+            }
+        }
+    }
+
+    fn close_spans(&mut self) {
+        for span in std::mem::take(&mut self.spans) {
+            if span.required {
+                self.missing(span);
+            }
+        }
+    }
+
+    fn missing(&mut self, span: OpenSpan) {
+        let message = match span.end == span.tag {
+            true => format!("\\{} is missing its \\{}*", span.tag, span.tag),
+            false => format!("\\{} is missing its \\{}", span.tag, span.end),
+        };
+        // End the span after its text, before the space leading up to
+        // whatever closed it.
+        let at = self.source[..self.closing]
+            .trim_end()
+            .len()
+            .max(span.range.end);
+        let fix = (self.fixable && span.end == span.tag).then(|| Fix {
+            span: Span {
+                start: self.position(at),
+                end: self.position(at),
+            },
+            replacement: format!("\\{}*", span.tag),
+        });
+        self.report(Code::MissingEndmarker, span.range, message);
+        if let Some(diagnostic) = self.diagnostics.last_mut() {
+            diagnostic.fix = fix;
+        }
+    }
+
+    fn end_milestone(&mut self) {
+        let Some((style, range, attributes)) = self.milestone.take() else {
+            return;
+        };
+        let marker = self.markers.get(style.as_str());
+        let closes = marker.and_then(|m| m.closes.clone()).or_else(|| {
+            (marker.is_none() && style.ends_with("-e"))
+                .then(|| format!("{}-s", style.trim_end_matches("-e")))
+        });
+        let opens = marker.is_some_and(|m| m.closedby.is_some())
+            || (marker.is_none() && style.ends_with("-s"));
+        if opens {
+            self.milestones
+                .push((style, range, attributes.get("sid").cloned()));
+            return;
+        }
+        let Some(start) = closes else {
+            return;
+        };
+        let found = match attributes.get("eid") {
+            Some(eid) => self
+                .milestones
+                .iter()
+                .rposition(|(s, _, sid)| *s == start && sid.as_deref() == Some(eid.as_str())),
+            None => self.milestones.iter().rposition(|(s, _, _)| *s == start),
+        };
+        match (found, attributes.get("eid")) {
+            (Some(n), _) => {
+                self.milestones.remove(n);
+            }
+            (None, Some(eid)) => self.report(
+                Code::UnmatchedEndmarker,
+                range,
+                format!("\\{style} has no \\{start} with sid {eid}"),
+            ),
+            (None, None) => self.report(
+                Code::UnmatchedEndmarker,
+                range,
+                format!("\\{style} without \\{start}"),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::balance;
+    use crate::{
+        diagnostic::Code,
+        document::{Content, Document, Node},
+        extension::Extensions,
+    };
+
+    #[test]
+    fn occurs_under() {
+        let markers: Extensions = "\\marker p\n\\category versepara\n\\occursunder c esb\n\n\
+                                   \\marker ft\n\\category footnotechar\n\\occursunder f fe\n\n\
+                                   \\marker nd\n\\category char\n\\occursunder p q1\n"
+            .parse()
+            .expect("Extensions");
+        let mut doc = "\\id MRK\n\\mt1 The \\nd Lord\\nd*\n\\p Before\n\\c 1\n\\p \\v 1 In \\nd the\\nd*\\f + \\ft fine\\f*\n".parse::<Document>()
+        .expect("Document");
+        let diagnostics = doc.validate(&markers);
+        assert_eq!(
+            diagnostics
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            [
+                "2:10: error[unexpected-marker]: \\nd cannot occur under \\mt1",
+                "3:1: error[unexpected-marker]: \\p cannot occur under \\id",
+            ]
+        );
+
+        let root = doc.nodes.as_mut().expect("root");
+        root.content
+            .retain(|item| !matches!(item, Content::Para(_)));
+        let Some(Content::Chapter(chapter)) = root.content.last_mut() else {
+            panic!("expected a chapter");
+        };
+        chapter.content.push(Content::Para(Node {
+            style: "ft".into(),
+            ..Node::default()
+        }));
+        let diagnostics = doc.validate(&markers);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].code, Code::UnexpectedMarker);
+        assert_eq!(diagnostics[0].message, "\\ft cannot occur under \\c");
+    }
+
+    #[test]
+    fn categories() {
+        let doc: Document =
+            "\\id MRK\n\\c 1\n\\p \\v 1 Text\\f + \\cat People\\cat* \\ft Note\\f*\n\
+                             \\esb \\cat Places\\cat*\n\\p Sidebar\n\\esbe\n"
+                .parse()
+                .expect("Document");
+        let written = doc.to_string();
+        assert!(written.contains("\\f + \\cat People\\cat* \\ft Note\\f*"));
+        assert_eq!(
+            written.parse::<Document>().expect("Document").nodes,
+            doc.nodes
+        );
+
+        let markers = Extensions::usfm(Default::default());
+        assert!(doc.validate(markers).is_empty());
+        assert!(doc
+            .validate_with_categories(markers, &["People", "Places"])
+            .is_empty());
+        assert_eq!(
+            doc.validate_with_categories(markers, &["People"])
+                .iter()
+                .map(|d| (d.code, d.message.as_str()))
+                .collect::<Vec<_>>(),
+            [(Code::InvalidAttribute, "unknown category Places on \\esb")]
+        );
+    }
+
+    #[test]
+    fn attributes() {
+        let doc = "\\id MRK\n\\usfm 3.0\n\\c 1\n\\p \\v 1 \\w grace|lemma=\"charis\" x-note=\"a\"\\w* \
+             \\rb 漢|gloss=\"han\"\\rb* \\rb 字|glos=\"zi\"\\rb* \\qt-s |who=\"Jesus\" mood=\"calm\"\\*Peace\\qt-e\\*\n\
+             \\p \\fig Map|src=\"map.png\" size=\"huge\"\\fig*\n".parse::<Document>()
+        .expect("Document");
+        let diagnostics = doc.validate(Extensions::usfm(Default::default()));
+        assert_eq!(
+            diagnostics
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            [
+                "4:69: error[invalid-attribute]: unknown attribute glos on \\rb",
+                "4:69: error[invalid-attribute]: missing required attribute gloss on \\rb",
+                "4:89: error[invalid-attribute]: unknown attribute mood on \\qt-s",
+                "5:4: error[invalid-attribute]: invalid value \"huge\" for attribute size on \\fig, \
+                 expected col, span or page",
+            ]
+        );
+
+        let error = "\\id MRK\n\\c 1\n\\p \\nd Lord|god\\nd*\n"
+            .parse::<Document>()
+            .expect_err("default attribute");
+        assert_eq!(
+            error.to_string(),
+            "3:12: error[invalid-attribute]: default attribute given but none is defined"
+        );
+    }
+
+    #[test]
+    fn balanced() {
+        let markers = Extensions::usfm(Default::default());
+        let check = |source: &str| {
+            balance(source, markers)
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            check(
+                "\\id MRK\n\\c 1\n\\p \\v 1 \\wj Come \\+nd Lord\\+nd*\\wj*\\f + \\fr 1.1 \\ft a \\fq b\\f*\n\
+                 \\qt-s |sid=\"a\"\\*\\qt-s\\*x\\qt-e\\*\\qt-e |eid=\"a\"\\*\n\\esb\n\\p side\n\\esbe\n"
+            ),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            check(
+                "\\id MRK\n\\c 1\n\\p \\v 1 \\wj Come \\+nd Lord\\wj* \\bd bold\n\\p end\\it*\\f + \\ft a\n\
+                 \\p \\qt-s |sid=\"a\"\\*x\\qt-e |eid=\"b\"\\*\\ts-e\\*\n\\esb\n"
+            ),
+            [
+                "3:18: error[missing-endmarker]: \\+nd is missing its \\+nd*",
+                "3:32: error[missing-endmarker]: \\bd is missing its \\bd*",
+                "4:7: error[unmatched-endmarker]: \\it* without \\it",
+                "4:11: error[missing-endmarker]: \\f is missing its \\f*",
+                "5:4: error[missing-endmarker]: \\qt-s is not closed",
+                "5:21: error[unmatched-endmarker]: \\qt-e has no \\qt-s with sid b",
+                "5:37: error[unmatched-endmarker]: \\ts-e without \\ts-s",
+                "6:1: error[missing-endmarker]: \\esb without \\esbe",
+            ]
+        );
+    }
+
+    #[test]
+    fn balanced_line_endings() {
+        let markers = Extensions::usfm(Default::default());
+        let source = "\\id MRK\n\\c 1\n\\p \\v 1 \\bd bold\n\\p end\\it*\n\\esb\n";
+        let check = |source: &str| {
+            balance(source, markers)
+                .iter()
+                .map(|d| (d.span.start.line, d.span.start.column))
+                .collect::<Vec<_>>()
+        };
+        let expected = [(3, 9), (4, 7), (5, 1)];
+        assert_eq!(check(source), expected);
+        assert_eq!(check(&source.replace('\n', "\r\n")), expected);
+        assert_eq!(check(&source.replace('\n', "\r")), expected);
+    }
+}
@@ -0,0 +1,214 @@
+//! Where a verse or chapter is in a document. Verses are milestones in the
+//! tree, as in USX, so a verse can run on through several paragraphs and a
+//! paragraph can hold several verses; the span of one is worked out from
+//! the markers either side of it.
+
+use std::ops::Range;
+
+use crate::{
+    document::{Content, Document, Node, Position, Span, State},
+    extension::{Category, Extensions},
+    reference::Reference,
+    versification::verse_range,
+};
+
+/// The content of a verse, or of a whole chapter.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VerseSpan<'d> {
+    /// The `\v` marker starting the verse, or the `\c` marker of a chapter.
+    pub marker: &'d Node<'d>,
+    /// The runs of content making up the verse, each a range of indexes
+    /// into the content of a paragraph or other block, in document order.
+    pub parts: Vec<(&'d Node<'d>, Range<usize>)>,
+    /// Where the verse is in the source, from its marker up to the next
+    /// verse, chapter or heading, or to the end of its last paragraph. Only
+    /// set for documents parsed from USFM.
+    pub span: Option<Span>,
+}
+
+impl<'d> VerseSpan<'d> {
+    /// The items of every part in turn, starting with the verse marker.
+    pub fn content(&self) -> impl Iterator<Item = &'d Content<'d>> + '_ {
+        self.parts
+            .iter()
+            .flat_map(|(block, range)| &block.content[range.clone()])
+    }
+}
+
+impl Document<'_> {
+    /// The span of a verse, or of a chapter for a reference without a verse.
+    /// A verse given with others, as in `\v 16-17`, has the span of them all.
+    pub fn verse_span(&self, reference: &Reference) -> Option<VerseSpan<'_>> {
+        if self.book() != Some(reference.book) {
+            return None;
+        }
+        let root = self.root()?;
+        let Some(verse) = reference.verse else {
+            return root.content.iter().find_map(|item| match item {
+                Content::Chapter(node) if number(node) == Some(reference.chapter) => {
+                    Some(VerseSpan {
+                        marker: node,
+                        parts: vec![(node, 0..node.content.len())],
+                        span: node.span,
+                    })
+                }
+                _ => None,
+            });
+        };
+        let mut finder = Finder {
+            markers: State::usfm_ext(),
+            chapter: reference.chapter,
+            verse,
+            current: 0,
+            progress: Progress::Before,
+            marker: None,
+            parts: Vec::new(),
+            end: None,
+        };
+        finder.block(root);
+        if finder.progress == Progress::Within {
+            finder.end = finder
+                .parts
+                .last()
+                .and_then(|(block, _)| Some(block.span?.end));
+        }
+        let marker = finder.marker?;
+        Some(VerseSpan {
+            marker,
+            parts: finder.parts,
+            span: marker.span.zip(finder.end).map(|(start, end)| Span {
+                start: start.start,
+                end,
+            }),
+        })
+    }
+}
+
+fn number(node: &Node) -> Option<u32> {
+    node.attributes.get("number")?.parse().ok()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Progress {
+    Before,
+    Within,
+    Done,
+}
+
+struct Finder<'d> {
+    markers: &'static Extensions,
+    chapter: u32,
+    verse: u32,
+    // The chapter being walked through.
+    current: u32,
+    progress: Progress,
+    marker: Option<&'d Node<'d>>,
+    parts: Vec<(&'d Node<'d>, Range<usize>)>,
+    end: Option<Position>,
+}
+
+// Whether a paragraph is a heading or other block ending the verse before it.
+pub(crate) fn is_heading(markers: &Extensions, node: &Node) -> bool {
+    markers.get(node.style.as_ref()).is_some_and(|marker| {
+        matches!(
+            marker.category,
+            Category::SectionPara | Category::Title | Category::Header | Category::Introduction
+        )
+    })
+}
+
+impl<'d> Finder<'d> {
+    // The verse ends where `node` starts.
+    fn stop(&mut self, node: &Node) {
+        if self.progress == Progress::Within {
+            self.progress = Progress::Done;
+            self.end = node.span.map(|span| span.start);
+        }
+    }
+
+    fn push(&mut self, block: &'d Node<'d>, n: usize) {
+        match self.parts.last_mut() {
+            Some((last, range)) if std::ptr::eq(*last, block) && range.end == n => range.end += 1,
+            _ => self.parts.push((block, n..n + 1)),
+        }
+    }
+
+    fn block(&mut self, block: &'d Node<'d>) {
+        for (n, item) in block.content.iter().enumerate() {
+            if self.progress == Progress::Done {
+                return;
+            }
+            match item {
+                Content::Chapter(node) => {
+                    self.stop(node);
+                    self.current = number(node).unwrap_or(self.current);
+                    self.block(node);
+                }
+                Content::Verse(node) => {
+                    self.stop(node);
+                    let found = self.current == self.chapter
+                        && node
+                            .attributes
+                            .get("number")
+                            .and_then(|n| verse_range(n))
+                            .is_some_and(|(first, last)| (first..=last).contains(&self.verse));
+                    if found && self.progress == Progress::Before {
+                        self.progress = Progress::Within;
+                        self.marker = Some(node);
+                        self.push(block, n);
+                    }
+                }
+                Content::Para(node) if is_heading(self.markers, node) => self.stop(node),
+                Content::Para(node)
+                | Content::List(node)
+                | Content::Stanza(node)
+                | Content::Table(node)
+                | Content::Row(node)
+                | Content::Cell(node)
+                | Content::Sidebar(node)
+                | Content::Periph(node) => self.block(node),
+                Content::Book(_) => {}
+                _ if self.progress == Progress::Within => self.push(block, n),
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{books, document::Document, reference::Reference};
+
+    #[test]
+    fn verse_span() {
+        let source = "\\id MRK\n\\c 1\n\\p \\v 1 The beginning. \\v 2 As it is written,\n\
+                      \\q1 “I will send\n\\q2 my messenger.”\n\\s1 John\n\\p \\v 3 A voice\n\
+                      \\c 2\n\\p \\v 1 Again \\v 2-3 Many\n";
+        let doc = source.parse::<Document>().expect("Document");
+        let mrk = |chapter, verse| Reference::new(books::get("MRK").unwrap(), chapter, verse);
+        let text = |chapter, verse| {
+            let span = doc.verse_span(&mrk(chapter, verse)).expect("verse");
+            source[span.span.expect("span").range()].to_owned()
+        };
+
+        assert_eq!(text(1, Some(1)), "\\v 1 The beginning. ");
+        assert_eq!(
+            text(1, Some(2)),
+            "\\v 2 As it is written,\n\\q1 “I will send\n\\q2 my messenger.”\n"
+        );
+        assert_eq!(text(1, Some(3)), "\\v 3 A voice\n");
+        assert_eq!(text(2, Some(3)), "\\v 2-3 Many\n");
+        assert!(text(2, None).starts_with("\\c 2\n"));
+        assert!(doc.verse_span(&mrk(1, Some(4))).is_none());
+
+        let span = doc.verse_span(&mrk(1, Some(2))).expect("verse");
+        assert_eq!(
+            span.parts
+                .iter()
+                .map(|(block, range)| (block.style.as_ref(), range.len()))
+                .collect::<Vec<_>>(),
+            [("p", 2), ("q1", 1), ("q2", 1)]
+        );
+        assert_eq!(span.content().count(), 4);
+    }
+}
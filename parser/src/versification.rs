@@ -0,0 +1,667 @@
+//! Versification schemes in the Paratext `.vrs` format, and checking a
+//! document's chapter and verse numbers against them.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::{self, Read},
+    ops::RangeInclusive,
+    path::Path,
+    str::FromStr,
+};
+
+use nom::{
+    bytes::complete::{tag, take},
+    character::complete::{char, space0, space1, u32},
+    combinator::{eof, map_opt, opt},
+    error::{context, convert_error},
+    multi::many1,
+    sequence::{delimited, preceded, separated_pair, terminated},
+    Finish, Parser,
+};
+
+use super::Result;
+use crate::{
+    books,
+    diagnostic::{Code, Diagnostic},
+    document::{Content, Document, Node},
+    reference::Reference,
+};
+
+/// The standard schemes shipped with Paratext.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Scheme {
+    English,
+    Original,
+    Septuagint,
+    Vulgate,
+    RussianCanonical,
+}
+
+impl Scheme {
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            Scheme::English => "eng.vrs",
+            Scheme::Original => "org.vrs",
+            Scheme::Septuagint => "lxx.vrs",
+            Scheme::Vulgate => "vul.vrs",
+            Scheme::RussianCanonical => "rsc.vrs",
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Versification {
+    /// The last verse of each chapter, by book code.
+    books: HashMap<String, Vec<u32>>,
+    excluded: HashSet<(String, u32, u32)>,
+    /// Verses numbered differently from the original scheme.
+    mappings: HashMap<(String, u32, u32), Reference>,
+}
+
+fn book(input: &str) -> Result<'_, &str> {
+    context("book code", take(3usize)).parse(input)
+}
+
+fn verse(input: &str) -> Result<'_, (u32, u32)> {
+    separated_pair(u32, char(':'), u32).parse(input)
+}
+
+// GEN 1:31 2:25 3:24 ...
+fn chapters(input: &str) -> Result<'_, (&str, Vec<u32>)> {
+    let chapter = preceded(space1, verse);
+    let chapters = map_opt(many1(chapter), |chapters| {
+        chapters
+            .iter()
+            .enumerate()
+            .all(|(n, (c, _))| *c as usize == n + 1)
+            .then(|| chapters.into_iter().map(|(_, v)| v).collect())
+    });
+    terminated(book.and(context("chapters", chapters)), space0.and(eof)).parse(input)
+}
+
+// -ACT 8:37
+fn exclusion(input: &str) -> Result<'_, (&str, (u32, u32))> {
+    preceded(
+        tag("-"),
+        terminated(separated_pair(book, space1, verse), space0.and(eof)),
+    )
+    .parse(input)
+}
+
+// Book, chapter and first and last verse.
+type Verses<'i> = (&'i str, u32, u32, u32);
+
+// BOOK 3:1 or BOOK 3:1-8
+fn verses(input: &str) -> Result<'_, Verses<'_>> {
+    let (input, (book, (chapter, first))) = separated_pair(book, space1, verse).parse(input)?;
+    let (input, last) = opt(preceded(char('-'), u32)).parse(input)?;
+    Ok((input, (book, chapter, first, last.unwrap_or(first))))
+}
+
+// PSA 3:1-8 = PSA 3:2-9
+fn mapping(input: &str) -> Result<'_, (Verses<'_>, Verses<'_>)> {
+    terminated(
+        separated_pair(verses, delimited(space0, char('='), space0), verses),
+        space0.and(eof),
+    )
+    .parse(input)
+}
+
+impl FromStr for Versification {
+    type Err = io::Error;
+
+    /// Mapping lines (`GEN 31:55 = GEN 32:1`) relate verses to the original
+    /// scheme; a range maps verse by verse onto a range of the same length,
+    /// or wholly onto a single verse.
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let mut res = Versification::default();
+        for line in s.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = |e| io::Error::new(io::ErrorKind::InvalidData, convert_error(line, e));
+            if line.contains('=') {
+                let (_, (from, to)) = mapping(line).finish().map_err(invalid)?;
+                res.insert_mapping(line, from, to)?;
+            } else if line.starts_with('-') {
+                let (_, (book, (chapter, verse))) = exclusion(line).finish().map_err(invalid)?;
+                res.excluded.insert((book.to_owned(), chapter, verse));
+            } else {
+                let (_, (book, chapters)) = chapters(line).finish().map_err(invalid)?;
+                res.books.insert(book.to_owned(), chapters);
+            }
+        }
+        Ok(res)
+    }
+}
+
+impl Versification {
+    fn insert_mapping(
+        &mut self,
+        line: &str,
+        (book, chapter, first, last): Verses,
+        (to_book, to_chapter, to_first, to_last): Verses,
+    ) -> io::Result<()> {
+        let invalid = |message: &str| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("{line}: {message}"))
+        };
+        let to_book = books::get(to_book).ok_or_else(|| invalid("unknown book"))?;
+        let single = to_first == to_last;
+        if last < first || to_last < to_first || (!single && to_last - to_first != last - first) {
+            return Err(invalid("mismatched verse ranges"));
+        }
+        for verse in verse_numbers((first, last)) {
+            let to = if single {
+                to_first
+            } else {
+                to_first + (verse - first)
+            };
+            self.mappings.insert(
+                (book.to_owned(), chapter, verse),
+                Reference::new(to_book, to_chapter, Some(to)),
+            );
+        }
+        Ok(())
+    }
+
+    #[inline]
+    pub fn from_reader<R: Read>(reader: R) -> io::Result<Self> {
+        io::read_to_string(reader)?.parse()
+    }
+
+    /// Load a standard scheme from a directory holding the Paratext `.vrs`
+    /// files, such as a Paratext installation.
+    pub fn load<P: AsRef<Path>>(scheme: Scheme, dir: P) -> io::Result<Self> {
+        Self::from_reader(File::open(dir.as_ref().join(scheme.file_name()))?)
+    }
+
+    pub fn last_chapter(&self, book: &str) -> Option<u32> {
+        Some(self.books.get(book)?.len() as u32)
+    }
+
+    pub fn last_verse(&self, book: &str, chapter: u32) -> Option<u32> {
+        let chapters = self.books.get(book)?;
+        chapters
+            .get(usize::try_from(chapter).ok()?.checked_sub(1)?)
+            .copied()
+    }
+
+    /// The verse in the original scheme that a verse of this scheme
+    /// corresponds to. Chapter references and verses without a mapping are
+    /// returned unchanged.
+    pub fn to_original(&self, reference: &Reference) -> Reference {
+        let Some(verse) = reference.verse else {
+            return *reference;
+        };
+        let key = (reference.book.code.to_owned(), reference.chapter, verse);
+        self.mappings.get(&key).copied().unwrap_or(*reference)
+    }
+
+    /// Whether the scheme leaves a verse out, as with textual variants.
+    pub fn is_excluded(&self, book: &str, chapter: u32, verse: u32) -> bool {
+        self.excluded.contains(&(book.to_owned(), chapter, verse))
+    }
+}
+
+impl Document<'_> {
+    /// Check chapter and verse numbers against a versification scheme,
+    /// reporting numbers past the end of a book or chapter, verses out of
+    /// order and, as warnings, verses missing from a chapter, except in a
+    /// fragment. Spans are empty for documents not parsed from USFM.
+    pub fn check_versification(&self, versification: &Versification) -> Vec<Diagnostic> {
+        let mut res = Vec::new();
+        let (Some(book), Some(root)) = (self.book(), &self.nodes) else {
+            return res;
+        };
+        let Some(last_chapter) = versification.last_chapter(book.code) else {
+            return res;
+        };
+        for chapter in root.content.iter().filter_map(|item| match item {
+            Content::Chapter(node) => Some(node),
+            _ => None,
+        }) {
+            let span = chapter.span.unwrap_or_default();
+            let Some(number) = chapter
+                .attributes
+                .get("number")
+                .and_then(|n| n.parse().ok())
+            else {
+                continue;
+            };
+            if number > last_chapter {
+                res.push(Diagnostic::error(
+                    Code::OutOfBounds,
+                    span,
+                    format!("{} has {last_chapter} chapters, not {number}", book.code),
+                ));
+                continue;
+            }
+            let last_verse = versification
+                .last_verse(book.code, number)
+                .unwrap_or_default();
+            let mut verses = Vec::new();
+            collect_verses(&chapter.content, &mut verses);
+            let mut seen = Numbers::default();
+            let mut previous = 0;
+            for verse in verses {
+                let span = verse.span.unwrap_or_default();
+                let Some((first, last)) =
+                    verse.attributes.get("number").and_then(|n| verse_range(n))
+                else {
+                    continue;
+                };
+                if last > last_verse {
+                    res.push(Diagnostic::error(
+                        Code::OutOfBounds,
+                        span,
+                        format!("{} {number} has {last_verse} verses, not {last}", book.code),
+                    ));
+                }
+                if first <= previous {
+                    res.push(Diagnostic::error(
+                        Code::OutOfOrder,
+                        span,
+                        format!("verse {first} follows verse {previous}"),
+                    ));
+                }
+                previous = previous.max(last);
+                seen.insert((first, last));
+            }
+            let missing = seen.missing(1, last_verse, |v| {
+                versification.is_excluded(book.code, number, v)
+            });
+            if !missing.is_empty() && !self.is_fragment() {
+                res.push(Diagnostic::warning(
+                    Code::MissingVerse,
+                    span,
+                    format!(
+                        "{} {number} is missing verses {}",
+                        book.code,
+                        missing.join(", ")
+                    ),
+                ));
+            }
+        }
+        res
+    }
+}
+
+impl Document<'_> {
+    /// Check chapter and verse numbering for the most common data errors:
+    /// verses before the first chapter, chapters and verses given twice,
+    /// verse ranges overlapping earlier verses and, as warnings, chapters and
+    /// verses missing. Without a versification a chapter or verse counts as
+    /// missing only when a later one is present; with one, the last chapter
+    /// and verse of the scheme are expected too, leaving out excluded
+    /// verses. A fragment is only checked for gaps between the chapters and
+    /// verses it has, and may start with verses before any chapter. Spans
+    /// are empty for documents not parsed from USFM.
+    pub fn check_continuity(&self, versification: Option<&Versification>) -> Vec<Diagnostic> {
+        let mut res = Vec::new();
+        let Some(root) = &self.nodes else {
+            return res;
+        };
+        let code = self.book().map(|book| book.code).unwrap_or_default();
+        let fragment = self.is_fragment();
+        let versification = versification.filter(|_| self.book().is_some() && !fragment);
+
+        let mut early = Vec::new();
+        for item in &root.content {
+            match item {
+                Content::Chapter(_) => {}
+                Content::Verse(node) => early.push(node),
+                item => collect_verses(item.node().map_or(&[], |n| &n.content), &mut early),
+            }
+        }
+        for verse in early.into_iter().filter(|_| !fragment) {
+            res.push(Diagnostic::error(
+                Code::VerseBeforeChapter,
+                verse.span.unwrap_or_default(),
+                format!(
+                    "verse {} before the first chapter",
+                    verse.attributes.get("number").map_or("", |n| n.as_ref())
+                ),
+            ));
+        }
+
+        let mut chapters = Numbers::default();
+        for chapter in root.content.iter().filter_map(|item| match item {
+            Content::Chapter(node) => Some(node),
+            _ => None,
+        }) {
+            let span = chapter.span.unwrap_or_default();
+            let Some(number) = chapter
+                .attributes
+                .get("number")
+                .and_then(|n| n.parse::<u32>().ok())
+            else {
+                continue;
+            };
+            if chapters.contains(number) {
+                res.push(Diagnostic::error(
+                    Code::DuplicateNumber,
+                    span,
+                    format!("chapter {number} is repeated"),
+                ));
+            }
+            chapters.insert((number, number));
+            let mut verses = Vec::new();
+            collect_verses(&chapter.content, &mut verses);
+            let mut seen = Numbers::default();
+            for verse in verses {
+                let span = verse.span.unwrap_or_default();
+                let Some(text) = verse.attributes.get("number") else {
+                    continue;
+                };
+                let Some((first, last)) = verse_range(text) else {
+                    continue;
+                };
+                if first == last && seen.contains(first) {
+                    res.push(Diagnostic::error(
+                        Code::DuplicateNumber,
+                        span,
+                        format!("verse {first} is repeated"),
+                    ));
+                } else if seen.overlaps((first, last)) {
+                    res.push(Diagnostic::error(
+                        Code::OverlappingVerses,
+                        span,
+                        format!("verses {text} overlap an earlier verse"),
+                    ));
+                }
+                seen.insert((first, last));
+            }
+            let expected = versification.and_then(|v| v.last_verse(code, number));
+            let last_verse = seen.max().into_iter().chain(expected).max().unwrap_or(0);
+            let first_verse = match fragment {
+                true => seen.min().unwrap_or(1),
+                false => 1,
+            };
+            let missing = seen.missing(first_verse, last_verse, |v| {
+                versification.is_some_and(|vrs| vrs.is_excluded(code, number, v))
+            });
+            if !missing.is_empty() {
+                res.push(Diagnostic::warning(
+                    Code::MissingVerse,
+                    span,
+                    format!("{code} {number} is missing verses {}", missing.join(", ")),
+                ));
+            }
+        }
+
+        let expected = versification.and_then(|v| v.last_chapter(code));
+        let last_chapter = chapters
+            .max()
+            .into_iter()
+            .chain(expected)
+            .max()
+            .unwrap_or(0);
+        let first_chapter = match fragment {
+            true => chapters.min().unwrap_or(1),
+            false => 1,
+        };
+        let missing = chapters.missing(first_chapter, last_chapter, |_| false);
+        if !missing.is_empty() {
+            let span = root
+                .content
+                .first()
+                .and_then(Content::node)
+                .and_then(|n| n.span);
+            res.push(Diagnostic::warning(
+                Code::MissingChapter,
+                span.unwrap_or_default(),
+                format!("{code} is missing chapters {}", missing.join(", ")),
+            ));
+        }
+        res.sort_by_key(|d| d.span.start);
+        res
+    }
+}
+
+fn collect_verses<'d>(content: &'d [Content<'d>], verses: &mut Vec<&'d Node<'d>>) {
+    for item in content {
+        match item {
+            Content::Verse(node) => verses.push(node),
+            item => {
+                if let Some(node) = item.node() {
+                    collect_verses(&node.content, verses);
+                }
+            }
+        }
+    }
+}
+
+// Chapter or verse numbers seen so far, kept as sorted, disjoint ranges so
+// that a mistyped range such as `1-4000000000` costs no more than a single
+// verse. A reversed range such as `5-3` holds no numbers.
+#[derive(Debug, Default)]
+struct Numbers(Vec<(u32, u32)>);
+
+impl Numbers {
+    fn contains(&self, n: u32) -> bool {
+        self.overlaps((n, n))
+    }
+
+    fn overlaps(&self, (first, last): (u32, u32)) -> bool {
+        first <= last && self.0.iter().any(|&(a, b)| a <= last && first <= b)
+    }
+
+    fn insert(&mut self, (first, last): (u32, u32)) {
+        if first > last {
+            return;
+        }
+        // The ranges that overlap or adjoin this one are merged into it.
+        let start = self
+            .0
+            .partition_point(|&(_, b)| b.saturating_add(1) < first);
+        let end = self
+            .0
+            .partition_point(|&(a, _)| a <= last.saturating_add(1));
+        let merged = self.0[start..end]
+            .iter()
+            .fold((first, last), |(first, last), &(a, b)| {
+                (first.min(a), last.max(b))
+            });
+        self.0.splice(start..end, [merged]);
+    }
+
+    fn min(&self) -> Option<u32> {
+        self.0.first().map(|&(first, _)| first)
+    }
+
+    fn max(&self) -> Option<u32> {
+        self.0.last().map(|&(_, last)| last)
+    }
+
+    // The numbers from `first` to `last` that were not seen and that `skip`
+    // does not leave out, for a diagnostic. A gap too long for any chapter
+    // is given as one range rather than number by number.
+    fn missing(&self, first: u32, last: u32, skip: impl Fn(u32) -> bool) -> Vec<String> {
+        let mut gaps = Vec::new();
+        let mut next = u64::from(first);
+        for &(a, b) in &self.0 {
+            if u64::from(a) > next && next <= u64::from(last) {
+                gaps.push((next as u32, (a - 1).min(last)));
+            }
+            next = next.max(u64::from(b) + 1);
+        }
+        if next <= u64::from(last) {
+            gaps.push((next as u32, last));
+        }
+        let mut res = Vec::new();
+        for (first, last) in gaps {
+            if last - first < MAX_VERSE {
+                res.extend((first..=last).filter(|&n| !skip(n)).map(|n| n.to_string()));
+            } else {
+                res.push(format!("{first}-{last}"));
+            }
+        }
+        res
+    }
+}
+
+// Verse numbers may be ranges or carry a segment letter: 3, 4-6, 7a.
+pub(crate) fn verse_range(number: &str) -> Option<(u32, u32)> {
+    let digits = |s: &str| {
+        s.trim_end_matches(|c: char| c.is_alphabetic())
+            .parse::<u32>()
+            .ok()
+    };
+    match number.split_once('-') {
+        Some((first, last)) => Some((digits(first)?, digits(last)?)),
+        None => digits(number).map(|n| (n, n)),
+    }
+}
+
+// No chapter has more than 176 verses, so a range running past this is a
+// typo and is cut short rather than expanded in full.
+pub(crate) const MAX_VERSE: u32 = 200;
+
+// The verses a range from `verse_range` covers. A reversed range such as
+// `5-3` covers none.
+pub(crate) fn verse_numbers((first, last): (u32, u32)) -> RangeInclusive<u32> {
+    first..=last.min(MAX_VERSE.max(first))
+}
+
+#[cfg(test)]
+mod test {
+    use super::Versification;
+    use crate::{books, document::Document, reference::Reference};
+
+    const VRS: &str = "# Versification  \"Test\"\n\
+                       JUD 1:25\n\
+                       3JN 1:15\n\
+                       MRK 1:45 2:28 3:35\n\
+                       -MRK 1:3\n\
+                       MRK 1:46 = MRK 2:1\n";
+
+    #[test]
+    fn parse_vrs() {
+        let vrs: Versification = VRS.parse().expect("Versification");
+        assert_eq!(vrs.last_chapter("MRK"), Some(3));
+        assert_eq!(vrs.last_verse("MRK", 2), Some(28));
+        assert_eq!(vrs.last_verse("MRK", 4), None);
+        assert_eq!(vrs.last_verse("MRK", 0), None);
+        assert_eq!(vrs.last_chapter("GEN"), None);
+        assert!(vrs.is_excluded("MRK", 1, 3));
+        let mrk = |c, v| Reference::new(books::get("MRK").unwrap(), c, Some(v));
+        assert_eq!(vrs.to_original(&mrk(1, 46)), mrk(2, 1));
+        assert_eq!(vrs.to_original(&mrk(1, 45)), mrk(1, 45));
+        let vrs: Versification = "PSA 3:1-8 = PSA 3:2-9\nPSA 51:1-2 = PSA 51:2"
+            .parse()
+            .expect("Versification");
+        let psa = |c, v| Reference::new(books::get("PSA").unwrap(), c, Some(v));
+        assert_eq!(vrs.to_original(&psa(3, 8)), psa(3, 9));
+        assert_eq!(vrs.to_original(&psa(51, 1)), psa(51, 2));
+        assert!("PSA 3:1-8 = PSA 3:2-5".parse::<Versification>().is_err());
+        assert!("PSA 3:1-2 = PSA 3:9-2".parse::<Versification>().is_err());
+        let vrs: Versification = "PSA 3:1-4000000000 = PSA 3:2"
+            .parse()
+            .expect("Versification");
+        assert_eq!(vrs.to_original(&psa(3, 200)), psa(3, 2));
+        assert_eq!(vrs.to_original(&psa(3, 201)), psa(3, 201));
+        assert!("MRK 1:45 3:35".parse::<Versification>().is_err());
+        assert!("MRK 1:45 2:x".parse::<Versification>().is_err());
+    }
+
+    #[test]
+    fn check_versification() {
+        let vrs: Versification = "MRK 1:5 2:3\n-MRK 1:4\n".parse().expect("Versification");
+        let doc = "\\id MRK\n\
+             \\c 1\n\
+             \\p \\v 1 a \\v 2-3 b \\v 2 c \\v 6 d\n\
+             \\c 2\n\
+             \\p \\v 1 a \\v 2 b \\v 3a c\n\
+             \\c 3\n\
+             \\p \\v 1 a\n"
+            .parse::<Document>()
+            .expect("Document");
+        let diagnostics = doc
+            .check_versification(&vrs)
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            diagnostics,
+            [
+                "3:20: error[out-of-order]: verse 2 follows verse 3",
+                "3:27: error[out-of-bounds]: MRK 1 has 5 verses, not 6",
+                "2:1: warning[missing-verse]: MRK 1 is missing verses 5",
+                "6:1: error[out-of-bounds]: MRK has 2 chapters, not 3",
+            ]
+        );
+    }
+
+    #[test]
+    fn check_continuity() {
+        let source = "\\id MRK\n\
+                      \\p \\v 1 early\n\
+                      \\c 1\n\
+                      \\p \\v 1 a \\v 2-3 b \\v 3-4 c \\v 2 d \\v 6 e\n\
+                      \\c 3\n\
+                      \\p \\v 1 a\n\
+                      \\c 3\n";
+        let doc = source.parse::<Document>().expect("Document");
+        let check = |vrs| {
+            doc.check_continuity(vrs)
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            check(None),
+            [
+                "1:1: warning[missing-chapter]: MRK is missing chapters 2",
+                "2:4: error[verse-before-chapter]: verse 1 before the first chapter",
+                "3:1: warning[missing-verse]: MRK 1 is missing verses 5",
+                "4:20: error[overlapping-verses]: verses 3-4 overlap an earlier verse",
+                "4:29: error[duplicate-number]: verse 2 is repeated",
+                "7:1: error[duplicate-number]: chapter 3 is repeated",
+            ]
+        );
+        let vrs: Versification = "MRK 1:6 2:3 3:2 4:1\n-MRK 1:5\n"
+            .parse()
+            .expect("Versification");
+        assert_eq!(
+            check(Some(&vrs)),
+            [
+                "1:1: warning[missing-chapter]: MRK is missing chapters 2, 4",
+                "2:4: error[verse-before-chapter]: verse 1 before the first chapter",
+                "4:20: error[overlapping-verses]: verses 3-4 overlap an earlier verse",
+                "4:29: error[duplicate-number]: verse 2 is repeated",
+                "5:1: warning[missing-verse]: MRK 3 is missing verses 2",
+                "7:1: error[duplicate-number]: chapter 3 is repeated",
+                "7:1: warning[missing-verse]: MRK 3 is missing verses 1, 2",
+            ]
+        );
+    }
+
+    #[test]
+    fn check_long_ranges() {
+        let doc =
+            "\\id MRK\n\\c 1\n\\p \\v 1 a \\v 5-3 b \\v 4-4000000000 c \\v 7 d\n\\c 4000000000\n"
+                .parse::<Document>()
+                .expect("Document");
+        let vrs: Versification = "MRK 1:6\n".parse().expect("Versification");
+        let diagnostics = doc
+            .check_continuity(None)
+            .into_iter()
+            .chain(doc.check_versification(&vrs))
+            .map(|d| d.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            diagnostics,
+            [
+                "1:1: warning[missing-chapter]: MRK is missing chapters 2-3999999999",
+                "2:1: warning[missing-verse]: MRK 1 is missing verses 2, 3",
+                "3:38: error[duplicate-number]: verse 7 is repeated",
+                "3:20: error[out-of-bounds]: MRK 1 has 6 verses, not 4000000000",
+                "3:38: error[out-of-bounds]: MRK 1 has 6 verses, not 7",
+                "3:38: error[out-of-order]: verse 7 follows verse 4000000000",
+                "2:1: warning[missing-verse]: MRK 1 is missing verses 2, 3",
+                "4:1: error[out-of-bounds]: MRK has 1 chapters, not 4000000000",
+            ]
+        );
+    }
+}
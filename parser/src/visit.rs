@@ -0,0 +1,178 @@
+//! Visitors over a document tree.
+//!
+//! Each method defaults to walking into the children, so an implementation
+//! overrides only what it needs. An overriding `visit_node` calls
+//! [`walk_node`] to carry on into the children, or leaves it out to skip
+//! them. Returning [`ControlFlow::Break`] stops the whole walk.
+
+use std::ops::ControlFlow;
+
+use crate::document::{Content, Document, Node, Text};
+
+pub trait Visit {
+    fn visit_content(&mut self, content: &Content) -> ControlFlow<()> {
+        walk_content(self, content)
+    }
+
+    fn visit_node(&mut self, node: &Node) -> ControlFlow<()> {
+        walk_node(self, node)
+    }
+
+    fn visit_text(&mut self, _text: &Text) -> ControlFlow<()> {
+        ControlFlow::Continue(())
+    }
+}
+
+pub fn walk_content<V: Visit + ?Sized>(visitor: &mut V, content: &Content) -> ControlFlow<()> {
+    match content {
+        Content::Text(text) => visitor.visit_text(text),
+        Content::OptBreak | Content::NoBreakSpace => ControlFlow::Continue(()),
+        item => item
+            .node()
+            .map_or(ControlFlow::Continue(()), |node| visitor.visit_node(node)),
+    }
+}
+
+pub fn walk_node<V: Visit + ?Sized>(visitor: &mut V, node: &Node) -> ControlFlow<()> {
+    for item in &node.content {
+        visitor.visit_content(item)?;
+    }
+    ControlFlow::Continue(())
+}
+
+/// The in-place counterpart of [`Visit`].
+pub trait VisitMut {
+    fn visit_content_mut(&mut self, content: &mut Content) -> ControlFlow<()> {
+        walk_content_mut(self, content)
+    }
+
+    fn visit_node_mut(&mut self, node: &mut Node) -> ControlFlow<()> {
+        walk_node_mut(self, node)
+    }
+
+    fn visit_text_mut(&mut self, _text: &mut Text) -> ControlFlow<()> {
+        ControlFlow::Continue(())
+    }
+}
+
+pub fn walk_content_mut<V: VisitMut + ?Sized>(
+    visitor: &mut V,
+    content: &mut Content,
+) -> ControlFlow<()> {
+    match content {
+        Content::Text(text) => visitor.visit_text_mut(text),
+        Content::OptBreak | Content::NoBreakSpace => ControlFlow::Continue(()),
+        item => item.node_mut().map_or(ControlFlow::Continue(()), |node| {
+            visitor.visit_node_mut(node)
+        }),
+    }
+}
+
+pub fn walk_node_mut<V: VisitMut + ?Sized>(visitor: &mut V, node: &mut Node) -> ControlFlow<()> {
+    for item in &mut node.content {
+        visitor.visit_content_mut(item)?;
+    }
+    ControlFlow::Continue(())
+}
+
+impl Document<'_> {
+    /// Visit the root `usfm` node and everything below it.
+    pub fn accept<V: Visit + ?Sized>(&self, visitor: &mut V) -> ControlFlow<()> {
+        match &self.nodes {
+            Some(root) => visitor.visit_node(root),
+            None => ControlFlow::Continue(()),
+        }
+    }
+
+    pub fn accept_mut<V: VisitMut + ?Sized>(&mut self, visitor: &mut V) -> ControlFlow<()> {
+        match &mut self.nodes {
+            Some(root) => visitor.visit_node_mut(root),
+            None => ControlFlow::Continue(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::ops::ControlFlow;
+
+    use super::{walk_content, walk_node, Visit, VisitMut};
+    use crate::document::{Content, Document, Node, Text};
+
+    const USFM: &str = "\\id MRK\n\
+                        \\c 1\n\
+                        \\p \\v 1 In the \\nd beginning\\nd*\\f + \\ft a note\\f* was\n\
+                        \\p \\v 2 the word\n";
+
+    // Counts words outside notes, stopping after a limit.
+    struct Words {
+        count: usize,
+        limit: usize,
+    }
+
+    impl Visit for Words {
+        fn visit_content(&mut self, content: &Content) -> ControlFlow<()> {
+            match content {
+                Content::Note(_) => ControlFlow::Continue(()),
+                item => walk_content(self, item),
+            }
+        }
+
+        fn visit_text(&mut self, text: &Text) -> ControlFlow<()> {
+            self.count += text.as_str().split_whitespace().count();
+            if self.count >= self.limit {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        }
+    }
+
+    #[test]
+    fn visit() {
+        let doc: Document = USFM.parse().expect("Document");
+        let mut words = Words {
+            count: 0,
+            limit: usize::MAX,
+        };
+        assert_eq!(doc.accept(&mut words), ControlFlow::Continue(()));
+        assert_eq!(words.count, 6);
+
+        let mut words = Words { count: 0, limit: 3 };
+        assert_eq!(doc.accept(&mut words), ControlFlow::Break(()));
+        assert_eq!(words.count, 3);
+
+        struct Styles(Vec<String>);
+        impl Visit for Styles {
+            fn visit_node(&mut self, node: &Node) -> ControlFlow<()> {
+                self.0.push(node.style.to_string());
+                match node.style.as_ref() {
+                    "p" => ControlFlow::Continue(()),
+                    _ => walk_node(self, node),
+                }
+            }
+        }
+        let mut styles = Styles(Vec::new());
+        let _ = doc.accept(&mut styles);
+        assert_eq!(styles.0, ["usfm", "id", "c", "p", "p"]);
+    }
+
+    #[test]
+    fn visit_mut() {
+        struct Upper;
+        impl VisitMut for Upper {
+            fn visit_text_mut(&mut self, text: &mut Text) -> ControlFlow<()> {
+                text.text = text.text.to_uppercase().into();
+                ControlFlow::Continue(())
+            }
+        }
+        let mut doc: Document = "\\id MRK\n\\c 1\n\\p \\v 1 in the \\nd lord\\nd*\n"
+            .parse()
+            .expect("Document");
+        let _ = doc.accept_mut(&mut Upper);
+        assert_eq!(
+            doc.to_string(),
+            "\\id MRK\n\\c 1\n\\p\n\\v 1 IN THE \\nd LORD\\nd*\n"
+        );
+    }
+}
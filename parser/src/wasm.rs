@@ -0,0 +1,84 @@
+//! `wasm-bindgen` exports for web editors running the parser client side.
+//! Each takes source text and hands back a JavaScript value, converted with
+//! `serde-wasm-bindgen` so that JSON objects arrive as plain objects.
+//! Failures come back as a thrown `Error` rather than a panic.
+
+use std::io;
+
+use serde::Serialize;
+use serde_json::Value;
+use serde_wasm_bindgen::Serializer;
+use wasm_bindgen::prelude::{wasm_bindgen, JsError, JsValue};
+
+use crate::{
+    document::{Document, State},
+    lsp,
+};
+
+/// Parse leniently, so a document being edited still yields a tree, and
+/// return it as USJ.
+#[wasm_bindgen(js_name = parseUsfm)]
+pub fn parse_usfm(text: &str) -> Result<JsValue, JsError> {
+    to_js(&usj(text))
+}
+
+#[wasm_bindgen(js_name = toUsx)]
+pub fn to_usx(text: &str) -> Result<String, JsError> {
+    Ok(usx(text)?)
+}
+
+/// Parse and validation diagnostics, with line and UTF-16 character
+/// positions as in the Language Server Protocol.
+#[wasm_bindgen]
+pub fn diagnostics(text: &str) -> Result<JsValue, JsError> {
+    to_js(&lsp_diagnostics(text))
+}
+
+fn to_js(value: &impl Serialize) -> Result<JsValue, JsError> {
+    Ok(value.serialize(&Serializer::json_compatible())?)
+}
+
+fn usj(text: &str) -> Value {
+    Document::from_str_lenient(text).0.to_usj()
+}
+
+fn usx(text: &str) -> io::Result<String> {
+    let mut out = Vec::new();
+    Document::from_str_lenient(text).0.to_usx(&mut out)?;
+    String::from_utf8(out).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn lsp_diagnostics(text: &str) -> Vec<lsp::Diagnostic> {
+    let (doc, mut diagnostics) = Document::from_str_lenient(text);
+    diagnostics.extend(doc.validate(State::usfm_ext()));
+    lsp::publish_diagnostics("", None, text, &diagnostics).diagnostics
+}
+
+// `JsValue`s can only be made on a wasm32 target, so the tests check the
+// values before they are handed across.
+#[cfg(test)]
+mod test {
+    use super::{lsp_diagnostics, usj, usx};
+
+    #[test]
+    fn entry_points() {
+        let usj_value = usj("\\id MRK\n\\c 1\n\\p \\v 1 In\n");
+        assert_eq!(usj_value["type"], "USJ");
+        assert_eq!(usj_value["content"][1]["type"], "chapter");
+        assert!(usx("\\id MRK\n\\p x\n")
+            .expect("USX")
+            .contains("<para style=\"p\">x</para>"));
+        let diagnostics = serde_json::to_value(lsp_diagnostics("\\id MRK\n\\p a \\zzz b\n\\c x\n"))
+            .expect("diagnostics serialize");
+        let diagnostics = diagnostics.as_array().expect("array");
+        assert!(!diagnostics.is_empty());
+        assert!(diagnostics
+            .iter()
+            .all(|d| d["range"]["start"]["line"].is_u64()));
+        assert_eq!(
+            usj("\\id MRK\n\\c x\n")["type"],
+            "USJ",
+            "a broken document still parses"
+        );
+    }
+}
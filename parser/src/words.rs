@@ -0,0 +1,141 @@
+//! Words marked up with `\w` and their Strong's numbers and lemmas, for
+//! concordance and interlinear tools.
+
+use crate::{
+    document::{Content, Document, Span},
+    reference::Reference,
+    versification::verse_range,
+};
+
+/// A `\w` span, such as `\w God|strong="G2316" lemma="θεός"\w*`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaggedWord {
+    /// The verse the word is in, with no verse before the first one.
+    pub reference: Reference,
+    /// The word as it appears in the text.
+    pub surface: String,
+    /// The Strong's numbers, several when the attribute lists them
+    /// separated by commas as in `strong="H1254,H0853"`.
+    pub strong: Vec<String>,
+    pub lemma: Option<String>,
+    /// Where the span is in the source, for a document parsed from USFM.
+    pub span: Option<Span>,
+}
+
+impl Document<'_> {
+    /// Every `\w` word outside notes, in document order.
+    pub fn tagged_words(&self) -> Vec<TaggedWord> {
+        let (Some(book), Some(root)) = (self.book(), &self.nodes) else {
+            return Vec::new();
+        };
+        let mut collector = Collector {
+            reference: Reference::new(book, 0, None),
+            words: Vec::new(),
+        };
+        collector.content(&root.content);
+        collector.words
+    }
+}
+
+struct Collector {
+    reference: Reference,
+    words: Vec<TaggedWord>,
+}
+
+impl Collector {
+    fn content(&mut self, content: &[Content]) {
+        for item in content {
+            match item {
+                Content::Chapter(node) => {
+                    self.reference.chapter = node
+                        .attributes
+                        .get("number")
+                        .and_then(|n| n.parse().ok())
+                        .unwrap_or(self.reference.chapter);
+                    self.reference.verse = None;
+                    self.content(&node.content);
+                }
+                Content::Verse(node) => {
+                    if let Some((first, _)) =
+                        node.attributes.get("number").and_then(|n| verse_range(n))
+                    {
+                        self.reference.verse = Some(first);
+                    }
+                }
+                Content::Char(node) if node.style == "w" => {
+                    let attribute = |name| node.attributes.get(name).map(|v| v.trim());
+                    self.words.push(TaggedWord {
+                        reference: self.reference,
+                        surface: node.text(),
+                        strong: attribute("strong")
+                            .map(|strong| {
+                                strong
+                                    .split(',')
+                                    .map(str::trim)
+                                    .filter(|s| !s.is_empty())
+                                    .map(str::to_owned)
+                                    .collect()
+                            })
+                            .unwrap_or_default(),
+                        lemma: attribute("lemma")
+                            .filter(|lemma| !lemma.is_empty())
+                            .map(str::to_owned),
+                        span: node.span,
+                    });
+                }
+                Content::Note(_) => {}
+                item => {
+                    if let Some(node) = item.node() {
+                        self.content(&node.content);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::document::Document;
+
+    #[test]
+    fn tagged_words() {
+        let source = "\\id GEN\n\\c 1\n\\p \\v 1 In the beginning \
+                      \\w God|strong=\"H0430\" lemma=\"אֱלֹהִים\"\\w* \
+                      \\w created|strong=\"H1254, H0853\"\\w*\\f + \\fr 1:1 \\w note\\w*\\f*\n\
+                      \\v 2 \\nd \\+w Lord|Yahweh\\+w*\\nd*\n";
+        let doc = source.parse::<Document>().expect("Document");
+        let words = doc.tagged_words();
+        let summary = words
+            .iter()
+            .map(|w| {
+                (
+                    w.reference.to_string(),
+                    w.surface.as_str(),
+                    w.strong.clone(),
+                    w.lemma.as_deref(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            [
+                (
+                    "GEN 1:1".to_owned(),
+                    "God",
+                    vec!["H0430".to_owned()],
+                    Some("אֱלֹהִים")
+                ),
+                (
+                    "GEN 1:1".to_owned(),
+                    "created",
+                    vec!["H1254".to_owned(), "H0853".to_owned()],
+                    None
+                ),
+                ("GEN 1:2".to_owned(), "Lord", vec![], Some("Yahweh")),
+            ]
+        );
+        let span = words[1].span.expect("span");
+        assert!(source[span.range()].starts_with("\\w created|"));
+    }
+}
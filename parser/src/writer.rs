@@ -0,0 +1,598 @@
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fmt::{self, Display, Write},
+};
+
+use crate::{
+    document::{Content, Document, FragmentContext, LineEnding, Node, State},
+    extension::{Category, Extensions},
+};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Whitespace {
+    /// One paragraph per line, each verse on its own line, and runs of
+    /// whitespace inside text collapsed to a single space.
+    #[default]
+    Normalized,
+    /// Reproduce the original source byte for byte if the tree has not been
+    /// changed since it was parsed, otherwise keep text whitespace as is.
+    /// Nodes parsed from USFM are copied verbatim while they and
+    /// everything in them still carry a span, even when the rest of the
+    /// tree has been edited.
+    Preserve,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Options {
+    pub whitespace: Whitespace,
+    /// The line ending to write, by default the one the source used most,
+    /// see [`Document::line_ending`]. Text copied verbatim from the source
+    /// keeps its own.
+    pub line_ending: Option<LineEnding>,
+}
+
+pub struct Usfm<'d> {
+    doc: &'d Document<'d>,
+    options: Options,
+}
+
+impl Document<'_> {
+    pub fn to_usfm(&self, options: Options) -> Usfm<'_> {
+        Usfm { doc: self, options }
+    }
+}
+
+impl Display for Document<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.to_usfm(Options::default()).fmt(f)
+    }
+}
+
+impl Display for Usfm<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let preserve = self.options.whitespace == Whitespace::Preserve;
+        let line_ending = self
+            .options
+            .line_ending
+            .unwrap_or_else(|| self.doc.line_ending());
+        let mut out = LineEnds {
+            out: f,
+            ending: line_ending.as_str(),
+            cr: false,
+        };
+        let mut writer = Writer {
+            out: &mut out,
+            markers: State::usfm_ext(),
+            collapse: !preserve,
+            source: preserve.then(|| self.doc.source()),
+            fragment: self.doc.fragment().copied(),
+        };
+        match &self.doc.nodes {
+            Some(root) => writer.document(root),
+            None => Ok(()),
+        }
+    }
+}
+
+// Writes each `\n` as `ending`, leaving `\r\n` copied from the source as
+// it is.
+struct LineEnds<'w, W> {
+    out: &'w mut W,
+    ending: &'static str,
+    // Whether the last thing written ended with `\r`.
+    cr: bool,
+}
+
+impl<W: Write> Write for LineEnds<'_, W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if self.ending == "\n" {
+            return self.out.write_str(s);
+        }
+        let mut start = 0;
+        for (n, _) in s.match_indices('\n') {
+            let after_cr = match n {
+                0 => self.cr,
+                n => s.as_bytes()[n - 1] == b'\r',
+            };
+            if !after_cr {
+                self.out.write_str(&s[start..n])?;
+                self.out.write_str(self.ending)?;
+                start = n + 1;
+            }
+        }
+        if let Some(&last) = s.as_bytes().last() {
+            self.cr = last == b'\r';
+        }
+        self.out.write_str(&s[start..])
+    }
+}
+
+struct Writer<'w, W> {
+    out: &'w mut W,
+    markers: &'static Extensions,
+    collapse: bool,
+    source: Option<&'w str>,
+    fragment: Option<FragmentContext>,
+}
+
+// Content added or edited after parsing has no span, and neither should
+// anything containing it.
+fn intact(node: &Node) -> bool {
+    node.span.is_some()
+        && node.content.iter().all(|item| match item {
+            Content::Text(text) => text.span.is_some(),
+            Content::OptBreak | Content::NoBreakSpace => true,
+            item => item.node().is_some_and(intact),
+        })
+}
+
+impl<W: Write> Writer<'_, W> {
+    fn document(&mut self, root: &Node) -> fmt::Result {
+        if self.verbatim(root)? {
+            return Ok(());
+        }
+        let version = root.attributes.get("version").map(|s| s.as_ref());
+        if let Some(fragment) = self.fragment {
+            // The `\id` line and any chapter the fragment was assumed to
+            // start in were not in the source.
+            let mut content = root.content.get(1..).unwrap_or_default();
+            if let (Some(_), [Content::Chapter(chapter), rest @ ..]) = (fragment.chapter, content) {
+                self.blocks(&chapter.content)?;
+                content = rest;
+            }
+            return self.blocks(content);
+        }
+        for item in &root.content {
+            self.block(item)?;
+            if let (Content::Book(book), Some(version)) = (item, version) {
+                if version != "3" && (self.source.is_none() || book.span.is_none()) {
+                    writeln!(self.out, "\\usfm {version}")?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Runs of text and inline markers outside any paragraph, as at the start
+    // of a fragment, are written as a line of their own.
+    fn blocks(&mut self, mut content: &[Content]) -> fmt::Result {
+        while let Some(item) = content.first() {
+            let inline = content
+                .iter()
+                .take_while(|item| {
+                    matches!(
+                        item,
+                        Content::Text(_)
+                            | Content::Char(_)
+                            | Content::Note(_)
+                            | Content::Verse(_)
+                            | Content::Milestone(_)
+                            | Content::OptBreak
+                            | Content::NoBreakSpace
+                    )
+                })
+                .count();
+            if inline == 0 {
+                self.block(item)?;
+                content = &content[1..];
+            } else {
+                self.inlines(&content[..inline])?;
+                self.out.write_char('\n')?;
+                content = &content[inline..];
+            }
+        }
+        Ok(())
+    }
+
+    // Copy a node's original text when it still has a source span.
+    fn verbatim(&mut self, node: &Node) -> Result<bool, fmt::Error> {
+        match self
+            .source
+            .zip(node.span.filter(|_| intact(node)))
+            .and_then(|(source, span)| source.get(span.range()))
+        {
+            Some(text) => self.out.write_str(text).map(|_| true),
+            None => Ok(false),
+        }
+    }
+
+    fn block(&mut self, item: &Content) -> fmt::Result {
+        if item.node().map_or(Ok(false), |node| self.verbatim(node))? {
+            return Ok(());
+        }
+        match item {
+            Content::Book(node) => {
+                let code = node.attributes.get("code").map_or("", |s| s.as_ref());
+                write!(self.out, "\\id {code}")?;
+                if !node.content.is_empty() {
+                    self.out.write_char(' ')?;
+                    self.inlines(&node.content)?;
+                }
+                self.out.write_char('\n')
+            }
+            Content::Chapter(node) => {
+                let number = node.attributes.get("number").map_or("", |s| s.as_ref());
+                writeln!(self.out, "\\c {number}")?;
+                if let Some(altnumber) = node.attributes.get("altnumber") {
+                    writeln!(self.out, "\\ca {altnumber}\\ca*")?;
+                }
+                if let Some(pubnumber) = node.attributes.get("pubnumber") {
+                    writeln!(self.out, "\\cp {pubnumber}")?;
+                }
+                self.blocks(&node.content)
+            }
+            Content::Para(node) | Content::Unknown(node) => {
+                write!(self.out, "\\{}", node.style)?;
+                match node.content.first() {
+                    None => (),
+                    Some(Content::Verse(_)) => self.out.write_char('\n')?,
+                    Some(_) => self.out.write_char(' ')?,
+                }
+                self.inlines(&node.content)?;
+                self.out.write_char('\n')
+            }
+            Content::List(node) | Content::Stanza(node) | Content::Table(node) => {
+                self.blocks(&node.content)
+            }
+            Content::Row(node) => {
+                write!(self.out, "\\{}", node.style)?;
+                if !node.content.is_empty() {
+                    self.out.write_char(' ')?;
+                }
+                for cell in &node.content {
+                    self.cell(cell)?;
+                }
+                self.out.write_char('\n')
+            }
+            Content::Periph(node) => {
+                write!(self.out, "\\periph")?;
+                if let Some(title) = node.attributes.get("alt") {
+                    write!(self.out, " {title}")?;
+                }
+                self.attributes(&node.attributes, &["alt"])?;
+                self.out.write_char('\n')?;
+                self.blocks(&node.content)
+            }
+            Content::Sidebar(node) => {
+                write!(self.out, "\\{}", node.style)?;
+                if let Some(category) = node.attributes.get("category") {
+                    write!(self.out, " \\cat {category}\\cat*")?;
+                }
+                self.out.write_char('\n')?;
+                self.blocks(&node.content)?;
+                writeln!(self.out, "\\{}e", node.style)
+            }
+            item => self.inline(item, None),
+        }
+    }
+
+    fn cell(&mut self, item: &Content) -> fmt::Result {
+        let Content::Cell(node) = item else {
+            return self.inline(item, None);
+        };
+        if self.verbatim(node)? {
+            return Ok(());
+        }
+        write!(self.out, "\\{}", node.style)?;
+        let span = node.attributes.get("colspan");
+        if let Some(span) = span.and_then(|span| span.parse::<usize>().ok()) {
+            let base = node.style.trim_end_matches(|c: char| c.is_ascii_digit());
+            let first = node.style[base.len()..].parse::<usize>().unwrap_or(1);
+            write!(self.out, "-{}", first + span - 1)?;
+        }
+        self.out.write_char(' ')?;
+        self.inlines(&node.content)
+    }
+
+    fn inlines(&mut self, content: &[Content]) -> fmt::Result {
+        for (n, item) in content.iter().enumerate() {
+            self.inline(item, content.get(n + 1))?;
+        }
+        Ok(())
+    }
+
+    fn inline(&mut self, item: &Content, next: Option<&Content>) -> fmt::Result {
+        if item.node().map_or(Ok(false), |node| self.verbatim(node))? {
+            return Ok(());
+        }
+        match item {
+            Content::Text(text) => {
+                let text = self.normalize(text.as_str());
+                let text = escape(&text);
+                match next {
+                    Some(Content::Verse(_)) if self.collapse && text.ends_with(' ') => {
+                        writeln!(self.out, "{}", &text[..text.len() - 1])
+                    }
+                    _ => self.out.write_str(&text),
+                }
+            }
+            Content::OptBreak => self.out.write_str("//"),
+            Content::NoBreakSpace => self.out.write_char('~'),
+            Content::Verse(node) => {
+                let number = node.attributes.get("number").map_or("", |s| s.as_ref());
+                write!(self.out, "\\v {number}")?;
+                if let Some(altnumber) = node.attributes.get("altnumber") {
+                    write!(self.out, " \\va {altnumber}\\va*")?;
+                }
+                if let Some(pubnumber) = node.attributes.get("pubnumber") {
+                    write!(self.out, " \\vp {pubnumber}\\vp*")?;
+                }
+                match next {
+                    Some(_) => self.out.write_char(' '),
+                    None => Ok(()),
+                }
+            }
+            Content::Milestone(node) => {
+                write!(self.out, "\\{}", node.style)?;
+                if !node.attributes.is_empty() {
+                    self.out.write_char(' ')?;
+                    self.attributes(&node.attributes, &[])?;
+                }
+                self.out.write_str("\\*")
+            }
+            Content::Char(node) => {
+                let prefix = if node.nested { "\\+" } else { "\\" };
+                write!(self.out, "{prefix}{} ", node.style)?;
+                self.inlines(&node.content)?;
+                self.attributes(&node.attributes, &[])?;
+                // Note content markers are conventionally left unclosed when
+                // the next marker implicitly ends them, unless attributes
+                // need the closing marker.
+                let category = self.markers.category(node.style.as_ref());
+                let note_char = matches!(
+                    category,
+                    Some(Category::FootnoteChar | Category::CrossreferenceChar)
+                );
+                if note_char
+                    && node.attributes.is_empty()
+                    && !matches!(next, Some(Content::Text(_)))
+                {
+                    return Ok(());
+                }
+                write!(self.out, "{prefix}{}*", node.style)
+            }
+            Content::Note(node) => {
+                let caller = node.attributes.get("caller").map_or("+", |s| s.as_ref());
+                write!(self.out, "\\{} {caller} ", node.style)?;
+                if let Some(category) = node.attributes.get("category") {
+                    write!(self.out, "\\cat {category}\\cat* ")?;
+                }
+                self.inlines(&node.content)?;
+                write!(self.out, "\\{}*", node.style)
+            }
+            Content::Figure(node) => {
+                write!(self.out, "\\{} ", node.style)?;
+                if node.attributes.is_empty() {
+                    // An attribute-less figure only survives in the USFM 2 form.
+                    self.out.write_str("|||||")?;
+                    self.inlines(&node.content)?;
+                    self.out.write_char('|')?;
+                } else {
+                    self.inlines(&node.content)?;
+                    self.attributes(&node.attributes, &[])?;
+                }
+                write!(self.out, "\\{}*", node.style)
+            }
+            Content::Unknown(node) => {
+                write!(self.out, "\\{} ", node.style)?;
+                if node.content.is_empty() {
+                    return Ok(());
+                }
+                self.inlines(&node.content)?;
+                write!(self.out, "\\{}*", node.style)
+            }
+            block => self.block(block),
+        }
+    }
+
+    fn attributes(
+        &mut self,
+        attributes: &HashMap<Cow<'_, str>, Cow<'_, str>>,
+        skip: &[&str],
+    ) -> fmt::Result {
+        let mut attributes = attributes
+            .iter()
+            .filter(|(k, _)| !skip.contains(&k.as_ref()))
+            .collect::<Vec<_>>();
+        if attributes.is_empty() {
+            return Ok(());
+        }
+        attributes.sort();
+        self.out.write_char('|')?;
+        for (n, (name, value)) in attributes.into_iter().enumerate() {
+            if n > 0 {
+                self.out.write_char(' ')?;
+            }
+            write!(self.out, "{name}=\"")?;
+            for c in value.chars() {
+                if matches!(c, '"' | '\\') {
+                    self.out.write_char('\\')?;
+                }
+                self.out.write_char(c)?;
+            }
+            self.out.write_char('"')?;
+        }
+        Ok(())
+    }
+
+    fn normalize(&self, text: &str) -> String {
+        if !self.collapse {
+            return text.to_owned();
+        }
+        let mut res = String::with_capacity(text.len());
+        let mut space = false;
+        for c in text.chars() {
+            if c.is_whitespace() {
+                space = true;
+                continue;
+            }
+            if space {
+                res.push(' ');
+                space = false;
+            }
+            res.push(c);
+        }
+        if space {
+            res.push(' ');
+        }
+        res
+    }
+}
+
+// Characters with a meaning of their own in text, written as escapes. A `/`
+// is only escaped where it could run into another.
+fn escape(text: &str) -> Cow<'_, str> {
+    if !text.contains(['\\', '|', '~', '/']) {
+        return Cow::Borrowed(text);
+    }
+    let mut res = String::with_capacity(text.len() + 1);
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' | '|' | '~' => {
+                res.push('\\');
+                res.push(c);
+            }
+            '/' if chars.peek().is_none_or(|&next| next == '/') => res.push_str("\\/"),
+            c => res.push(c),
+        }
+    }
+    Cow::Owned(res)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Options, Whitespace};
+    use crate::document::{Content, Document, LineEnding, ParseOptions, UnknownMarkers};
+
+    const PRESERVE: Options = Options {
+        whitespace: Whitespace::Preserve,
+        line_ending: None,
+    };
+
+    fn round_trip(usfm: &str) -> String {
+        let doc: Document = usfm.parse().expect("Document");
+        let out = doc.to_string();
+        let reparsed: Document = out.parse().expect("Reparsed document");
+        assert_eq!(reparsed.nodes, doc.nodes, "{out}");
+        out
+    }
+
+    #[test]
+    fn write_normalized() {
+        assert_eq!(
+            round_trip(
+                "\\id MRK Good News\n\
+                 \\h Mark\n\
+                 \\mt1 Mark\n\
+                 \\c 1\n\
+                 \\s1 John the Baptist\n\
+                 \\p\n\
+                 \\v 1 The beginning \\nd of\\nd* the\n\
+                 \\v 2 As it is written\\f + \\fr 1.2 \\ft Isaiah & \\fk Malachi\\fk*:\\f*:\n\
+                 \\q1 “I will send\n\
+                 \\q2 my messenger.”\n"
+            ),
+            "\\id MRK Good News\n\
+             \\h Mark\n\
+             \\mt1 Mark\n\
+             \\c 1\n\
+             \\s1 John the Baptist\n\
+             \\p\n\
+             \\v 1 The beginning \\nd of\\nd* the\n\
+             \\v 2 As it is written\\f + \\fr 1.2 \\ft Isaiah & \\fk Malachi\\fk*:\\f*:\n\
+             \\q1 “I will send\n\
+             \\q2 my messenger.”\n"
+        );
+    }
+
+    #[test]
+    fn write_structures() {
+        round_trip(
+            "\\id NUM\n\
+             \\usfm 3.1\n\
+             \\c 2 \\ca 3\\ca*\n\
+             \\cp B\n\
+             \\p \\v 1 \\vp 1a\\vp* Census \\qt-s |who=\"Moses\"\\*count\\qt-e\\*//\n\
+             \\li1 first \\w tents|lemma=\"te\\\"nt\"\\w* \\nd \\+w Lord|strong=\"H3068\"\\+w*\\nd*\n\
+             \\tr \\th1 Tribe \\thr2 Number\n\
+             \\tr \\tc1-2 Reuben\n\
+             \\esb \\cat People\\cat*\n\
+             \\p Sidebar\n\
+             \\esbe\n\
+             \\p \\v 2 \\fig Camp|src=\"camp.png\" size=\"col\"\\fig* \\fig |||||Tent|\\fig*\n",
+        );
+        round_trip(
+            "\\id FRT\n\
+             \\periph Title Page|id=\"title\"\n\
+             \\mt1 Scripture\n",
+        );
+    }
+
+    #[test]
+    fn write_preserved() {
+        let source = "\\id MRK\r\n\\c 1\r\n\\p\r\n\\v 1  Text   spread\r\nover lines\r\n";
+        let mut doc: Document = source.parse().expect("Document");
+        assert_eq!(doc.to_usfm(PRESERVE).to_string(), source);
+        assert_eq!(
+            doc.to_string(),
+            "\\id MRK\r\n\\c 1\r\n\\p\r\n\\v 1 Text spread over lines\r\n"
+        );
+        let lf = Options {
+            line_ending: Some(LineEnding::Lf),
+            ..Options::default()
+        };
+        assert_eq!(
+            doc.to_usfm(lf).to_string(),
+            "\\id MRK\n\\c 1\n\\p\n\\v 1 Text spread over lines\n"
+        );
+
+        let root = doc.nodes.as_mut().expect("root");
+        root.content.truncate(1);
+        root.content.push(Content::Text("Edited  text".into()));
+        assert_eq!(
+            doc.to_usfm(PRESERVE).to_string(),
+            "\\id MRK\r\nEdited  text"
+        );
+    }
+
+    #[test]
+    fn write_verbatim() {
+        let source = "\u{FEFF}\\id MRK  Mark\r\n\\c 1\r\n\\p\r\n\\v 1  Text \\nd  Lord\\nd*\r\n\
+                      \\v 2 More\r\n\r\n\\p  \\v 3 Last\r\n";
+        let mut doc = source.parse::<Document>().expect("Document");
+        assert_eq!(doc.to_usfm(PRESERVE).to_string(), source);
+
+        let root = doc.nodes.as_mut().expect("root");
+        root.span = None;
+        let Some(Content::Chapter(chapter)) = root.content.last_mut() else {
+            panic!("expected chapter");
+        };
+        chapter.span = None;
+        let Some(Content::Para(para)) = chapter.content.last_mut() else {
+            panic!("expected paragraph");
+        };
+        para.span = None;
+        para.content.push(Content::Text(" edited".into()));
+        assert_eq!(
+            doc.to_usfm(PRESERVE).to_string(),
+            "\u{FEFF}\\id MRK  Mark\r\n\\c 1\r\n\\p\r\n\\v 1  Text \\nd  Lord\\nd*\r\n\
+             \\v 2 More\r\n\r\n\\p\r\n\\v 3 Last edited\r\n"
+        );
+    }
+
+    #[test]
+    fn write_unknown() {
+        let options = ParseOptions {
+            unknown_markers: UnknownMarkers::Preserve,
+            ..ParseOptions::default()
+        };
+        let source = "\\id MRK\n\\c 1\n\\p\n\\v 1 In \\xyz the\\xyz* \\abc start\n\\yy1 odd\n\\tr \\tc1-2 cell\n";
+        let doc = Document::from_str_with(source, options).expect("Document");
+        assert_eq!(doc.to_string(), source);
+        let written = doc.to_string();
+        let reparsed = Document::from_str_with(&written, options).expect("Reparsed");
+        assert_eq!(reparsed.nodes, doc.nodes);
+    }
+}
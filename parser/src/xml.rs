@@ -0,0 +1,205 @@
+use std::borrow::Cow;
+
+use nom::{
+    branch::alt,
+    bytes::complete::{is_not, tag, take_until, take_while1},
+    character::complete::{char, multispace0, multispace1},
+    combinator::{cut, eof, opt, value},
+    error::context,
+    multi::many0,
+    sequence::{delimited, pair, preceded, separated_pair, terminated},
+    Parser,
+};
+
+use super::Result;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub(crate) enum Xml<'i> {
+    Element(Element<'i>),
+    Text(Cow<'i, str>),
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub(crate) struct Element<'i> {
+    pub name: &'i str,
+    pub attributes: Vec<(&'i str, Cow<'i, str>)>,
+    pub children: Vec<Xml<'i>>,
+}
+
+impl<'i> Element<'i> {
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find_map(|(k, v)| (*k == name).then_some(v.as_ref()))
+    }
+}
+
+fn name(input: &str) -> Result<'_, &str> {
+    take_while1(|c: char| c.is_alphanumeric() || "-_:.".contains(c)).parse(input)
+}
+
+fn comment(input: &str) -> Result<'_, &str> {
+    delimited(tag("<!--"), take_until("-->"), tag("-->")).parse(input)
+}
+
+fn instruction(input: &str) -> Result<'_, &str> {
+    delimited(tag("<?"), take_until("?>"), tag("?>")).parse(input)
+}
+
+fn doctype(input: &str) -> Result<'_, &str> {
+    delimited(tag("<!DOCTYPE"), is_not(">"), char('>')).parse(input)
+}
+
+fn misc(input: &str) -> Result<'_, ()> {
+    value((), many0(alt((multispace1, comment, instruction, doctype)))).parse(input)
+}
+
+fn cdata(input: &str) -> Result<'_, &str> {
+    delimited(tag("<![CDATA["), take_until("]]>"), tag("]]>")).parse(input)
+}
+
+pub(crate) fn unescape(text: &str) -> Cow<'_, str> {
+    if !text.contains('&') {
+        return Cow::Borrowed(text);
+    }
+    let mut res = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        res.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entity = rest[1..].find(';').map(|end| &rest[1..end + 1]);
+        let decoded = match entity {
+            Some("amp") => Some('&'),
+            Some("lt") => Some('<'),
+            Some("gt") => Some('>'),
+            Some("quot") => Some('"'),
+            Some("apos") => Some('\''),
+            Some(code) if code.starts_with("#x") => u32::from_str_radix(&code[2..], 16)
+                .ok()
+                .and_then(char::from_u32),
+            Some(code) if code.starts_with('#') => code[1..].parse().ok().and_then(char::from_u32),
+            _ => None,
+        };
+        match (entity, decoded) {
+            (Some(entity), Some(c)) => {
+                res.push(c);
+                rest = &rest[entity.len() + 2..];
+            }
+            _ => {
+                res.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    res.push_str(rest);
+    Cow::Owned(res)
+}
+
+fn attribute(input: &str) -> Result<'_, (&str, Cow<'_, str>)> {
+    let quoted = alt((
+        delimited(char('"'), opt(is_not("\"")), char('"')),
+        delimited(char('\''), opt(is_not("'")), char('\'')),
+    ))
+    .map(|value| unescape(value.unwrap_or_default()));
+    separated_pair(name, delimited(multispace0, char('='), multispace0), quoted).parse(input)
+}
+
+fn text(input: &str) -> Result<'_, Xml<'_>> {
+    alt((is_not("<").map(unescape), cdata.map(Cow::Borrowed)))
+        .map(Xml::Text)
+        .parse(input)
+}
+
+fn node(input: &str) -> Result<'_, Option<Xml<'_>>> {
+    alt((
+        value(None, alt((comment, instruction))),
+        element.map(|e| Some(Xml::Element(e))),
+        text.map(Some),
+    ))
+    .parse(input)
+}
+
+pub(crate) fn element(input: &str) -> Result<'_, Element<'_>> {
+    let (input, (name, attributes)) = preceded(
+        char('<'),
+        pair(name, many0(preceded(multispace1, attribute))),
+    )
+    .parse(input)?;
+    let (input, _) = multispace0(input)?;
+    if let Ok((input, _)) = tag::<_, _, ()>("/>")(input) {
+        return Ok((
+            input,
+            Element {
+                name,
+                attributes,
+                children: Vec::new(),
+            },
+        ));
+    }
+    let end_tag = context(
+        "end tag",
+        delimited(tag("</"), tag(name), pair(multispace0, char('>'))),
+    );
+    let (input, children) =
+        context("element", cut(delimited(char('>'), many0(node), end_tag))).parse(input)?;
+    Ok((
+        input,
+        Element {
+            name,
+            attributes,
+            children: children.into_iter().flatten().collect(),
+        },
+    ))
+}
+
+pub(crate) fn document(input: &str) -> Result<'_, Element<'_>> {
+    delimited(
+        pair(opt(char('\u{FEFF}')), misc),
+        element,
+        terminated(misc, eof),
+    )
+    .parse(input)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{document, unescape, Element, Xml};
+
+    #[test]
+    fn entities() {
+        assert_eq!(unescape("plain"), "plain");
+        assert_eq!(unescape("a &amp; b &lt;c&gt;"), "a & b <c>");
+        assert_eq!(unescape("&#65;&#x42;&quot;&apos;"), "AB\"'");
+        assert_eq!(unescape("fish & chips &bogus;"), "fish & chips &bogus;");
+    }
+
+    #[test]
+    fn elements() {
+        let input = "<?xml version=\"1.0\"?>\n<!-- header -->\n\
+                     <usx version='3.0'><para style=\"p\">In &amp; <ms style=\"ts\" />out</para></usx>\n";
+        assert_eq!(
+            document(input),
+            Ok((
+                "",
+                Element {
+                    name: "usx",
+                    attributes: vec![("version", "3.0".into())],
+                    children: vec![Xml::Element(Element {
+                        name: "para",
+                        attributes: vec![("style", "p".into())],
+                        children: vec![
+                            Xml::Text("In & ".into()),
+                            Xml::Element(Element {
+                                name: "ms",
+                                attributes: vec![("style", "ts".into())],
+                                children: vec![],
+                            }),
+                            Xml::Text("out".into()),
+                        ],
+                    })],
+                }
+            ))
+        );
+        assert!(document("<usx><para></usx>").is_err());
+    }
+}
@@ -0,0 +1,30 @@
+//! The usfm-grammar test suite, run when `USFM_TEST_SUITE` names its
+//! `tests` folder, as in
+//!
+//! ```text
+//! git clone https://github.com/usfm-bible/usfm-grammar
+//! USFM_TEST_SUITE=usfm-grammar/tests cargo test --features conformance --test conformance -- --nocapture
+//! ```
+//!
+//! Failures are reported rather than failing the run, since the suite
+//! covers markup the parser does not yet support. Set
+//! `USFM_TEST_SUITE_STRICT` to fail on any.
+#![cfg(feature = "conformance")]
+
+use std::env;
+
+use parser::conformance;
+
+#[test]
+fn conformance() {
+    let Some(dir) = env::var_os("USFM_TEST_SUITE") else {
+        eprintln!("USFM_TEST_SUITE is not set, skipping the conformance suite");
+        return;
+    };
+    let report = conformance::run(&dir).expect("test suite");
+    println!("{report}");
+    assert!(!report.results.is_empty(), "no tests found in {dir:?}");
+    if env::var_os("USFM_TEST_SUITE_STRICT").is_some() {
+        assert_eq!(report.failed(), 0, "conformance failures");
+    }
+}
@@ -0,0 +1,49 @@
+use parser::{
+    diagnostic::{Code, Diagnostic},
+    document::Document,
+};
+
+const MARK: &str = r#"\id MRK 41MRKGNT92.SFM, Good News Translation, June 2003
+\usfm 3.0
+\ide UTF-8
+\h Mark
+\toc1 The Gospel according to Mark
+\mt1 The Gospel according to Mark
+\is Introduction
+\ip \bk The Gospel according to Mark\bk* begins with the statement
+that it is "the Good News about Jesus Christ, the Son of God."
+\ie
+\c 1
+\s1 The Preaching of John the Baptist
+\r (Matthew 3.1-12; Luke 3.1-18; John 1.19-28)
+\p
+\v 1 This is the Good News about Jesus Christ, the Son of God.\f + \fr 1.1: \ft Some manuscripts do not have \fq the Son of God.\f*
+\v 2 It began as the prophet Isaiah had written:
+\q1 “God said, ‘I will send my messenger ahead of you
+\q2 to clear the way for you.’
+"#;
+
+#[test]
+fn parse_book() {
+    let res = MARK.parse::<Document>();
+    if let Err(err) = res {
+        panic!("parsing book failed:\n{err}\n");
+    }
+}
+
+#[test]
+fn parse_book_errors() {
+    assert!("\\c 1\n\\p text\n".parse::<Document>().is_err());
+    let error = "\\id MRK\n\\c 1\n\\p \\v 1 text\\f + \\ft note"
+        .parse::<Document>()
+        .expect_err("unterminated footnote");
+    let diagnostic = error
+        .get_ref()
+        .and_then(|e| e.downcast_ref::<Diagnostic>())
+        .expect("diagnostic");
+    assert_eq!(diagnostic.code, Code::MissingEndmarker);
+    assert_eq!(
+        (diagnostic.span.start.line, diagnostic.span.start.column),
+        (3, 26)
+    );
+}
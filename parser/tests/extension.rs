@@ -16,5 +16,17 @@ fn load_usfm_ext() {
     } else {
         markers = res.unwrap();
     }
-    assert_eq!(markers.len(), 302);
+    assert_eq!(markers.len(), 304);
+}
+
+#[test]
+fn write_usfm_ext() {
+    let markers: Extensions = include_str!("../docs/grammar/usfm.ext")
+        .parse()
+        .expect("usfm.ext");
+    let mut out = Vec::new();
+    markers.write_to(&mut out).expect("write_to");
+    let text = String::from_utf8(out).expect("UTF-8");
+    assert!(text.starts_with("\\marker add\n\\category char\n"));
+    assert_eq!(text.parse::<Extensions>().expect("reparse"), markers);
 }
@@ -0,0 +1,42 @@
+#![cfg(feature = "serde")]
+use parser::{
+    document::Document,
+    extension::{Category, Extensions},
+};
+
+const MARKERS: &str = r#"
+\marker w
+\attributes lemma? strong? srcloc?
+\category char
+\defattrib lemma
+\description A wordlist entry
+"#;
+
+#[test]
+fn extensions_round_trip() {
+    let markers: Extensions = MARKERS.parse().expect("Extensions");
+    let json = serde_json::to_string(&markers).expect("serialize");
+    assert!(json.contains(r#""category":"char""#));
+    assert_eq!(
+        serde_json::from_str::<Extensions>(&json).expect("deserialize"),
+        markers
+    );
+    assert_eq!(
+        serde_json::from_str::<Category>(r#""footnotechar""#).expect("category"),
+        Category::FootnoteChar
+    );
+}
+
+#[test]
+fn document_round_trip() {
+    let doc: Document = "\\id MRK\n\
+                         \\c 1\n\
+                         \\p \\v 1 The \\w beginning|lemma=\"ἀρχή\"\\w* of the good news\\f + \\ft note\\f*\n"
+        .parse()
+        .expect("Document");
+    let json = serde_json::to_string(&doc).expect("serialize");
+    assert_eq!(
+        serde_json::from_str::<Document>(&json).expect("deserialize"),
+        doc
+    );
+}